@@ -0,0 +1,51 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    process::{Command, Stdio},
+};
+
+use test::Bencher;
+
+// copies of the loop below, each is a few dozen tokens spread over 8 lines
+const BLOCKS: usize = 1_000;
+
+// a long file of the usual mix of labels, comments, expressions and macros
+fn source() -> String {
+    let mut src = String::from("copy MACRO\n    LD A, [\\1]\n    LD [\\2], A\nEND\n    ADJ $150\n");
+    for i in 0..BLOCKS {
+        writeln!(src, "block{i}  ; block number {i}").unwrap();
+        writeln!(src, "    LD HL, $C000 + ({i} % $100)").unwrap();
+        writeln!(src, "    LD B, %0000_0111 | ({i} % 8)").unwrap();
+        writeln!(src, ".loop").unwrap();
+        writeln!(src, "    copy(HL, $D000)").unwrap();
+        writeln!(src, "    DEC B").unwrap();
+        writeln!(src, "    JR NZ, .loop").unwrap();
+        writeln!(src, "    DB \"block\", {i} % $100").unwrap();
+    }
+    src
+}
+
+#[bench]
+fn assemble(b: &mut Bencher) {
+    let dir = env::temp_dir().join(format!("gb23-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("bench.asm");
+    let output = dir.join("bench.gb");
+    fs::write(&input, source()).unwrap();
+    b.iter(|| {
+        let status = Command::new(env!("CARGO_BIN_EXE_gb23-asm"))
+            .arg(&input)
+            .arg("-o")
+            .arg(&output)
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+#![feature(test)]
+
+extern crate test;
+
+use gb23::{
+    config::Settings,
+    emu::{
+        bus::{Bus, BusDevice, Port},
+        cpu::WideRegister,
+        mbc::mbc0::Mbc0,
+        Emu,
+    },
+};
+use test::Bencher;
+
+// each iteration runs this many instructions, so instructions/sec is
+// `INSTRUCTIONS * 1e9 / ns_per_iter`
+const INSTRUCTIONS: usize = 10_000;
+
+struct NoInput;
+
+impl<B: Bus> BusDevice<B> for NoInput {
+    fn reset(&mut self, _bus: &mut B) {}
+
+    fn read(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    fn tick(&mut self, _bus: &mut B) -> usize {
+        0
+    }
+}
+
+// runs the code at the cart entry point
+fn bench_code(b: &mut Bencher, code: &[u8]) {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x100..(0x100 + code.len())].copy_from_slice(code);
+    let mut sram = Vec::new();
+    let mut emu = Emu::new(
+        &Settings::default(),
+        Vec::new(),
+        Mbc0::new(&rom, &mut sram),
+        NoInput,
+    );
+    emu.reset();
+    let (cpu, mut cpu_view) = emu.cpu_view();
+    cpu.set_wide_register(WideRegister::PC, 0x100);
+    cpu_view.write(Port::BOOT, 0x01);
+    cpu_view.write(Port::LCDC, 0x81);
+    b.iter(|| {
+        for _ in 0..INSTRUCTIONS {
+            test::black_box(emu.tick());
+        }
+    });
+}
+
+#[bench]
+fn tick(b: &mut Bencher) {
+    // a busy loop touching WRAM:
+    //   loop: inc a
+    //         ld [hl], a
+    //         jr loop
+    bench_code(b, &[0x21, 0x00, 0xC0, 0x3C, 0x77, 0x18, 0xFC]);
+}
+
+#[bench]
+fn tick_mixed(b: &mut Bencher) {
+    // a loop running lots of different opcodes, closer to a game than the one above and
+    // harder on the dispatch's branch prediction
+    #[rustfmt::skip]
+    bench_code(b, &[
+        0x31, 0xFF, 0xDF, //       ld sp, $DFFF
+        0x21, 0x00, 0xC0, //       ld hl, $C000
+        0x3C, //             loop: inc a
+        0x47, //                   ld b, a
+        0x81, //                   add a, c
+        0xAA, //                   xor d
+        0x22, //                   ld [hl+], a
+        0xCB, 0x00, //             rlc b
+        0xCB, 0x31, //             swap c
+        0xCB, 0x5F, //             bit 3, a
+        0xE6, 0x0F, //             and $0F
+        0x5E, //                   ld e, [hl]
+        0x1D, //                   dec e
+        0xC5, //                   push bc
+        0xD1, //                   pop de
+        0x93, //                   sub e
+        0xB4, //                   or h
+        0x26, 0xC0, //             ld h, $C0
+        0xFE, 0x10, //             cp $10
+        0x20, 0xE7, //             jr nz, loop
+        0xC3, 0x06, 0x01, //       jp loop
+    ]);
+}
@@ -0,0 +1,228 @@
+use std::{iter::Peekable, str::Chars};
+
+/// Rows of numbers read from a CSV or JSON file for the `DATA` directive.
+pub struct Table {
+    rows: Vec<Vec<(Option<String>, i32)>>,
+}
+
+// a column picked out of every row, by its header or object key, or by position
+pub enum Field<'a> {
+    Name(&'a str),
+    Index(usize),
+}
+
+impl Table {
+    // CSV needs a header row, JSON is an array of objects or of arrays
+    pub fn parse(path: &str, text: &str) -> Result<Self, String> {
+        if path.to_ascii_lowercase().ends_with(".json") {
+            Self::parse_json(text)
+        } else {
+            Self::parse_csv(text)
+        }
+    }
+
+    fn parse_csv(text: &str) -> Result<Self, String> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Ok(Self { rows: Vec::new() });
+        };
+        let names = header.split(',').map(unquote).collect::<Vec<_>>();
+        let mut rows = Vec::new();
+        for (i, line) in lines {
+            let cells = line.split(',').map(unquote).collect::<Vec<_>>();
+            if cells.len() != names.len() {
+                return Err(format!(
+                    "line {}: {} columns, the header has {}",
+                    i + 1,
+                    cells.len(),
+                    names.len()
+                ));
+            }
+            let mut row = Vec::new();
+            for (name, cell) in names.iter().zip(cells) {
+                let value = number(cell)
+                    .ok_or_else(|| format!("line {}: {cell:?} is not a number", i + 1))?;
+                row.push((Some(name.to_string()), value));
+            }
+            rows.push(row);
+        }
+        Ok(Self { rows })
+    }
+
+    fn parse_json(text: &str) -> Result<Self, String> {
+        let mut chars = text.chars().peekable();
+        let Json::Array(items) = Json::parse(&mut chars)? else {
+            return Err("expected an array of rows".to_string());
+        };
+        skip_space(&mut chars);
+        if chars.next().is_some() {
+            return Err("unexpected garbage after the rows".to_string());
+        }
+        let mut rows = Vec::new();
+        for (i, item) in items.into_iter().enumerate() {
+            let fields = match item {
+                Json::Object(fields) => fields
+                    .into_iter()
+                    .map(|(key, value)| (Some(key), value))
+                    .collect::<Vec<_>>(),
+                Json::Array(values) => values.into_iter().map(|value| (None, value)).collect(),
+                _ => return Err(format!("row {i} is not an object or an array")),
+            };
+            let mut row = Vec::new();
+            for (key, value) in fields {
+                let value = match value {
+                    Json::Number(value) => value,
+                    Json::Bool(value) => value as i32,
+                    _ => return Err(format!("row {i} has something other than a number")),
+                };
+                row.push((key, value));
+            }
+            rows.push(row);
+        }
+        Ok(Self { rows })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn get(&self, row: usize, field: &Field) -> Option<i32> {
+        let row = &self.rows[row];
+        match field {
+            Field::Name(name) => row
+                .iter()
+                .find(|(key, _)| key.as_deref() == Some(name))
+                .map(|(_, value)| *value),
+            Field::Index(index) => row.get(*index).map(|(_, value)| *value),
+        }
+    }
+}
+
+fn unquote(cell: &str) -> &str {
+    let cell = cell.trim();
+    cell.strip_prefix('"')
+        .and_then(|cell| cell.strip_suffix('"'))
+        .unwrap_or(cell)
+}
+
+// the same number formats as the assembler, plus the `0x` level editors like
+fn number(cell: &str) -> Option<i32> {
+    let (negative, cell) = match cell.strip_prefix('-') {
+        Some(cell) => (true, cell),
+        None => (false, cell),
+    };
+    let value = if let Some(hex) = cell.strip_prefix('$').or_else(|| cell.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = cell.strip_prefix('%') {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        cell.parse::<i64>().ok()?
+    };
+    i32::try_from(if negative { -value } else { value }).ok()
+}
+
+enum Json {
+    Null,
+    Bool(bool),
+    Number(i32),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+fn skip_space(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+impl Json {
+    fn parse(chars: &mut Peekable<Chars>) -> Result<Self, String> {
+        skip_space(chars);
+        match chars.peek().copied() {
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                skip_space(chars);
+                if chars.next_if_eq(&']').is_some() {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(Json::parse(chars)?);
+                    skip_space(chars);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some(']') => return Ok(Json::Array(items)),
+                        _ => return Err("expected , or ] in array".to_string()),
+                    }
+                }
+            }
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                skip_space(chars);
+                if chars.next_if_eq(&'}').is_some() {
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let Json::String(key) = Json::parse(chars)? else {
+                        return Err("expected a string key in object".to_string());
+                    };
+                    skip_space(chars);
+                    if chars.next() != Some(':') {
+                        return Err("expected : in object".to_string());
+                    }
+                    fields.push((key, Json::parse(chars)?));
+                    skip_space(chars);
+                    match chars.next() {
+                        Some(',') => continue,
+                        Some('}') => return Ok(Json::Object(fields)),
+                        _ => return Err("expected , or } in object".to_string()),
+                    }
+                }
+            }
+            Some('"') => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => return Ok(Json::String(string)),
+                        Some('\\') => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(c @ ('"' | '\\' | '/')) => string.push(c),
+                            _ => return Err("unsupported escape in string".to_string()),
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut text = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || "-+.".contains(*c))
+                {
+                    text.push(c);
+                }
+                text.parse::<i32>()
+                    .map(Json::Number)
+                    .map_err(|_| format!("{text} is not a whole number that fits in 32 bits"))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    "null" => Ok(Json::Null),
+                    _ => Err(format!("unexpected {word}")),
+                }
+            }
+            _ => Err("unexpected end of JSON".to_string()),
+        }
+    }
+}
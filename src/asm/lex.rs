@@ -1,17 +1,29 @@
 use std::{
-    io::{self, ErrorKind, Read, Seek},
+    fmt,
+    io::{self, ErrorKind, Read},
     marker::PhantomData,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
     slice, str,
 };
 
+use clap::ValueEnum;
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Dir(&'static str);
 
 impl Dir {
     pub const ADJ: Self = Self("ADJ");
+    pub const ALIGN: Self = Self("ALIGN");
+    pub const DATA: Self = Self("DATA");
     pub const DB: Self = Self("DB");
+    pub const DL: Self = Self("DL");
+    pub const DS: Self = Self("DS");
     pub const DW: Self = Self("DW");
+    pub const DWBE: Self = Self("DWBE");
     pub const END: Self = Self("END");
+    pub const EQU: Self = Self("EQU");
+    pub const EXPORT: Self = Self("EXPORT");
     pub const IF: Self = Self("IF");
     pub const IFDEF: Self = Self("IFDEF");
     pub const IFNDEF: Self = Self("IFNDEF");
@@ -19,6 +31,10 @@ impl Dir {
     pub const INCLUDE: Self = Self("INCLUDE");
     pub const MACRO: Self = Self("MACRO");
     pub const PAD: Self = Self("PAD");
+    pub const POPS: Self = Self("POPS");
+    pub const PURGE: Self = Self("PURGE");
+    pub const PUSHS: Self = Self("PUSHS");
+    pub const SECTION: Self = Self("SECTION");
     pub const SEGMENT: Self = Self("SEGMENT");
 }
 
@@ -30,9 +46,14 @@ impl AsRef<str> for Dir {
 
 const DIRECTIVES: &[Dir] = &[
     Dir::ADJ,
+    Dir::ALIGN,
+    Dir::DATA,
     Dir::DB,
+    Dir::DL,
     Dir::DW,
+    Dir::DWBE,
     Dir::END,
+    Dir::EQU,
     Dir::IF,
     Dir::IFDEF,
     Dir::IFNDEF,
@@ -40,6 +61,9 @@ const DIRECTIVES: &[Dir] = &[
     Dir::INCLUDE,
     Dir::MACRO,
     Dir::PAD,
+    Dir::POPS,
+    Dir::PURGE,
+    Dir::PUSHS,
     Dir::SEGMENT,
 ];
 
@@ -99,6 +123,19 @@ impl AsRef<str> for Mne {
     }
 }
 
+// only directives when assembling RGBDS sources
+const RGBDS_DIRECTIVES: &[Dir] = &[Dir::DS, Dir::EXPORT, Dir::SECTION];
+
+// RGBDS spellings of directives we have under another name
+const RGBDS_ALIASES: &[(&str, Dir)] = &[("ENDC", Dir::END), ("ENDM", Dir::END)];
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dialect {
+    Gb23,
+    // case insensitive keywords, `label:` and `label::`, and a few RGBDS directives
+    Rgbds,
+}
+
 const MNEMONICS: &[Mne] = &[
     Mne::ADC,
     Mne::ADD,
@@ -205,6 +242,8 @@ impl Tok {
     pub const SP: Self = Self(0xA4);
     pub const NC: Self = Self(0xA5);
     pub const NZ: Self = Self(0xA6);
+
+    pub const BANKALIGN: Self = Self(0xB0); // BANKALIGN(), never lexed, only an operator
 }
 
 const GRAPHEMES: &[(&[u8; 2], Tok)] = &[
@@ -246,6 +285,16 @@ pub trait TokStream {
     fn num(&self) -> i32;
 
     fn line(&self) -> usize;
+
+    // the source file, when this stream reads from one
+    fn file(&self) -> Option<&str> {
+        None
+    }
+
+    // the macro, when this stream is expanding one
+    fn macro_name(&self) -> Option<&str> {
+        None
+    }
 }
 
 pub struct StrInterner<'a> {
@@ -317,31 +366,77 @@ impl<'a> Label<'a> {
     pub fn string(&self) -> &'a str {
         self.string
     }
+
+    pub fn scope(&self) -> Option<&'a str> {
+        self.scope
+    }
 }
 
-pub struct Lexer<R> {
-    reader: PeekReader<R>,
+// locals show with the label they are under
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.scope.unwrap_or(""), self.string)
+    }
+}
+
+pub struct Lexer {
+    name: String,
+    // sources are small, so they are read whole and scanned in place
+    source: Vec<u8>,
+    pos: usize,
     string: String,
     number: i32,
     stash: Option<Tok>,
+    // the token before this one, `%` after a value is always modulus
+    last: Option<Tok>,
     line: usize,
+    dialect: Dialect,
 }
 
-impl<R: Read + Seek> Lexer<R> {
-    pub fn new(reader: R) -> Self {
-        Self {
-            reader: PeekReader::new(reader),
+impl Lexer {
+    pub fn new<R: Read>(name: &str, mut reader: R, dialect: Dialect) -> io::Result<Self> {
+        let mut source = Vec::new();
+        reader.read_to_end(&mut source)?;
+        Ok(Self {
+            name: name.to_string(),
+            source,
+            pos: 0,
             string: String::new(),
             number: 0,
             stash: None,
+            last: None,
             line: 1,
-        }
+            dialect,
+        })
+    }
+
+    fn peek_byte(&self) -> Option<u8> {
+        self.source.get(self.pos).copied()
+    }
+
+    // skips over every byte matching `pred`, returning where the run started
+    fn scan(&mut self, pred: impl Fn(u8) -> bool) -> usize {
+        let start = self.pos;
+        self.pos += self.source[start..]
+            .iter()
+            .position(|&c| !pred(c))
+            .unwrap_or(self.source.len() - start);
+        start
+    }
+
+    // copies the bytes scanned since `start` into the token string
+    fn take(&mut self, start: usize) {
+        self.string
+            .push_str(&String::from_utf8_lossy(&self.source[start..self.pos]));
     }
 }
 
-impl<R: Read + Seek> TokStream for Lexer<R> {
+impl TokStream for Lexer {
     fn err(&self, msg: &str) -> io::Error {
-        io::Error::new(ErrorKind::InvalidData, format!("{}: {msg}", self.line))
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{}:{}: {msg}", self.name, self.line),
+        )
     }
 
     fn peek(&mut self) -> io::Result<Tok> {
@@ -349,34 +444,21 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
             return Ok(tok);
         }
         // skip whitespace
-        while let Some(c) = self.reader.peek()? {
-            if !b" \t\r".contains(&c) {
-                break;
-            }
-            self.reader.eat();
-        }
+        self.scan(|c| b" \t\r".contains(&c));
         // skip comment
-        if let Some(b';') = self.reader.peek()? {
-            while !matches!(self.reader.peek()?, Some(b'\n')) {
-                self.reader.eat();
-            }
+        if let Some(b';') = self.peek_byte() {
+            self.scan(|c| c != b'\n');
         }
-        match self.reader.peek()? {
+        match self.peek_byte() {
             None => {
-                self.reader.eat();
                 self.stash = Some(Tok::EOF);
                 Ok(Tok::EOF)
             }
             // macro argument
             Some(b'\\') => {
-                self.reader.eat();
-                while let Some(c) = self.reader.peek()? {
-                    if !c.is_ascii_digit() {
-                        break;
-                    }
-                    self.string.push(c as char);
-                    self.reader.eat();
-                }
+                self.pos += 1;
+                let start = self.scan(|c| c.is_ascii_digit());
+                self.take(start);
                 self.number =
                     i32::from_str_radix(&self.string, 10).map_err(|e| self.err(&e.to_string()))?;
                 if self.number < 1 {
@@ -389,31 +471,34 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
             Some(c) if c.is_ascii_digit() || c == b'$' || c == b'%' => {
                 let radix = match c {
                     b'$' => {
-                        self.reader.eat();
+                        self.pos += 1;
                         16
                     }
                     b'%' => {
-                        self.reader.eat();
+                        self.pos += 1;
                         2
                     }
                     _ => 10,
                 };
                 // edge case: modulus
-                if (c == b'%') && self.reader.peek()?.is_some_and(|nc| !b"01".contains(&nc)) {
+                let after_value = matches!(
+                    self.last,
+                    Some(Tok::NUM | Tok::IDENT | Tok::ARG | Tok::RPAREN)
+                );
+                if (c == b'%')
+                    && (after_value || self.peek_byte().is_some_and(|nc| !b"01".contains(&nc)))
+                {
                     self.stash = Some(Tok::MODULUS);
                     return Ok(Tok::MODULUS);
                 }
-                // parse number
-                while let Some(c) = self.reader.peek()? {
-                    if c == b'_' {
-                        continue; // allow '_' separators in numbers
-                    }
-                    if !c.is_ascii_alphanumeric() {
-                        break;
-                    }
-                    self.string.push(c as char);
-                    self.reader.eat();
-                }
+                // parse number, allowing '_' separators
+                let start = self.scan(|c| c.is_ascii_alphanumeric() || (c == b'_'));
+                self.string.extend(
+                    self.source[start..self.pos]
+                        .iter()
+                        .filter(|&&c| c != b'_')
+                        .map(|&c| c as char),
+                );
                 self.number = i32::from_str_radix(&self.string, radix)
                     .map_err(|e| self.err(&e.to_string()))?;
                 self.stash = Some(Tok::NUM);
@@ -421,24 +506,21 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
             }
             // string
             Some(b'"') => {
-                self.reader.eat();
-                while let Some(c) = self.reader.peek()? {
-                    if c == b'"' {
-                        self.reader.eat();
-                        break;
-                    }
-                    self.string.push(c as char);
-                    self.reader.eat();
+                self.pos += 1;
+                let start = self.scan(|c| c != b'"');
+                self.take(start);
+                if self.peek_byte().is_some() {
+                    self.pos += 1;
                 }
                 self.stash = Some(Tok::STR);
                 Ok(Tok::STR)
             }
             // char
             Some(b'\'') => {
-                self.reader.eat();
-                if let Some(c) = self.reader.peek()? {
+                self.pos += 1;
+                if let Some(c) = self.peek_byte() {
                     if c.is_ascii_graphic() {
-                        self.reader.eat();
+                        self.pos += 1;
                         self.number = c as i32;
                         self.stash = Some(Tok::NUM);
                         return Ok(Tok::NUM);
@@ -448,12 +530,30 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
             }
             // idents and single chars
             Some(c) => {
-                while let Some(c) = self.reader.peek()? {
-                    if !c.is_ascii_alphanumeric() && !b"_.".contains(&c) {
-                        break;
+                let start = self.scan(|c| c.is_ascii_alphanumeric() || b"_.".contains(&c));
+                self.take(start);
+                if (self.string.len() > 1) && (self.dialect == Dialect::Rgbds) {
+                    let keyword = self.string.to_ascii_uppercase();
+                    if let Some((_, dir)) =
+                        RGBDS_ALIASES.iter().find(|(alias, _)| *alias == keyword)
+                    {
+                        self.string = dir.0.to_string();
+                    } else if RGBDS_DIRECTIVES.iter().any(|dir| dir.0 == keyword) {
+                        self.string = keyword;
+                        self.stash = Some(Tok::DIR);
+                        return Ok(Tok::DIR);
+                    } else if DIRECTIVES.iter().any(|dir| dir.0 == keyword)
+                        || MNEMONICS.iter().any(|mne| mne.0 == keyword)
+                        || ["LOW", "HIGH", "BANKALIGN"].contains(&keyword.as_str())
+                    {
+                        self.string = keyword;
+                    } else if self.peek_byte() == Some(b':') {
+                        // `label:`, or `label::` to export it which means nothing here
+                        self.pos += 1;
+                        if self.peek_byte() == Some(b':') {
+                            self.pos += 1;
+                        }
                     }
-                    self.reader.eat();
-                    self.string.push(c as char);
                 }
                 if self.string.len() > 1 {
                     if DIRECTIVES
@@ -476,19 +576,19 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
                     self.stash = Some(Tok::IDENT);
                     return Ok(Tok::IDENT);
                 }
-                // the char wasn't an ident, so wasnt eaten
+                // the char wasn't an ident, so wasnt scanned
                 if self.string.len() == 0 {
-                    self.reader.eat();
+                    self.pos += 1;
                 }
                 // check for grapheme
-                if let Some(nc) = self.reader.peek()? {
+                if let Some(nc) = self.peek_byte() {
                     let s = &[c, nc];
                     if let Some(tok) = GRAPHEMES
                         .iter()
                         .find_map(|(gf, tok)| (*gf == s).then_some(tok))
                         .copied()
                     {
-                        self.reader.eat();
+                        self.pos += 1;
                         self.stash = Some(tok);
                         return Ok(tok);
                     }
@@ -502,7 +602,8 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
 
     fn eat(&mut self) {
         self.string.clear();
-        if let Some(Tok::NEWLINE) = self.stash.take() {
+        self.last = self.stash.take();
+        if let Some(Tok::NEWLINE) = self.last {
             self.line += 1;
         }
     }
@@ -510,8 +611,10 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
     fn rewind(&mut self) -> io::Result<()> {
         self.string.clear();
         self.stash = None;
+        self.last = None;
         self.line = 1;
-        self.reader.rewind()
+        self.pos = 0;
+        Ok(())
     }
 
     fn str(&self) -> &str {
@@ -525,6 +628,10 @@ impl<R: Read + Seek> TokStream for Lexer<R> {
     fn line(&self) -> usize {
         self.line
     }
+
+    fn file(&self) -> Option<&str> {
+        Some(&self.name)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -556,88 +663,94 @@ impl<'a> Macro<'a> {
 
 pub struct MacroInvocation<'a> {
     mac: Macro<'a>,
+    // where it was invoked from
+    file: &'a str,
     line: usize,
     index: usize,
-    args: Vec<MacroTok<'a>>,
+    // position within the argument being substituted, if any
+    arg_index: usize,
+    args: Vec<Vec<MacroTok<'a>>>,
 }
 
 impl<'a> MacroInvocation<'a> {
-    pub fn new(mac: Macro<'a>, line: usize, args: Vec<MacroTok<'a>>) -> Self {
+    pub fn new(mac: Macro<'a>, file: &'a str, line: usize, args: Vec<Vec<MacroTok<'a>>>) -> Self {
         Self {
             mac,
+            file,
             line,
             index: 0,
+            arg_index: 0,
             args,
         }
     }
+
+    // the token under the cursor, looking through argument references
+    fn current(&self) -> MacroTok<'a> {
+        match self.mac.toks[self.index] {
+            MacroTok::Arg(index) => self.args[index][self.arg_index],
+            tok => tok,
+        }
+    }
 }
 
 impl<'a> TokStream for MacroInvocation<'a> {
     fn err(&self, msg: &str) -> io::Error {
         io::Error::new(
             ErrorKind::InvalidData,
-            format!("{}:{}: {msg}", self.line, self.mac.name),
+            format!("{}:{}:{}: {msg}", self.file, self.line, self.mac.name),
         )
     }
 
     fn peek(&mut self) -> io::Result<Tok> {
-        match self.mac.toks[self.index] {
+        // skip past arguments that are used up (or empty)
+        while let MacroTok::Arg(index) = self.mac.toks[self.index] {
+            if index >= self.args.len() {
+                return Err(self.err("argument is undefined"));
+            }
+            if self.arg_index < self.args[index].len() {
+                break;
+            }
+            self.index += 1;
+            self.arg_index = 0;
+        }
+        match self.current() {
             MacroTok::Tok(tok) => Ok(tok),
             MacroTok::Str(_) => Ok(Tok::STR),
             MacroTok::Ident(_) => Ok(Tok::IDENT),
             MacroTok::Dir(_) => Ok(Tok::DIR),
             MacroTok::Mne(_) => Ok(Tok::MNE),
             MacroTok::Num(_) => Ok(Tok::NUM),
-            MacroTok::Arg(index) => {
-                if index >= self.args.len() {
-                    return Err(self.err("argument is undefined"));
-                }
-                match self.args[index] {
-                    MacroTok::Tok(tok) => Ok(tok),
-                    MacroTok::Str(_) => Ok(Tok::STR),
-                    MacroTok::Ident(_) => Ok(Tok::IDENT),
-                    MacroTok::Dir(_) => Ok(Tok::DIR),
-                    MacroTok::Mne(_) => Ok(Tok::MNE),
-                    MacroTok::Num(_) => Ok(Tok::NUM),
-                    _ => unreachable!(),
-                }
-            }
+            MacroTok::Arg(_) => unreachable!(),
         }
     }
 
     fn eat(&mut self) {
-        self.index += 1;
+        if let MacroTok::Arg(_) = self.mac.toks[self.index] {
+            self.arg_index += 1;
+        } else {
+            self.index += 1;
+        }
     }
 
     fn rewind(&mut self) -> io::Result<()> {
         self.index = 0;
+        self.arg_index = 0;
         Ok(())
     }
 
     fn str(&self) -> &str {
-        match self.mac.toks[self.index] {
+        match self.current() {
             MacroTok::Str(string) => string,
             MacroTok::Ident(string) => string,
             MacroTok::Dir(string) => string,
             MacroTok::Mne(string) => string,
-            MacroTok::Arg(index) => match self.args[index] {
-                MacroTok::Str(string) => string,
-                MacroTok::Ident(string) => string,
-                MacroTok::Dir(string) => string,
-                MacroTok::Mne(string) => string,
-                _ => unreachable!(),
-            },
             _ => unreachable!(),
         }
     }
 
     fn num(&self) -> i32 {
-        match self.mac.toks[self.index] {
+        match self.current() {
             MacroTok::Num(val) => val,
-            MacroTok::Arg(index) => match self.args[index] {
-                MacroTok::Num(val) => val,
-                _ => unreachable!(),
-            },
             _ => unreachable!(),
         }
     }
@@ -645,81 +758,86 @@ impl<'a> TokStream for MacroInvocation<'a> {
     fn line(&self) -> usize {
         self.line
     }
+
+    fn macro_name(&self) -> Option<&str> {
+        Some(self.mac.name)
+    }
 }
 
+// smallest chunk the interner allocates, bigger macros get a chunk of their own size
+const TOK_CHUNK: usize = 256;
+
 pub struct TokInterner<'a> {
-    storages: Vec<Vec<MacroTok<'a>>>,
+    // each chunk is a leaked boxed slice, only ever touched through its pointer so that
+    // writing into one never reborrows the slices already handed out of it. They stay put
+    // when this grows, and are only freed with the interner
+    chunks: Vec<(NonNull<MacroTok<'a>>, usize)>,
+    // slots filled in the last chunk
+    used: usize,
+    // slots filled across all chunks
+    len: usize,
 }
 
 impl<'a> TokInterner<'a> {
     pub fn new() -> Self {
         Self {
-            storages: Vec::new(),
+            chunks: Vec::new(),
+            used: 0,
+            len: 0,
         }
     }
 
     pub fn intern(&mut self, toks: &[MacroTok<'a>]) -> &'a [MacroTok<'a>] {
-        let mut has_space = None;
-        for (i, storage) in self.storages.iter().enumerate() {
-            // pre-check if we have space for the toks in case we have a cache miss
-            if has_space.is_none() && ((storage.capacity() - storage.len()) >= toks.len()) {
-                has_space = Some(i);
-            }
-            if let Some(index) = storage.windows(toks.len()).position(|win| win == toks) {
-                // SAFETY: the assumption is that we never re-allocate storages
-                unsafe {
-                    return slice::from_raw_parts(storage.as_ptr().add(index), toks.len());
-                }
+        let fits = self
+            .chunks
+            .last()
+            .is_some_and(|&(_, cap)| (cap - self.used) >= toks.len());
+        if !fits {
+            // whatever is left in the last chunk is given up on
+            let cap = toks.len().max(TOK_CHUNK);
+            let chunk = Box::into_raw(Box::<[MacroTok<'a>]>::new_uninit_slice(cap));
+            // SAFETY: `Box::into_raw` never gives back null
+            let ptr = unsafe { NonNull::new_unchecked(chunk as *mut MacroTok<'a>) };
+            self.chunks.push((ptr, cap));
+            self.used = 0;
+        }
+        let (chunk, _) = *self.chunks.last().unwrap();
+        // SAFETY: the slots from `used` on are in bounds (checked above) and in no slice
+        // handed out yet, so writing them through the chunk's raw pointer aliases nothing.
+        // Once written a slot is never written again, and the chunk isn't freed before the
+        // interner is, so the shared slices handed out stay valid and unchanged for as long
+        // as the `Asm` that owns this interner, which is the only place they're kept
+        unsafe {
+            let start = chunk.as_ptr().add(self.used);
+            for (i, tok) in toks.iter().enumerate() {
+                start.add(i).write(*tok);
             }
+            self.used += toks.len();
+            self.len += toks.len();
+            slice::from_raw_parts(start, toks.len())
         }
-        // cache miss, add to a storage if possible
-        let storage = if let Some(index) = has_space {
-            &mut self.storages[index]
-        } else {
-            self.storages.push(Vec::with_capacity(toks.len().max(256)));
-            self.storages.last_mut().unwrap()
-        };
-        let index = storage.len();
-        storage.extend_from_slice(toks);
-        // SAFETY: the assumption is that we never re-allocate storages
-        unsafe { slice::from_raw_parts(storage.as_ptr().add(index), toks.len()) }
     }
 
-    pub fn storages(&'a self) -> &[Vec<MacroTok<'a>>] {
-        &self.storages
+    pub fn len(&self) -> usize {
+        self.len
     }
-}
-
-struct PeekReader<R> {
-    inner: R,
-    stash: Option<u8>,
-}
 
-impl<R: Read + Seek> PeekReader<R> {
-    fn new(reader: R) -> Self {
-        Self {
-            inner: reader,
-            stash: None,
-        }
+    pub fn capacity(&self) -> usize {
+        self.chunks.iter().map(|&(_, cap)| cap).sum()
     }
+}
 
-    fn peek(&mut self) -> io::Result<Option<u8>> {
-        if self.stash.is_none() {
-            let mut buf = [0];
-            self.stash = self
-                .inner
-                .read(&mut buf)
-                .map(|n| if n == 0 { None } else { Some(buf[0]) })?;
+impl Drop for TokInterner<'_> {
+    fn drop(&mut self) {
+        for &(ptr, cap) in &self.chunks {
+            // SAFETY: each chunk came from `Box::into_raw` with this length, and is freed
+            // once. `MacroTok` is `Copy`, so there is nothing in the slots to drop
+            drop(unsafe {
+                Box::from_raw(ptr::slice_from_raw_parts_mut(
+                    ptr.as_ptr() as *mut MaybeUninit<MacroTok<'_>>,
+                    cap,
+                ))
+            });
         }
-        Ok(self.stash)
-    }
-
-    fn eat(&mut self) {
-        self.stash.take();
-    }
-
-    fn rewind(&mut self) -> io::Result<()> {
-        self.stash = None;
-        self.inner.rewind()
     }
 }
@@ -0,0 +1,2054 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, ErrorKind, Write},
+    mem,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use data::{Field, Table};
+pub use lex::{Dialect, Lexer};
+use lex::{
+    Dir, Label, Macro, MacroInvocation, MacroTok, Op, StrInterner, Tok, TokInterner, TokStream,
+};
+
+use crate::{disasm, emu::cpu::OPCODES};
+
+mod data;
+mod lex;
+mod obj;
+
+/// How deep macros may expand inside of each other unless told otherwise.
+pub const DEFAULT_MACRO_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Segment {
+    ROM(u16),  // ROM0 $0000-$3FFF, ROMX $4000-$7FFF
+    WRAM(u16), // WRAM0 $C000-$CFFF, WRAMX $D000-$DFFF
+    SRAM(u16), // $A000-$BFFF
+    VRAM(u16), // $8000-$9FFF
+    HRAM,      // $FF00-$FFFF
+    // ROMX in whichever bank has room, an index into the floating sections
+    Floating(usize),
+}
+
+impl Segment {
+    fn name(&self) -> String {
+        match self {
+            Segment::ROM(bank) => format!("ROM{bank:02X}"),
+            Segment::WRAM(bank) => format!("WRAM{bank:X}"),
+            Segment::SRAM(bank) => format!("SRAM{bank:X}"),
+            Segment::VRAM(bank) => format!("VRAM{bank:X}"),
+            Segment::HRAM => "HRAM".to_string(),
+            Segment::Floating(index) => format!("ROMX #{index}"),
+        }
+    }
+
+    // where the PC starts the first time the segment is used
+    fn start(&self) -> u16 {
+        match self {
+            Segment::ROM(0) => 0x0000,
+            Segment::ROM(_) | Segment::Floating(_) => 0x4000,
+            Segment::WRAM(0) => 0xC000,
+            Segment::WRAM(_) => 0xD000,
+            Segment::SRAM(_) => 0xA000,
+            Segment::VRAM(_) => 0x8000,
+            Segment::HRAM => 0xFF80,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Segment::ROM(_) | Segment::Floating(_) => 0x4000,
+            Segment::WRAM(_) => 0x1000,
+            Segment::SRAM(_) | Segment::VRAM(_) => 0x2000,
+            Segment::HRAM => 0x7F,
+        }
+    }
+
+    fn is_rom(&self) -> bool {
+        matches!(self, Segment::ROM(_) | Segment::Floating(_))
+    }
+}
+
+// the addresses a segment covered and how many bytes went in it during the last pass
+#[derive(Clone, Copy)]
+struct Extent {
+    segment: Segment,
+    start: u16,
+    end: u16,
+    size: usize,
+}
+
+// a `SEGMENT "ROMX"` without a bank, its size is known after pass 1 and it is placed then
+struct Floating<'a> {
+    defined_at: Location<'a>,
+    align: u16,
+    placed: Option<(u16, u16)>,
+}
+
+// an instruction operand, with registers and conditions already in `disasm` template form
+#[derive(Clone, Copy)]
+enum Operand {
+    Fixed(&'static str),
+    Value(Option<i32>),
+    Indirect(Option<i32>),
+    SpOffset(Option<i32>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Location<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Sym<'a> {
+    value: i32,
+    bank: u16,
+    defined_at: Location<'a>,
+    // `=` may be bound to symbols that only get a value further down the file
+    solved: bool,
+    // seen yet during this pass, `EQU` may only refer back to these
+    defined: bool,
+    // bound with `=`, so `PURGE` may remove it
+    purgeable: bool,
+    // referred to by an expression at some point, kept across redefinitions
+    used: bool,
+    // where a label points into, constants have none
+    segment: Option<Segment>,
+}
+
+fn hex(value: i32) -> String {
+    if value < 0 {
+        format!("-${:X}", value.unsigned_abs())
+    } else {
+        format!("${value:X}")
+    }
+}
+
+/// How numbers are written out in map and symbol files, since whatever reads them back
+/// tends to only take one way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Radix {
+    /// Hex after a `$`, like `$C0DE`
+    Dollar,
+    /// Hex after a `0x`, like `0xC0DE`
+    #[value(name = "0x")]
+    ZeroX,
+    /// Hex on its own, like `C0DE`
+    Bare,
+    /// Binary after a `%` in whole bytes, like `%00000100`, for flags
+    Binary,
+    Decimal,
+}
+
+impl Radix {
+    /// `value` with its sign in front, padded to at least `digits` hex digits' worth.
+    pub fn format(self, value: i32, digits: usize) -> String {
+        let sign = if value < 0 { "-" } else { "" };
+        let value = value.unsigned_abs();
+        match self {
+            Self::Dollar => format!("{sign}${value:0digits$X}"),
+            Self::ZeroX => format!("{sign}0x{value:0digits$X}"),
+            Self::Bare => format!("{sign}{value:0digits$X}"),
+            Self::Binary => {
+                let bits = (32 - value.leading_zeros() as usize).max(digits * 4);
+                let bits = bits.div_ceil(8).max(1) * 8;
+                format!("{sign}%{value:0bits$b}")
+            }
+            Self::Decimal => format!("{sign}{value}"),
+        }
+    }
+}
+
+pub struct Asm<'a> {
+    toks: Vec<Box<dyn TokStream + 'a>>,
+    syms: Vec<(Label<'a>, Sym<'a>)>,
+    str_int: StrInterner<'a>,
+    tok_int: TokInterner<'a>,
+    dir: PathBuf,
+    dialect: Dialect,
+    output: Box<dyn Write + 'a>,
+    pc: u16,
+    pc_end: bool,
+    dat: u16,
+    dat_end: bool,
+    segment: Segment,
+    // where every other segment left off, and what `PUSHS` saved
+    parked: Vec<(Segment, u16, bool)>,
+    pushed: Vec<(Segment, u16, bool)>,
+    // the bytes for each ROM segment, a bank's bytes may run on into the following ones
+    rom: Vec<(Segment, Vec<u8>)>,
+    floating: Vec<Floating<'a>>,
+    floating_count: usize,
+
+    scope: Option<&'a str>,
+    // each macro expansion gets a scope of its own, the callers' are put back after
+    scopes: Vec<Option<&'a str>>,
+    expansions: usize,
+    emit: bool,
+    if_level: usize,
+
+    macros: Vec<Macro<'a>>,
+    // names `PURGE` took away, so calling one after says why it fails
+    purged: Vec<&'a str>,
+    macro_depth: usize,
+    values: Vec<i32>,
+    operators: Vec<Op>,
+    // the symbols the last expression couldn't solve, and the first used before its definition
+    unsolved: Vec<Label<'a>>,
+    forward: Option<Label<'a>>,
+    // the last expression went past 32 bits somewhere along the way
+    overflowed: bool,
+    // every symbol that was never defined and where it was used, reported after pass 2
+    undefined: Vec<(Label<'a>, Vec<Location<'a>>)>,
+    extents: Vec<Extent>,
+    // every file read along the way, for --watch
+    deps: Vec<PathBuf>,
+    warnings: Vec<String>,
+}
+
+impl<'a> Asm<'a> {
+    pub fn new(
+        lexer: Lexer,
+        dir: &Path,
+        output: Box<dyn Write + 'a>,
+        macro_depth: usize,
+        dialect: Dialect,
+    ) -> Self {
+        Self {
+            toks: vec![Box::new(lexer)],
+            syms: Vec::new(),
+            str_int: StrInterner::new(),
+            tok_int: TokInterner::new(),
+            dir: dir.to_path_buf(),
+            dialect,
+            output,
+            pc: 0,
+            pc_end: false,
+            dat: 0,
+            dat_end: false,
+            segment: Segment::ROM(0),
+            parked: Vec::new(),
+            pushed: Vec::new(),
+            rom: Vec::new(),
+            floating: Vec::new(),
+            floating_count: 0,
+            scope: None,
+            scopes: Vec::new(),
+            expansions: 0,
+            emit: false,
+            if_level: 0,
+            macros: Vec::new(),
+            purged: Vec::new(),
+            macro_depth,
+            values: Vec::new(),
+            operators: Vec::new(),
+            unsolved: Vec::new(),
+            forward: None,
+            overflowed: false,
+            undefined: Vec::new(),
+            extents: Vec::new(),
+            deps: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // everything up to knowing where each symbol and segment ends up
+    pub fn first_pass(&mut self) -> io::Result<()> {
+        // macros can be used before their definition, so pick them all up front
+        self.collect_macros()?;
+        self.rewind(false)?;
+        self.pass()?;
+        // `=` can be bound to symbols defined further down, which takes another look to solve
+        let mut unsolved = self.unsolved_syms();
+        while unsolved > 0 {
+            self.rewind(false)?;
+            self.pass()?;
+            let still_unsolved = self.unsolved_syms();
+            if still_unsolved == unsolved {
+                break;
+            }
+            unsolved = still_unsolved;
+        }
+        // segments without a bank have a size now, so they can be given one and looked at again
+        if !self.floating.is_empty() {
+            self.place()?;
+            self.rewind(false)?;
+            self.pass()?;
+        }
+        Ok(())
+    }
+
+    pub fn second_pass(&mut self) -> io::Result<()> {
+        self.rewind(true)?;
+        self.pass()?;
+        self.write_rom()
+    }
+
+    pub fn print_stats(&self) {
+        eprintln!("== stats ==");
+        eprintln!("symbols: {}", self.syms.len());
+        eprintln!(
+            "string heap: {}/{} bytes",
+            self.str_int
+                .storages()
+                .iter()
+                .fold(0, |accum, storage| accum + storage.len()),
+            self.str_int
+                .storages()
+                .iter()
+                .fold(0, |accum, storage| accum + storage.capacity())
+        );
+        eprintln!(
+            "macro heap: {}/{} bytes",
+            self.tok_int.len() * mem::size_of::<MacroTok>(),
+            self.tok_int.capacity() * mem::size_of::<MacroTok>()
+        );
+    }
+
+    /// Every label as its bank, address and name, the same as a `.sym` file lists them.
+    pub fn labels(&self) -> impl Iterator<Item = (u16, u16, String)> + '_ {
+        self.syms
+            .iter()
+            .filter(|(_, sym)| sym.segment.is_some())
+            .map(|(label, sym)| (sym.bank, sym.value as u16, label.to_string()))
+    }
+
+    /// Every label, a `bank:addr name` line each. [`Radix::Bare`] is the `.sym` file
+    /// emulators and debuggers load.
+    pub fn write_sym(&self, out: &mut dyn Write, radix: Radix) -> io::Result<()> {
+        let mut labels = self.labels().collect::<Vec<_>>();
+        labels.sort();
+        for (bank, addr, name) in labels {
+            let bank = radix.format(bank.into(), 2);
+            let addr = radix.format(addr.into(), 4);
+            writeln!(out, "{bank}:{addr} {name}")?;
+        }
+        Ok(())
+    }
+
+    /// Every file read along the way, starting with the includes.
+    pub fn deps(&self) -> &[PathBuf] {
+        &self.deps
+    }
+
+    /// Every warning printed so far, as `file:line: message`.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    fn rewind(&mut self, emit: bool) -> io::Result<()> {
+        self.toks.last_mut().unwrap().rewind()?;
+        self.pc = 0;
+        self.pc_end = false;
+        self.dat = 0;
+        self.dat_end = false;
+        self.segment = Segment::ROM(0);
+        self.parked.clear();
+        self.pushed.clear();
+        self.rom.clear();
+        self.floating_count = 0;
+        for floating in &mut self.floating {
+            floating.align = 1;
+        }
+        self.scope = None;
+        self.scopes.clear();
+        self.expansions = 0;
+        self.emit = emit;
+        self.if_level = 0;
+        for (_, sym) in &mut self.syms {
+            sym.defined = false;
+        }
+        self.undefined.clear();
+        self.extents.clear();
+        Ok(())
+    }
+
+    // where a ROM segment's bytes go in the output
+    fn rom_offset(&self, segment: Segment) -> usize {
+        match segment {
+            Segment::ROM(bank) => (bank as usize) * 0x4000,
+            Segment::Floating(index) => {
+                let (bank, addr) = self.floating[index].placed.unwrap_or((1, 0x4000));
+                (bank as usize) * 0x4000 + (addr as usize - 0x4000)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the ROM segments with anything in them, in the order they land in the output
+    fn rom_layout(&self) -> Vec<(usize, usize)> {
+        let mut layout = self
+            .rom
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, bytes))| !bytes.is_empty())
+            .map(|(index, (segment, _))| (self.rom_offset(*segment), index))
+            .collect::<Vec<_>>();
+        layout.sort();
+        layout
+    }
+
+    // gives every floating section the first spot it fits, around what already has a bank
+    fn place(&mut self) -> io::Result<()> {
+        let mut taken = self
+            .rom
+            .iter()
+            .filter(|(segment, _)| matches!(segment, Segment::ROM(_)))
+            .map(|(segment, bytes)| {
+                let start = self.rom_offset(*segment);
+                (start, start + bytes.len())
+            })
+            .collect::<Vec<_>>();
+        for index in 0..self.floating.len() {
+            let size = self
+                .rom
+                .iter()
+                .find(|(segment, _)| *segment == Segment::Floating(index))
+                .map_or(0, |(_, bytes)| bytes.len());
+            let align = self.floating[index].align as usize;
+            taken.sort();
+            let mut placed = None;
+            for bank in 1..=0x1FF {
+                let window = bank * 0x4000;
+                let mut start = window;
+                for &(from, to) in &taken {
+                    if (to <= start) || (from >= (window + 0x4000)) {
+                        continue;
+                    }
+                    if (start + size) <= from {
+                        break;
+                    }
+                    start = to.next_multiple_of(align);
+                }
+                if (start + size) <= (window + 0x4000) {
+                    placed = Some((bank, start));
+                    break;
+                }
+            }
+            let Some((bank, start)) = placed else {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "no bank has room for the {size} byte ROMX segment at {}",
+                        self.floating[index].defined_at
+                    ),
+                ));
+            };
+            taken.push((start, start + size));
+            self.floating[index].placed =
+                Some((bank as u16, (0x4000 + (start - (bank * 0x4000))) as u16));
+        }
+        Ok(())
+    }
+
+    fn write_rom(&mut self) -> io::Result<()> {
+        let mut len = 0;
+        let mut last = None;
+        for (offset, index) in self.rom_layout() {
+            let segment = self.rom[index].0;
+            if let Some(last) = last.filter(|_| offset < len) {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} runs into {}", Segment::name(&last), segment.name()),
+                ));
+            }
+            // anything left out in between is filled in with zeros
+            self.output.write_all(&vec![0; offset - len])?;
+            self.output.write_all(&self.rom[index].1)?;
+            len = offset + self.rom[index].1.len();
+            last = Some(segment);
+        }
+        self.output.flush()
+    }
+
+    pub fn report(&self) {
+        eprintln!("== usage ==");
+        // counted by where the bytes land in the output, a bank can run on into the next
+        let mut rom_usage = Vec::new();
+        for (offset, index) in self.rom_layout() {
+            let end = offset + self.rom[index].1.len();
+            let mut at = offset;
+            while at < end {
+                let bank = at / 0x4000;
+                let next = ((bank + 1) * 0x4000).min(end);
+                if bank >= rom_usage.len() {
+                    rom_usage.resize(bank + 1, 0);
+                }
+                rom_usage[bank] += next - at;
+                at = next;
+            }
+        }
+        let rom = rom_usage
+            .into_iter()
+            .enumerate()
+            .map(|(bank, used)| (Segment::ROM(bank as u16), used));
+        let ram = self
+            .extents
+            .iter()
+            .filter(|extent| !extent.segment.is_rom())
+            .map(|extent| (extent.segment, extent.size));
+        for (segment, used) in rom.chain(ram) {
+            eprintln!(
+                "{}: {used}/{} bytes, {} free",
+                segment.name(),
+                segment.size(),
+                segment.size().saturating_sub(used)
+            );
+        }
+        for (index, floating) in self.floating.iter().enumerate() {
+            let Some((bank, addr)) = floating.placed else {
+                continue;
+            };
+            let size = self
+                .rom
+                .iter()
+                .find(|(segment, _)| *segment == Segment::Floating(index))
+                .map_or(0, |(_, bytes)| bytes.len());
+            eprintln!(
+                "ROMX #{index} from {}: bank {bank:X}, ${addr:04X}-${:04X}",
+                floating.defined_at,
+                (addr as usize + size).saturating_sub(1)
+            );
+        }
+        for (label, sym) in &self.syms {
+            // labels inside macro expansions come and go with each use of the macro
+            if sym.used || label.scope().is_some_and(|scope| scope.contains('@')) {
+                continue;
+            }
+            eprintln!(
+                "warning: {}: {} is never used",
+                sym.defined_at,
+                label.string()
+            );
+        }
+    }
+
+    /// Every segment and the labels in it, then the constants. Addresses are written in
+    /// `addrs` and constants in `values`.
+    pub fn write_map(&self, out: &mut dyn Write, addrs: Radix, values: Radix) -> io::Result<()> {
+        // ROM in the order it is output, then RAM in the order it was first used
+        let mut extents = self
+            .rom_layout()
+            .into_iter()
+            .filter_map(|(_, index)| {
+                self.extents
+                    .iter()
+                    .find(|extent| extent.segment == self.rom[index].0)
+            })
+            .collect::<Vec<_>>();
+        extents.extend(
+            self.extents
+                .iter()
+                .filter(|extent| !extent.segment.is_rom()),
+        );
+        let mut syms = self.syms.iter().collect::<Vec<_>>();
+        syms.sort_by_key(|(_, sym)| sym.value);
+        for extent in extents {
+            let bank = match extent.segment {
+                Segment::Floating(index) => {
+                    let (bank, _) = self.floating[index].placed.unwrap_or((1, 0));
+                    format!(" (bank {bank:X}, from {})", self.floating[index].defined_at)
+                }
+                _ => String::new(),
+            };
+            writeln!(
+                out,
+                "{}{bank}: {}-{}, {} bytes",
+                extent.segment.name(),
+                addrs.format(extent.start.into(), 4),
+                addrs.format(extent.end.into(), 4),
+                extent.size
+            )?;
+            for (label, sym) in &syms {
+                if sym.segment == Some(extent.segment) {
+                    writeln!(out, "  {} {}", addrs.format(sym.value, 4), label)?;
+                }
+            }
+        }
+        writeln!(out, "constants:")?;
+        for (label, sym) in &syms {
+            if sym.segment.is_none() {
+                writeln!(out, "  {} {}", values.format(sym.value, 0), label)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unsolved_syms(&self) -> usize {
+        self.syms.iter().filter(|(_, sym)| !sym.solved).count()
+    }
+
+    fn pass(&mut self) -> io::Result<()> {
+        loop {
+            if self.peek()? == Tok::EOF {
+                if self.toks.len() <= 1 {
+                    break;
+                }
+                self.pop_toks();
+            }
+            // special case, setting the PC
+            if self.peek()? == Tok::STAR {
+                self.eat();
+                if self.peek()? != Tok::EQU {
+                    return Err(self.err("expected ="));
+                }
+                self.eat();
+                let expr = self.expr()?;
+                let pc = self.const_16(expr)?;
+                self.set_pc(pc);
+                self.eol()?;
+                continue;
+            }
+            // is this a label?
+            if self.peek()? == Tok::IDENT {
+                // is this a macro?
+                if let Some(mac) = self
+                    .macros
+                    .iter()
+                    .find(|mac| self.str() == mac.name())
+                    .copied()
+                {
+                    let at = self.location();
+                    self.eat();
+                    // the macros are known before their definitions are reached again
+                    if (self.peek()? == Tok::DIR) && self.str_like(Dir::MACRO) {
+                        self.eat();
+                        self.macrodef(Label::new(None, mac.name()))?;
+                        self.eol()?;
+                        continue;
+                    }
+                    let chain = self
+                        .toks
+                        .iter()
+                        .filter_map(|toks| toks.macro_name())
+                        .collect::<Vec<_>>();
+                    if chain.len() >= self.macro_depth {
+                        return Err(self.err(&format!(
+                            "macro expansion too deep: {} -> {}",
+                            chain.join(" -> "),
+                            mac.name()
+                        )));
+                    }
+                    let args = self.macro_args()?;
+                    // numbered the same way every pass, so labels in it line up between passes
+                    self.expansions += 1;
+                    let scope = self
+                        .str_int
+                        .intern(&format!("{}@{}", mac.name(), self.expansions));
+                    self.scopes.push(self.scope.replace(scope));
+                    self.toks
+                        .push(Box::new(MacroInvocation::new(mac, at.file, at.line, args)));
+                    continue;
+                }
+                let string = self.str_intern();
+                let label = if !self.str().starts_with(".") {
+                    self.scope.replace(string);
+                    Label::new(None, string)
+                } else {
+                    Label::new(self.scope, string)
+                };
+                self.eat();
+                // is this label being defined to a macro?
+                if (self.peek()? == Tok::DIR) && self.str_like(Dir::MACRO) {
+                    if label.string().starts_with(".") {
+                        return Err(self.err("macro must be global"));
+                    }
+                    self.eat();
+                    self.macrodef(label)?;
+                    self.eol()?;
+                    continue;
+                }
+                // still a call to the macro it named, unless it's being bound to a value
+                let binding = (self.peek()? == Tok::EQU)
+                    || ((self.peek()? == Tok::DIR) && self.str_like(Dir::EQU));
+                if self.purged.contains(&string) && !binding {
+                    return Err(self.err(&format!("macro {string} was purged")));
+                }
+                let at = self.location();
+                let index = if let Some((index, _)) = self
+                    .syms
+                    .iter()
+                    .enumerate()
+                    .find(|(_, item)| item.0 == label)
+                {
+                    // every pass defines everything again, so only complain about the same pass
+                    if self.syms[index].1.defined {
+                        return Err(self.err(&format!(
+                            "symbol already defined, previously defined at {}",
+                            self.syms[index].1.defined_at
+                        )));
+                    }
+                    index
+                } else {
+                    // save in the symbol table with default value
+                    let index = self.syms.len();
+                    self.syms.push((
+                        label,
+                        Sym {
+                            value: 0,
+                            bank: self.bank(),
+                            defined_at: at,
+                            solved: false,
+                            defined: false,
+                            purgeable: false,
+                            used: false,
+                            segment: None,
+                        },
+                    ));
+                    index
+                };
+                // being defined to a value? `EQU` is a constant, `=` is bound late
+                let constant = (self.peek()? == Tok::DIR) && self.str_like(Dir::EQU);
+                if constant || (self.peek()? == Tok::EQU) {
+                    self.eat();
+                    let expr = self.expr()?;
+                    let value = if self.emit {
+                        let value = self.const_expr(expr)?;
+                        if let Some(forward) = self.forward.filter(|_| constant) {
+                            return Err(self.err(&format!(
+                                "EQU uses {} before its definition, use = to bind it late",
+                                forward.string()
+                            )));
+                        }
+                        Some(value)
+                    } else {
+                        expr
+                    };
+                    self.syms[index].1 = Sym {
+                        value: value.unwrap_or(0),
+                        bank: self.bank(),
+                        defined_at: at,
+                        solved: value.is_some(),
+                        defined: true,
+                        purgeable: !constant,
+                        used: self.syms[index].1.used,
+                        segment: None,
+                    };
+                    self.eol()?;
+                    continue;
+                }
+                // otherwise it is a pointer to the current PC
+                self.syms[index].1 = Sym {
+                    value: self.pc() as u32 as i32,
+                    bank: self.bank(),
+                    defined_at: at,
+                    solved: true,
+                    defined: true,
+                    purgeable: false,
+                    used: self.syms[index].1.used,
+                    segment: Some(self.segment),
+                };
+                continue;
+            }
+            // directive?
+            if self.peek()? == Tok::DIR {
+                // includes take over the token stream, the rest of this line is read after
+                if self.str_like(Dir::INCLUDE) {
+                    self.include()?;
+                    continue;
+                }
+                self.directive()?;
+                self.eol()?;
+                continue;
+            }
+            // must be mnemonic
+            if self.peek()? == Tok::MNE {
+                self.mnemonic()?;
+            }
+            self.eol()?;
+        }
+        if !self.pushed.is_empty() {
+            return Err(self.err("PUSHS without POPS"));
+        }
+        if !self.undefined.is_empty() {
+            let mut msg = String::from("undefined symbols");
+            for (label, uses) in &self.undefined {
+                let uses = uses.iter().map(Location::to_string).collect::<Vec<_>>();
+                msg.push_str(&format!("\n  {}: {}", label.string(), uses.join(", ")));
+            }
+            return Err(io::Error::new(ErrorKind::InvalidData, msg));
+        }
+        Ok(())
+    }
+
+    fn peek(&mut self) -> io::Result<Tok> {
+        self.tok_mut().peek()
+    }
+
+    fn eat(&mut self) {
+        self.tok_mut().eat();
+    }
+
+    fn tok(&self) -> &dyn TokStream {
+        self.toks.last().unwrap().as_ref()
+    }
+
+    fn tok_mut(&mut self) -> &mut dyn TokStream {
+        self.toks.last_mut().unwrap().as_mut()
+    }
+
+    fn err(&self, msg: &str) -> io::Error {
+        self.tok().err(msg)
+    }
+
+    fn str(&self) -> &str {
+        self.tok().str()
+    }
+
+    fn str_like<S: AsRef<str>>(&self, string: S) -> bool {
+        self.tok().str().eq_ignore_ascii_case(string.as_ref())
+    }
+
+    fn str_intern(&mut self) -> &'a str {
+        let Self {
+            ref mut str_int,
+            toks,
+            ..
+        } = self;
+        let string = toks.last().unwrap().str();
+        str_int.intern(string)
+    }
+
+    fn warn(&mut self, msg: &str) {
+        let warning = format!("{}: {msg}", self.location());
+        eprintln!("warning: {warning}");
+        self.warnings.push(warning);
+    }
+
+    // macros report the line they were invoked from, so look for the file under them
+    fn location(&mut self) -> Location<'a> {
+        let Self {
+            ref mut str_int,
+            toks,
+            ..
+        } = self;
+        let file = toks.iter().rev().find_map(|toks| toks.file()).unwrap();
+        Location {
+            file: str_int.intern(file),
+            line: toks.last().unwrap().line(),
+        }
+    }
+
+    fn pop_toks(&mut self) {
+        if let Some(toks) = self.toks.pop() {
+            if toks.macro_name().is_some() {
+                self.scope = self.scopes.pop().unwrap();
+            }
+        }
+    }
+
+    fn eol(&mut self) -> io::Result<()> {
+        match self.peek()? {
+            Tok::NEWLINE => {
+                self.eat();
+                Ok(())
+            }
+            Tok::EOF => {
+                if self.toks.len() > 1 {
+                    self.pop_toks();
+                }
+                Ok(())
+            }
+            _ => Err(self.err("expected end of line")),
+        }
+    }
+
+    fn pc(&self) -> u16 {
+        match self.segment {
+            Segment::ROM(_) | Segment::Floating(_) => self.pc,
+            _ => self.dat,
+        }
+    }
+
+    fn set_pc(&mut self, val: u16) {
+        match self.segment {
+            Segment::ROM(_) | Segment::Floating(_) => {
+                self.pc = val;
+                self.pc_end = false;
+            }
+            _ => {
+                self.dat = val;
+                self.dat_end = false;
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for &byte in bytes {
+            if self.end() {
+                return Err(self.err("address space overflow"));
+            }
+            // only the ROM actually gets output, other segments just reserve space.
+            // sizes are needed to place floating segments, so keep the bytes every pass
+            if self.segment.is_rom() {
+                let index = match self.rom.iter().position(|(s, _)| *s == self.segment) {
+                    Some(index) => index,
+                    None => {
+                        self.rom.push((self.segment, Vec::new()));
+                        self.rom.len() - 1
+                    }
+                };
+                self.rom[index].1.push(byte);
+            }
+            let pc = self.pc();
+            if self.emit {
+                if let Some(extent) = self
+                    .extents
+                    .iter_mut()
+                    .find(|extent| extent.segment == self.segment)
+                {
+                    extent.start = extent.start.min(pc);
+                    extent.end = extent.end.max(pc);
+                    extent.size += 1;
+                } else {
+                    self.extents.push(Extent {
+                        segment: self.segment,
+                        start: pc,
+                        end: pc,
+                        size: 1,
+                    });
+                }
+            }
+            if pc == 0xFFFF {
+                match self.segment {
+                    Segment::ROM(_) | Segment::Floating(_) => self.pc_end = true,
+                    _ => self.dat_end = true,
+                }
+            } else {
+                self.set_pc(pc + 1);
+            }
+        }
+        Ok(())
+    }
+
+    fn end(&self) -> bool {
+        match self.segment {
+            Segment::ROM(_) | Segment::Floating(_) => self.pc_end,
+            _ => self.dat_end,
+        }
+    }
+
+    // picks up where the segment was left, or at its start the first time
+    fn switch_segment(&mut self, segment: Segment, pc: Option<(u16, bool)>) {
+        let current = (self.segment, self.pc(), self.end());
+        match self.parked.iter_mut().find(|(s, ..)| *s == self.segment) {
+            Some(parked) => *parked = current,
+            None => self.parked.push(current),
+        }
+        let (pc, end) = pc.unwrap_or_else(|| {
+            self.parked
+                .iter()
+                .find(|(s, ..)| *s == segment)
+                .map_or((segment.start(), false), |&(_, pc, end)| (pc, end))
+        });
+        self.segment = segment;
+        self.set_pc(pc);
+        match self.segment {
+            Segment::ROM(_) | Segment::Floating(_) => self.pc_end = end,
+            _ => self.dat_end = end,
+        }
+    }
+
+    fn bank(&self) -> u16 {
+        match self.segment {
+            Segment::ROM(bank)
+            | Segment::WRAM(bank)
+            | Segment::SRAM(bank)
+            | Segment::VRAM(bank) => bank,
+            Segment::HRAM => 0,
+            Segment::Floating(index) => self.floating[index].placed.map_or(1, |(bank, _)| bank),
+        }
+    }
+
+    fn const_expr(&mut self, expr: Option<i32>) -> io::Result<i32> {
+        if let Some(value) = expr {
+            return Ok(value);
+        }
+        let Some(&first) = self.unsolved.first() else {
+            return Err(self.err("expression unsolved"));
+        };
+        // a symbol that exists but has no value yet is different from a typo
+        if let Some(label) = self
+            .unsolved
+            .iter()
+            .find(|label| self.syms.iter().any(|sym| sym.0 == **label))
+        {
+            return Err(self.err(&format!(
+                "{} is not solved yet, it depends on symbols defined after it",
+                label.string()
+            )));
+        }
+        if !self.emit {
+            return Err(self.err(&format!(
+                "{} must be defined before it is used here",
+                first.string()
+            )));
+        }
+        // keep going so every undefined symbol gets reported at once
+        let at = self.location();
+        for &label in &self.unsolved {
+            match self.undefined.iter_mut().find(|(other, _)| *other == label) {
+                Some((_, uses)) if uses.last() == Some(&at) => {}
+                Some((_, uses)) => uses.push(at),
+                None => self.undefined.push((label, vec![at])),
+            }
+        }
+        Ok(0)
+    }
+
+    // like const_expr, but unsolved expressions are fine during the first pass
+    fn pass_expr(&mut self, expr: Option<i32>) -> io::Result<i32> {
+        if self.emit {
+            self.const_expr(expr)
+        } else {
+            Ok(expr.unwrap_or(0))
+        }
+    }
+
+    // negative values are fine as long as they fit, e.g. -1 is $FFFF
+    fn const_24(&mut self, expr: Option<i32>) -> io::Result<u32> {
+        let expr = self.const_expr(expr)?;
+        if self.emit && !(-0x80_0000..=0xFF_FFFF).contains(&expr) {
+            self.warn(&format!("expression {} truncated to 3 bytes", hex(expr)));
+        }
+        Ok((expr as u32) & 0xFF_FFFF)
+    }
+
+    fn const_16(&mut self, expr: Option<i32>) -> io::Result<u16> {
+        let expr = self.const_expr(expr)?;
+        if self.emit && !(-0x8000..=0xFFFF).contains(&expr) {
+            self.warn(&format!(
+                "expression {} truncated to 2 bytes, use LOW(), HIGH() or BANKALIGN() if intended",
+                hex(expr)
+            ));
+        }
+        Ok(expr as u16)
+    }
+
+    fn const_8(&mut self, expr: Option<i32>) -> io::Result<u8> {
+        let expr = self.const_expr(expr)?;
+        if self.emit && !(-0x80..=0xFF).contains(&expr) {
+            self.warn(&format!(
+                "expression {} truncated to 1 byte, use LOW() or HIGH() if intended",
+                hex(expr)
+            ));
+        }
+        Ok(expr as u8)
+    }
+
+    fn expr_precedence(&self, op: Op) -> u8 {
+        match op {
+            Op::Unary(Tok::LPAREN) => 0xFF, // lparen is lowest precedence
+            Op::Unary(_) => 0,              // other unary is highest precedence
+            Op::Binary(Tok::SOLIDUS | Tok::MODULUS | Tok::STAR) => 1,
+            Op::Binary(Tok::PLUS | Tok::MINUS) => 2,
+            Op::Binary(Tok::ASL | Tok::ASR | Tok::LSR) => 3,
+            Op::Binary(Tok::LT | Tok::LTE | Tok::GT | Tok::GTE) => 4,
+            Op::Binary(Tok::EQ | Tok::NEQ) => 5,
+            Op::Binary(Tok::AMP) => 6,
+            Op::Binary(Tok::CARET) => 7,
+            Op::Binary(Tok::PIPE) => 8,
+            Op::Binary(Tok::AND) => 9,
+            Op::Binary(Tok::OR) => 10,
+            _ => unreachable!(),
+        }
+    }
+
+    fn expr_apply(&mut self, op: Op) -> io::Result<()> {
+        let rhs = self.values.pop().unwrap();
+        match op {
+            Op::Unary(Tok::PLUS) => self.values.push(rhs),
+            Op::Unary(Tok::MINUS) => {
+                let value = rhs.checked_neg().unwrap_or_else(|| {
+                    self.overflowed = true;
+                    rhs.wrapping_neg()
+                });
+                self.values.push(value);
+            }
+            Op::Unary(Tok::TILDE) => self.values.push(!rhs),
+            Op::Unary(Tok::BANG) => self.values.push((rhs == 0) as i32),
+            Op::Unary(Tok::LT) => self.values.push(((rhs as u32) & 0xFF) as i32),
+            Op::Unary(Tok::GT) => self.values.push((((rhs as u32) & 0xFF00) >> 8) as i32),
+            // where a linear ROM address shows up in the CPU's view of its bank
+            Op::Unary(Tok::BANKALIGN) => {
+                let window = if (rhs & !0x3FFF) != 0 { 0x4000 } else { 0 };
+                self.values.push((rhs & 0x3FFF) | window);
+            }
+            Op::Binary(tok) => {
+                let lhs = self.values.pop().unwrap();
+                match tok {
+                    Tok::PLUS | Tok::MINUS | Tok::STAR => {
+                        let (value, overflowed) = match tok {
+                            Tok::PLUS => lhs.overflowing_add(rhs),
+                            Tok::MINUS => lhs.overflowing_sub(rhs),
+                            _ => lhs.overflowing_mul(rhs),
+                        };
+                        self.overflowed |= overflowed;
+                        self.values.push(value);
+                    }
+                    // placeholders for unsolved symbols can make for bogus zeroes and shifts
+                    Tok::SOLIDUS | Tok::MODULUS if !self.unsolved.is_empty() => self.values.push(0),
+                    Tok::SOLIDUS | Tok::MODULUS => {
+                        if rhs == 0 {
+                            return Err(self.err("division by zero"));
+                        }
+                        let (value, overflowed) = if tok == Tok::SOLIDUS {
+                            lhs.overflowing_div(rhs)
+                        } else {
+                            lhs.overflowing_rem(rhs)
+                        };
+                        self.overflowed |= overflowed;
+                        self.values.push(value);
+                    }
+                    Tok::ASL | Tok::ASR | Tok::LSR if !self.unsolved.is_empty() => {
+                        self.values.push(0)
+                    }
+                    Tok::ASL | Tok::ASR | Tok::LSR => {
+                        if !(0..32).contains(&rhs) {
+                            return Err(self.err(&format!("shift by {rhs} out of range 0-31")));
+                        }
+                        match tok {
+                            Tok::ASL => {
+                                let value = lhs << rhs;
+                                // bits shifted out the top
+                                self.overflowed |= (value >> rhs) != lhs;
+                                self.values.push(value);
+                            }
+                            Tok::ASR => self.values.push(lhs >> rhs),
+                            _ => self.values.push(((lhs as u32) >> rhs) as i32),
+                        }
+                    }
+                    Tok::LT => self.values.push((lhs < rhs) as i32),
+                    Tok::LTE => self.values.push((lhs <= rhs) as i32),
+                    Tok::GT => self.values.push((lhs > rhs) as i32),
+                    Tok::GTE => self.values.push((lhs >= rhs) as i32),
+                    Tok::EQ => self.values.push((lhs == rhs) as i32),
+                    Tok::NEQ => self.values.push((lhs != rhs) as i32),
+                    Tok::AMP => self.values.push(lhs & rhs),
+                    Tok::PIPE => self.values.push(lhs | rhs),
+                    Tok::CARET => self.values.push(lhs ^ rhs),
+                    Tok::AND => self.values.push(((lhs != 0) && (rhs != 0)) as i32),
+                    Tok::OR => self.values.push(((lhs != 0) || (rhs != 0)) as i32),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    fn expr_push_apply(&mut self, op: Op) -> io::Result<()> {
+        // a unary op has no value to its left yet, so there's nothing to apply before it
+        while let Some(top) = self
+            .operators
+            .last()
+            .filter(|_| matches!(op, Op::Binary(_)))
+        {
+            if self.expr_precedence(*top) > self.expr_precedence(op) {
+                break;
+            }
+            self.expr_apply(*top)?;
+            self.operators.pop();
+        }
+        self.operators.push(op);
+        Ok(())
+    }
+
+    fn expr(&mut self) -> io::Result<Option<i32>> {
+        self.values.clear();
+        self.operators.clear();
+        self.unsolved.clear();
+        self.forward = None;
+        self.overflowed = false;
+        let mut seen_val = false;
+        let mut paren_depth = 0;
+        let mut seen_unknown_label = false;
+        loop {
+            match self.peek()? {
+                // star is multiply or the PC
+                Tok::STAR => {
+                    if !seen_val {
+                        self.values.push(self.pc() as u32 as i32);
+                        seen_val = true;
+                        self.eat();
+                        continue;
+                    }
+                    self.expr_push_apply(Op::Binary(Tok::STAR))?;
+                    seen_val = false;
+                    self.eat();
+                    continue;
+                }
+                // these are optionally unary
+                tok @ (Tok::PLUS | Tok::MINUS | Tok::LT | Tok::GT) => {
+                    if seen_val {
+                        self.expr_push_apply(Op::Binary(tok))?;
+                    } else {
+                        self.expr_push_apply(Op::Unary(tok))?;
+                    }
+                    seen_val = false;
+                    self.eat();
+                    continue;
+                }
+                // always unary
+                tok @ (Tok::BANG | Tok::TILDE) => {
+                    if seen_val {
+                        return Err(self.err("expected operator"));
+                    }
+                    self.expr_push_apply(Op::Unary(tok))?;
+                    seen_val = false;
+                    self.eat();
+                    continue;
+                }
+                #[rustfmt::skip]
+                tok @ (Tok::AMP | Tok::CARET | Tok::PIPE | Tok::AND | Tok::OR | Tok::SOLIDUS | Tok::MODULUS | Tok::ASL
+                      | Tok::ASR | Tok::LSR | Tok::LTE | Tok::GTE | Tok::EQ | Tok::NEQ) => {
+                    if !seen_val {
+                        return Err(self.err("expected value"));
+                    }
+                    self.expr_push_apply(Op::Binary(tok))?;
+                    seen_val = false;
+                    self.eat();
+                    continue;
+                }
+                Tok::NUM => {
+                    if seen_val {
+                        return Err(self.err("expected operator"));
+                    }
+                    self.values.push(self.tok().num());
+                    seen_val = true;
+                    self.eat();
+                    continue;
+                }
+                Tok::LPAREN => {
+                    if seen_val {
+                        return Err(self.err("expected operator"));
+                    }
+                    paren_depth += 1;
+                    self.operators.push(Op::Unary(Tok::LPAREN));
+                    seen_val = false;
+                    self.eat();
+                    continue;
+                }
+                Tok::RPAREN => {
+                    // this rparen is probably part of the indirect address
+                    if self.operators.is_empty() && (paren_depth == 0) {
+                        break;
+                    }
+                    paren_depth -= 1;
+                    if !seen_val {
+                        return Err(self.err("expected value"));
+                    }
+                    loop {
+                        if let Some(op) = self.operators.pop() {
+                            // we apply ops until we see the start of this grouping
+                            match op {
+                                Op::Binary(tok) | Op::Unary(tok) if tok == Tok::LPAREN => {
+                                    break;
+                                }
+                                _ => {}
+                            }
+                            self.expr_apply(op)?;
+                        } else {
+                            return Err(self.err("unbalanced parens"));
+                        }
+                    }
+                    self.eat();
+                    continue;
+                }
+                // byte and bank helpers, for truncating on purpose
+                Tok::IDENT if ["LOW", "HIGH", "BANKALIGN"].contains(&self.str()) => {
+                    if seen_val {
+                        return Err(self.err("expected operator"));
+                    }
+                    let op = match self.str() {
+                        "LOW" => Tok::LT,
+                        "HIGH" => Tok::GT,
+                        _ => Tok::BANKALIGN,
+                    };
+                    self.eat();
+                    if self.peek()? != Tok::LPAREN {
+                        return Err(self.err("expected ("));
+                    }
+                    self.expr_push_apply(Op::Unary(op))?;
+                    continue;
+                }
+                Tok::IDENT => {
+                    let string = self.str_intern();
+                    let label = if !self.str().starts_with(".") {
+                        Label::new(None, string)
+                    } else {
+                        Label::new(self.scope, string)
+                    };
+                    if let Some(index) = self.syms.iter().position(|sym| sym.0 == label) {
+                        self.syms[index].1.used = true;
+                        let sym = self.syms[index];
+                        if seen_val {
+                            return Err(self.err("expected operator"));
+                        }
+                        if !sym.1.defined {
+                            self.forward.get_or_insert(label);
+                        }
+                        if !sym.1.solved {
+                            seen_unknown_label = true;
+                            self.unsolved.push(label);
+                        }
+                        self.values.push(if sym.1.solved { sym.1.value } else { 1 });
+                        seen_val = true;
+                        self.eat();
+                        continue;
+                    }
+                    seen_unknown_label = true;
+                    self.unsolved.push(label);
+                    if seen_val {
+                        return Err(self.err("expected operator"));
+                    }
+                    self.values.push(1);
+                    seen_val = true;
+                    self.eat();
+                    continue;
+                }
+                _ => break,
+            }
+        }
+        if paren_depth > 0 {
+            return Err(self.err("expected )"));
+        }
+        // an operator with nothing after it
+        if !seen_val && !self.operators.is_empty() {
+            return Err(self.err("expected value"));
+        }
+        while let Some(top) = self.operators.pop() {
+            self.expr_apply(top)?;
+        }
+        if self.overflowed && self.emit && !seen_unknown_label {
+            self.warn("expression overflows 32 bits, the result wrapped around");
+        }
+        if seen_unknown_label {
+            return Ok(None);
+        }
+        if let Some(value) = self.values.pop() {
+            return Ok(Some(value));
+        }
+        Err(self.err("expected value"))
+    }
+
+    fn macrodef(&mut self, label: Label<'a>) -> io::Result<()> {
+        self.eol()?;
+        let mut toks = Vec::new();
+        let mut if_level = 0;
+        loop {
+            if self.peek()? == Tok::DIR {
+                if self.str_like(Dir::IF)
+                    || self.str_like(Dir::IFDEF)
+                    || self.str_like(Dir::IFNDEF)
+                    || self.str_like(Dir::MACRO)
+                {
+                    if_level += 1;
+                } else if self.str_like(Dir::END) {
+                    if if_level == 0 {
+                        self.eat();
+                        toks.push(MacroTok::Tok(Tok::EOF));
+                        break;
+                    }
+                    if_level -= 1;
+                }
+            }
+            match self.peek()? {
+                Tok::EOF => return Err(self.err("unexpected end of file")),
+                Tok::IDENT => toks.push(MacroTok::Ident(self.str_intern())),
+                Tok::DIR => toks.push(MacroTok::Dir(self.str_intern())),
+                Tok::MNE => toks.push(MacroTok::Mne(self.str_intern())),
+                Tok::STR => toks.push(MacroTok::Str(self.str_intern())),
+                Tok::NUM => toks.push(MacroTok::Num(self.tok().num())),
+                Tok::ARG => toks.push(MacroTok::Arg((self.tok().num() as usize) - 1)),
+                tok => toks.push(MacroTok::Tok(tok)),
+            }
+            self.eat();
+        }
+        let toks = self.tok_int.intern(&toks);
+        let mac = Macro::new(label.string(), toks);
+        self.purged.retain(|&name| name != mac.name());
+        // every pass sees the definitions again
+        match self
+            .macros
+            .iter_mut()
+            .find(|other| other.name() == mac.name())
+        {
+            Some(other) => *other = mac,
+            None => self.macros.push(mac),
+        }
+        Ok(())
+    }
+
+    fn collect_macros(&mut self) -> io::Result<()> {
+        loop {
+            match self.peek()? {
+                Tok::EOF => {
+                    if self.toks.len() <= 1 {
+                        return Ok(());
+                    }
+                    self.pop_toks();
+                    continue;
+                }
+                Tok::IDENT => {
+                    let string = self.str_intern();
+                    self.eat();
+                    // local names are left for the pass to complain about
+                    if (self.peek()? == Tok::DIR)
+                        && self.str_like(Dir::MACRO)
+                        && !string.starts_with(".")
+                    {
+                        self.eat();
+                        self.macrodef(Label::new(None, string))?;
+                        continue;
+                    }
+                }
+                Tok::DIR if self.str_like(Dir::INCLUDE) => {
+                    self.include()?;
+                    continue;
+                }
+                _ => {}
+            }
+            // nothing else matters yet, skip the rest of the line
+            while !matches!(self.peek()?, Tok::NEWLINE | Tok::EOF) {
+                self.eat();
+            }
+            if self.peek()? == Tok::NEWLINE {
+                self.eat();
+            }
+        }
+    }
+
+    // each argument runs up to the next comma that isn't nested in parens or brackets,
+    // so whole expressions like `label+1` or `[hl]` can be passed along
+    fn macro_args(&mut self) -> io::Result<Vec<Vec<MacroTok<'a>>>> {
+        let mut args = Vec::new();
+        // RGBDS has no parentheses, the arguments run to the end of the line
+        let parens = self.dialect == Dialect::Gb23;
+        if parens {
+            if self.peek()? != Tok::LPAREN {
+                return Ok(args);
+            }
+            self.eat();
+            if self.peek()? == Tok::RPAREN {
+                self.eat();
+                return Ok(args);
+            }
+        } else if matches!(self.peek()?, Tok::NEWLINE | Tok::EOF) {
+            return Ok(args);
+        }
+        let mut arg = Vec::new();
+        let mut depth = 0;
+        loop {
+            match self.peek()? {
+                Tok::NEWLINE | Tok::EOF if parens => return Err(self.err("expected )")),
+                Tok::NEWLINE | Tok::EOF => {
+                    args.push(arg);
+                    return Ok(args);
+                }
+                Tok::COMMA if depth == 0 => {
+                    args.push(mem::take(&mut arg));
+                    self.eat();
+                    continue;
+                }
+                Tok::RPAREN if parens && (depth == 0) => {
+                    args.push(arg);
+                    self.eat();
+                    return Ok(args);
+                }
+                Tok::LPAREN | Tok::LBRACK => depth += 1,
+                Tok::RPAREN | Tok::RBRACK => depth -= 1,
+                _ => {}
+            }
+            match self.peek()? {
+                Tok::IDENT => arg.push(MacroTok::Ident(self.str_intern())),
+                Tok::DIR => arg.push(MacroTok::Dir(self.str_intern())),
+                Tok::MNE => arg.push(MacroTok::Mne(self.str_intern())),
+                Tok::STR => arg.push(MacroTok::Str(self.str_intern())),
+                Tok::NUM => arg.push(MacroTok::Num(self.tok().num())),
+                tok => arg.push(MacroTok::Tok(tok)),
+            }
+            self.eat();
+        }
+    }
+
+    fn directive(&mut self) -> io::Result<()> {
+        if self.str_like(Dir::SEGMENT) {
+            self.eat();
+            if self.peek()? != Tok::STR {
+                return Err(self.err("expected segment name"));
+            }
+            let name = self.str_intern();
+            self.eat();
+            let bank = if self.peek()? == Tok::COMMA {
+                self.eat();
+                let expr = self.expr()?;
+                Some(self.const_expr(expr)?)
+            } else {
+                None
+            };
+            let segment = self.segment(name, bank)?;
+            self.enter_segment(segment);
+            return Ok(());
+        }
+        // RGBDS sections are segments that may start at a fixed address
+        if self.str_like(Dir::SECTION) {
+            self.eat();
+            if self.peek()? != Tok::STR {
+                return Err(self.err("expected section name"));
+            }
+            self.eat();
+            if self.peek()? != Tok::COMMA {
+                return Err(self.err("expected ,"));
+            }
+            self.eat();
+            if self.peek()? != Tok::IDENT {
+                return Err(self.err("expected section type"));
+            }
+            let kind = self.str().to_ascii_uppercase();
+            self.eat();
+            let addr = if self.peek()? == Tok::LBRACK {
+                Some(self.bracketed()?)
+            } else {
+                None
+            };
+            let mut bank = None;
+            let mut align = None;
+            while self.peek()? == Tok::COMMA {
+                self.eat();
+                if (self.peek()? == Tok::IDENT) && self.str_like("BANK") {
+                    self.eat();
+                    bank = Some(self.bracketed()?);
+                } else if (self.peek()? == Tok::DIR) && self.str_like(Dir::ALIGN) {
+                    self.eat();
+                    align = Some(self.bracketed()?);
+                } else {
+                    return Err(self.err("expected BANK[] or ALIGN[]"));
+                }
+            }
+            // RGBDS lets the linker pick a WRAMX bank, we just use the first
+            let bank = bank.or((kind == "WRAMX").then_some(1));
+            let segment = self.segment(&kind, bank)?;
+            if addr.is_some() && matches!(segment, Segment::Floating(_)) {
+                return Err(self.err("ROMX at a fixed address needs a BANK[]"));
+            }
+            self.enter_segment(segment);
+            if let Some(addr) = addr {
+                if (self.pc() as i32) > addr {
+                    return Err(self.err(&format!(
+                        "section at {} overlaps what is already in {}",
+                        hex(addr),
+                        segment.name()
+                    )));
+                }
+                while (self.pc() as i32) < addr {
+                    self.write_bytes(&[0])?;
+                }
+            }
+            if let Some(align) = align {
+                if !(0..=14).contains(&align) {
+                    return Err(self.err("ALIGN[] must be 0-14"));
+                }
+                self.align(1 << align)?;
+            }
+            return Ok(());
+        }
+        if self.str_like(Dir::DS) {
+            self.eat();
+            let expr = self.expr()?;
+            let count = self.const_expr(expr)?;
+            if !(0..=0x10000).contains(&count) {
+                return Err(self.err(&format!("can't reserve {} bytes", hex(count))));
+            }
+            let fill = if self.peek()? == Tok::COMMA {
+                self.eat();
+                let expr = self.expr()?;
+                if self.emit {
+                    self.const_8(expr)?
+                } else {
+                    0
+                }
+            } else {
+                0
+            };
+            for _ in 0..count {
+                self.write_bytes(&[fill])?;
+            }
+            return Ok(());
+        }
+        // everything is visible everywhere already
+        if self.str_like(Dir::EXPORT) {
+            self.eat();
+            loop {
+                if self.peek()? != Tok::IDENT {
+                    return Err(self.err("expected symbol name"));
+                }
+                self.eat();
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+            return Ok(());
+        }
+        if self.str_like(Dir::ALIGN) {
+            self.eat();
+            let expr = self.expr()?;
+            let align = self.const_expr(expr)?;
+            if !(1..=0x4000).contains(&align) || ((align & (align - 1)) != 0) {
+                return Err(self.err("alignment must be a power of 2 up to $4000"));
+            }
+            self.align(align)?;
+            return Ok(());
+        }
+        // include files can put things somewhere else and go back to the caller's segment
+        if self.str_like(Dir::PUSHS) {
+            self.eat();
+            self.pushed.push((self.segment, self.pc(), self.end()));
+            return Ok(());
+        }
+        if self.str_like(Dir::POPS) {
+            self.eat();
+            let Some((segment, pc, end)) = self.pushed.pop() else {
+                return Err(self.err("POPS without PUSHS"));
+            };
+            self.switch_segment(segment, Some((pc, end)));
+            return Ok(());
+        }
+        if self.str_like(Dir::PURGE) {
+            self.eat();
+            loop {
+                if self.peek()? != Tok::IDENT {
+                    return Err(self.err("expected macro or symbol name"));
+                }
+                let string = self.str_intern();
+                let label = if !string.starts_with(".") {
+                    Label::new(None, string)
+                } else {
+                    Label::new(self.scope, string)
+                };
+                self.eat();
+                if let Some(index) = self.macros.iter().position(|mac| mac.name() == string) {
+                    self.macros.remove(index);
+                    self.purged.push(string);
+                } else if let Some(index) = self.syms.iter().position(|sym| sym.0 == label) {
+                    if !self.syms[index].1.purgeable {
+                        return Err(self.err(&format!(
+                            "{string} is a constant or label, only = symbols can be purged"
+                        )));
+                    }
+                    self.syms.remove(index);
+                } else {
+                    return Err(self.err(&format!("{string} is not defined, nothing to purge")));
+                }
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+            return Ok(());
+        }
+        if self.str_like(Dir::ADJ) {
+            self.eat();
+            let expr = self.expr()?;
+            let expr = self.const_16(expr)?;
+            self.set_pc(expr);
+            return Ok(());
+        }
+        // tables exported from spreadsheets and level editors, one row after another
+        if self.str_like(Dir::DATA) {
+            self.eat();
+            if self.peek()? != Tok::STR {
+                return Err(self.err("expected file path"));
+            }
+            let path = self.dir.join(self.str());
+            self.eat();
+            let mut columns = Vec::new();
+            while self.peek()? == Tok::COMMA {
+                self.eat();
+                if self.peek()? != Tok::DIR {
+                    return Err(self.err("expected DB, DW, DWBE or DL"));
+                }
+                let width = [Dir::DB, Dir::DW, Dir::DWBE, Dir::DL]
+                    .into_iter()
+                    .find(|dir| self.str_like(dir))
+                    .ok_or_else(|| self.err("expected DB, DW, DWBE or DL"))?;
+                self.eat();
+                let field = match self.peek()? {
+                    Tok::STR => Field::Name(self.str_intern()),
+                    Tok::NUM if self.tok().num() >= 0 => Field::Index(self.tok().num() as usize),
+                    _ => return Err(self.err("expected a field name or column number")),
+                };
+                self.eat();
+                columns.push((width, field));
+            }
+            if columns.is_empty() {
+                return Err(self.err("expected at least one column"));
+            }
+            self.depend(&path);
+            let text = fs::read_to_string(&path)
+                .map_err(|e| self.err(&format!("cant read {}: {e}", path.display())))?;
+            let table = Table::parse(&path.display().to_string(), &text)
+                .map_err(|e| self.err(&format!("{}: {e}", path.display())))?;
+            for row in 0..table.rows() {
+                for (width, field) in &columns {
+                    let Some(value) = table.get(row, field) else {
+                        return Err(self.err(&format!(
+                            "{}: row {row} has no {}",
+                            path.display(),
+                            match field {
+                                Field::Name(name) => format!("field {name}"),
+                                Field::Index(index) => format!("column {index}"),
+                            }
+                        )));
+                    };
+                    match *width {
+                        Dir::DB => {
+                            let byte = self.const_8(Some(value))?;
+                            self.write_bytes(&[byte])?;
+                        }
+                        Dir::DW => {
+                            let word = self.const_16(Some(value))?;
+                            self.write_bytes(&word.to_le_bytes())?;
+                        }
+                        Dir::DWBE => {
+                            let word = self.const_16(Some(value))?;
+                            self.write_bytes(&word.to_be_bytes())?;
+                        }
+                        _ => {
+                            let long = self.const_24(Some(value))?;
+                            self.write_bytes(&long.to_le_bytes()[..3])?;
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+        if self.str_like(Dir::DB) {
+            self.eat();
+            loop {
+                if self.peek()? == Tok::STR {
+                    let string = self.str_intern();
+                    self.eat();
+                    self.write_bytes(string.as_bytes())?;
+                } else {
+                    let expr = self.expr()?;
+                    let byte = if self.emit { self.const_8(expr)? } else { 0 };
+                    self.write_bytes(&[byte])?;
+                }
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+            return Ok(());
+        }
+        if self.str_like(Dir::DW) || self.str_like(Dir::DWBE) {
+            // some table formats want their words the other way around
+            let big_endian = self.str_like(Dir::DWBE);
+            self.eat();
+            loop {
+                let expr = self.expr()?;
+                let word = if self.emit { self.const_16(expr)? } else { 0 };
+                if big_endian {
+                    self.write_bytes(&word.to_be_bytes())?;
+                } else {
+                    self.write_bytes(&word.to_le_bytes())?;
+                }
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+            return Ok(());
+        }
+        // 24 bit longs, mostly for address:bank triplets
+        if self.str_like(Dir::DL) {
+            self.eat();
+            loop {
+                let expr = self.expr()?;
+                let long = if self.emit { self.const_24(expr)? } else { 0 };
+                self.write_bytes(&long.to_le_bytes()[..3])?;
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+        }
+        Ok(())
+    }
+
+    fn segment(&mut self, name: &str, bank: Option<i32>) -> io::Result<Segment> {
+        let segment = match (name, bank) {
+            ("ROM0", None) => Segment::ROM(0),
+            ("ROMX", Some(bank @ 1..=0x1FF)) => Segment::ROM(bank as u16),
+            ("WRAM0", None) => Segment::WRAM(0),
+            ("WRAMX", Some(bank @ 1..=7)) => Segment::WRAM(bank as u16),
+            ("SRAM", Some(bank @ 0..=15)) => Segment::SRAM(bank as u16),
+            ("SRAM", None) => Segment::SRAM(0),
+            ("VRAM", Some(bank @ 0..=1)) => Segment::VRAM(bank as u16),
+            ("VRAM", None) => Segment::VRAM(0),
+            ("HRAM", None) => Segment::HRAM,
+            ("ROMX", None) => {
+                // numbered the same way every pass, so the placement lines up
+                let index = self.floating_count;
+                self.floating_count += 1;
+                let defined_at = self.location();
+                if let Some(floating) = self.floating.get_mut(index) {
+                    floating.defined_at = defined_at;
+                } else {
+                    self.floating.push(Floating {
+                        defined_at,
+                        align: 1,
+                        placed: None,
+                    });
+                }
+                Segment::Floating(index)
+            }
+            ("WRAMX", None) => {
+                return Err(self.err(&format!("{name} needs a bank")));
+            }
+            ("ROMX" | "WRAMX" | "SRAM" | "VRAM", Some(bank)) => {
+                return Err(self.err(&format!("{name} has no bank {}", hex(bank))));
+            }
+            ("ROM0" | "WRAM0" | "HRAM", Some(_)) => {
+                return Err(self.err(&format!("{name} is not banked")));
+            }
+            _ => return Err(self.err(&format!("unknown segment {name}"))),
+        };
+        Ok(segment)
+    }
+
+    fn enter_segment(&mut self, segment: Segment) {
+        let pc = match segment {
+            Segment::Floating(index) => self.floating[index].placed.map(|(_, addr)| (addr, false)),
+            _ => None,
+        };
+        self.switch_segment(segment, pc);
+    }
+
+    fn align(&mut self, align: i32) -> io::Result<()> {
+        // a floating segment has to be placed so that it still lines up
+        if let Segment::Floating(index) = self.segment {
+            let floating = &mut self.floating[index];
+            floating.align = floating.align.max(align as u16);
+        }
+        while ((self.pc() as i32) % align) != 0 {
+            self.write_bytes(&[0])?;
+        }
+        Ok(())
+    }
+
+    // `[expr]` for RGBDS section options
+    fn bracketed(&mut self) -> io::Result<i32> {
+        if self.peek()? != Tok::LBRACK {
+            return Err(self.err("expected ["));
+        }
+        self.eat();
+        let expr = self.expr()?;
+        let value = self.const_expr(expr)?;
+        if self.peek()? != Tok::RBRACK {
+            return Err(self.err("expected ]"));
+        }
+        self.eat();
+        Ok(value)
+    }
+
+    fn depend(&mut self, path: &Path) {
+        if !self.deps.iter().any(|dep| dep == path) {
+            self.deps.push(path.to_path_buf());
+        }
+    }
+
+    fn include(&mut self) -> io::Result<()> {
+        self.eat();
+        if self.peek()? != Tok::STR {
+            return Err(self.err("expected file path"));
+        }
+        let path = self.dir.join(self.str());
+        self.eat();
+        self.depend(&path);
+        let file = File::open(&path)
+            .map_err(|e| self.err(&format!("cant open {}: {e}", path.display())))?;
+        let lexer = Lexer::new(&path.display().to_string(), file, self.dialect)
+            .map_err(|e| self.err(&format!("cant read {}: {e}", path.display())))?;
+        self.toks.push(Box::new(lexer));
+        Ok(())
+    }
+
+    fn operand(&mut self) -> io::Result<Operand> {
+        let reg = match self.peek()? {
+            Tok::A => Some("a"),
+            Tok::B => Some("b"),
+            Tok::C => Some("c"),
+            Tok::D => Some("d"),
+            Tok::E => Some("e"),
+            Tok::H => Some("h"),
+            Tok::L => Some("l"),
+            Tok::Z => Some("z"),
+            // two letter registers lex as identifiers
+            Tok::IDENT => ["af", "bc", "de", "hl", "sp", "nc", "nz"]
+                .into_iter()
+                .find(|reg| self.str_like(reg)),
+            _ => None,
+        };
+        if let Some(reg) = reg {
+            self.eat();
+            if (reg == "sp") && matches!(self.peek()?, Tok::PLUS | Tok::MINUS) {
+                // the sign is parsed as a unary op
+                return Ok(Operand::SpOffset(self.expr()?));
+            }
+            return Ok(Operand::Fixed(reg));
+        }
+        if self.peek()? != Tok::LBRACK {
+            return Ok(Operand::Value(self.expr()?));
+        }
+        self.eat();
+        let reg = match self.peek()? {
+            Tok::C => Some("[c]"),
+            Tok::IDENT => ["bc", "de", "hl"]
+                .into_iter()
+                .find(|reg| self.str_like(reg))
+                .map(|reg| match reg {
+                    "bc" => "[bc]",
+                    "de" => "[de]",
+                    _ => "[hl]",
+                }),
+            _ => None,
+        };
+        let operand = if let Some(reg) = reg {
+            self.eat();
+            match (reg, self.peek()?) {
+                ("[hl]", Tok::PLUS) => {
+                    self.eat();
+                    Operand::Fixed("[hl+]")
+                }
+                ("[hl]", Tok::MINUS) => {
+                    self.eat();
+                    Operand::Fixed("[hl-]")
+                }
+                _ => Operand::Fixed(reg),
+            }
+        } else {
+            Operand::Indirect(self.expr()?)
+        };
+        if self.peek()? != Tok::RBRACK {
+            return Err(self.err("expected ]"));
+        }
+        self.eat();
+        Ok(operand)
+    }
+
+    fn mnemonic(&mut self) -> io::Result<()> {
+        let mne = self.str().to_ascii_lowercase();
+        self.eat();
+        let mut operands = Vec::new();
+        if !matches!(self.peek()?, Tok::NEWLINE | Tok::EOF) {
+            loop {
+                operands.push(self.operand()?);
+                if self.peek()? != Tok::COMMA {
+                    break;
+                }
+                self.eat();
+            }
+        }
+        // these have a number baked into the opcode rather than an operand
+        match (mne.as_str(), operands.as_slice()) {
+            ("bit" | "res" | "set", &[Operand::Value(bit), Operand::Fixed(reg)]) => {
+                let bit = self.pass_expr(bit)?;
+                if !(0..8).contains(&bit) {
+                    return Err(self.err("bit number out of range"));
+                }
+                let opcode = disasm::cb_suffix(&format!("{mne} {bit}, {reg}"))
+                    .ok_or_else(|| self.err("invalid operands"))?;
+                return self.write_bytes(&[0xCB, opcode]);
+            }
+            ("rst", &[Operand::Value(vector)]) => {
+                let vector = self.pass_expr(vector)?;
+                let opcode = disasm::opcode(&format!("rst ${vector:02X}"))
+                    .ok_or_else(|| self.err("invalid rst vector"))?;
+                return self.write_bytes(&[opcode]);
+            }
+            _ => {}
+        }
+        // build up the template for the instruction, leaving a hole for the value if any
+        let mut template = mne;
+        let mut value = None;
+        for (i, operand) in operands.iter().enumerate() {
+            template.push_str(if i == 0 { " " } else { ", " });
+            match *operand {
+                Operand::Fixed(reg) => template.push_str(reg),
+                Operand::Value(expr) => {
+                    value = Some(expr);
+                    template.push_str("{}");
+                }
+                Operand::Indirect(expr) => {
+                    value = Some(expr);
+                    template.push_str("[{}]");
+                }
+                Operand::SpOffset(expr) => {
+                    value = Some(expr);
+                    template.push_str("sp + {}");
+                }
+            }
+        }
+        let Some(expr) = value else {
+            if let Some(opcode) = disasm::cb_suffix(&template) {
+                return self.write_bytes(&[0xCB, opcode]);
+            }
+            let opcode = disasm::opcode(&template).ok_or_else(|| self.err("invalid operands"))?;
+            // stop has a padding byte
+            let len = OPCODES[opcode as usize].len as usize;
+            return self.write_bytes(&[opcode, 0x00][..len]);
+        };
+        for kind in ["{n8}", "{n16}", "{a16}", "{a8}", "{r8}", "{e8}"] {
+            let Some(opcode) = disasm::opcode(&template.replace("{}", kind)) else {
+                continue;
+            };
+            let value = self.pass_expr(expr)?;
+            return match kind {
+                "{n8}" => {
+                    let value = if self.emit { self.const_8(expr)? } else { 0 };
+                    self.write_bytes(&[opcode, value])
+                }
+                "{n16}" | "{a16}" => {
+                    let value = if self.emit { self.const_16(expr)? } else { 0 };
+                    let [lo, hi] = value.to_le_bytes();
+                    self.write_bytes(&[opcode, lo, hi])
+                }
+                "{a8}" => {
+                    // accept either the full address or just the offset into the high page
+                    if !(0xFF00..=0xFFFF).contains(&value) && !(0x00..=0xFF).contains(&value) {
+                        return Err(self.err("address not in high page"));
+                    }
+                    self.write_bytes(&[opcode, value as u8])
+                }
+                "{r8}" => {
+                    let offset = value - ((self.pc() as i32) + 2);
+                    // an undefined target is reported later, not as a bogus distance
+                    if self.emit && self.unsolved.is_empty() && !(-128..=127).contains(&offset) {
+                        return Err(self.err("jump out of range"));
+                    }
+                    self.write_bytes(&[opcode, offset as u8])
+                }
+                _ => {
+                    if !(-128..=127).contains(&value) {
+                        return Err(self.err("offset out of range"));
+                    }
+                    self.write_bytes(&[opcode, value as u8])
+                }
+            };
+        }
+        Err(self.err("invalid operands"))
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::{self, Write};
+
+use super::{Asm, Segment};
+
+// `SECTION` types, as RGBDS numbers them
+const WRAM0: u8 = 0;
+const VRAM: u8 = 1;
+const ROMX: u8 = 2;
+const ROM0: u8 = 3;
+const HRAM: u8 = 4;
+const WRAMX: u8 = 5;
+const SRAM: u8 = 6;
+
+// symbol visibility
+const LOCAL: u8 = 0;
+const EXPORT: u8 = 2;
+
+const REVISION: u32 = 9;
+
+// everything we assembled is already placed, so each section is fixed to its bank and address
+struct Section<'s> {
+    segment: Segment,
+    name: String,
+    kind: u8,
+    org: u16,
+    bank: u16,
+    size: usize,
+    // only ROM sections carry their bytes
+    data: Option<&'s [u8]>,
+}
+
+impl Section<'_> {
+    // a label just past the end still belongs to the section, like one marking where it ends
+    fn contains(&self, segment: Segment, bank: u16, addr: u16) -> bool {
+        (self.segment == segment)
+            && (self.bank == bank)
+            && (self.org as usize..=(self.org as usize + self.size)).contains(&(addr as usize))
+    }
+}
+
+impl<'a> Asm<'a> {
+    /// Writes what was assembled as an RGBDS object file (`RGB9` revision 9, the format
+    /// rgblink 0.6 reads), so it can be linked alongside code built with RGBDS. Every
+    /// segment becomes sections fixed at the bank and address it was given here, a ROM
+    /// segment that runs on past its bank gets a section for each bank. Labels and constants
+    /// are all exported. Everything has been resolved already, so there are no patches and
+    /// nothing is imported.
+    pub fn write_object(&self, out: &mut dyn Write) -> io::Result<()> {
+        let root = self.toks[0].file().unwrap_or("<stdin>");
+        let sections = self.sections(root);
+        // one node for each file symbols come from, the root file first
+        let mut files = vec![root];
+        for (_, sym) in &self.syms {
+            if !files.contains(&sym.defined_at.file) {
+                files.push(sym.defined_at.file);
+            }
+        }
+
+        out.write_all(b"RGB9")?;
+        long(out, REVISION)?;
+        long(out, self.syms.len() as u32)?;
+        long(out, sections.len() as u32)?;
+
+        long(out, files.len() as u32)?;
+        // nodes go out in reverse, ID 0 last
+        for file in files.iter().rev() {
+            long(out, u32::MAX)?;
+            long(out, 0)?;
+            out.write_all(&[1])?;
+            string(out, file)?;
+        }
+
+        for (label, sym) in &self.syms {
+            string(out, &label.to_string())?;
+            // labels inside macro expansions are only unique to this file
+            let local = label.scope().is_some_and(|scope| scope.contains('@'));
+            out.write_all(&[if local { LOCAL } else { EXPORT }])?;
+            let node = files
+                .iter()
+                .position(|&file| file == sym.defined_at.file)
+                .unwrap();
+            long(out, node as u32)?;
+            long(out, sym.defined_at.line as u32)?;
+            // labels are offsets into their section, anything else (including labels moved
+            // off somewhere else with `* =`) is a plain value
+            let section = sym.segment.and_then(|segment| {
+                sections
+                    .iter()
+                    .position(|section| section.contains(segment, sym.bank, sym.value as u16))
+            });
+            match section {
+                Some(index) => {
+                    long(out, index as u32)?;
+                    long(out, (sym.value - sections[index].org as i32) as u32)?;
+                }
+                None => {
+                    long(out, u32::MAX)?;
+                    long(out, sym.value as u32)?;
+                }
+            }
+        }
+
+        for section in &sections {
+            string(out, &section.name)?;
+            long(out, section.size as u32)?;
+            out.write_all(&[section.kind])?;
+            long(out, section.org as u32)?;
+            long(out, section.bank as u32)?;
+            // no alignment, the address is fixed
+            out.write_all(&[0])?;
+            long(out, 0)?;
+            if let Some(data) = section.data {
+                out.write_all(data)?;
+                // no patches
+                long(out, 0)?;
+            }
+        }
+
+        // no assertions
+        long(out, 0)?;
+        out.flush()
+    }
+
+    fn sections(&self, root: &str) -> Vec<Section<'_>> {
+        let mut sections = Vec::new();
+        for (offset, index) in self.rom_layout() {
+            let (segment, bytes) = &self.rom[index];
+            let end = offset + bytes.len();
+            let mut at = offset;
+            while at < end {
+                let bank = at / 0x4000;
+                let next = ((bank + 1) * 0x4000).min(end);
+                // names have to be unique across everything linked together
+                let name = if at == offset {
+                    format!("{root} {}", segment.name())
+                } else {
+                    format!("{root} {} +{:X}", segment.name(), at - offset)
+                };
+                sections.push(Section {
+                    segment: *segment,
+                    name,
+                    kind: if bank == 0 { ROM0 } else { ROMX },
+                    org: if bank == 0 {
+                        at as u16
+                    } else {
+                        (0x4000 + (at % 0x4000)) as u16
+                    },
+                    bank: bank as u16,
+                    size: next - at,
+                    data: Some(&bytes[(at - offset)..(next - offset)]),
+                });
+                at = next;
+            }
+        }
+        // RAM just keeps other objects off the addresses we use
+        for extent in self
+            .extents
+            .iter()
+            .filter(|extent| !extent.segment.is_rom())
+        {
+            let (kind, bank) = match extent.segment {
+                Segment::WRAM(0) => (WRAM0, 0),
+                Segment::WRAM(bank) => (WRAMX, bank),
+                Segment::SRAM(bank) => (SRAM, bank),
+                Segment::VRAM(bank) => (VRAM, bank),
+                Segment::HRAM => (HRAM, 0),
+                Segment::ROM(_) | Segment::Floating(_) => unreachable!(),
+            };
+            sections.push(Section {
+                segment: extent.segment,
+                name: format!("{root} {}", extent.segment.name()),
+                kind,
+                org: extent.start,
+                bank,
+                size: (extent.end - extent.start) as usize + 1,
+                data: None,
+            });
+        }
+        sections
+    }
+}
+
+fn long(out: &mut dyn Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn string(out: &mut dyn Write, value: &str) -> io::Result<()> {
+    out.write_all(value.as_bytes())?;
+    out.write_all(&[0])
+}
@@ -0,0 +1,324 @@
+use crate::png::crc32;
+
+// ROMs are usually shared compressed, so the loader looks inside these itself
+const GZIP_MAGIC: &[u8] = b"\x1F\x8B";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Unpacks `data` if it's a gzip file or a zip archive (taking its first Game Boy ROM),
+/// otherwise hands it back as is.
+pub fn unpack(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if data.starts_with(GZIP_MAGIC) {
+        gunzip(&data)
+    } else if data.starts_with(ZIP_MAGIC) {
+        unzip(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || "truncated gzip file".to_string();
+    let header = data.get(..10).ok_or_else(truncated)?;
+    if header[2] != 8 {
+        return Err(format!("unsupported gzip compression method {}", header[2]));
+    }
+    let flags = header[3];
+    let mut pos = 10;
+    // FEXTRA
+    if (flags & 0x04) != 0 {
+        let len = data.get(pos..(pos + 2)).ok_or_else(truncated)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    // FNAME and FCOMMENT are both zero terminated
+    for flag in [0x08, 0x10] {
+        if (flags & flag) != 0 {
+            let len = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .ok_or_else(truncated)?;
+            pos += len + 1;
+        }
+    }
+    // FHCRC
+    if (flags & 0x02) != 0 {
+        pos += 2;
+    }
+    let (out, len) = inflate(data.get(pos..).ok_or_else(truncated)?)?;
+    let trailer = data
+        .get((pos + len)..(pos + len + 8))
+        .ok_or_else(truncated)?;
+    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if crc != !crc32(!0, &out) || size != out.len() as u32 {
+        return Err("corrupt gzip file: checksum mismatch".to_string());
+    }
+    Ok(out)
+}
+
+fn unzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupt = || "corrupt zip archive".to_string();
+    let u16_at = |pos: usize| {
+        data.get(pos..(pos + 2))
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(corrupt)
+    };
+    let u32_at = |pos: usize| {
+        data.get(pos..(pos + 4))
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(corrupt)
+    };
+    // the central directory has the real sizes, local headers may leave them for later
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| data[pos..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(corrupt)?;
+    let entries = u16_at(end + 10)?;
+    let mut pos = u32_at(end + 16)?;
+    for _ in 0..entries {
+        if !data
+            .get(pos..)
+            .ok_or_else(corrupt)?
+            .starts_with(b"PK\x01\x02")
+        {
+            return Err(corrupt());
+        }
+        let method = u16_at(pos + 10)?;
+        let crc = u32_at(pos + 16)? as u32;
+        let compressed_size = u32_at(pos + 20)?;
+        let size = u32_at(pos + 24)?;
+        let name_len = u16_at(pos + 28)?;
+        let extra_len = u16_at(pos + 30)?;
+        let comment_len = u16_at(pos + 32)?;
+        let offset = u32_at(pos + 42)?;
+        let name = data
+            .get((pos + 46)..(pos + 46 + name_len))
+            .ok_or_else(corrupt)?;
+        let name = String::from_utf8_lossy(name).to_ascii_lowercase();
+        pos += 46 + name_len + extra_len + comment_len;
+        if !name.ends_with(".gb") && !name.ends_with(".gbc") {
+            continue;
+        }
+
+        let start = offset + 30 + u16_at(offset + 26)? + u16_at(offset + 28)?;
+        let compressed = data
+            .get(start..(start + compressed_size))
+            .ok_or_else(corrupt)?;
+        let out = match method {
+            0 => compressed.to_vec(),
+            8 => inflate(compressed)?.0,
+            _ => {
+                return Err(format!(
+                    "{name} uses unsupported zip compression method {method}"
+                ))
+            }
+        };
+        if crc != !crc32(!0, &out) || size != out.len() {
+            return Err(format!("corrupt zip archive: checksum mismatch in {name}"));
+        }
+        return Ok(out);
+    }
+    Err("no .gb or .gbc file in zip archive".to_string())
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| "truncated deflate stream".to_string())?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<usize, String> {
+        // canonical codes: walk down one length at a time, codes of each length are consecutive
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for len in 1..16 {
+            code |= self.bits(1)? as usize;
+            let count = huffman.counts[len] as usize;
+            if code < first + count {
+                return Ok(huffman.symbols[index + code - first] as usize);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid deflate code".to_string())
+    }
+}
+
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order code length code lengths are sent in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Decompresses a raw deflate stream, also returning how many bytes of `data` it took up.
+fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        buf: 0,
+        count: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? != 0;
+        match bits.bits(2)? {
+            0 => {
+                // stored blocks start on a byte boundary
+                bits.buf = 0;
+                bits.count = 0;
+                let header = data
+                    .get(bits.pos..(bits.pos + 4))
+                    .ok_or_else(|| "truncated deflate stream".to_string())?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err("corrupt stored deflate block".to_string());
+                }
+                bits.pos += 4;
+                let block = data
+                    .get(bits.pos..(bits.pos + len as usize))
+                    .ok_or_else(|| "truncated deflate stream".to_string())?;
+                out.extend_from_slice(block);
+                bits.pos += len as usize;
+            }
+            1 => {
+                let mut lengths = [0; 288 + 30];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..288].fill(8);
+                lengths[288..].fill(5);
+                let lit = Huffman::new(&lengths[..288]);
+                let dist = Huffman::new(&lengths[288..]);
+                inflate_block(&mut bits, &mut out, &lit, &dist)?;
+            }
+            2 => {
+                let lit_count = bits.bits(5)? as usize + 257;
+                let dist_count = bits.bits(5)? as usize + 1;
+                let code_count = bits.bits(4)? as usize + 4;
+                let mut code_lengths = [0; 19];
+                for &i in &CODE_LENGTH_ORDER[..code_count] {
+                    code_lengths[i] = bits.bits(3)? as u8;
+                }
+                let codes = Huffman::new(&code_lengths);
+                let mut lengths = Vec::with_capacity(lit_count + dist_count);
+                while lengths.len() < (lit_count + dist_count) {
+                    let (len, repeat) = match bits.decode(&codes)? {
+                        len @ 0..=15 => (len as u8, 1),
+                        16 => {
+                            let prev = *lengths
+                                .last()
+                                .ok_or_else(|| "corrupt deflate code lengths".to_string())?;
+                            (prev, 3 + bits.bits(2)?)
+                        }
+                        17 => (0, 3 + bits.bits(3)?),
+                        _ => (0, 11 + bits.bits(7)?),
+                    };
+                    lengths.extend((0..repeat).map(|_| len));
+                }
+                if lengths.len() > (lit_count + dist_count) {
+                    return Err("corrupt deflate code lengths".to_string());
+                }
+                let lit = Huffman::new(&lengths[..lit_count]);
+                let dist = Huffman::new(&lengths[lit_count..]);
+                inflate_block(&mut bits, &mut out, &lit, &dist)?;
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok((out, bits.pos));
+        }
+    }
+}
+
+fn inflate_block(
+    bits: &mut Bits,
+    out: &mut Vec<u8>,
+    lit: &Huffman,
+    dist: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = bits.decode(lit)?;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err("invalid deflate length".to_string());
+                }
+                let len = LENGTH_BASE[i] as usize + bits.bits(LENGTH_EXTRA[i] as u32)? as usize;
+                let i = bits.decode(dist)?;
+                if i >= DIST_BASE.len() {
+                    return Err("invalid deflate distance".to_string());
+                }
+                let distance = DIST_BASE[i] as usize + bits.bits(DIST_EXTRA[i] as u32)? as usize;
+                if distance > out.len() {
+                    return Err("invalid deflate distance".to_string());
+                }
+                // copies can overlap what they write, so one byte at a time
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
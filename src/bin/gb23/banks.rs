@@ -0,0 +1,58 @@
+use std::collections::VecDeque;
+
+/// A ROM bank switch, and the instruction that made it.
+pub struct BankSwitch {
+    pub frame: usize,
+    pub pc: u16,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl BankSwitch {
+    /// Where the switch came from is given as `BB:AAAA` when it ran from ROM, same as a
+    /// breakpoint takes it. Trampolines copied out to RAM are just `AAAA`.
+    pub fn describe(&self) -> String {
+        let pc = match self.pc {
+            0x0000..=0x3FFF => format!("00:{:04X}", self.pc),
+            0x4000..=0x7FFF => format!("{:02X}:{:04X}", self.from, self.pc),
+            _ => format!("   {:04X}", self.pc),
+        };
+        format!(
+            "frame {:>6}: {pc} switched bank {:02X} -> {:02X}",
+            self.frame, self.from, self.to
+        )
+    }
+}
+
+/// The last few ROM bank switches, oldest first.
+pub struct BankLog {
+    switches: VecDeque<BankSwitch>,
+}
+
+impl BankLog {
+    const CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self {
+            switches: VecDeque::with_capacity(Self::CAPACITY),
+        }
+    }
+
+    pub fn record(&mut self, switch: BankSwitch) {
+        if self.switches.len() == Self::CAPACITY {
+            self.switches.pop_front();
+        }
+        self.switches.push_back(switch);
+    }
+
+    pub fn clear(&mut self) {
+        self.switches.clear();
+    }
+
+    /// The `n` latest switches, still oldest first.
+    pub fn recent(&self, n: usize) -> impl Iterator<Item = &BankSwitch> {
+        self.switches
+            .iter()
+            .skip(self.switches.len().saturating_sub(n))
+    }
+}
@@ -0,0 +1,77 @@
+use std::{path::PathBuf, time::Instant};
+
+use clap::Args;
+use gb23::{
+    config::{BootProfile, Model, Settings},
+    emu::{mbc::header::Header, Emu},
+};
+
+use crate::{
+    cart::{CameraFeed, Cart},
+    check_rom,
+    pace::{CYCLES_PER_FRAME, CYCLES_PER_SECOND},
+    read_rom, skip_boot,
+    test::NoInput,
+};
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Path to ROM file
+    rom: PathBuf,
+
+    /// How many frames of emulated time to run for
+    #[arg(short, long, default_value_t = 10000)]
+    frames: usize,
+
+    /// Hardware model to run as, `dmg`, `sgb` or `cgb`
+    #[arg(short, long, default_value_t = Model::Dmg)]
+    model: Model,
+}
+
+// runs without a window or pacing, so only the emulator itself is being timed. Nothing is
+// pressed, a ROM that waits on input idles at its title screen which is still a fair load
+pub fn bench(args: BenchArgs) -> Result<(), String> {
+    let settings = Settings {
+        model: args.model,
+        ..Settings::default()
+    };
+    let rom = read_rom(&args.rom)?;
+    check_rom(&rom);
+    let save_size = Header::parse(&rom).map_or(0, |header| header.save_size());
+    let mut sram = vec![0; (8192 * 4).max(save_size)];
+    let mut emu = Emu::new(
+        &settings,
+        Vec::new(),
+        Cart::new(&rom, &mut sram, None, &CameraFeed::Gray),
+        NoInput,
+    );
+    emu.reset();
+    let (cpu, mut cpu_view) = emu.cpu_view();
+    skip_boot(cpu, &mut cpu_view, BootProfile::of(settings.model));
+
+    let total = args.frames * CYCLES_PER_FRAME;
+    let (mut cycles, mut instructions) = (0, 0u64);
+    let start = Instant::now();
+    while cycles < total {
+        let step = emu.step();
+        cycles += step.cycles;
+        instructions += step.opcode.is_some() as u64;
+    }
+    let wall = start.elapsed().as_secs_f64();
+    let emulated = (cycles as f64) / (CYCLES_PER_SECOND as f64);
+
+    println!(
+        "ran {} frames ({emulated:.1}s emulated) in {wall:.2}s",
+        args.frames
+    );
+    println!(
+        "speed:        {:.1}x real time, {:.0} frames/s",
+        emulated / wall,
+        (args.frames as f64) / wall
+    );
+    println!(
+        "instructions: {:.2}M/s ({instructions} in all)",
+        (instructions as f64) / wall / 1e6
+    );
+    Ok(())
+}
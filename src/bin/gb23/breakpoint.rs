@@ -0,0 +1,53 @@
+use crate::sym::Symbols;
+
+pub struct Breakpoint {
+    // what the user typed, so listings show it back the same way
+    spec: String,
+    bank: Option<u8>,
+    start: u16,
+    end: u16,
+}
+
+impl Breakpoint {
+    /// Parses `ADDR`, `ADDR-ADDR`, or a symbol name, any of which can be prefixed with a
+    /// ROM bank as `BB:`. Symbols carry their bank from the symbol file.
+    pub fn parse(spec: &str, symbols: &Symbols) -> Option<Self> {
+        let (bank, range) = match spec.split_once(':') {
+            Some((bank, range)) => (Some(u8::from_str_radix(bank, 16).ok()?), range),
+            None => (None, spec),
+        };
+        // symbols win over hex so labels like `Add` still work
+        let addr = |s: &str| match symbols.get(s) {
+            Some((bank, addr)) => Some((Some(bank), addr)),
+            None => Some((None, u16::from_str_radix(s, 16).ok()?)),
+        };
+        let ((sym_bank, start), end) = match range.split_once('-') {
+            Some((start, end)) => (addr(start)?, addr(end)?.1),
+            None => {
+                let (sym_bank, addr) = addr(range)?;
+                ((sym_bank, addr), addr)
+            }
+        };
+        if end < start {
+            return None;
+        }
+        Some(Self {
+            spec: spec.to_string(),
+            bank: bank.or(sym_bank),
+            start,
+            end,
+        })
+    }
+
+    /// Only the switchable ROM window cares about the bank, everything else is the same
+    /// memory no matter what the mapper is doing.
+    pub fn hit(&self, pc: u16, rom_bank: usize) -> bool {
+        (self.start..=self.end).contains(&pc)
+            && (!(0x4000..=0x7FFF).contains(&pc)
+                || self.bank.is_none_or(|bank| (bank as usize) == rom_bank))
+    }
+
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use gb23::asm::{Asm, Dialect, Lexer, DEFAULT_MACRO_DEPTH};
+
+use crate::{
+    run::{self, RunOptions},
+    sym::Symbols,
+};
+
+#[derive(Args)]
+pub struct BuildAndRunArgs {
+    /// Path to assembly source file
+    source: PathBuf,
+
+    /// Source syntax, `gb23` or `rgbds`
+    #[arg(long, value_enum, default_value_t = Dialect::Gb23)]
+    dialect: Dialect,
+
+    #[command(flatten)]
+    options: RunOptions,
+}
+
+pub fn build_and_run(args: BuildAndRunArgs) -> Result<(), String> {
+    let (rom, symbols, deps) = assemble(&args.source, args.dialect)?;
+    let mut watched = vec![args.source.clone()];
+    watched.extend(deps);
+    // includes added after starting aren't watched until the next run
+    let reload = || {
+        let (rom, symbols, _) = assemble(&args.source, args.dialect)?;
+        Ok((rom, symbols))
+    };
+    let sav = args.source.with_extension("sav");
+    run::play(&args.options, rom, symbols, &watched, &sav, &reload)
+}
+
+fn assemble(source: &Path, dialect: Dialect) -> Result<(Vec<u8>, Symbols, Vec<PathBuf>), String> {
+    let file = File::open(source)
+        .map_err(|e| format!("failed to open source file {}: {e}", source.display()))?;
+    let lexer = Lexer::new(&source.display().to_string(), file, dialect)
+        .map_err(|e| format!("failed to read source file {}: {e}", source.display()))?;
+    let mut rom = Vec::new();
+    let (symbols, deps) = {
+        let dir = source.parent().unwrap_or(Path::new("."));
+        let mut asm = Asm::new(lexer, dir, Box::new(&mut rom), DEFAULT_MACRO_DEPTH, dialect);
+        asm.first_pass()
+            .and_then(|_| asm.second_pass())
+            .map_err(|e| format!("failed to assemble {}: {e}", source.display()))?;
+        (Symbols::from_labels(asm.labels()), asm.deps().to_vec())
+    };
+    Ok((rom, symbols, deps))
+}
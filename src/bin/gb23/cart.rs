@@ -0,0 +1,208 @@
+use std::{fs, hash::Hasher, path::Path};
+
+use gb23::emu::{
+    bus::{Bus, BusDevice},
+    mbc::{
+        camera::{self, Camera},
+        header::Header,
+        mbc1::Mbc1,
+    },
+    state::State,
+};
+
+use crate::mbc1;
+#[cfg(feature = "webcam")]
+use crate::webcam::Webcam;
+
+/// Whichever mapper the cart header asks for, out of the ones we have.
+pub enum Cart<'a> {
+    Mbc1(Mbc1<'a>),
+    Camera(Camera<'a>),
+}
+
+impl<'a> Cart<'a> {
+    /// Anything that isn't a Pocket Camera runs as an MBC1, which also covers plain ROMs.
+    pub fn new(
+        rom: &'a [u8],
+        sram: &'a mut [u8],
+        multicart: Option<bool>,
+        feed: &CameraFeed,
+    ) -> Self {
+        match Header::parse(rom).map(|header| header.cart_type) {
+            Some(0xFC) => {
+                let mut camera = Camera::new(rom, sram);
+                match feed {
+                    CameraFeed::Gray => {
+                        tracing::info!("no --camera-image given, the camera only sees gray")
+                    }
+                    CameraFeed::Image(image) => camera.set_image(image),
+                    // gray until the first frame comes in
+                    #[cfg(feature = "webcam")]
+                    CameraFeed::Webcam(_) => {}
+                }
+                Cart::Camera(camera)
+            }
+            _ => Cart::Mbc1(mbc1(rom, sram, multicart)),
+        }
+    }
+
+    /// The bank currently mapped at $4000-$7FFF.
+    pub fn rom_bank(&self) -> usize {
+        match self {
+            Cart::Mbc1(mbc) => mbc.rom_bank(),
+            Cart::Camera(mbc) => mbc.rom_bank(),
+        }
+    }
+
+    /// The bank currently mapped at $A000-$BFFF.
+    pub fn sram_bank(&self) -> usize {
+        match self {
+            Cart::Mbc1(mbc) => mbc.sram_bank(),
+            Cart::Camera(mbc) => mbc.sram_bank(),
+        }
+    }
+
+    /// What a Pocket Camera sees from now on. Other carts have nowhere to put it.
+    pub fn set_camera_image(&mut self, image: &[u8; camera::WIDTH * camera::HEIGHT]) {
+        if let Cart::Camera(mbc) = self {
+            mbc.set_image(image);
+        }
+    }
+}
+
+/// Where a Pocket Camera's pictures come from.
+pub enum CameraFeed {
+    /// Nowhere, the sensor sees a flat gray.
+    Gray,
+    /// The same picture the whole time.
+    Image(Box<[u8; camera::WIDTH * camera::HEIGHT]>),
+    /// Whatever the webcam sees as it sees it.
+    #[cfg(feature = "webcam")]
+    Webcam(Webcam),
+}
+
+impl CameraFeed {
+    /// A new picture for the sensor, if the feed has moved on since it was last asked.
+    pub fn poll(&self) -> Option<Box<[u8; camera::WIDTH * camera::HEIGHT]>> {
+        match self {
+            CameraFeed::Gray | CameraFeed::Image(_) => None,
+            #[cfg(feature = "webcam")]
+            CameraFeed::Webcam(webcam) => webcam.poll(),
+        }
+    }
+}
+
+impl<'a, B: Bus> BusDevice<B> for Cart<'a> {
+    fn reset(&mut self, bus: &mut B) {
+        match self {
+            Cart::Mbc1(mbc) => mbc.reset(bus),
+            Cart::Camera(mbc) => mbc.reset(bus),
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::read(mbc, addr),
+            Cart::Camera(mbc) => BusDevice::<B>::read(mbc, addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::write(mbc, addr, value),
+            Cart::Camera(mbc) => BusDevice::<B>::write(mbc, addr, value),
+        }
+    }
+
+    fn tick(&mut self, bus: &mut B) -> usize {
+        match self {
+            Cart::Mbc1(mbc) => mbc.tick(bus),
+            Cart::Camera(mbc) => mbc.tick(bus),
+        }
+    }
+
+    fn advance(&mut self, cycles: usize) {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::advance(mbc, cycles),
+            Cart::Camera(mbc) => BusDevice::<B>::advance(mbc, cycles),
+        }
+    }
+
+    fn rom_offset(&self, addr: u16) -> Option<usize> {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::rom_offset(mbc, addr),
+            Cart::Camera(mbc) => BusDevice::<B>::rom_offset(mbc, addr),
+        }
+    }
+
+    fn hash_state(&self, state: &mut dyn Hasher) {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::hash_state(mbc, state),
+            Cart::Camera(mbc) => BusDevice::<B>::hash_state(mbc, state),
+        }
+    }
+
+    fn snapshot_state(&mut self, state: &mut State<'_>) {
+        match self {
+            Cart::Mbc1(mbc) => BusDevice::<B>::snapshot_state(mbc, state),
+            Cart::Camera(mbc) => BusDevice::<B>::snapshot_state(mbc, state),
+        }
+    }
+}
+
+/// Reads a binary PGM (`P5`) for the camera to see, scaled to the sensor's size.
+pub fn read_camera_image(path: &Path) -> Result<Box<[u8; camera::WIDTH * camera::HEIGHT]>, String> {
+    let data = fs::read(path)
+        .map_err(|e| format!("failed to read camera image {}: {e}", path.display()))?;
+    let invalid = || format!("{} isn't a binary PGM file", path.display());
+    // magic, width, height and maxval, each after whitespace and any comments
+    let mut pos = 0;
+    let mut fields = [0; 4];
+    for (i, field) in fields.iter_mut().enumerate() {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => {
+                    while data.get(pos).is_some_and(|&b| b != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => pos += 1,
+                _ => break,
+            }
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        let token = &data[start..pos];
+        if i == 0 {
+            if token != b"P5" {
+                return Err(invalid());
+            }
+            continue;
+        }
+        *field = std::str::from_utf8(token)
+            .ok()
+            .and_then(|token| token.parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .ok_or_else(invalid)?;
+    }
+    let [_, width, height, maxval] = fields;
+    // a single whitespace byte separates the header from the pixels
+    let pixels = data.get((pos + 1)..).ok_or_else(invalid)?;
+    if (maxval > 255) || (pixels.len() < width * height) {
+        return Err(format!(
+            "{} must be an 8-bit PGM with all {width}x{height} pixels",
+            path.display()
+        ));
+    }
+    // nearest neighbour is plenty for what ends up as 4 shades
+    let mut image = Box::new([0; camera::WIDTH * camera::HEIGHT]);
+    for y in 0..camera::HEIGHT {
+        for x in 0..camera::WIDTH {
+            let pixel = pixels[(y * height / camera::HEIGHT) * width + (x * width / camera::WIDTH)];
+            image[y * camera::WIDTH + x] = ((pixel as usize) * 255 / maxval).min(255) as u8;
+        }
+    }
+    Ok(image)
+}
@@ -0,0 +1,166 @@
+use std::{
+    backtrace::Backtrace,
+    cell::Cell,
+    fmt::Write as _,
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use gb23::emu::{
+    cpu::{Cpu, WideRegister},
+    ppu::Ppu,
+    Emu,
+};
+
+use crate::{cart::Cart, describe_registers, run::Input};
+
+// the panic message and backtrace, kept by the hook for the report
+static PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    // inside `catch`, where a panic is written up with the machine by `report`
+    static CAUGHT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keeps the message and a backtrace of each panic inside [`catch`] for [`report`]. Any other
+/// panic has no machine to go with it, so its report is written straight away with just
+/// those. Then carries on with the default hook.
+pub fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture();
+        let panic = format!("{info}\n\nbacktrace:\n{backtrace}");
+        if CAUGHT.get() {
+            *PANIC.lock().unwrap_or_else(PoisonError::into_inner) = Some(panic);
+        } else {
+            let (path, mut text) = start();
+            text.push_str(&panic);
+            match fs::write(&path, text) {
+                Ok(()) => tracing::error!("crash report written to {}", path.display()),
+                Err(e) => tracing::error!("failed to write {}: {e}", path.display()),
+            }
+        }
+        default(info);
+    }));
+}
+
+/// Runs `f`, leaving a panic in it for [`report`] to write up along with the machine.
+pub fn catch<T>(f: impl FnOnce() -> T) -> thread::Result<T> {
+    let caught = CAUGHT.replace(true);
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CAUGHT.set(caught);
+    result
+}
+
+// a new report's path in the current directory, and its first line
+fn start() -> (PathBuf, String) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = PathBuf::from(format!("gb23-crash-{time}.txt"));
+    (
+        path,
+        format!("gb23 {} crashed\n\n", env!("CARGO_PKG_VERSION")),
+    )
+}
+
+// an instruction about to run, and the registers going into it
+#[derive(Clone, Copy)]
+struct Entry {
+    bank: usize,
+    // PC, AF, BC, DE, HL, SP
+    registers: [u16; 6],
+}
+
+/// The last instructions run, for the crash report.
+pub struct Trace {
+    entries: Vec<Entry>,
+    next: usize,
+}
+
+impl Trace {
+    const CAPACITY: usize = 1000;
+
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::with_capacity(Self::CAPACITY),
+            next: 0,
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, cpu: &Cpu, bank: usize) {
+        let entry = Entry {
+            bank,
+            registers: [
+                WideRegister::PC,
+                WideRegister::AF,
+                WideRegister::BC,
+                WideRegister::DE,
+                WideRegister::HL,
+                WideRegister::SP,
+            ]
+            .map(|reg| cpu.wide_register(reg)),
+        };
+        if self.entries.len() < Self::CAPACITY {
+            self.entries.push(entry);
+        } else {
+            self.entries[self.next] = entry;
+        }
+        self.next = (self.next + 1) % Self::CAPACITY;
+    }
+
+    // oldest first
+    fn iter(&self) -> impl Iterator<Item = &Entry> {
+        let (newer, older) = self.entries.split_at(self.next.min(self.entries.len()));
+        older.iter().chain(newer)
+    }
+}
+
+/// Writes what the machine was up to when it panicked in [`catch`] into
+/// `gb23-crash-<time>.txt` in the current directory: the panic, the registers and the last
+/// instructions run. The state goes next to it in a `.state` file. Gives the report's path.
+pub fn report(emu: &mut Emu<Cart<'_>, Ppu, Input>, trace: &Trace) -> Result<PathBuf, String> {
+    let (path, mut text) = start();
+    let state_path = path.with_extension("state");
+
+    let panic = PANIC
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+        .unwrap_or_else(|| "unknown panic".to_string());
+    let _ = writeln!(text, "registers:\n{}\n", describe_registers(emu.cpu()));
+    let _ = writeln!(
+        text,
+        "last {} instructions, oldest first:",
+        trace.entries.len()
+    );
+    for entry in trace.iter() {
+        let [pc, af, bc, de, hl, sp] = entry.registers;
+        let _ = writeln!(
+            text,
+            "{:02X}:{pc:04X} AF={af:04X} BC={bc:04X} DE={de:04X} HL={hl:04X} SP={sp:04X}",
+            entry.bank
+        );
+    }
+    // the machine may be halfway through an instruction, and whatever broke may break again
+    let state = catch(|| emu.save_state());
+    match &state {
+        Ok(_) => {
+            let _ = writeln!(text, "\nstate: {}", state_path.display());
+        }
+        Err(_) => text.push_str("\nstate: couldn't be saved\n"),
+    }
+    let _ = writeln!(text, "\n{panic}");
+
+    fs::write(&path, text).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+    if let Ok(state) = state {
+        fs::write(&state_path, state)
+            .map_err(|e| format!("failed to write {}: {e}", state_path.display()))?;
+    }
+    Ok(path)
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use clap::Args;
+use gb23::{
+    disasm::{self, Flow},
+    emu::cdl::Cdl,
+};
+
+use crate::read_rom;
+
+#[derive(Args)]
+pub struct DisasmArgs {
+    /// Path to ROM file
+    rom: PathBuf,
+
+    /// Only disassemble this ROM bank
+    #[arg(short, long)]
+    bank: Option<usize>,
+
+    /// Directory to write per-bank source files into (default: stdout)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// File of `code BB:AAAA` and `data BB:AAAA[-AAAA]` lines to guide the trace
+    #[arg(long)]
+    hints: Option<PathBuf>,
+
+    /// Code/data log from `gb23 run --cdl`, whatever ran is traced from and whatever was
+    /// only read is kept as data
+    #[arg(long)]
+    cdl: Option<PathBuf>,
+}
+
+// interrupt vectors and the cart entry point
+const ENTRY_POINTS: &[usize] = &[0x40, 0x48, 0x50, 0x58, 0x60, 0x100];
+
+struct Disassembly<'a> {
+    rom: &'a [u8],
+    code: Vec<bool>,
+    data: Vec<bool>,
+    labels: BTreeSet<usize>,
+}
+
+impl<'a> Disassembly<'a> {
+    fn new(rom: &'a [u8]) -> Self {
+        Self {
+            rom,
+            code: vec![false; rom.len()],
+            data: vec![false; rom.len()],
+            labels: BTreeSet::new(),
+        }
+    }
+
+    fn banks(&self) -> usize {
+        self.rom.len().div_ceil(0x4000)
+    }
+
+    // where code running from `bank` finds `addr`, if we can tell
+    fn offset(&self, bank: usize, addr: u16) -> Option<usize> {
+        let offset = match addr {
+            0x0000..=0x3FFF => addr as usize,
+            0x4000..=0x7FFF if bank != 0 => (bank * 0x4000) + (addr as usize - 0x4000),
+            // from bank 0 we only know what is switched in when there is no switching
+            0x4000..=0x7FFF if self.rom.len() <= 0x8000 => addr as usize,
+            _ => return None,
+        };
+        (offset < self.rom.len()).then_some(offset)
+    }
+
+    fn location(offset: usize) -> (usize, u16) {
+        match offset / 0x4000 {
+            0 => (0, offset as u16),
+            bank => (bank, (0x4000 + (offset % 0x4000)) as u16),
+        }
+    }
+
+    fn bank_range(&self, bank: usize) -> (usize, usize) {
+        (bank * 0x4000, self.rom.len().min((bank + 1) * 0x4000))
+    }
+
+    fn label(offset: usize) -> String {
+        let (bank, addr) = Self::location(offset);
+        format!("L{bank:02X}_{addr:04X}")
+    }
+
+    fn hints(&mut self, text: &str) -> Result<Vec<usize>, String> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = || {
+                format!(
+                    "hints line {}: expected `code BB:AAAA` or `data BB:AAAA[-AAAA]`",
+                    i + 1
+                )
+            };
+            let (kind, range) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+            let (bank, range) = range.trim().split_once(':').ok_or_else(err)?;
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let bank = usize::from_str_radix(bank, 16).map_err(|_| err())?;
+            let start = u16::from_str_radix(start, 16).map_err(|_| err())?;
+            let end = u16::from_str_radix(end, 16).map_err(|_| err())?;
+            let start = self.offset(bank, start).ok_or_else(err)?;
+            let end = self.offset(bank, end).ok_or_else(err)?;
+            match kind {
+                "code" => entries.push(start),
+                "data" => self.data[start..=end].fill(true),
+                _ => return Err(err()),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn cdl(&mut self, log: &Cdl) -> Vec<usize> {
+        let mut entries = Vec::new();
+        for offset in 0..self.rom.len() {
+            let flags = log.get(offset);
+            if (flags & Cdl::CODE) != 0 {
+                // operands are logged as code too, so only the start of each run is known
+                // to be an opcode
+                if (offset == 0) || ((log.get(offset - 1) & Cdl::CODE) == 0) {
+                    entries.push(offset);
+                }
+            } else if (flags & (Cdl::DATA | Cdl::DMA)) != 0 {
+                self.data[offset] = true;
+            }
+        }
+        entries
+    }
+
+    // recursively follow every path out of the entry points, marking instruction starts
+    fn trace(&mut self, mut work: Vec<usize>) {
+        self.labels.extend(work.iter().copied());
+        while let Some(mut offset) = work.pop() {
+            while (offset < self.rom.len()) && !self.code[offset] && !self.data[offset] {
+                let (bank, addr) = Self::location(offset);
+                let (_, end) = self.bank_range(bank);
+                let instr = disasm::decode(&self.rom[offset..end], addr);
+                if (offset + instr.len) > end {
+                    break;
+                }
+                self.code[offset] = true;
+                if let Some(target) = instr.target().and_then(|addr| self.offset(bank, addr)) {
+                    if self.labels.insert(target) {
+                        work.push(target);
+                    }
+                }
+                if matches!(instr.flow, Flow::Jump(_) | Flow::End) {
+                    break;
+                }
+                offset += instr.len;
+            }
+        }
+    }
+
+    fn write_bank<W: Write>(&self, out: &mut W, bank: usize) -> io::Result<()> {
+        let (start, end) = self.bank_range(bank);
+        let (_, base) = Self::location(start);
+        writeln!(out, "; bank {bank:02X}")?;
+        writeln!(out, "* = ${base:04X}")?;
+        let mut offset = start;
+        while offset < end {
+            if self.labels.contains(&offset) {
+                writeln!(out, "{}", Self::label(offset))?;
+            }
+            if self.code[offset] {
+                let (_, addr) = Self::location(offset);
+                let instr = disasm::decode(&self.rom[offset..end], addr);
+                // anything that jumps into the middle of an instruction has to stay data, and
+                // the assembler always pads stop with a zero
+                let len = instr.len;
+                if ((offset + len) <= end)
+                    && (1..len)
+                        .all(|i| !self.labels.contains(&(offset + i)) && !self.code[offset + i])
+                    && ((instr.bytes[0] != 0x10) || (instr.bytes[1] == 0x00))
+                {
+                    let text = instr.format(|addr| {
+                        self.offset(bank, addr)
+                            .filter(|offset| self.labels.contains(offset))
+                            .map(Self::label)
+                    });
+                    writeln!(out, "    {}", text.to_ascii_uppercase())?;
+                    offset += len;
+                    continue;
+                }
+            }
+            let mut bytes = vec![format!("${:02X}", self.rom[offset])];
+            offset += 1;
+            while (bytes.len() < 8)
+                && (offset < end)
+                && !self.labels.contains(&offset)
+                && !self.code[offset]
+            {
+                bytes.push(format!("${:02X}", self.rom[offset]));
+                offset += 1;
+            }
+            writeln!(out, "    DB {}", bytes.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn disasm(args: DisasmArgs) -> Result<(), String> {
+    let rom = read_rom(&args.rom)?;
+    let mut disasm = Disassembly::new(&rom);
+    let mut entries = ENTRY_POINTS.to_vec();
+    if let Some(path) = &args.hints {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read hints: {e}"))?;
+        entries.extend(disasm.hints(&text)?);
+    }
+    if let Some(path) = &args.cdl {
+        let data = fs::read(path).map_err(|e| format!("failed to read code/data log: {e}"))?;
+        if data.len() != rom.len() {
+            return Err(format!(
+                "{} is {} bytes, it doesn't go with a {} byte ROM",
+                path.display(),
+                data.len(),
+                rom.len()
+            ));
+        }
+        entries.extend(disasm.cdl(&Cdl::from_bytes(&data)));
+    }
+    entries.retain(|&offset| offset < rom.len());
+    disasm.trace(entries);
+
+    let banks = match args.bank {
+        Some(bank) if bank >= disasm.banks() => {
+            return Err(format!(
+                "bank {bank} out of range, ROM has {} banks",
+                disasm.banks()
+            ));
+        }
+        Some(bank) => bank..(bank + 1),
+        None => 0..disasm.banks(),
+    };
+    let Some(dir) = args.output else {
+        let mut out = BufWriter::new(io::stdout());
+        for bank in banks {
+            disasm
+                .write_bank(&mut out, bank)
+                .map_err(|e| format!("failed to write source: {e}"))?;
+        }
+        return out
+            .flush()
+            .map_err(|e| format!("failed to write source: {e}"));
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("failed to create output directory: {e}"))?;
+    let write = |name: String, f: &dyn Fn(&mut BufWriter<File>) -> io::Result<()>| {
+        let path = dir.join(name);
+        let mut out = BufWriter::new(
+            File::create(&path).map_err(|e| format!("failed to create {}: {e}", path.display()))?,
+        );
+        f(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|e| format!("failed to write {}: {e}", path.display()))
+    };
+    for bank in banks.clone() {
+        write(format!("bank_{bank:02X}.asm"), &|out| {
+            disasm.write_bank(out, bank)
+        })?;
+    }
+    // the banks have to be assembled in order for the output to line up with the ROM
+    if banks.len() == disasm.banks() {
+        write("main.asm".into(), &|out| {
+            writeln!(out, "; disassembled from {}", args.rom.display())?;
+            for bank in banks.clone() {
+                writeln!(out, "INCLUDE \"bank_{bank:02X}.asm\"")?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
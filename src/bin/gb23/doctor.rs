@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+};
+
+use clap::Args;
+use gb23::{
+    config::{BootProfile, Model, Settings},
+    disasm,
+    emu::{
+        bus::Port,
+        cpu::{Register, WideRegister},
+        mbc::mbc1::Mbc1,
+        ppu::Ppu,
+        Emu,
+    },
+};
+
+use crate::{check_rom, read_rom, skip_boot, test::NoInput};
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// Path to ROM file
+    rom: PathBuf,
+
+    /// Reference trace, one line of `A:01 F:B0 ... SP:FFFE PC:0100 PCMEM:00,C3,13,02` per
+    /// instruction as Gameboy Doctor logs them. A `CY:` field with the cycles run so far is
+    /// checked too when present
+    trace: PathBuf,
+
+    /// Hardware model to run as, `dmg`, `sgb` or `cgb`
+    #[arg(short, long, default_value_t = Model::Dmg)]
+    model: Model,
+
+    /// Read LY as this (hex) value like Gameboy Doctor expects, rather than the real line
+    #[arg(long, value_parser = |s: &str| u8::from_str_radix(s.trim_start_matches('$'), 16))]
+    ly: Option<u8>,
+
+    /// How many of the lines leading up to a divergence to show
+    #[arg(short, long, default_value_t = 8)]
+    context: usize,
+}
+
+const REGISTERS: [(&str, Register); 8] = [
+    ("A", Register::A),
+    ("F", Register::F),
+    ("B", Register::B),
+    ("C", Register::C),
+    ("D", Register::D),
+    ("E", Register::E),
+    ("H", Register::H),
+    ("L", Register::L),
+];
+
+const WIDE_REGISTERS: [(&str, WideRegister); 2] =
+    [("SP", WideRegister::SP), ("PC", WideRegister::PC)];
+
+// one line of a trace, as fields in the order they were logged
+struct Step(Vec<(String, String)>);
+
+impl Step {
+    fn parse(line: &str) -> Result<Self, String> {
+        line.split_whitespace()
+            .map(|field| {
+                let (name, value) = field
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected `NAME:VALUE`, found `{field}`"))?;
+                Ok((name.to_ascii_uppercase(), value.to_ascii_uppercase()))
+            })
+            .collect::<Result<_, String>>()
+            .map(Self)
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn hex(&self, name: &str) -> Result<Option<u16>, String> {
+        self.get(name)
+            .map(|value| {
+                u16::from_str_radix(value, 16).map_err(|e| format!("bad {name} `{value}`: {e}"))
+            })
+            .transpose()
+    }
+}
+
+// our side of a step, with the same fields the reference logged
+fn state(emu: &mut Emu<Mbc1<'_>, Ppu, NoInput>, cycles: usize) -> Step {
+    let cpu = emu.cpu();
+    let mut fields = Vec::new();
+    for (name, reg) in REGISTERS {
+        fields.push((name.to_string(), format!("{:02X}", cpu.register(reg))));
+    }
+    for (name, reg) in WIDE_REGISTERS {
+        fields.push((name.to_string(), format!("{:04X}", cpu.wide_register(reg))));
+    }
+    let mut pcmem = [0; 4];
+    emu.read_range(emu.cpu().wide_register(WideRegister::PC), &mut pcmem);
+    fields.push((
+        "PCMEM".to_string(),
+        pcmem.map(|b| format!("{b:02X}")).join(","),
+    ));
+    fields.push(("CY".to_string(), format!("{cycles}")));
+    Step(fields)
+}
+
+fn format(step: &Step, only: &Step) -> String {
+    step.0
+        .iter()
+        .filter(|(name, _)| only.get(name).is_some())
+        .map(|(name, value)| format!("{name}:{value}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn doctor(args: DoctorArgs) -> Result<(), String> {
+    let settings = Settings {
+        model: args.model,
+        ..Settings::default()
+    };
+    let rom = read_rom(&args.rom)?;
+    check_rom(&rom);
+    let lines = BufReader::new(
+        File::open(&args.trace).map_err(|e| format!("failed to open trace file: {e}"))?,
+    )
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()));
+
+    let mut sram = vec![0; 8192 * 4];
+    let mut emu = Emu::new(
+        &settings,
+        Vec::new(),
+        Mbc1::detect(&rom, &mut sram),
+        NoInput,
+    );
+    emu.reset();
+    emu.stub_ly(args.ly);
+    let (cpu, mut cpu_view) = emu.cpu_view();
+    skip_boot(cpu, &mut cpu_view, BootProfile::of(settings.model));
+
+    let mut history = VecDeque::new();
+    let mut cycles = 0;
+    let mut steps = 0;
+    for (i, line) in lines {
+        let line = line.map_err(|e| format!("failed to read trace file: {e}"))?;
+        let expected = Step::parse(&line).map_err(|e| format!("line {}: {e}", i + 1))?;
+        // the reference starts wherever its boot ROM left off, so take its registers as is
+        if steps == 0 {
+            let (cpu, _) = emu.cpu_view();
+            for (name, reg) in REGISTERS {
+                if let Some(value) = expected.hex(name)? {
+                    cpu.set_register(reg, value as u8);
+                }
+            }
+            for (name, reg) in WIDE_REGISTERS {
+                if let Some(value) = expected.hex(name)? {
+                    cpu.set_wide_register(reg, value);
+                }
+            }
+            cycles = expected
+                .get("CY")
+                .and_then(|cy| cy.parse().ok())
+                .unwrap_or(0);
+        }
+
+        // halted time (waking up included) and interrupt dispatch run no instruction, so
+        // they aren't logged
+        loop {
+            let mut flags = [0; 1];
+            emu.read_range(Port::IF, &mut flags);
+            let mut enabled = [0; 1];
+            emu.read_range(Port::IE, &mut enabled);
+            let pending = (flags[0] & enabled[0] & 0x1F) != 0;
+            let cpu = emu.cpu();
+            if cpu.halted() || (cpu.ime() && pending) {
+                cycles += emu.tick();
+            } else {
+                break;
+            }
+        }
+
+        let actual = state(&mut emu, cycles);
+        let diffs = expected
+            .0
+            .iter()
+            .filter_map(|(name, value)| {
+                let ours = actual.get(name)?;
+                // the reference may not zero pad, so compare by value where there is one
+                let same = match (
+                    u32::from_str_radix(value, 16),
+                    u32::from_str_radix(ours, 16),
+                ) {
+                    _ if name == "CY" => value.parse::<usize>().ok() == ours.parse().ok(),
+                    (Ok(a), Ok(b)) => a == b,
+                    _ => value == ours,
+                };
+                (!same).then(|| format!("{name}: expected {value}, got {ours}"))
+            })
+            .collect::<Vec<_>>();
+        if !diffs.is_empty() {
+            println!("diverged at line {} after {steps} steps:", i + 1);
+            for (line, step) in &history {
+                println!("  {line:>8}  {step}");
+            }
+            println!("> {:>8}  {}", i + 1, format(&expected, &expected));
+            println!("  {:>8}  {}", "gb23", format(&actual, &expected));
+            for diff in diffs {
+                println!("  {diff}");
+            }
+            return Err(format!("diverged from the reference at line {}", i + 1));
+        }
+
+        let mut bytes = [0; 3];
+        let pc = emu.cpu().wide_register(WideRegister::PC);
+        emu.read_range(pc, &mut bytes);
+        history.push_back((
+            i + 1,
+            format!(
+                "{}  ; {}",
+                format(&actual, &expected),
+                disasm::decode(&bytes, pc).format(|_| None)
+            ),
+        ));
+        if history.len() > args.context {
+            history.pop_front();
+        }
+        cycles += emu.tick();
+        steps += 1;
+    }
+    println!("no divergence in {steps} steps");
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use gb23::emu::mbc::{
+    header::{CgbSupport, Header},
+    mbc1::Mbc1,
+};
+
+use crate::{
+    read_rom,
+    sha1::{hex, sha1},
+};
+
+#[derive(Args)]
+pub struct InfoArgs {
+    /// Path to ROM file
+    rom: PathBuf,
+}
+
+fn size(size: Option<usize>) -> String {
+    match size {
+        Some(size) if size < 1024 => format!("{size} B"),
+        Some(size) => format!("{} KiB", size / 1024),
+        None => "unknown".into(),
+    }
+}
+
+fn ok(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "BAD"
+    }
+}
+
+pub fn info(args: InfoArgs) -> Result<(), String> {
+    let rom = read_rom(&args.rom)?;
+    let header = Header::parse(&rom)
+        .ok_or_else(|| format!("ROM is too small to have a header: {} bytes", rom.len()))?;
+    println!("title:           {}", header.title);
+    if let Some(manufacturer) = &header.manufacturer {
+        println!("manufacturer:    {manufacturer}");
+    }
+    println!("licensee:        {}", header.licensee);
+    #[rustfmt::skip]
+    println!("CGB:             {}", match header.cgb {
+        CgbSupport::None => "no",
+        CgbSupport::Compatible => "compatible",
+        CgbSupport::Only => "only",
+    });
+    println!("SGB:             {}", if header.sgb { "yes" } else { "no" });
+    println!(
+        "mapper:          {} (${:02X})",
+        header.mapper(),
+        header.cart_type
+    );
+    if Mbc1::is_multicart(&rom) {
+        println!("                 (looks like an MBC1M multicart)");
+    }
+    println!("ROM size:        {}", size(header.rom_size));
+    if header.rom_size.is_some_and(|size| size != rom.len()) {
+        println!("                 (file is {})", size(Some(rom.len())));
+    }
+    println!("RAM size:        {}", size(header.ram_size));
+    #[rustfmt::skip]
+    println!("destination:     {}", if header.japanese { "Japan" } else { "overseas" });
+    println!("version:         {}", header.version);
+    println!(
+        "header checksum: ${:02X} {}",
+        header.header_checksum,
+        ok(header.header_checksum_ok())
+    );
+    println!(
+        "global checksum: ${:04X} {}",
+        header.global_checksum,
+        ok(header.global_checksum_ok())
+    );
+    println!("SHA-1:           {}", hex(&sha1(&rom)));
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use bench::BenchArgs;
+use build::BuildAndRunArgs;
+use clap::{Parser, Subcommand};
+use disasm::DisasmArgs;
+use doctor::DoctorArgs;
+use gb23::{
+    config::BootProfile,
+    emu::{
+        bus::{Bus, Port},
+        cpu::{Cpu, Flag, WideRegister},
+        mbc::{header::Header, mbc1::Mbc1},
+        video::{Frame, VideoSink},
+    },
+};
+use info::InfoArgs;
+use netplay::NetplayArgs;
+use run::RunArgs;
+use sav::SavConvertArgs;
+use test::TestArgs;
+use tracing::Level;
+
+mod archive;
+mod banks;
+mod bench;
+mod breakpoint;
+mod build;
+mod cart;
+mod crash;
+mod disasm;
+mod doctor;
+mod info;
+mod netplay;
+mod overlay;
+mod pace;
+mod png;
+mod recent;
+mod run;
+mod sav;
+mod sha1;
+mod states;
+mod sym;
+mod test;
+#[cfg(feature = "webcam")]
+mod webcam;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// One of `TRACE`, `DEBUG`, `INFO`, `WARN`, or `ERROR`
+    #[arg(short, long, global = true, default_value_t = Level::INFO)]
+    log_level: Level,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Play a ROM
+    Run(RunArgs),
+    /// Print the cartridge header
+    Info(InfoArgs),
+    /// Disassemble a ROM bank
+    Disasm(DisasmArgs),
+    /// Run a test ROM headless and report whether it passed
+    Test(TestArgs),
+    /// Assemble a source file and play it, with its symbols in the debugger
+    BuildAndRun(BuildAndRunArgs),
+    /// Run a ROM in lockstep with a reference trace and stop at the first difference
+    Doctor(DoctorArgs),
+    /// Play a ROM linked to someone else's over the network
+    Netplay(NetplayArgs),
+    /// Run a ROM headless as fast as it will go and report how fast that was
+    Bench(BenchArgs),
+    /// Resize a battery save for a cart, e.g. one made by another emulator
+    SavConvert(SavConvertArgs),
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(io::stderr)
+        .init();
+    crash::install_hook();
+    let result = match args.command {
+        Command::Run(args) => run::run(args),
+        Command::Info(args) => info::info(args),
+        Command::Disasm(args) => disasm::disasm(args),
+        Command::Test(args) => test::test(args),
+        Command::BuildAndRun(args) => build::build_and_run(args),
+        Command::Doctor(args) => doctor::doctor(args),
+        Command::Netplay(args) => netplay::netplay(args),
+        Command::Bench(args) => bench::bench(args),
+        Command::SavConvert(args) => sav::sav_convert(args),
+    };
+    if let Err(e) = result {
+        tracing::error!("{e}");
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    let mut rom = Vec::new();
+    File::open(path)
+        .map_err(|e| format!("failed to open ROM file: {e}"))?
+        .read_to_end(&mut rom)
+        .map_err(|e| format!("failed to read ROM file: {e}"))?;
+    archive::unpack(rom)
+}
+
+// logs what was loaded, so a bad dump shows up here rather than as a crash much later
+fn check_rom(rom: &[u8]) {
+    let sha1 = sha1::hex(&sha1::sha1(rom));
+    let Some(header) = Header::parse(rom) else {
+        tracing::warn!(
+            "ROM is too small to have a header: {} bytes, SHA-1 {sha1}",
+            rom.len()
+        );
+        return;
+    };
+    tracing::info!(
+        "loaded {:?}: {} bytes, header checksum ${:02X}, global checksum ${:04X}, SHA-1 {sha1}",
+        header.title,
+        rom.len(),
+        header.header_checksum,
+        header.global_checksum,
+    );
+    if !header.header_checksum_ok() {
+        tracing::warn!("header checksum doesn't match, real hardware would refuse to boot this");
+    }
+    if !header.global_checksum_ok() {
+        tracing::warn!("global checksum doesn't match, this may be a bad dump");
+    }
+    if let Some(size) = header.rom_size.filter(|&size| size != rom.len()) {
+        tracing::warn!(
+            "header says the ROM is {size} bytes but it is {}, this may be a bad dump or truncated",
+            rom.len()
+        );
+    }
+}
+
+// an MBC1 or MBC1M, as `multicart` says or otherwise by looking at the ROM
+fn mbc1<'a>(rom: &'a [u8], sram: &'a mut [u8], multicart: Option<bool>) -> Mbc1<'a> {
+    match multicart.unwrap_or_else(|| Mbc1::is_multicart(rom)) {
+        true => {
+            tracing::info!("running as an MBC1M multicart");
+            Mbc1::multicart(rom, sram)
+        }
+        false => Mbc1::new(rom, sram),
+    }
+}
+
+// the registers each model's boot ROM leaves behind, as Pan Docs lists them
+fn skip_boot<B: Bus>(cpu: &mut Cpu, bus: &mut B, profile: BootProfile) {
+    // DMG and MGB's final header check leaves H and C set unless the checksum is 0
+    let checked = if bus.read(0x014D) != 0 { 0xB0 } else { 0x80 };
+    let cgb_cart = (bus.read(0x0143) & 0x80) != 0;
+    // CGB colorizes old Nintendo carts by hashing the title, which ends up in B
+    let licensee = bus.read(0x014B);
+    let nintendo = (licensee == 0x01)
+        || ((licensee == 0x33) && (bus.read(0x0144) == b'0') && (bus.read(0x0145) == b'1'));
+    let title_sum = if nintendo {
+        (0x0134..=0x0143).fold(0u8, |sum, addr| sum.wrapping_add(bus.read(addr)))
+    } else {
+        0x00
+    };
+    // AF, BC, DE, HL
+    let registers = match profile {
+        BootProfile::Dmg0 => [0x0100, 0xFF13, 0x00C1, 0x8403],
+        BootProfile::Dmg => [0x0100 | checked, 0x0013, 0x00D8, 0x014D],
+        BootProfile::Mgb => [0xFF00 | checked, 0x0013, 0x00D8, 0x014D],
+        BootProfile::Sgb => [0x0100, 0x0014, 0x0000, 0xC060],
+        BootProfile::Sgb2 => [0xFF00, 0x0014, 0x0000, 0xC060],
+        BootProfile::Cgb if cgb_cart => [0x1180, 0x0000, 0xFF56, 0x000D],
+        BootProfile::Cgb => [0x1180, (title_sum as u16) << 8, 0x0008, 0x007C],
+        // the AGB's boot ROM ends with an `inc b` on top of the CGB's
+        BootProfile::Agb if cgb_cart => [0x1100, 0x0100, 0xFF56, 0x000D],
+        BootProfile::Agb => {
+            let b = title_sum.wrapping_add(1);
+            // what `inc b` leaves in F: Z, and H when the low nibble carries
+            let f = if b == 0 { 0x80 } else { 0x00 } | if (b & 0x0F) == 0 { 0x20 } else { 0x00 };
+            [0x1100 | f, (b as u16) << 8, 0x0008, 0x007C]
+        }
+    };
+    for (reg, value) in [
+        WideRegister::AF,
+        WideRegister::BC,
+        WideRegister::DE,
+        WideRegister::HL,
+    ]
+    .into_iter()
+    .zip(registers)
+    {
+        cpu.set_wide_register(reg, value);
+    }
+    cpu.set_wide_register(WideRegister::SP, 0xFFFE);
+    cpu.set_wide_register(WideRegister::PC, 0x100);
+    bus.write(Port::BOOT, 0x01);
+    bus.write(Port::LCDC, 0x81);
+}
+
+// `PC=0150 AF=01B0 ... [Z-HC] IME=1`, as the debugger shows it
+#[rustfmt::skip]
+fn describe_registers(cpu: &Cpu) -> String {
+    format!(
+        "PC={:04X} AF={:04X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} [{}{}{}{}] IME={}{}",
+        cpu.wide_register(WideRegister::PC),
+        cpu.wide_register(WideRegister::AF),
+        cpu.wide_register(WideRegister::BC),
+        cpu.wide_register(WideRegister::DE),
+        cpu.wide_register(WideRegister::HL),
+        cpu.wide_register(WideRegister::SP),
+        if cpu.flag(Flag::Zero) { 'Z' } else { '-' },
+        if cpu.flag(Flag::Negative) { 'N' } else { '-' },
+        if cpu.flag(Flag::HalfCarry) { 'H' } else { '-' },
+        if cpu.flag(Flag::Carry) { 'C' } else { '-' },
+        cpu.ime() as u8,
+        if cpu.halted() { " HALT" } else if cpu.stopped() { " STOP" } else { "" },
+    )
+}
+
+/// Writes every frame into a directory as a numbered PNG.
+struct FrameDumper {
+    dir: PathBuf,
+    count: usize,
+    failed: bool,
+}
+
+impl FrameDumper {
+    fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            count: 0,
+            failed: false,
+        }
+    }
+}
+
+impl VideoSink for FrameDumper {
+    fn frame(&mut self, width: usize, pixels: &[u32]) {
+        if self.failed {
+            return;
+        }
+        let path = self.dir.join(format!("{:06}.png", self.count));
+        if let Err(e) = png::write(&path, &Frame::from_pixels(width, pixels)) {
+            // most likely the disk is full, which won't get better by trying every frame
+            tracing::error!(
+                "failed to write frame {}, not dumping any more: {e}",
+                path.display()
+            );
+            self.failed = true;
+        }
+        self.count += 1;
+    }
+}
@@ -0,0 +1,494 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use clap::Args;
+use gb23::{
+    config::{BootProfile, Model, Revision, Settings},
+    emu::{mbc::header::Header, ppu::Ppu, video::Frame, Emu},
+};
+use sdl2::{event::Event, keyboard::Scancode, pixels::PixelFormatEnum, rect::Rect};
+
+use crate::{
+    cart::{CameraFeed, Cart},
+    check_rom,
+    pace::{Pacer, CYCLES_PER_FRAME},
+    read_rom,
+    run::{Buttons, Input},
+    sav, sha1, skip_boot,
+};
+
+#[derive(Args)]
+pub struct NetplayArgs {
+    /// Path to ROM file, the same one on both ends
+    rom: PathBuf,
+
+    /// Wait for the other player on this address (e.g. `0.0.0.0:5023`) and play as player 1
+    #[arg(long, conflicts_with = "connect", required_unless_present = "connect")]
+    host: Option<String>,
+
+    /// Join the player hosting on this address and play as player 2
+    #[arg(long)]
+    connect: Option<String>,
+
+    /// Frames between pressing a button and the game seeing it, which has to cover the round
+    /// trip to keep from stalling. Only the host's counts
+    #[arg(short, long, default_value_t = 3)]
+    delay: u8,
+
+    /// Hardware model to run as, `dmg` or `cgb` (only the host's counts)
+    #[arg(short, long, default_value_t = Model::Dmg)]
+    model: Model,
+}
+
+const HELLO: &str = "gb23 netplay 1";
+
+// a peer this far behind has gone away rather than lagged
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// more than a save state and then some, anything longer is the other end gone wrong
+const MAX_BYTES: usize = 1024 * 1024;
+
+type LinkedEmu<'a> = Emu<Cart<'a>, Ppu, Input>;
+
+// what each end sends every frame: the buttons it pressed for `frame`, and the hash of
+// both machines after the last frame it finished
+struct Message {
+    frame: u32,
+    buttons: u8,
+    hash: u64,
+}
+
+impl Message {
+    const LEN: usize = 13;
+
+    fn send(&self, stream: &mut TcpStream) -> Result<(), String> {
+        let mut buf = [0; Self::LEN];
+        buf[..4].copy_from_slice(&self.frame.to_le_bytes());
+        buf[4] = self.buttons;
+        buf[5..].copy_from_slice(&self.hash.to_le_bytes());
+        stream
+            .write_all(&buf)
+            .map_err(|e| format!("lost the other player: {e}"))
+    }
+
+    fn recv(stream: &mut TcpStream) -> Result<Self, String> {
+        let mut buf = [0; Self::LEN];
+        stream
+            .read_exact(&mut buf)
+            .map_err(|e| format!("lost the other player: {e}"))?;
+        Ok(Self {
+            frame: u32::from_le_bytes(buf[..4].try_into().unwrap()),
+            buttons: buf[4],
+            hash: u64::from_le_bytes(buf[5..].try_into().unwrap()),
+        })
+    }
+}
+
+// a save's RAM or a machine's state, sent whole with its length in front
+fn send_bytes(stream: &mut TcpStream, bytes: &[u8]) -> Result<(), String> {
+    stream
+        .write_all(&(bytes.len() as u32).to_le_bytes())
+        .and_then(|()| stream.write_all(bytes))
+        .map_err(|e| format!("lost the other player: {e}"))
+}
+
+fn recv_bytes(stream: &mut TcpStream) -> Result<Vec<u8>, String> {
+    let mut len = [0; 4];
+    stream
+        .read_exact(&mut len)
+        .map_err(|e| format!("lost the other player: {e}"))?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_BYTES {
+        return Err(format!(
+            "the other player sent {len} bytes at once, too many"
+        ));
+    }
+    let mut bytes = vec![0; len];
+    stream
+        .read_exact(&mut bytes)
+        .map_err(|e| format!("lost the other player: {e}"))?;
+    Ok(bytes)
+}
+
+// what both ends have to agree on before the first frame, the host's settings win
+struct Session {
+    model: Model,
+    revision: Revision,
+    seed: u64,
+    delay: u8,
+}
+
+fn handshake(
+    stream: &mut TcpStream,
+    host: bool,
+    rom_sha1: &str,
+    ours: Session,
+) -> Result<Session, String> {
+    let hello = format!(
+        "{HELLO} {rom_sha1} {} {} {} {}\n",
+        ours.model, ours.revision, ours.seed, ours.delay
+    );
+    stream
+        .write_all(hello.as_bytes())
+        .map_err(|e| format!("failed to greet the other player: {e}"))?;
+    // read a byte at a time, anything past the line belongs to the first frame
+    let mut line = String::new();
+    BufReader::with_capacity(1, &mut *stream)
+        .read_line(&mut line)
+        .map_err(|e| format!("failed to hear from the other player: {e}"))?;
+    let theirs = line
+        .trim()
+        .strip_prefix(HELLO)
+        .ok_or_else(|| "the other end isn't a compatible gb23".to_string())?
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let [sha1, model, revision, seed, delay] = theirs[..] else {
+        return Err(format!("bad greeting from the other player: {line:?}"));
+    };
+    if sha1 != rom_sha1 {
+        return Err(format!(
+            "the other player has a different ROM (SHA-1 {sha1}, ours is {rom_sha1})"
+        ));
+    }
+    if host {
+        return Ok(ours);
+    }
+    let bad = || format!("bad greeting from the other player: {line:?}");
+    Ok(Session {
+        model: model.parse().map_err(|_| bad())?,
+        revision: revision.parse().map_err(|_| bad())?,
+        seed: seed.parse().map_err(|_| bad())?,
+        delay: delay.parse().map_err(|_| bad())?,
+    })
+}
+
+// a byte one machine finished clocking out goes into the other, and back
+fn link(master: &mut LinkedEmu, other: &mut LinkedEmu) {
+    if let Some(out) = master.serial_pending() {
+        let back = other.serial_exchange(out);
+        master.serial_exchange(back);
+    }
+}
+
+// both machines, run a cycle apart at most so neither sees the other from the future
+fn run_frame(emus: &mut [LinkedEmu; 2], cycles: &mut [usize; 2]) -> u64 {
+    let [a, b] = emus;
+    while (cycles[0] < CYCLES_PER_FRAME) || (cycles[1] < CYCLES_PER_FRAME) {
+        if cycles[0] <= cycles[1] {
+            cycles[0] += a.tick();
+        } else {
+            cycles[1] += b.tick();
+        }
+        link(a, b);
+        link(b, a);
+    }
+    for cycles in cycles {
+        *cycles -= CYCLES_PER_FRAME;
+    }
+    a.state_hash() ^ b.state_hash().rotate_left(1)
+}
+
+// one frame of both machines with these buttons held, and the hash of both after it
+fn play_frame(
+    emus: &mut [LinkedEmu; 2],
+    cycles: &mut [usize; 2],
+    buttons: &[Arc<AtomicU8>; 2],
+    pressed: [u8; 2],
+) -> u64 {
+    for (buttons, pressed) in buttons.iter().zip(pressed) {
+        buttons.store(pressed, Ordering::Relaxed);
+    }
+    for emu in emus.iter_mut() {
+        emu.input_mut().latch();
+    }
+    run_frame(emus, cycles)
+}
+
+// ours for theirs, the host's going first so neither end waits on the other with a
+// full buffer
+fn swap_saves(stream: &mut TcpStream, host: bool, ours: &[u8]) -> Result<Vec<u8>, String> {
+    if host {
+        send_bytes(stream, ours)?;
+        recv_bytes(stream)
+    } else {
+        let theirs = recv_bytes(stream)?;
+        send_bytes(stream, ours)?;
+        Ok(theirs)
+    }
+}
+
+// on a desync the host's machines win. It loads them back itself too, so anything that
+// isn't part of a save state is dropped on both ends alike
+fn send_machines(
+    stream: &mut TcpStream,
+    emus: &mut [LinkedEmu; 2],
+    cycles: [usize; 2],
+) -> Result<(), String> {
+    for (emu, cycles) in emus.iter_mut().zip(cycles) {
+        let state = emu.save_state();
+        emu.load_state(&state)
+            .map_err(|e| format!("failed to reload our own state: {e}"))?;
+        send_bytes(stream, &(cycles as u64).to_le_bytes())?;
+        send_bytes(stream, &state)?;
+    }
+    Ok(())
+}
+
+fn recv_machines(
+    stream: &mut TcpStream,
+    emus: &mut [LinkedEmu; 2],
+    cycles: &mut [usize; 2],
+) -> Result<(), String> {
+    for (emu, cycles) in emus.iter_mut().zip(cycles) {
+        let count = recv_bytes(stream)?;
+        *cycles = count
+            .try_into()
+            .map(u64::from_le_bytes)
+            .map_err(|_| "bad cycle count from the other player".to_string())?
+            as usize;
+        emu.load_state(&recv_bytes(stream)?)
+            .map_err(|e| format!("failed to load the other player's state: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Plays a ROM against someone else over the network, linked by a virtual cable. Each end
+/// runs both Game Boys and only the buttons go over the wire, so the cable is as fast as
+/// the real thing however far apart the players are. Carts with a battery play with both
+/// players' saves, and each end writes its own player's back to the `.sav` next to the ROM.
+pub fn netplay(args: NetplayArgs) -> Result<(), String> {
+    let mut settings = Settings::default_path()
+        .and_then(|path| Settings::load(path).ok())
+        .unwrap_or_default();
+    settings.model = args.model;
+    let rom = read_rom(&args.rom)?;
+    check_rom(&rom);
+    let rom_sha1 = sha1::hex(&sha1::sha1(&rom));
+
+    let host = args.host.is_some();
+    let mut stream = if let Some(addr) = &args.host {
+        let listener =
+            TcpListener::bind(addr).map_err(|e| format!("failed to listen on {addr}: {e}"))?;
+        tracing::info!("waiting for the other player on {addr}");
+        let (stream, peer) = listener
+            .accept()
+            .map_err(|e| format!("failed to accept the other player: {e}"))?;
+        tracing::info!("{peer} joined");
+        stream
+    } else {
+        let addr = args.connect.as_deref().unwrap();
+        TcpStream::connect(addr).map_err(|e| format!("failed to connect to {addr}: {e}"))?
+    };
+    // one small message a frame, waiting to batch them up only adds lag
+    stream
+        .set_nodelay(true)
+        .and_then(|_| stream.set_read_timeout(Some(TIMEOUT)))
+        .map_err(|e| format!("failed to set up the connection: {e}"))?;
+    let session = handshake(
+        &mut stream,
+        host,
+        &rom_sha1,
+        Session {
+            model: settings.model,
+            revision: settings.revision,
+            seed: settings.seed,
+            delay: args.delay,
+        },
+    )?;
+    if session.model == Model::Sgb {
+        return Err("netplay doesn't support the SGB".to_string());
+    }
+    settings.model = session.model;
+    settings.revision = session.revision;
+    settings.seed = session.seed;
+    let delay = session.delay as u32;
+    // player 1's machine first on both ends, so the two ends run exactly the same thing
+    let local = if host { 0 } else { 1 };
+    tracing::info!(
+        "playing as player {} with {delay} frames of input delay",
+        if host { 1 } else { 2 }
+    );
+
+    // each end only has its own player's save, and both machines run on both ends
+    let header = Header::parse(&rom).filter(Header::battery);
+    let size = (8192 * 4).max(header.as_ref().map_or(0, Header::save_size));
+    let mut srams = [vec![0; size], vec![0; size]];
+    let sav = args.rom.with_extension("sav");
+    let rtc = match &header {
+        Some(header) => {
+            let rtc = sav::load(&sav, header, &mut srams[local])?;
+            let ours = &srams[local][..header.save_size()];
+            let theirs = swap_saves(&mut stream, host, ours)?;
+            if theirs.len() != ours.len() {
+                return Err(format!(
+                    "the other player's save has {} bytes of RAM, ours has {}",
+                    theirs.len(),
+                    ours.len()
+                ));
+            }
+            srams[1 - local][..theirs.len()].copy_from_slice(&theirs);
+            rtc
+        }
+        None => None,
+    };
+
+    let sdl = sdl2::init().map_err(|e| format!("failed to initialize SDL2: {e}"))?;
+    let mut event_pump = sdl
+        .event_pump()
+        .map_err(|e| format!("failed to initialize SDL2 events: {e}"))?;
+    let video = sdl
+        .video()
+        .map_err(|e| format!("failed to initialize SDL2 video: {e}"))?;
+    let window = video
+        .window(
+            &format!("gb23 :: player {}", if host { 1 } else { 2 }),
+            160 * settings.scale,
+            144 * settings.scale,
+        )
+        .allow_highdpi()
+        .position_centered()
+        .build()
+        .map_err(|e| format!("failed to create window: {e}"))?;
+    let mut canvas = window
+        .into_canvas()
+        .accelerated()
+        .build()
+        .map_err(|e| format!("failed to map window to canvas: {e}"))?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, 160, 144)
+        .map_err(|e| format!("failed to create texture: {e}"))?;
+
+    let buttons = [Arc::new(AtomicU8::new(0)), Arc::new(AtomicU8::new(0))];
+    let [sram1, sram2] = &mut srams;
+    let mut emus = [(sram1, &buttons[0]), (sram2, &buttons[1])].map(|(sram, buttons)| {
+        Emu::new(
+            &settings,
+            Vec::new(),
+            Cart::new(&rom, sram, None, &CameraFeed::Gray),
+            Input::new(buttons.clone(), true),
+        )
+    });
+    for emu in &mut emus {
+        emu.reset();
+        emu.set_linked(true);
+        let (cpu, mut cpu_view) = emu.cpu_view();
+        // the model's own, the profile isn't part of the handshake
+        skip_boot(cpu, &mut cpu_view, BootProfile::of(settings.model));
+    }
+
+    // nobody presses anything in the first frames, that's what buys the delay
+    let mut inputs = [
+        VecDeque::from(vec![0; delay as usize]),
+        VecDeque::from(vec![0; delay as usize]),
+    ];
+    // ours, until the other end's hash for the same frame comes in
+    let mut hashes = VecDeque::new();
+    let mut cycles = [0; 2];
+    let mut pacer = Pacer::new(1.0);
+    let result = (|| {
+        for frame in 0.. {
+            for event in event_pump.poll_iter() {
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        scancode: Some(Scancode::Escape),
+                        ..
+                    } => return Ok(()),
+                    _ => {}
+                }
+            }
+            let pressed = Buttons::from_keyboard(&event_pump.keyboard_state());
+            inputs[local].push_back(pressed);
+            Message {
+                frame: frame + delay,
+                buttons: pressed,
+                hash: hashes.back().map_or(0, |&(_, hash)| hash),
+            }
+            .send(&mut stream)?;
+
+            // the game can't go on without knowing what the other player pressed
+            while inputs[1 - local].is_empty() {
+                let message = Message::recv(&mut stream)?;
+                if message.frame != frame {
+                    return Err(format!(
+                        "the other player skipped ahead to frame {} on frame {frame}",
+                        message.frame
+                    ));
+                }
+                inputs[1 - local].push_back(message.buttons);
+                // hashes are for the frame before the buttons were pressed
+                let Some(hashed) = message.frame.checked_sub(delay + 1) else {
+                    continue;
+                };
+                while hashes.front().is_some_and(|&(f, _)| f < hashed) {
+                    hashes.pop_front();
+                }
+                let Some(&(_, hash)) = hashes.front().filter(|&&(f, _)| f == hashed) else {
+                    continue;
+                };
+                if hash == message.hash {
+                    continue;
+                }
+                // both ends see the same two hashes on the same frame, so they resync together
+                tracing::warn!(
+                    "desynced on frame {hashed}: our state is {hash:016X}, theirs {:016X}",
+                    message.hash
+                );
+                if host {
+                    send_machines(&mut stream, &mut emus, cycles)?;
+                    tracing::info!("sent our state over to resync");
+                } else {
+                    // the host had already sent its buttons for the frames up to its delay
+                    // by the time it saw the desync, the state comes after those
+                    for ahead in 1..=delay {
+                        let message = Message::recv(&mut stream)?;
+                        if message.frame != frame + ahead {
+                            return Err(format!(
+                                "the other player skipped ahead to frame {} on frame {frame}",
+                                message.frame
+                            ));
+                        }
+                        inputs[1 - local].push_back(message.buttons);
+                    }
+                    recv_machines(&mut stream, &mut emus, &mut cycles)?;
+                    tracing::info!("resynced from the other player's state");
+                }
+                // the hashes on their way over are from before, there's nothing to check them
+                // against until the ones made from here on
+                hashes.clear();
+            }
+
+            let pressed = inputs.each_mut().map(|inputs| inputs.pop_front().unwrap());
+            let hash = play_frame(&mut emus, &mut cycles, &buttons, pressed);
+            hashes.push_back((frame, hash));
+
+            let frame = Frame::from_pixels(160, emus[local].lcd().as_flattened());
+            let rect = Rect::new(0, 0, 160, 144);
+            texture
+                .update(rect, frame.as_bytes(), frame.pitch())
+                .map_err(|e| format!("failed to lock texture: {e}"))?;
+            canvas
+                .copy(&texture, rect, None)
+                .map_err(|e| format!("failed to copy texture: {e}"))?;
+            canvas.present();
+            pacer.pace(CYCLES_PER_FRAME);
+        }
+        Ok(())
+    })();
+    drop(emus);
+    // whatever happened, the player's progress is worth keeping
+    if let Some(header) = &header {
+        sav::store(&sav, header, &srams[local], rtc)?;
+    }
+    result
+}
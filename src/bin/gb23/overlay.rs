@@ -0,0 +1,214 @@
+// 3x5 glyphs, one row per byte with the leftmost pixel in bit 2
+const FONT: &[(char, [u8; 5])] = &[
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+];
+
+const UNKNOWN: [u8; 5] = [0b111, 0b001, 0b010, 0b000, 0b010];
+
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+const TEXT: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const BACKGROUND: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const RELEASED: [u8; 4] = [0x55, 0x55, 0x55, 0xFF];
+
+// x, y, width and height of each button on a little pad, in the same bit order as the
+// buttons byte: right, left, up, down, A, B, select, start
+const PAD: [(usize, usize, usize, usize); 8] = [
+    (9, 5, 4, 4),
+    (1, 5, 4, 4),
+    (5, 1, 4, 4),
+    (5, 9, 4, 4),
+    (39, 3, 5, 5),
+    (32, 6, 5, 5),
+    (16, 8, 6, 2),
+    (24, 8, 6, 2),
+];
+const PAD_WIDTH: usize = 45;
+const PAD_HEIGHT: usize = 14;
+
+use gb23::emu::{
+    heatmap::{Access, Heatmap},
+    video::Frame,
+    ApuState,
+};
+
+/// What the emulator thread knew when it finished a frame.
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    pub ly: u8,
+    pub mode: u8,
+    pub rom_bank: usize,
+    pub sram_bank: usize,
+    // only CGBs switch these
+    pub banks: Option<(u8, u8)>,
+    pub buttons: u8,
+    pub apu: ApuState,
+}
+
+/// The APU as a few short lines: each channel's pitch, volume, duty, envelope (timer and
+/// period) and length, then the wave RAM.
+pub fn apu_lines(apu: &ApuState, muted: u8) -> Vec<String> {
+    let mut lines = vec![format!("APU {}", if apu.on { "ON" } else { "OFF" })];
+    for (i, channel) in apu.channels.iter().enumerate() {
+        let status = if (muted & (1 << i)) != 0 {
+            "MUTE"
+        } else if !channel.dac {
+            "DAC-"
+        } else if channel.on {
+            "ON"
+        } else {
+            "OFF"
+        };
+        let mut line = format!(
+            "{} {status:4} {:6.0}HZ V{:02}",
+            i + 1,
+            channel.hz,
+            channel.volume
+        );
+        if let Some(duty) = channel.duty {
+            line.push_str(&format!(" D{duty}"));
+        }
+        if let Some((timer, period)) = channel.envelope {
+            line.push_str(&format!(" E{timer}/{period}"));
+        }
+        if channel.length_enabled {
+            line.push_str(&format!(" L{}", channel.length));
+        }
+        lines.push(line);
+    }
+    let wave = apu
+        .wave_ram
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<String>();
+    lines.push(format!("W {wave}"));
+    lines
+}
+
+/// Draws `lines` of text over the top left corner of a frame.
+pub fn draw(frame: &mut Frame, lines: &[String]) {
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    let (width, height) = (frame.width(), frame.height());
+    // a pixel of border all around keeps the text readable over any picture
+    let box_width = (columns * GLYPH_WIDTH + 1).min(width);
+    let box_height = (lines.len() * GLYPH_HEIGHT + 1).min(height);
+    for row in frame.rows_mut().take(box_height) {
+        row[..box_width].fill(BACKGROUND);
+    }
+    for (i, line) in lines.iter().enumerate() {
+        for (j, c) in line.chars().enumerate() {
+            let c = c.to_ascii_uppercase();
+            let glyph = FONT
+                .iter()
+                .find(|(glyph, _)| *glyph == c)
+                .map_or(UNKNOWN, |(_, rows)| *rows);
+            let x = 1 + j * GLYPH_WIDTH;
+            let y = 1 + i * GLYPH_HEIGHT;
+            for (dy, bits) in glyph.iter().enumerate() {
+                for dx in 0..3 {
+                    if (bits & (0b100 >> dx)) == 0 || (x + dx) >= width || (y + dy) >= height {
+                        continue;
+                    }
+                    frame.set_rgba(x + dx, y + dy, TEXT);
+                }
+            }
+        }
+    }
+}
+
+/// Draws the pad in the bottom left corner of a frame, pressed buttons lit.
+pub fn draw_buttons(frame: &mut Frame, buttons: u8) {
+    let (width, height) = (frame.width(), frame.height());
+    if width < PAD_WIDTH || height < PAD_HEIGHT {
+        return;
+    }
+    let top = height - PAD_HEIGHT;
+    for row in frame.rows_mut().skip(top) {
+        row[..PAD_WIDTH].fill(BACKGROUND);
+    }
+    for (i, (x, y, w, h)) in PAD.into_iter().enumerate() {
+        let color = if (buttons & (1 << i)) != 0 {
+            TEXT
+        } else {
+            RELEASED
+        };
+        for row in frame.rows_mut().skip(top + y).take(h) {
+            row[x..(x + w)].fill(color);
+        }
+    }
+}
+
+/// The heatmap as a 256x256 picture, one pixel per address and one row per $100 bytes.
+/// Writes are red, executions green and reads blue.
+pub fn heatmap(heatmap: &Heatmap) -> Frame {
+    // each kind of access is scaled on its own and logarithmically, so one busy loop
+    // doesn't leave everything else black
+    let max = [Access::Read, Access::Write, Access::Execute].map(|access| {
+        (0..=0xFFFF)
+            .map(|addr| heatmap.count(addr, access))
+            .max()
+            .unwrap_or(0)
+    });
+    let pixels = (0..=0xFFFF)
+        .map(|addr| {
+            let [reads, writes, executes] = heatmap.counts(addr);
+            u32::from_be_bytes([
+                heat(writes, max[Access::Write as usize]),
+                heat(executes, max[Access::Execute as usize]),
+                heat(reads, max[Access::Read as usize]),
+                0xFF,
+            ])
+        })
+        .collect::<Vec<_>>();
+    Frame::from_pixels(256, &pixels)
+}
+
+// anything touched at all shows up
+fn heat(count: u32, max: u32) -> u8 {
+    if count == 0 {
+        return 0;
+    }
+    (64.0 + 191.0 * ((count as f32).ln_1p() / (max as f32).ln_1p())) as u8
+}
@@ -0,0 +1,103 @@
+use std::{
+    collections::VecDeque,
+    hint, thread,
+    time::{Duration, Instant},
+};
+
+pub const CYCLES_PER_SECOND: usize = 4194304;
+pub const CYCLES_PER_FRAME: usize = 70224;
+
+// thread::sleep routinely overshoots by a scheduler tick, so we wake up early and spin the rest
+const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+// falling further behind than this means we got stalled (debugger, slow host) rather than
+// jittered, and trying to catch up would just fast-forward the game
+const MAX_DRIFT: Duration = Duration::from_millis(100);
+
+pub struct Pacer {
+    origin: Instant,
+    cycles: u64,
+    speed: f64,
+}
+
+impl Pacer {
+    pub fn new(speed: f64) -> Self {
+        Self {
+            origin: Instant::now(),
+            cycles: 0,
+            speed,
+        }
+    }
+
+    // forget the accumulated drift and start pacing from now
+    pub fn resync(&mut self) {
+        self.origin = Instant::now();
+        self.cycles = 0;
+    }
+
+    fn emulated(&self) -> Duration {
+        Duration::from_secs_f64((self.cycles as f64) / (CYCLES_PER_SECOND as f64) / self.speed)
+    }
+
+    // block until the wall clock catches up with `cycles` more emulated cycles
+    pub fn pace(&mut self, cycles: usize) {
+        if self.speed <= 0.0 {
+            return;
+        }
+        self.cycles += cycles as u64;
+        let deadline = self.origin + self.emulated();
+        let now = Instant::now();
+        if deadline <= now {
+            if now - deadline > MAX_DRIFT {
+                tracing::debug!("pacer fell {:?} behind, resyncing", now - deadline);
+                self.resync();
+            }
+            return;
+        }
+        let remaining = deadline - now;
+        if remaining > SPIN_THRESHOLD {
+            thread::sleep(remaining - SPIN_THRESHOLD);
+        }
+        while Instant::now() < deadline {
+            hint::spin_loop();
+        }
+    }
+}
+
+pub struct FrameTimes {
+    last: Option<Instant>,
+    samples: VecDeque<Duration>,
+}
+
+impl FrameTimes {
+    const CAPACITY: usize = 240;
+
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            samples: VecDeque::with_capacity(Self::CAPACITY),
+        }
+    }
+
+    pub fn record(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last.replace(now) {
+            if self.samples.len() == Self::CAPACITY {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(now - last);
+        }
+    }
+
+    // percentiles are in 0..=100
+    pub fn percentiles<const N: usize>(&self, percentiles: [usize; N]) -> [Duration; N] {
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        percentiles.map(|p| {
+            if sorted.is_empty() {
+                return Duration::ZERO;
+            }
+            sorted[((sorted.len() - 1) * p.min(100)) / 100]
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use gb23::emu::video::Frame;
+
+/// Writes a frame to an RGB PNG file. The image data is stored without compression, which
+/// is plenty quick and needs nothing but a checksum.
+pub fn write(path: &Path, frame: &Frame) -> io::Result<()> {
+    let (width, height) = (frame.width(), frame.height());
+    // every scanline starts with its filter type, 0 is none
+    let rgb = frame.to_rgb8();
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for row in rgb.chunks(width * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // zlib header, then deflate blocks of at most 65535 stored bytes
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        zlib.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"\x89PNG\r\n\x1A\n")?;
+    chunk(&mut out, b"IHDR", &header)?;
+    chunk(&mut out, b"IDAT", &zlib)?;
+    chunk(&mut out, b"IEND", &[])?;
+    out.flush()
+}
+
+fn chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = !crc32(crc32(!0, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+pub fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
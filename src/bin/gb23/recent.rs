@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+// plenty to pick from without the list scrolling away
+const MAX_RECENT: usize = 10;
+
+/// The ROMs played most recently, newest first, kept one path per line in a file next to the
+/// settings file.
+pub struct Recent {
+    file: Option<PathBuf>,
+    roms: Vec<PathBuf>,
+}
+
+impl Recent {
+    /// A missing or unreadable list is just an empty one.
+    pub fn load(settings: Option<&Path>) -> Self {
+        let file = settings
+            .and_then(Path::parent)
+            .map(|dir| dir.join("recent"));
+        let roms = file
+            .as_deref()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|text| {
+                text.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(PathBuf::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { file, roms }
+    }
+
+    /// Moves `rom` to the top of the list and writes the list back out.
+    pub fn add(&mut self, rom: &Path) {
+        // absolute, so the list works from any directory
+        let rom = fs::canonicalize(rom).unwrap_or_else(|_| rom.to_path_buf());
+        self.roms.retain(|path| *path != rom);
+        self.roms.insert(0, rom);
+        self.roms.truncate(MAX_RECENT);
+        let Some(file) = &self.file else {
+            return;
+        };
+        let text = self
+            .roms
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect::<String>();
+        if let Err(e) =
+            fs::create_dir_all(file.parent().unwrap()).and_then(|_| fs::write(file, text))
+        {
+            tracing::warn!("failed to write recent ROMs to {}: {e}", file.display());
+        }
+    }
+
+    /// Lists the recent ROMs that are still around and asks which one to play.
+    pub fn pick(&self) -> Result<PathBuf, String> {
+        let roms = self
+            .roms
+            .iter()
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+        if roms.is_empty() {
+            return Err("no ROM given, and none played recently to pick from".to_string());
+        }
+        println!("recently played:");
+        for (i, path) in roms.iter().enumerate() {
+            println!("{:>3}: {}", i + 1, path.display());
+        }
+        let mut stdin = io::stdin().lock();
+        loop {
+            print!("play which? [1] ");
+            io::stdout()
+                .flush()
+                .map_err(|e| format!("failed to write prompt: {e}"))?;
+            let mut line = String::new();
+            let read = stdin
+                .read_line(&mut line)
+                .map_err(|e| format!("could not read line: {e}"))?;
+            if read == 0 {
+                return Err("no ROM picked".to_string());
+            }
+            let line = line.trim();
+            let choice = if line.is_empty() {
+                Some(1)
+            } else {
+                line.parse::<usize>().ok()
+            };
+            match choice
+                .and_then(|n| n.checked_sub(1))
+                .and_then(|i| roms.get(i))
+            {
+                Some(path) => return Ok(path.to_path_buf()),
+                None => println!("?"),
+            }
+        }
+    }
+}
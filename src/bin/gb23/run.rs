@@ -0,0 +1,1865 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{self, Read},
+    mem, panic,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        mpsc::{self, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use gb23::{
+    config::{BootProfile, Model, Revision, Settings},
+    emu::{
+        audio::{self, AudioSink, WavWriter},
+        bus::{Bus, BusDevice, Port},
+        cdl::Cdl,
+        cpu::{Cpu, Register, WideRegister},
+        iolog::IoLog,
+        mbc::header::Header,
+        ppu::{Layers, Ppu},
+        serial::SerialOutput,
+        state::{Snapshot, State},
+        video::{Frame, Ghosting, NullSink, VideoSink},
+        Emu,
+    },
+    patch,
+};
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    hint::{Hinter, HistoryHinter},
+    history::SearchDirection,
+    Completer, Config, Context, Editor, Helper, Highlighter, Hinter, Validator,
+};
+use sdl2::{
+    audio::{AudioQueue, AudioSpecDesired},
+    event::Event,
+    keyboard::{KeyboardState, Mod, Scancode},
+    pixels::PixelFormatEnum,
+    rect::Rect,
+};
+
+#[cfg(feature = "webcam")]
+use crate::webcam::Webcam;
+use crate::{
+    banks::{BankLog, BankSwitch},
+    breakpoint::Breakpoint,
+    cart::{self, CameraFeed, Cart},
+    check_rom,
+    crash::{self, Trace},
+    describe_registers,
+    overlay::{self, Stats},
+    pace::{FrameTimes, Pacer, CYCLES_PER_FRAME},
+    png, read_rom,
+    recent::Recent,
+    sav, skip_boot,
+    states::{ask_resume, slot_key, timestamp, Request, SlotControl, Slots, SLOTS},
+    sym::Symbols,
+    FrameDumper,
+};
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Path to ROM file (default: pick one of the recently played ones)
+    rom: Option<PathBuf>,
+
+    /// Debugger symbol file
+    #[arg(short, long)]
+    sym: Option<PathBuf>,
+
+    /// IPS or BPS patch to apply to the ROM before it boots
+    #[arg(long)]
+    patch: Option<PathBuf>,
+
+    #[command(flatten)]
+    options: RunOptions,
+}
+
+// what playing a ROM takes, however the ROM was come by
+#[derive(Args)]
+pub struct RunOptions {
+    /// Path to BIOS/BOOT ROM file (overrides the settings file)
+    #[arg(short, long)]
+    boot: Option<PathBuf>,
+
+    /// CGB revision to emulate, `cgb0` or `cgbe` (overrides the settings file)
+    #[arg(long)]
+    revision: Option<Revision>,
+
+    /// Whose registers to start with when skipping the boot ROM, `dmg0`, `dmg`, `mgb`,
+    /// `sgb`, `sgb2`, `cgb` or `agb` (overrides the settings file)
+    #[arg(long)]
+    boot_profile: Option<BootProfile>,
+
+    /// Wire the cart as an MBC1M multicart (`true`) or a plain MBC1 (`false`), rather than
+    /// guessing from the ROM
+    #[arg(long)]
+    multicart: Option<bool>,
+
+    /// A binary PGM image for a Pocket Camera to see, scaled to fit its sensor
+    #[arg(long)]
+    camera_image: Option<PathBuf>,
+
+    /// A V4L2 webcam, e.g. `/dev/video0`, for a Pocket Camera to see live instead. Needs
+    /// `ffmpeg` on the PATH
+    #[cfg(feature = "webcam")]
+    #[arg(long, conflicts_with = "camera_image")]
+    webcam: Option<PathBuf>,
+
+    /// Log which ROM bytes run as code, are read as data or feed OAM DMA into this file, in
+    /// the one-byte-per-ROM-byte `.cdl` layout. An existing log for the same ROM is added to
+    #[arg(long)]
+    cdl: Option<PathBuf>,
+
+    /// Log the CPU's reads and writes of these IO ports at debug level, with the frame, line
+    /// and PC of each. Ports go by address or name, e.g. `FF40,FF46-FF4B` or `LCDC,BGP-WX`
+    #[arg(long)]
+    log_io: Option<IoLog>,
+
+    /// Print what the game sends out the serial port to stdout a line at a time, which is how
+    /// test ROMs report. Otherwise it's logged at debug level, and the debugger's `serial`
+    /// shows it all
+    #[arg(long)]
+    serial_stdout: bool,
+
+    /// How much of the last frame lingers on screen, 0 up to just under 1, like a DMG LCD's
+    /// slow pixels (overrides the settings file). Dumped frames are left sharp
+    #[arg(long)]
+    ghosting: Option<f32>,
+
+    /// Settings file (default: `$XDG_CONFIG_HOME/gb23/settings`)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
+    /// Start with debugger enabled
+    #[arg(short, long)]
+    debug: bool,
+
+    /// Debugger commands to run at startup, one per line (`#` starts a comment)
+    #[arg(long)]
+    debug_script: Option<PathBuf>,
+
+    /// Start with the stats overlay shown, F2 toggles it
+    #[arg(long)]
+    overlay: bool,
+
+    /// Start with the pressed buttons shown, F3 toggles them
+    #[arg(long)]
+    input_display: bool,
+
+    /// Start with the sound channels' state shown, F4 toggles it
+    #[arg(long)]
+    apu_view: bool,
+
+    /// Start with the picture tinted by layer (BG blue, window green, objects red) and the
+    /// objects dropped past 10 a line outlined, F6 toggles it
+    #[arg(long)]
+    tint: bool,
+
+    /// Write every frame as a numbered PNG into this directory
+    #[arg(long)]
+    dump_frames: Option<PathBuf>,
+
+    /// Record the session's audio into this WAV file
+    #[arg(long)]
+    dump_audio: Option<PathBuf>,
+
+    /// Run reproducibly: buttons are only read once a frame, and each frame's state hash is
+    /// logged at debug level. Together with the `seed` setting, the same inputs on the same
+    /// frames give the same hashes
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Reload and reset whenever the ROM (or source) files change
+    #[arg(short, long)]
+    watch: bool,
+
+    /// Keep cartridge RAM across reloads instead of clearing it
+    #[arg(long, requires = "watch")]
+    keep_sram: bool,
+
+    /// Save the machine's state on the way out, and offer to pick up from it the next time
+    /// the same ROM is played. The cart's RAM goes back to how it was then too
+    #[arg(long)]
+    resume: bool,
+}
+
+struct LineCompleter {
+    completions: Vec<String>,
+}
+
+impl LineCompleter {
+    fn new() -> Self {
+        Self {
+            completions: Vec::new(),
+        }
+    }
+
+    fn add<S: ToString>(&mut self, string: S) {
+        self.completions.push(string.to_string());
+    }
+}
+
+impl Completer for LineCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        _pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        let words = line.split_whitespace();
+        if let Some(last) = words.last() {
+            let mut all_completions = Vec::new();
+            for completion in self.completions.iter() {
+                if completion.starts_with(last) {
+                    all_completions.push(completion.clone());
+                }
+            }
+            if !all_completions.is_empty() {
+                let pos = line.rfind(last).unwrap();
+                return Ok((pos, all_completions));
+            }
+        }
+        Ok((0, Vec::new()))
+    }
+}
+
+// whole lines from history first, otherwise finish a hex address typed before
+struct AddressHinter {
+    history: HistoryHinter,
+}
+
+impl Hinter for AddressHinter {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<Self::Hint> {
+        if let Some(hint) = self.history.hint(line, pos, ctx) {
+            return Some(hint);
+        }
+        if pos < line.len() {
+            return None;
+        }
+        // only arguments, `b` and friends are commands as well as hex digits
+        let (_, word) = line.rsplit_once(char::is_whitespace)?;
+        if word.is_empty() || !word.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let history = ctx.history();
+        (0..history.len()).rev().find_map(|i| {
+            let entry = history.get(i, SearchDirection::Reverse).ok()??.entry;
+            entry.split_whitespace().find_map(|other| {
+                let rest = other.get(word.len()..)?;
+                (other.len() > word.len()
+                    && other[..word.len()].eq_ignore_ascii_case(word)
+                    && other.chars().all(|c| c.is_ascii_hexdigit()))
+                .then(|| rest.to_string())
+            })
+        })
+    }
+}
+
+// in bytes of stereo f32 samples, a bit over a third of a second at the default 22050Hz
+const MAX_QUEUED_AUDIO: usize = 8192 * 2 * mem::size_of::<f32>();
+
+// batches of samples waiting on the render loop, at the APU's 256 a batch as many as SDL's
+// queue is allowed
+const MAX_SENT_AUDIO: usize = 32;
+
+const COMMANDS: &[&str] = &[
+    "s", "g", "b", "d", "r", "c", "x", "p", "savemem", "loadmem", "irq", "hash", "mute", "solo",
+    "hot", "heatmap", "banks", "state", "errors", "serial", "press", "hold", "release", "tint",
+    "hide", "i", "q",
+];
+
+#[derive(Helper, Completer, Hinter, Highlighter, Validator)]
+struct LineHelper {
+    #[rustyline(Hinter)]
+    hinter: AddressHinter,
+    #[rustyline(Completer)]
+    completer: LineCompleter,
+}
+
+fn settings_path(args: &RunOptions) -> Option<PathBuf> {
+    args.config.clone().or_else(Settings::default_path)
+}
+
+fn load_settings(args: &RunOptions) -> Result<Settings, String> {
+    let Some(path) = settings_path(args) else {
+        tracing::warn!("no settings file location, using defaults");
+        return Ok(Settings::default());
+    };
+    match Settings::load(&path) {
+        Ok(settings) => Ok(settings),
+        // first run, so write out the defaults for the user to edit
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let settings = Settings::default();
+            if let Err(e) = settings.save(&path) {
+                tracing::warn!("failed to write settings file {}: {e}", path.display());
+            }
+            Ok(settings)
+        }
+        Err(e) => Err(format!(
+            "failed to load settings file {}: {e}",
+            path.display()
+        )),
+    }
+}
+
+pub fn run(args: RunArgs) -> Result<(), String> {
+    let mut recent = Recent::load(settings_path(&args.options).as_deref());
+    let rom_path = match &args.rom {
+        Some(path) => path.clone(),
+        None => recent.pick()?,
+    };
+    // the symbol file is read again on a reload, the assembler may have rewritten it too
+    let load = || {
+        let mut rom = read_rom(&rom_path)?;
+        if let Some(path) = &args.patch {
+            let data = fs::read(path)
+                .map_err(|e| format!("failed to read patch {}: {e}", path.display()))?;
+            rom = patch::apply(&data, &rom)
+                .map_err(|e| format!("failed to apply {}: {e}", path.display()))?;
+            tracing::info!("applied {}", path.display());
+        }
+        let symbols = match &args.sym {
+            Some(path) => Symbols::load(path)?,
+            None => Symbols::default(),
+        };
+        Ok((rom, symbols))
+    };
+    let (rom, symbols) = load()?;
+    recent.add(&rom_path);
+    let mut watched = vec![rom_path.clone()];
+    watched.extend(args.patch.clone());
+    watched.extend(args.sym.clone());
+    // a hack keeps its saves apart from the original's
+    let sav = args
+        .patch
+        .as_ref()
+        .unwrap_or(&rom_path)
+        .with_extension("sav");
+    play(&args.options, rom, symbols, &watched, &sav, &load)
+}
+
+/// Builds the ROM and its symbols again for `--watch`.
+pub type Reload<'a> = dyn Fn() -> Result<(Vec<u8>, Symbols), String> + Sync + 'a;
+
+/// Runs `rom` until the window closes. With `--watch`, `reload` is called for a new ROM
+/// whenever one of the `watched` files changes. Carts with a battery load their RAM from
+/// `sav` and write it back there on the way out.
+pub fn play(
+    args: &RunOptions,
+    rom: Vec<u8>,
+    symbols: Symbols,
+    watched: &[PathBuf],
+    sav: &Path,
+    reload: &Reload<'_>,
+) -> Result<(), String> {
+    check_rom(&rom);
+    let header = Header::parse(&rom).filter(Header::battery);
+    let mut sram = vec![0; (8192 * 4).max(header.as_ref().map_or(0, Header::save_size))];
+    let rtc = match &header {
+        Some(header) => sav::load(sav, header, &mut sram)?,
+        None => None,
+    };
+    let camera_feed = match &args.camera_image {
+        Some(path) => CameraFeed::Image(cart::read_camera_image(path)?),
+        None => CameraFeed::Gray,
+    };
+    #[cfg(feature = "webcam")]
+    let camera_feed = match &args.webcam {
+        Some(device) => CameraFeed::Webcam(Webcam::open(device)?),
+        None => camera_feed,
+    };
+    // asked before the window opens, the question is on the terminal
+    let exit_state = args.resume.then(|| Slots::new(&rom).exit_state()).flatten();
+    let resume = match exit_state {
+        Some((state, saved)) if ask_resume(saved)? => Some(state),
+        _ => None,
+    };
+    let mut settings = load_settings(args)?;
+    if args.boot.is_some() {
+        settings.boot = args.boot.clone();
+    }
+    if let Some(revision) = args.revision {
+        settings.revision = revision;
+    }
+    if args.boot_profile.is_some() {
+        settings.boot_profile = args.boot_profile;
+    }
+    if let Some(ghosting) = args.ghosting {
+        if !(0.0..1.0).contains(&ghosting) {
+            return Err("--ghosting should be 0 up to 1".to_string());
+        }
+        settings.ghosting = ghosting;
+    }
+    let script = match &args.debug_script {
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| format!("failed to read debug script: {e}"))?
+            .lines()
+            .map(|line| line.split('#').next().unwrap().trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect(),
+        None => VecDeque::new(),
+    };
+    if let Some(dir) = &args.dump_frames {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create frame directory {}: {e}", dir.display()))?;
+    }
+    let wav = args
+        .dump_audio
+        .as_deref()
+        .map(|path| {
+            WavWriter::create(path, settings.sample_rate)
+                .map_err(|e| format!("failed to create WAV file {}: {e}", path.display()))
+        })
+        .transpose()?;
+    let mut boot_data = Vec::new();
+    if let Some(boot) = &settings.boot {
+        File::open(boot)
+            .map_err(|e| format!("failed to open BIOS file: {e}"))?
+            .read_to_end(&mut boot_data)
+            .map_err(|e| format!("failed to read BIOS file: {e}"))?;
+    }
+    let sdl = sdl2::init().map_err(|e| format!("failed to initialize SDL2: {e}"))?;
+    let mut event_pump = sdl
+        .event_pump()
+        .map_err(|e| format!("failed to initialize SDL2 events: {e}"))?;
+    let video = sdl
+        .video()
+        .map_err(|e| format!("failed to initialize SDL2 video: {e}"))?;
+
+    let mut audio_queue = None;
+    if settings.audio {
+        let audio = sdl
+            .audio()
+            .map_err(|e| format!("failed to initialize SDL2 audio: {e}"))?;
+        let queue: AudioQueue<f32> = audio
+            .open_queue(
+                None,
+                &AudioSpecDesired {
+                    freq: Some(settings.sample_rate as i32),
+                    channels: Some(2),
+                    samples: Some(512),
+                },
+            )
+            .map_err(|e| format!("failed to open audio device: {e}"))?;
+        queue.resume();
+        audio_queue = Some(queue);
+    }
+
+    // the SGB draws its border around the game screen
+    let (width, height) = if settings.model == Model::Sgb {
+        (256, 224)
+    } else {
+        (160, 144)
+    };
+    let window = video
+        .window("gb23", width * settings.scale, height * settings.scale)
+        .allow_highdpi()
+        .position_centered()
+        .build()
+        .map_err(|e| format!("failed to create window: {e}"))?;
+    let mut canvas = window
+        .into_canvas()
+        .accelerated()
+        .build()
+        .map_err(|e| format!("failed to map window to canvas: {e}"))?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, 256, 256)
+        .map_err(|e| format!("failed to create texture: {e}"))?;
+
+    // the script runs in the debugger, so start there
+    let debug_mode = Arc::new(AtomicBool::new(args.debug || !script.is_empty()));
+    signal_hook::flag::register(signal_hook::consts::SIGUSR1, debug_mode.clone())
+        .map_err(|e| {
+            tracing::warn!("external debugger unavailable: failed to install SIGUSR1 handler: {e}")
+        })
+        .ok();
+    let quit = AtomicBool::new(false);
+    let buttons = Arc::new(AtomicU8::new(0));
+    let muted = AtomicU8::new(0);
+    let tint = AtomicBool::new(args.tint);
+    let hidden = AtomicU8::new(0);
+    let cycles = AtomicUsize::new(0);
+    let changed = AtomicBool::new(false);
+    let stats = Mutex::new(Stats::default());
+    let slot_control = SlotControl::new();
+    let (frame_tx, frame_rx) = mpsc::sync_channel(1);
+    // SDL's queue can't leave this thread, so samples are handed over like frames
+    let (audio_tx, audio_rx) = mpsc::sync_channel(MAX_SENT_AUDIO);
+    let audio_tx = audio_queue.is_some().then_some(audio_tx);
+
+    let result = thread::scope(|s| {
+        if args.watch {
+            s.spawn(|| watch(watched, &changed, &quit));
+        }
+        let emu_thread = s.spawn(|| {
+            let result = emulate(Session {
+                settings: &settings,
+                rom,
+                boot_data,
+                sram: &mut sram,
+                symbols,
+                script,
+                resume,
+                buttons: &buttons,
+                muted: &muted,
+                tint: &tint,
+                hidden: &hidden,
+                slot_control: &slot_control,
+                debug_mode: &debug_mode,
+                quit: &quit,
+                stats: &stats,
+                cycles: &cycles,
+                frame_tx,
+                audio_tx,
+                dump_frames: args.dump_frames.as_deref(),
+                wav,
+                deterministic: args.deterministic,
+                serial_stdout: args.serial_stdout,
+                multicart: args.multicart,
+                camera_feed: &camera_feed,
+                cdl: args.cdl.as_deref(),
+                io_log: args.log_io.as_ref(),
+                save_on_exit: args.resume,
+                watch: args.watch.then_some((reload, &changed, args.keep_sram)),
+            });
+            // make sure the render loop notices if we bail out early
+            quit.store(true, Ordering::Relaxed);
+            result
+        });
+        let result = (|| {
+            // moved in so the channel hangs up as soon as we stop rendering
+            let frame_rx = frame_rx;
+            let audio_rx = audio_rx;
+            let mut start = Instant::now();
+            let mut frames = 0;
+            let mut frame_times = FrameTimes::new();
+            let mut overlay = args.overlay;
+            let mut input_display = args.input_display;
+            let mut apu_view = args.apu_view;
+            // last second's numbers, for the overlay
+            let (mut fps, mut mhz) = (0, 0.0);
+            'render_loop: while !quit.load(Ordering::Relaxed) {
+                for event in event_pump.poll_iter() {
+                    match event {
+                        Event::Quit { .. }
+                        | Event::KeyDown {
+                            scancode: Some(Scancode::Escape),
+                            ..
+                        } => break 'render_loop,
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F1),
+                            ..
+                        } => debug_mode.store(true, Ordering::Relaxed),
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F2),
+                            ..
+                        } => overlay = !overlay,
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F3),
+                            ..
+                        } => input_display = !input_display,
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F4),
+                            ..
+                        } => apu_view = !apu_view,
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F5),
+                            ..
+                        } => slot_control.request.store(Request::SAVE, Ordering::Relaxed),
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F6),
+                            ..
+                        } => {
+                            tint.fetch_xor(true, Ordering::Relaxed);
+                        }
+                        Event::KeyDown {
+                            scancode: Some(Scancode::F7),
+                            ..
+                        } => slot_control.request.store(Request::LOAD, Ordering::Relaxed),
+                        // F8-F10 hide the BG, window and objects
+                        Event::KeyDown {
+                            scancode: Some(scancode),
+                            repeat: false,
+                            ..
+                        } if layer_key(scancode).is_some() => {
+                            hidden.fetch_xor(layer_key(scancode).unwrap(), Ordering::Relaxed);
+                        }
+                        // ctrl and 0-9 picks a save state slot, leaving 1-4 for the channels
+                        Event::KeyDown {
+                            scancode: Some(scancode),
+                            keymod,
+                            repeat: false,
+                            ..
+                        } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD)
+                            && slot_key(scancode).is_some() =>
+                        {
+                            let slot = slot_key(scancode).unwrap();
+                            slot_control.slot.store(slot as u8, Ordering::Relaxed);
+                            slot_control.request.store(Request::SHOW, Ordering::Relaxed);
+                        }
+                        // 1-4 mute a channel, or solo it with shift held
+                        Event::KeyDown {
+                            scancode: Some(scancode),
+                            keymod,
+                            repeat: false,
+                            ..
+                        } if channel_key(scancode).is_some() => mute_channel(
+                            &muted,
+                            channel_key(scancode).unwrap(),
+                            keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                        ),
+                        _ => {}
+                    }
+                }
+                buttons.store(
+                    Buttons::from_keyboard(&event_pump.keyboard_state()),
+                    Ordering::Relaxed,
+                );
+                // keep pumping events even when the emulator is parked in the debugger
+                if let Some(queue) = &audio_queue {
+                    for samples in audio_rx.try_iter() {
+                        // running ahead of real time, better to skip than to fall behind
+                        if queue.size() as usize > MAX_QUEUED_AUDIO {
+                            continue;
+                        }
+                        queue
+                            .queue_audio(&samples)
+                            .map_err(|e| format!("failed to queue audio: {e}"))?;
+                    }
+                }
+                let mut frame = match frame_rx.recv_timeout(Duration::from_millis(16)) {
+                    Ok(frame) => frame,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let stats = *stats.lock().unwrap();
+                let mut lines = Vec::new();
+                if overlay {
+                    lines.push(format!("{fps} FPS {mhz:.2} MHZ"));
+                    lines.push(format!("LY {:3} MODE {}", stats.ly, stats.mode));
+                    let mut banks =
+                        format!("ROM {:02X} SRAM {:X}", stats.rom_bank, stats.sram_bank);
+                    if let Some((wram, vram)) = stats.banks {
+                        banks.push_str(&format!(" WRAM {wram} VRAM {vram}"));
+                    }
+                    lines.push(banks);
+                    lines.push(match &audio_queue {
+                        // queued stereo f32 samples, as time left to play
+                        Some(queue) => format!(
+                            "AUDIO {} MS",
+                            (queue.size() as usize / (2 * mem::size_of::<f32>())) * 1000
+                                / settings.sample_rate as usize
+                        ),
+                        None => "AUDIO OFF".to_string(),
+                    });
+                    let muted = muted.load(Ordering::Relaxed);
+                    if muted != 0 {
+                        lines.push(format!("MUTE {}", channel_list(muted)));
+                    }
+                    let hidden = hidden.load(Ordering::Relaxed);
+                    if hidden != 0 {
+                        lines.push(format!("HIDE {}", Layers::describe(hidden).to_uppercase()));
+                    }
+                }
+                if apu_view {
+                    lines.extend(overlay::apu_lines(
+                        &stats.apu,
+                        muted.load(Ordering::Relaxed),
+                    ));
+                }
+                if let Some(notice) = slot_control.notice() {
+                    lines.push(notice);
+                }
+                if !lines.is_empty() {
+                    overlay::draw(&mut frame, &lines);
+                }
+                if input_display {
+                    overlay::draw_buttons(&mut frame, stats.buttons);
+                }
+                let rect = Rect::new(0, 0, width, height);
+                texture
+                    .update(rect, frame.as_bytes(), frame.pitch())
+                    .map_err(|e| format!("failed to lock texture: {e}"))?;
+                canvas
+                    .copy(&texture, rect, None)
+                    .map_err(|e| format!("failed to copy texture: {e}"))?;
+                canvas.present();
+                frame_times.record();
+                frames += 1;
+                let now = Instant::now();
+                if now.duration_since(start) > Duration::from_secs(1) {
+                    mhz = (cycles.swap(0, Ordering::Relaxed) as f64) / 1_000_000.0;
+                    fps = frames;
+                    let [p50, p99, max] = frame_times.percentiles([50, 99, 100]);
+                    canvas
+                        .window_mut()
+                        .set_title(&format!(
+                            "gb23 :: {mhz:.03} MHz :: {frames} fps :: {:.1}/{:.1}/{:.1} ms",
+                            p50.as_secs_f64() * 1000.0,
+                            p99.as_secs_f64() * 1000.0,
+                            max.as_secs_f64() * 1000.0,
+                        ))
+                        .map_err(|e| format!("failed to update window title: {e}"))?;
+                    start = now;
+                    frames = 0;
+                }
+            }
+            Ok(())
+        })();
+        quit.store(true, Ordering::Relaxed);
+        let emu_result = emu_thread
+            .join()
+            .unwrap_or_else(|_| Err("emulator thread panicked".into()));
+        result.and(emu_result)
+    });
+    // whatever happened, the player's progress is worth keeping
+    if let Some(header) = &header {
+        sav::store(sav, header, &sram, rtc)?;
+    }
+    result
+}
+
+// flags a reload once the files changed and have stopped changing, so we
+// don't pick up one that is still being written
+fn watch(paths: &[PathBuf], changed: &AtomicBool, quit: &AtomicBool) {
+    let modified = || {
+        paths
+            .iter()
+            .map(|path| fs::metadata(path).and_then(|meta| meta.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    let mut loaded = modified();
+    let mut last = loaded.clone();
+    while !quit.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(250));
+        let now = modified();
+        if (now == last) && (now != loaded) {
+            loaded = now.clone();
+            changed.store(true, Ordering::Relaxed);
+        }
+        last = now;
+    }
+}
+
+// everything the emulator thread runs with, handed over whole when it starts
+struct Session<'a> {
+    settings: &'a Settings,
+    rom: Vec<u8>,
+    boot_data: Vec<u8>,
+    sram: &'a mut [u8],
+    symbols: Symbols,
+    // debugger commands to run before asking for any
+    script: VecDeque<String>,
+    // the state the last session quit at, to pick up from
+    resume: Option<Vec<u8>>,
+
+    // shared with the render loop, which sets most of these and reads back the last two
+    buttons: &'a Arc<AtomicU8>,
+    muted: &'a AtomicU8,
+    tint: &'a AtomicBool,
+    hidden: &'a AtomicU8,
+    slot_control: &'a SlotControl,
+    debug_mode: &'a AtomicBool,
+    quit: &'a AtomicBool,
+    stats: &'a Mutex<Stats>,
+    cycles: &'a AtomicUsize,
+
+    // where the picture and sound go
+    frame_tx: SyncSender<Frame>,
+    audio_tx: Option<SyncSender<Vec<f32>>>,
+    dump_frames: Option<&'a Path>,
+    wav: Option<WavWriter>,
+
+    // the rest of the command line
+    deterministic: bool,
+    serial_stdout: bool,
+    multicart: Option<bool>,
+    camera_feed: &'a CameraFeed,
+    cdl: Option<&'a Path>,
+    io_log: Option<&'a IoLog>,
+    save_on_exit: bool,
+    watch: Option<(&'a Reload<'a>, &'a AtomicBool, bool)>,
+}
+
+fn emulate(session: Session<'_>) -> Result<(), String> {
+    let Session {
+        settings,
+        mut rom,
+        boot_data,
+        sram,
+        mut symbols,
+        mut script,
+        mut resume,
+        buttons,
+        muted,
+        tint,
+        hidden,
+        slot_control,
+        debug_mode,
+        quit,
+        stats,
+        cycles,
+        frame_tx,
+        audio_tx,
+        dump_frames,
+        wav,
+        deterministic,
+        serial_stdout,
+        multicart,
+        camera_feed,
+        cdl,
+        io_log,
+        save_on_exit,
+        watch,
+    } = session;
+    // the debugger and its breakpoints outlive a reload, the machine does not
+    let mut breakpoints: Vec<Breakpoint> = Vec::new();
+    // set by `g`, and forgotten as soon as we stop for any reason
+    let mut run_to: Option<Breakpoint> = None;
+
+    let mut rl = Editor::with_config(Config::builder().auto_add_history(true).build())
+        .map_err(|e| format!("failed to initialize line editor: {e}"))?;
+    rl.set_helper(Some(LineHelper {
+        hinter: AddressHinter {
+            history: HistoryHinter::new(),
+        },
+        completer: LineCompleter::new(),
+    }));
+    let completer = &mut rl.helper_mut().unwrap().completer;
+    for command in COMMANDS {
+        completer.add(command);
+    }
+    for info in Port::INFO {
+        completer.add(info.name);
+    }
+    for name in symbols.names() {
+        completer.add(name);
+    }
+    let screen: Box<dyn VideoSink> = match settings.ghosting {
+        0.0 => Box::new(Screen(frame_tx)),
+        strength => Box::new(Ghosting::new(Box::new(Screen(frame_tx)), strength)),
+    };
+    // handed from one machine to the next on a reload, so dumped frames keep counting up
+    let mut video: Box<dyn VideoSink> = match dump_frames {
+        Some(dir) => Box::new(vec![screen, Box::new(FrameDumper::new(dir))]),
+        None => screen,
+    };
+    let mut audio: Box<dyn AudioSink> = match (audio_tx, wav) {
+        (Some(tx), Some(wav)) => Box::new(vec![
+            Box::new(Speaker {
+                tx,
+                volume: settings.volume,
+            }) as Box<dyn AudioSink>,
+            Box::new(wav),
+        ]),
+        (Some(tx), None) => Box::new(Speaker {
+            tx,
+            volume: settings.volume,
+        }),
+        (None, Some(wav)) => Box::new(wav),
+        (None, None) => Box::new(audio::NullSink),
+    };
+    let mut pacer = Pacer::new(settings.speed);
+    let mut frame_cycles = 0;
+    // only the first ROM picks up where an earlier session's log left off, a reloaded one has
+    // moved things around
+    let mut resume_cdl = true;
+    loop {
+        let mbc = Cart::new(&rom, sram, multicart, camera_feed);
+        let mut emu = Emu::new(
+            settings,
+            boot_data.clone(),
+            mbc,
+            Input::new(buttons.clone(), deterministic),
+        );
+        emu.set_video_sink(video);
+        emu.set_audio_sink(audio);
+        emu.reset();
+        if let Some(path) = cdl {
+            let log = if mem::take(&mut resume_cdl) {
+                load_cdl(path, rom.len())?
+            } else {
+                Cdl::new()
+            };
+            emu.set_cdl(Some(log));
+        }
+        emu.set_io_log(io_log.cloned());
+        if settings.boot.is_none() {
+            let (cpu, mut cpu_view) = emu.cpu_view();
+            let profile = settings
+                .boot_profile
+                .unwrap_or(BootProfile::of(settings.model));
+            skip_boot(cpu, &mut cpu_view, profile);
+        }
+        // only into the first machine, a reloaded ROM starts fresh
+        if let Some(state) = resume.take() {
+            match emu.load_state(&state) {
+                Ok(()) => tracing::info!("resumed from where the last session quit"),
+                Err(e) => tracing::warn!("{e}, starting from the beginning"),
+            }
+        }
+        let slots = Slots::new(&rom);
+        let mut reloaded = None;
+        let mut frame = 0;
+        let mut bank_log = BankLog::new();
+        let mut trace = Trace::new();
+        let mut latch_cycles = 0;
+        'da_loop: while !quit.load(Ordering::Relaxed) {
+            if let Some((reload, changed, _)) = watch {
+                if changed.swap(false, Ordering::Relaxed) {
+                    match reload() {
+                        Ok(build) => {
+                            reloaded = Some(build);
+                            break;
+                        }
+                        Err(e) => tracing::warn!("{e}, keeping the old ROM"),
+                    }
+                }
+            }
+            emu.set_muted_channels(muted.load(Ordering::Relaxed));
+            emu.set_tint(tint.load(Ordering::Relaxed));
+            emu.set_hidden_layers(hidden.load(Ordering::Relaxed));
+            let pc = emu.cpu().wide_register(WideRegister::PC);
+            let bank = emu.mbc().rom_bank();
+            if breakpoints.iter().chain(&run_to).any(|b| b.hit(pc, bank)) {
+                debug_mode.store(true, Ordering::Relaxed);
+            }
+            if debug_mode.load(Ordering::Relaxed) {
+                run_to = None;
+                loop {
+                    println!("{}", describe_registers(emu.cpu()));
+                    let line = match script.pop_front() {
+                        Some(line) => {
+                            println!("> {line}");
+                            Ok(line)
+                        }
+                        None => rl.readline("> "),
+                    };
+                    match line {
+                        Ok(line) => {
+                            let line = if line.is_empty() {
+                                if let Some(line) = rl.history().iter().last() {
+                                    line
+                                } else {
+                                    continue;
+                                }
+                            } else {
+                                &line
+                            };
+                            let parts = line
+                                .split_whitespace()
+                                .map(String::from)
+                                .collect::<Vec<String>>();
+                            match parts[0].as_str() {
+                                "s" => {
+                                    let n = parts.get(1).map_or(Ok(1), |n| n.parse::<usize>());
+                                    let Ok(n) = n else {
+                                        println!("?");
+                                        continue;
+                                    };
+                                    for i in 0..n {
+                                        step(&mut emu, &mut bank_log, &mut trace, frame);
+                                        let pc = emu.cpu().wide_register(WideRegister::PC);
+                                        let bank = emu.mbc().rom_bank();
+                                        if (i + 1 < n)
+                                            && breakpoints.iter().any(|b| b.hit(pc, bank))
+                                        {
+                                            break;
+                                        }
+                                    }
+                                }
+                                "g" => {
+                                    if parts.len() > 1 {
+                                        if let Some(breakpoint) =
+                                            Breakpoint::parse(&parts[1], &symbols)
+                                        {
+                                            run_to = Some(breakpoint);
+                                            debug_mode.store(false, Ordering::Relaxed);
+                                            break;
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "b" => {
+                                    if parts.len() > 1 {
+                                        if let Some(breakpoint) =
+                                            Breakpoint::parse(&parts[1], &symbols)
+                                        {
+                                            breakpoints.push(breakpoint);
+                                            continue;
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "d" => {
+                                    if parts.len() > 1 {
+                                        if let Ok(n) = usize::from_str_radix(&parts[1], 10) {
+                                            if n < breakpoints.len() {
+                                                breakpoints.remove(n);
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "r" => {
+                                    if parts.len() > 1 {
+                                        let (cpu, _) = emu.cpu_view();
+                                        if parts[1..]
+                                            .iter()
+                                            .all(|assign| assign_register(cpu, assign))
+                                        {
+                                            continue;
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "c" => {
+                                    debug_mode.store(false, Ordering::Relaxed);
+                                    break;
+                                }
+                                "x" => {
+                                    let addr =
+                                        parts.get(1).map(|addr| u16::from_str_radix(addr, 16));
+                                    let len = parts
+                                        .get(2)
+                                        .map_or(Ok(1), |len| usize::from_str_radix(len, 16));
+                                    if let (Some(Ok(addr)), Ok(len)) = (addr, len) {
+                                        let mut buf = vec![0; len];
+                                        emu.read_range(addr, &mut buf);
+                                        if len == 1 {
+                                            match describe_port(addr, buf[0]) {
+                                                Some(port) => println!("{:02X} {port}", buf[0]),
+                                                None => println!("{:02X}", buf[0]),
+                                            }
+                                            continue;
+                                        }
+                                        for (i, line) in buf.chunks(16).enumerate() {
+                                            let addr = addr.wrapping_add((i * 16) as u16);
+                                            let bytes = line
+                                                .iter()
+                                                .map(|b| format!("{b:02X}"))
+                                                .collect::<Vec<_>>();
+                                            println!("{addr:04X}: {}", bytes.join(" "));
+                                        }
+                                        for (i, &value) in buf.iter().enumerate() {
+                                            let addr = addr.wrapping_add(i as u16);
+                                            if let Some(port) = describe_port(addr, value) {
+                                                println!("{addr:04X}: {port}");
+                                            }
+                                        }
+                                        continue;
+                                    }
+                                    println!("?");
+                                }
+                                "p" => {
+                                    if parts.len() > 2 {
+                                        if let Ok(addr) = u16::from_str_radix(&parts[1], 16) {
+                                            if let Ok(value) = u8::from_str_radix(&parts[2], 16) {
+                                                let (_, mut cpu_view) = emu.cpu_view();
+                                                cpu_view.write(addr, value);
+                                                continue;
+                                            }
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "savemem" => {
+                                    if parts.len() > 3 {
+                                        let addr = u16::from_str_radix(&parts[1], 16);
+                                        let len = usize::from_str_radix(&parts[2], 16);
+                                        if let (Ok(addr), Ok(len)) = (addr, len) {
+                                            let mut buf = vec![0; len];
+                                            emu.read_range(addr, &mut buf);
+                                            if let Err(e) = fs::write(&parts[3], &buf) {
+                                                println!("failed to write {}: {e}", parts[3]);
+                                            }
+                                            continue;
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "loadmem" => {
+                                    if parts.len() > 2 {
+                                        if let Ok(addr) = u16::from_str_radix(&parts[2], 16) {
+                                            match fs::read(&parts[1]) {
+                                                Ok(data) => {
+                                                    let (_, mut cpu_view) = emu.cpu_view();
+                                                    for (i, &value) in data.iter().enumerate() {
+                                                        cpu_view.write(
+                                                            addr.wrapping_add(i as u16),
+                                                            value,
+                                                        );
+                                                    }
+                                                }
+                                                Err(e) => {
+                                                    println!("failed to read {}: {e}", parts[1])
+                                                }
+                                            }
+                                            continue;
+                                        }
+                                    }
+                                    println!("?");
+                                }
+                                "irq" => print_irq_status(&mut emu),
+                                "hash" => println!("{:016X}", emu.state_hash()),
+                                "mute" | "solo" => {
+                                    if let Some(channel) = parts.get(1) {
+                                        let Some(channel) = channel
+                                            .parse::<usize>()
+                                            .ok()
+                                            .filter(|channel| (1..=4).contains(channel))
+                                        else {
+                                            println!("?");
+                                            continue;
+                                        };
+                                        mute_channel(muted, channel - 1, parts[0] == "solo");
+                                    }
+                                    let muted = muted.load(Ordering::Relaxed);
+                                    if muted == 0 {
+                                        println!("muted: none");
+                                    } else {
+                                        println!("muted: {}", channel_list(muted));
+                                    }
+                                }
+                                "hot" => {
+                                    let n = parts.get(1).map_or(Ok(16), |n| n.parse::<usize>());
+                                    let Ok(n) = n else {
+                                        println!("?");
+                                        continue;
+                                    };
+                                    let Some(heatmap) = emu.heatmap() else {
+                                        println!("heatmap is off, `heatmap on` starts it");
+                                        continue;
+                                    };
+                                    for (addr, [reads, writes, executes]) in heatmap.hottest(n) {
+                                        println!(
+                                            "{addr:04X}: R {reads:>10} W {writes:>10} X {executes:>10}"
+                                        );
+                                    }
+                                }
+                                "hide" => {
+                                    if let Some(name) = parts.get(1) {
+                                        let Some(layer) = Layers::parse(name) else {
+                                            println!("?");
+                                            continue;
+                                        };
+                                        hidden.fetch_xor(layer, Ordering::Relaxed);
+                                    }
+                                    emu.set_hidden_layers(hidden.load(Ordering::Relaxed));
+                                    println!("hidden: {}", Layers::describe(emu.hidden_layers()));
+                                }
+                                "tint" => {
+                                    match parts.get(1).map(String::as_str) {
+                                        None => {}
+                                        Some("on") => tint.store(true, Ordering::Relaxed),
+                                        Some("off") => tint.store(false, Ordering::Relaxed),
+                                        Some(_) => {
+                                            println!("?");
+                                            continue;
+                                        }
+                                    }
+                                    emu.set_tint(tint.load(Ordering::Relaxed));
+                                    println!("tint: {}", if emu.tint() { "on" } else { "off" });
+                                }
+                                "heatmap" => match parts.get(1).map(String::as_str) {
+                                    None => println!(
+                                        "heatmap: {}",
+                                        if emu.heatmap().is_some() { "on" } else { "off" }
+                                    ),
+                                    Some("on") => emu.set_heatmap(true),
+                                    Some("off") => emu.set_heatmap(false),
+                                    Some("clear") => {
+                                        if let Some(heatmap) = emu.heatmap_mut() {
+                                            heatmap.clear();
+                                        }
+                                    }
+                                    Some(path) => match emu.heatmap() {
+                                        Some(heatmap) => {
+                                            let frame = overlay::heatmap(heatmap);
+                                            if let Err(e) = png::write(Path::new(path), &frame) {
+                                                println!("failed to write {path}: {e}");
+                                            }
+                                        }
+                                        None => {
+                                            println!("heatmap is off, `heatmap on` starts it")
+                                        }
+                                    },
+                                },
+                                "banks" => match parts.get(1).map(String::as_str) {
+                                    Some("clear") => bank_log.clear(),
+                                    n => {
+                                        let Ok(n) = n.map_or(Ok(16), |n| n.parse::<usize>()) else {
+                                            println!("?");
+                                            continue;
+                                        };
+                                        for switch in bank_log.recent(n) {
+                                            println!("{}", switch.describe());
+                                        }
+                                    }
+                                },
+                                "errors" => match parts.get(1).map(String::as_str) {
+                                    None => {
+                                        for (error, count) in emu.errors().iter() {
+                                            println!("{error} ({count}x)");
+                                        }
+                                    }
+                                    Some("clear") => emu.clear_errors(),
+                                    _ => println!("?"),
+                                },
+                                // scripted input, timed in frames like --deterministic latches
+                                "press" | "hold" | "release" => {
+                                    let buttons = match parts.get(1) {
+                                        Some(names) => Buttons::parse(names),
+                                        None if parts[0] == "release" => Some(0xFF),
+                                        None => Some(0),
+                                    };
+                                    let frames = parts.get(2).map_or(Ok(1), |n| n.parse::<u32>());
+                                    let (Some(buttons), Ok(frames)) = (buttons, frames) else {
+                                        println!("?");
+                                        continue;
+                                    };
+                                    let input = emu.input_mut();
+                                    match parts[0].as_str() {
+                                        "press" => input.press(buttons, frames),
+                                        "hold" => input.hold(buttons),
+                                        _ => input.release(buttons),
+                                    }
+                                    println!("{}", input.describe_injected());
+                                }
+                                "serial" => match parts.get(1).map(String::as_str) {
+                                    None => println!("{}", emu.serial_output().text()),
+                                    Some("clear") => emu.serial_output_mut().clear(),
+                                    _ => println!("?"),
+                                },
+                                "state" => {
+                                    let slot = parts.get(2).map_or(
+                                        Ok(slot_control.slot.load(Ordering::Relaxed) as usize),
+                                        |n| n.parse::<usize>(),
+                                    );
+                                    let slot = slot.ok().filter(|&slot| slot < SLOTS);
+                                    let result = match (parts.get(1).map(String::as_str), slot) {
+                                        (None, _) => {
+                                            // the one the window's keys save to and load from
+                                            let selected =
+                                                slot_control.slot.load(Ordering::Relaxed) as usize;
+                                            for slot in 0..SLOTS {
+                                                let mark = if slot == selected { '*' } else { ' ' };
+                                                match slots.saved_at(slot) {
+                                                    Some(time) => println!(
+                                                        "{mark}{slot}: {}",
+                                                        timestamp(time)
+                                                    ),
+                                                    None => println!("{mark}{slot}: empty"),
+                                                }
+                                            }
+                                            continue;
+                                        }
+                                        (Some("save"), Some(slot)) => {
+                                            save_slot(&mut emu, &slots, slot)
+                                                .map(|_| format!("saved slot {slot}"))
+                                        }
+                                        (Some("load"), Some(slot)) => {
+                                            load_slot(&mut emu, &slots, slot)
+                                                .map(|_| format!("loaded slot {slot}"))
+                                        }
+                                        _ => {
+                                            println!("?");
+                                            continue;
+                                        }
+                                    };
+                                    match result {
+                                        Ok(done) => println!("{done}"),
+                                        Err(e) => println!("{e}"),
+                                    }
+                                }
+                                "i" => {
+                                    if parts.len() > 1 {
+                                        match parts[1].as_str() {
+                                            "b" => {
+                                                for (i, breakpoint) in
+                                                    breakpoints.iter().enumerate()
+                                                {
+                                                    println!("{i:03}: {}", breakpoint.spec());
+                                                }
+                                            }
+                                            "a" => {
+                                                let apu = emu.apu_state();
+                                                let muted = muted.load(Ordering::Relaxed);
+                                                for line in overlay::apu_lines(&apu, muted) {
+                                                    println!("{line}");
+                                                }
+                                            }
+                                            _ => println!("?"),
+                                        }
+                                        continue;
+                                    }
+                                    println!("?");
+                                }
+                                "q" => {
+                                    break 'da_loop;
+                                }
+                                _ => println!("?"),
+                            }
+                        }
+                        Err(ReadlineError::Eof) => {
+                            break 'da_loop;
+                        }
+                        Err(ReadlineError::Io(e)) => {
+                            return Err(format!("could not read line: {e}"));
+                        }
+                        Err(ReadlineError::Errno(e)) => {
+                            return Err(format!("could not read line: {}", e.desc()));
+                        }
+                        Err(_) => {}
+                    }
+                }
+                pacer.resync();
+                frame_cycles = 0;
+            }
+            let elapsed = step(&mut emu, &mut bank_log, &mut trace, frame);
+            cycles.fetch_add(elapsed, Ordering::Relaxed);
+            frame_cycles += elapsed;
+            latch_cycles += elapsed;
+            let vblanked = emu.vblanked();
+            // by emulated time alone, so the debugger or a slow host can't change what's seen
+            if vblanked || latch_cycles >= CYCLES_PER_FRAME {
+                emu.input_mut().latch();
+                print_serial(emu.serial_output_mut(), serial_stdout);
+                latch_cycles = 0;
+            }
+            // still keep time when the LCD is off and there are no vblanks to pace against
+            if vblanked || frame_cycles >= CYCLES_PER_FRAME * 2 {
+                pacer.pace(mem::take(&mut frame_cycles));
+            }
+            if vblanked {
+                *stats.lock().unwrap() = Stats {
+                    ly: emu.ppu().ly(),
+                    mode: emu.ppu().mode(),
+                    rom_bank: emu.mbc().rom_bank(),
+                    sram_bank: emu.mbc().sram_bank(),
+                    banks: (settings.model == Model::Cgb).then(|| {
+                        let (mut svbk, mut vbk) = ([0], [0]);
+                        emu.read_range(Port::SVBK, &mut svbk);
+                        emu.read_range(Port::VBK, &mut vbk);
+                        // bank 0 in SVBK still maps bank 1
+                        ((svbk[0] & 0x07).max(1), vbk[0] & 0x01)
+                    }),
+                    buttons: emu.input_mut().buttons(),
+                    apu: emu.apu_state(),
+                };
+                if deterministic {
+                    tracing::debug!("frame {frame}: state {:016X}", emu.state_hash());
+                }
+                handle_slot_request(&mut emu, &slots, slot_control);
+                if let Some(image) = camera_feed.poll() {
+                    emu.mbc_mut().set_camera_image(&image);
+                }
+                frame += 1;
+            }
+        }
+        print_serial(emu.serial_output_mut(), serial_stdout);
+        if let (Some(path), Some(log)) = (cdl, emu.cdl()) {
+            fs::write(path, log.to_bytes(rom.len()))
+                .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        }
+        let (Some((new_rom, new_symbols)), Some((_, _, keep_sram))) = (reloaded, watch) else {
+            if save_on_exit {
+                slots.save_exit(&emu.save_state())?;
+            }
+            return Ok(());
+        };
+        video = emu.set_video_sink(Box::new(NullSink));
+        audio = emu.set_audio_sink(Box::new(audio::NullSink));
+        drop(emu);
+        rom = new_rom;
+        symbols = new_symbols;
+        check_rom(&rom);
+        if !keep_sram {
+            sram.fill(0);
+        }
+        tracing::info!("reloaded ROM");
+        pacer.resync();
+        frame_cycles = 0;
+    }
+}
+
+// runs an instruction, noting it down if it switched ROM banks. A panic leaves a crash
+// report behind on its way out
+fn step(
+    emu: &mut Emu<Cart<'_>, Ppu, Input>,
+    bank_log: &mut BankLog,
+    trace: &mut Trace,
+    frame: usize,
+) -> usize {
+    let from = emu.mbc().rom_bank();
+    trace.record(emu.cpu(), from);
+    let info = match crash::catch(|| emu.step()) {
+        Ok(info) => info,
+        Err(panic) => {
+            match crash::report(emu, trace) {
+                Ok(path) => tracing::error!("crash report written to {}", path.display()),
+                Err(e) => tracing::error!("{e}"),
+            }
+            panic::resume_unwind(panic);
+        }
+    };
+    let to = emu.mbc().rom_bank();
+    if to != from {
+        bank_log.record(BankSwitch {
+            frame,
+            pc: info.pc_before,
+            from,
+            to,
+        });
+    }
+    info.cycles
+}
+
+// the serial lines finished since last time, to stdout or the debug log
+fn print_serial(output: &mut SerialOutput, stdout: bool) {
+    for line in output.take_lines() {
+        if stdout {
+            println!("{line}");
+        } else {
+            tracing::debug!("serial: {line}");
+        }
+    }
+}
+
+// does whatever the window asked of the selected slot, and says how it went on screen
+fn handle_slot_request(emu: &mut Emu<Cart<'_>, Ppu, Input>, slots: &Slots, control: &SlotControl) {
+    let slot = control.slot.load(Ordering::Relaxed) as usize;
+    let (result, done, failed) = match control.request.swap(Request::NONE, Ordering::Relaxed) {
+        Request::SAVE => (save_slot(emu, slots, slot), "SAVED", "SAVE"),
+        Request::LOAD => (load_slot(emu, slots, slot), "LOADED", "LOAD"),
+        Request::SHOW => {
+            control.notify(slots.describe(slot));
+            return;
+        }
+        _ => return,
+    };
+    match result {
+        Ok(()) => control.notify(format!("{done} SLOT {slot}")),
+        Err(e) => {
+            tracing::warn!("{e}");
+            control.notify(format!("SLOT {slot} {failed} FAILED"));
+        }
+    }
+}
+
+fn save_slot(
+    emu: &mut Emu<Cart<'_>, Ppu, Input>,
+    slots: &Slots,
+    slot: usize,
+) -> Result<(), String> {
+    slots.save(slot, &emu.save_state())
+}
+
+fn load_slot(
+    emu: &mut Emu<Cart<'_>, Ppu, Input>,
+    slots: &Slots,
+    slot: usize,
+) -> Result<(), String> {
+    let state = slots.load(slot)?;
+    emu.load_state(&state)
+        .map_err(|e| format!("failed to load slot {slot}: {e}"))
+}
+
+// an earlier session's log to add to, as long as it's for a ROM this size
+fn load_cdl(path: &Path, rom_len: usize) -> Result<Cdl, String> {
+    match fs::read(path) {
+        Ok(data) if data.len() == rom_len => Ok(Cdl::from_bytes(&data)),
+        Ok(_) => {
+            tracing::warn!("{} is for a different ROM, starting over", path.display());
+            Ok(Cdl::new())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Cdl::new()),
+        Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+    }
+}
+
+// hands frames over to the render loop, which draws them in the window
+struct Screen(SyncSender<Frame>);
+
+impl VideoSink for Screen {
+    fn frame(&mut self, width: usize, pixels: &[u32]) {
+        // the render loop is behind or gone, so just drop the frame
+        let _ = self.0.try_send(Frame::from_pixels(width, pixels));
+    }
+}
+
+// hands samples over to the render loop, which queues them up for SDL to play
+struct Speaker {
+    tx: SyncSender<Vec<f32>>,
+    volume: f32,
+}
+
+impl AudioSink for Speaker {
+    fn samples(&mut self, samples: &[[f32; 2]]) {
+        let samples = samples
+            .as_flattened()
+            .iter()
+            .map(|sample| sample * self.volume)
+            .collect();
+        // the render loop is behind or gone, so just drop the samples
+        let _ = self.tx.try_send(samples);
+    }
+}
+
+// the APU channel (from 0) a number key stands for
+fn channel_key(scancode: Scancode) -> Option<usize> {
+    match scancode {
+        Scancode::Num1 => Some(0),
+        Scancode::Num2 => Some(1),
+        Scancode::Num3 => Some(2),
+        Scancode::Num4 => Some(3),
+        _ => None,
+    }
+}
+
+// the layer a function key hides
+fn layer_key(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::F8 => Some(Layers::BG),
+        Scancode::F9 => Some(Layers::WINDOW),
+        Scancode::F10 => Some(Layers::OBJ),
+        _ => None,
+    }
+}
+
+// toggles a channel, or with `solo` mutes all the others (and soloing it again brings them
+// back)
+fn mute_channel(muted: &AtomicU8, channel: usize, solo: bool) {
+    let bit = 1 << channel;
+    let others = !bit & 0x0F;
+    let _ = muted.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |muted| {
+        Some(match solo {
+            true if muted == others => 0,
+            true => others,
+            false => muted ^ bit,
+        })
+    });
+}
+
+// the muted channels by number, e.g. `1 3`
+fn channel_list(muted: u8) -> String {
+    (0..4)
+        .filter(|channel| (muted & (1 << channel)) != 0)
+        .map(|channel| (channel + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// everything that decides whether and when an interrupt gets serviced
+fn print_irq_status(emu: &mut Emu<Cart<'_>, Ppu, Input>) {
+    let mut ie = [0];
+    let mut iflags = [0];
+    emu.read_range(Port::IE, &mut ie);
+    emu.read_range(Port::IF, &mut iflags);
+    let (ie, iflags) = (ie[0], iflags[0]);
+    println!("IME={} IE={ie:02X} IF={iflags:02X}", emu.cpu().ime() as u8);
+    println!("        IE IF");
+    for (i, name) in ["VBlank", "STAT", "timer", "serial", "joypad"]
+        .iter()
+        .enumerate()
+    {
+        let enabled = (ie >> i) & 1;
+        let requested = (iflags >> i) & 1;
+        let pending = if (enabled & requested) != 0 {
+            " pending"
+        } else {
+            ""
+        };
+        println!("{name:<7} {enabled:>2} {requested:>2}{pending}");
+    }
+
+    let mut dma = [0];
+    emu.read_range(Port::DMA, &mut dma);
+    let ppu = emu.ppu();
+    let mode = ["HBlank", "VBlank", "OAM", "drawing"][ppu.mode() as usize];
+    println!(
+        "PPU: mode {} ({mode}) LY={} dot={}",
+        ppu.mode(),
+        ppu.ly(),
+        ppu.dot()
+    );
+    match ppu.dma_remaining() {
+        0 => println!("DMA: idle"),
+        remaining => println!("DMA: ${:02X}00 {}/160 bytes", dma[0], 160 - remaining),
+    }
+
+    let mut timer = [0; 4];
+    emu.read_range(Port::DIV, &mut timer);
+    let [div, tima, tma, tac] = timer;
+    println!(
+        "timer: DIV={div:02X} TIMA={tima:02X} TMA={tma:02X} {}",
+        describe_port(Port::TAC, tac).unwrap()
+    );
+}
+
+// `LCDC: display on, window map $9800, ...`
+fn describe_port(addr: u16, value: u8) -> Option<String> {
+    let info = Port::info(addr)?;
+    let fields = info.describe(value);
+    if fields.is_empty() {
+        Some(info.name.to_string())
+    } else {
+        Some(format!("{}: {fields}", info.name))
+    }
+}
+
+// `A=3F` or `HL=C000`
+fn assign_register(cpu: &mut Cpu, assign: &str) -> bool {
+    let Some((name, value)) = assign.split_once('=') else {
+        return false;
+    };
+    let Ok(value) = u16::from_str_radix(value, 16) else {
+        return false;
+    };
+    if let Ok(reg) = name.parse::<WideRegister>() {
+        cpu.set_wide_register(reg, value);
+        return true;
+    }
+    match (name.parse::<Register>(), u8::try_from(value)) {
+        (Ok(reg), Ok(value)) => {
+            cpu.set_register(reg, value);
+            true
+        }
+        _ => false,
+    }
+}
+
+pub enum Buttons {}
+
+impl Buttons {
+    const RIGHT: u8 = 0x01;
+    const LEFT: u8 = 0x02;
+    const UP: u8 = 0x04;
+    const DOWN: u8 = 0x08;
+    const A: u8 = 0x10;
+    const B: u8 = 0x20;
+    const SELECT: u8 = 0x40;
+    const START: u8 = 0x80;
+
+    const NAMES: [(&'static str, u8); 8] = [
+        ("RIGHT", Self::RIGHT),
+        ("LEFT", Self::LEFT),
+        ("UP", Self::UP),
+        ("DOWN", Self::DOWN),
+        ("A", Self::A),
+        ("B", Self::B),
+        ("SELECT", Self::SELECT),
+        ("START", Self::START),
+    ];
+
+    /// `A`, or several at once like `A+B`, any case.
+    pub fn parse(names: &str) -> Option<u8> {
+        names.split('+').try_fold(0, |buttons, name| {
+            Self::NAMES
+                .iter()
+                .find(|(other, _)| other.eq_ignore_ascii_case(name))
+                .map(|(_, button)| buttons | button)
+        })
+    }
+
+    /// `START A`, or `none`.
+    pub fn describe(buttons: u8) -> String {
+        if buttons == 0 {
+            return "none".to_string();
+        }
+        Self::NAMES
+            .iter()
+            .filter(|(_, button)| (buttons & button) != 0)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn from_keyboard(keyboard: &KeyboardState) -> u8 {
+        let mut buttons = 0;
+        for (scancode, button) in [
+            (Scancode::Right, Self::RIGHT),
+            (Scancode::Left, Self::LEFT),
+            (Scancode::Up, Self::UP),
+            (Scancode::Down, Self::DOWN),
+            (Scancode::X, Self::A),
+            (Scancode::Z, Self::B),
+            (Scancode::RShift, Self::SELECT),
+            (Scancode::Return, Self::START),
+        ] {
+            if keyboard.is_scancode_pressed(scancode) {
+                buttons |= button;
+            }
+        }
+        buttons
+    }
+}
+
+pub struct Input {
+    buttons: Arc<AtomicU8>,
+    p1: u8,
+    // with --deterministic, the buttons as they were at the start of the frame
+    latched: Option<u8>,
+    // what the debugger holds down on top of the keyboard, until released
+    held: u8,
+    // frames left on each button the debugger pressed, in `Buttons` bit order
+    presses: [u32; 8],
+}
+
+impl Input {
+    pub fn new(buttons: Arc<AtomicU8>, deterministic: bool) -> Self {
+        Self {
+            buttons,
+            p1: 0x3F,
+            latched: deterministic.then_some(0),
+            held: 0,
+            presses: [0; 8],
+        }
+    }
+
+    // what the game gets the next time it reads the joypad
+    fn buttons(&self) -> u8 {
+        self.latched
+            .unwrap_or_else(|| self.buttons.load(Ordering::Relaxed) | self.injected())
+    }
+
+    // the debugger's buttons
+    fn injected(&self) -> u8 {
+        self.presses
+            .iter()
+            .enumerate()
+            .filter(|(_, &frames)| frames != 0)
+            .fold(self.held, |buttons, (i, _)| buttons | (1 << i))
+    }
+
+    /// Called once a frame, which is what presses are timed in.
+    pub fn latch(&mut self) {
+        let injected = self.injected();
+        if let Some(latched) = &mut self.latched {
+            *latched = self.buttons.load(Ordering::Relaxed) | injected;
+        }
+        for frames in &mut self.presses {
+            *frames = frames.saturating_sub(1);
+        }
+    }
+
+    /// Holds `buttons` down for the next `frames` frames.
+    pub fn press(&mut self, buttons: u8, frames: u32) {
+        for (i, left) in self.presses.iter_mut().enumerate() {
+            if (buttons & (1 << i)) != 0 {
+                *left = frames;
+            }
+        }
+    }
+
+    /// Holds `buttons` down until they're released.
+    pub fn hold(&mut self, buttons: u8) {
+        self.held |= buttons;
+    }
+
+    /// Lets go of `buttons`, whether they were held or pressed.
+    pub fn release(&mut self, buttons: u8) {
+        self.held &= !buttons;
+        self.press(buttons, 0);
+    }
+
+    // what the debugger has down, with how many frames are left on each press
+    fn describe_injected(&self) -> String {
+        let mut text = format!("held: {}", Buttons::describe(self.held));
+        for (name, button) in Buttons::NAMES {
+            let frames = self.presses[button.trailing_zeros() as usize];
+            if frames != 0 {
+                text.push_str(&format!(", {name} for {frames} more frames"));
+            }
+        }
+        text
+    }
+}
+
+impl<B: Bus> BusDevice<B> for Input {
+    fn reset(&mut self, _bus: &mut B) {
+        self.p1 = 0x3F;
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            Port::P1 => self.p1,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            Port::P1 => {
+                let buttons = self.buttons();
+                if (value & 0x30) == 0x20 {
+                    self.p1 |= 0x0F;
+                    if (buttons & Buttons::DOWN) != 0 {
+                        self.p1 &= 0x27;
+                    }
+                    if (buttons & Buttons::UP) != 0 {
+                        self.p1 &= 0x2B;
+                    }
+                    if (buttons & Buttons::LEFT) != 0 {
+                        self.p1 &= 0x2D;
+                    }
+                    if (buttons & Buttons::RIGHT) != 0 {
+                        self.p1 &= 0x2E;
+                    }
+                    return;
+                }
+                if (value & 0x30) == 0x10 {
+                    self.p1 |= 0x0F;
+                    if (buttons & Buttons::START) != 0 {
+                        self.p1 &= 0x17;
+                    }
+                    if (buttons & Buttons::SELECT) != 0 {
+                        self.p1 &= 0x1B;
+                    }
+                    if (buttons & Buttons::B) != 0 {
+                        self.p1 &= 0x1D;
+                    }
+                    if (buttons & Buttons::A) != 0 {
+                        self.p1 &= 0x1E;
+                    }
+                    return;
+                }
+                self.p1 |= 0x3F;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, _bus: &mut B) -> usize {
+        0
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) {
+        (self.p1, self.latched).hash(&mut state);
+    }
+
+    // the buttons themselves come from the keyboard, whatever they were then
+    fn snapshot_state(&mut self, state: &mut State<'_>) {
+        self.p1.snapshot(state);
+        let mut latched = self.latched.unwrap_or(0);
+        latched.snapshot(state);
+        if let Some(buttons) = &mut self.latched {
+            *buttons = latched;
+        }
+    }
+}
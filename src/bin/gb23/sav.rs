@@ -0,0 +1,112 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use gb23::emu::mbc::{
+    header::Header,
+    sav::{Rtc, Save},
+};
+
+use crate::read_rom;
+
+#[derive(Args)]
+pub struct SavConvertArgs {
+    /// Save file to convert, from another emulator or an older gb23
+    input: PathBuf,
+
+    /// Where to write the converted save
+    output: PathBuf,
+
+    /// ROM the save belongs to, which decides how much RAM it has and whether there's a clock
+    #[arg(long, conflicts_with = "size", required_unless_present = "size")]
+    rom: Option<PathBuf>,
+
+    /// Bytes of RAM to keep, for when the ROM isn't at hand
+    #[arg(long)]
+    size: Option<usize>,
+
+    /// Keep (or add) the MBC3 clock footer, along with `--size`
+    #[arg(long, requires = "size")]
+    rtc: bool,
+}
+
+/// Rewrites a save in the layout `gb23 run` and most other emulators use: exactly as much
+/// RAM as the cart has, then a 48-byte clock footer if it has a clock.
+pub fn sav_convert(args: SavConvertArgs) -> Result<(), String> {
+    let (size, rtc) = match &args.rom {
+        Some(path) => {
+            let rom = read_rom(path)?;
+            let header = Header::parse(&rom)
+                .ok_or_else(|| format!("ROM is too small to have a header: {} bytes", rom.len()))?;
+            (header.save_size(), header.rtc())
+        }
+        None => (args.size.unwrap(), args.rtc),
+    };
+    let data = fs::read(&args.input)
+        .map_err(|e| format!("failed to read save {}: {e}", args.input.display()))?;
+    let mut save = Save::parse(&data);
+    tracing::info!(
+        "read {} bytes of RAM{}",
+        save.sram.len(),
+        if save.rtc.is_some() {
+            " and a clock"
+        } else {
+            ""
+        }
+    );
+    if save.sram.len() != size {
+        tracing::info!("resizing RAM to {size} bytes");
+        save.resize(size);
+    }
+    save.rtc = match (rtc, save.rtc) {
+        (true, None) => Some(Rtc::new()),
+        (true, rtc) => rtc,
+        (false, _) => None,
+    };
+    write(&args.output, &save)
+}
+
+/// Loads the save for `header`'s cart into the front of `sram`, returning its clock. A
+/// save that's the wrong size, as some other emulators write them, is cut down or padded
+/// out. Nothing's loaded if there's no save yet.
+pub fn load(path: &Path, header: &Header, sram: &mut [u8]) -> Result<Option<Rtc>, String> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(header.rtc().then(Rtc::new));
+        }
+        Err(e) => return Err(format!("failed to read save {}: {e}", path.display())),
+    };
+    let mut save = Save::parse(&data);
+    let size = header.save_size();
+    if save.sram.len() != size {
+        tracing::warn!(
+            "save {} has {} bytes of RAM but the cart has {size}, resizing it",
+            path.display(),
+            save.sram.len()
+        );
+        save.resize(size);
+    }
+    sram[..size].copy_from_slice(&save.sram);
+    tracing::info!("loaded save {}", path.display());
+    Ok(header.rtc().then(|| save.rtc.unwrap_or_default()))
+}
+
+/// Writes the save for `header`'s cart from the front of `sram`.
+pub fn store(path: &Path, header: &Header, sram: &[u8], rtc: Option<Rtc>) -> Result<(), String> {
+    let save = Save {
+        sram: sram[..header.save_size()].to_vec(),
+        rtc: header.rtc().then(|| rtc.unwrap_or_default()),
+    };
+    write(path, &save)
+}
+
+// through a temporary file, so a crash halfway through can't eat the old save
+fn write(path: &Path, save: &Save) -> Result<(), String> {
+    let temp = path.with_extension("sav.tmp");
+    fs::write(&temp, save.to_bytes())
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("failed to write save {}: {e}", path.display()))
+}
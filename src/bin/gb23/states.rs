@@ -0,0 +1,205 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicU8, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use sdl2::keyboard::Scancode;
+
+use crate::sha1;
+
+pub const SLOTS: usize = 10;
+
+const EXIT_STATE: &str = "exit.state";
+
+// how long a notice stays on screen
+const NOTICE_TIME: Duration = Duration::from_secs(2);
+
+/// What the window asks the emulator thread to do with the selected slot.
+pub enum Request {}
+
+impl Request {
+    pub const NONE: u8 = 0;
+    pub const SAVE: u8 = 1;
+    pub const LOAD: u8 = 2;
+    // just say what's in it
+    pub const SHOW: u8 = 3;
+}
+
+/// The slot picked in the window, what to do with it next, and a line to show for a bit once
+/// it's done. Requests are picked up between frames.
+pub struct SlotControl {
+    pub slot: AtomicU8,
+    pub request: AtomicU8,
+    notice: Mutex<Option<(String, Instant)>>,
+}
+
+impl SlotControl {
+    pub fn new() -> Self {
+        Self {
+            slot: AtomicU8::new(0),
+            request: AtomicU8::new(Request::NONE),
+            notice: Mutex::new(None),
+        }
+    }
+
+    /// Shows `text` over the picture for a couple of seconds.
+    pub fn notify(&self, text: String) {
+        *self.notice.lock().unwrap() = Some((text, Instant::now()));
+    }
+
+    /// The notice to show, as long as it's still fresh.
+    pub fn notice(&self) -> Option<String> {
+        self.notice
+            .lock()
+            .unwrap()
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < NOTICE_TIME)
+            .map(|(text, _)| text.clone())
+    }
+}
+
+/// The save state slots for one ROM, in a directory of their own named for its SHA-1, under
+/// `$XDG_DATA_HOME/gb23/states` (falling back to `~/.local/share`).
+pub struct Slots {
+    dir: Option<PathBuf>,
+}
+
+impl Slots {
+    pub fn new(rom: &[u8]) -> Self {
+        let dir = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+            .map(|dir| {
+                dir.join("gb23")
+                    .join("states")
+                    .join(sha1::hex(&sha1::sha1(rom)))
+            });
+        Self { dir }
+    }
+
+    fn path(&self, slot: usize) -> Result<PathBuf, String> {
+        self.file(&format!("slot{slot}.state"))
+    }
+
+    fn file(&self, name: &str) -> Result<PathBuf, String> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(name))
+            .ok_or_else(|| "nowhere to keep save states, HOME isn't set".to_string())
+    }
+
+    pub fn save(&self, slot: usize, state: &[u8]) -> Result<(), String> {
+        write(&self.path(slot)?, state)
+    }
+
+    pub fn load(&self, slot: usize) -> Result<Vec<u8>, String> {
+        let path = self.path(slot)?;
+        fs::read(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))
+    }
+
+    /// Keeps the state the machine quit in, apart from the numbered slots.
+    pub fn save_exit(&self, state: &[u8]) -> Result<(), String> {
+        write(&self.file(EXIT_STATE)?, state)
+    }
+
+    /// The state the machine last quit in, and when, if there is one.
+    pub fn exit_state(&self) -> Option<(Vec<u8>, SystemTime)> {
+        let path = self.file(EXIT_STATE).ok()?;
+        let time = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+        Some((fs::read(&path).ok()?, time))
+    }
+
+    /// When `slot` was last saved to, if it ever was.
+    pub fn saved_at(&self, slot: usize) -> Option<SystemTime> {
+        fs::metadata(self.path(slot).ok()?)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// `SLOT 3 2026-10-16 09:41`, or `SLOT 3 EMPTY`, in capitals for the overlay's font.
+    pub fn describe(&self, slot: usize) -> String {
+        match self.saved_at(slot) {
+            Some(time) => format!("SLOT {slot} {}", timestamp(time)),
+            None => format!("SLOT {slot} EMPTY"),
+        }
+    }
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC, there's nothing to look the local time zone up with.
+pub fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // days to a civil date, after Howard Hinnant's `civil_from_days`
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as u64;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60
+    )
+}
+
+/// The slot a number key (with Ctrl held) picks.
+pub fn slot_key(scancode: Scancode) -> Option<usize> {
+    [
+        Scancode::Num0,
+        Scancode::Num1,
+        Scancode::Num2,
+        Scancode::Num3,
+        Scancode::Num4,
+        Scancode::Num5,
+        Scancode::Num6,
+        Scancode::Num7,
+        Scancode::Num8,
+        Scancode::Num9,
+    ]
+    .iter()
+    .position(|&key| key == scancode)
+}
+
+// through a temporary file, so a crash halfway through can't eat the state that was there
+fn write(path: &Path, state: &[u8]) -> Result<(), String> {
+    let temp = path.with_extension("state.tmp");
+    fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| fs::write(&temp, state))
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))
+}
+
+/// Asks whether to pick up from the state saved at `saved`. Without a terminal to ask on, the
+/// answer is no.
+pub fn ask_resume(saved: SystemTime) -> Result<bool, String> {
+    let mut stdin = io::stdin().lock();
+    if !stdin.is_terminal() {
+        return Ok(false);
+    }
+    loop {
+        print!("resume from where you quit at {}? [Y/n] ", timestamp(saved));
+        io::stdout()
+            .flush()
+            .map_err(|e| format!("failed to write prompt: {e}"))?;
+        let mut line = String::new();
+        let read = stdin
+            .read_line(&mut line)
+            .map_err(|e| format!("could not read line: {e}"))?;
+        if read == 0 {
+            return Ok(false);
+        }
+        match line.trim().to_ascii_lowercase().as_str() {
+            "" | "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("?"),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::{collections::HashMap, fs, path::Path};
+
+/// Labels from an RGBDS-style `.sym` file, one `BB:AAAA name` per line.
+#[derive(Default)]
+pub struct Symbols {
+    labels: HashMap<String, (u8, u16)>,
+}
+
+impl Symbols {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read symbol file {}: {e}", path.display()))?;
+        let mut labels = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = || format!("{}:{}: expected `BB:AAAA name`", path.display(), i + 1);
+            let (location, name) = line.split_once(char::is_whitespace).ok_or_else(err)?;
+            let (bank, addr) = location.split_once(':').ok_or_else(err)?;
+            let bank = u8::from_str_radix(bank, 16).map_err(|_| err())?;
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| err())?;
+            labels.insert(name.trim().to_string(), (bank, addr));
+        }
+        Ok(Self { labels })
+    }
+
+    /// Labels as the assembler hands them out, bank, address and name.
+    pub fn from_labels(labels: impl IntoIterator<Item = (u16, u16, String)>) -> Self {
+        let labels = labels
+            .into_iter()
+            .map(|(bank, addr, name)| (name, (bank as u8, addr)))
+            .collect();
+        Self { labels }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.labels.keys().map(String::as_str)
+    }
+
+    /// The bank and address of a label.
+    pub fn get(&self, name: &str) -> Option<(u8, u16)> {
+        self.labels.get(name).copied()
+    }
+}
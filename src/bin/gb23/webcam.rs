@@ -0,0 +1,66 @@
+use std::{
+    io::Read,
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+};
+
+use gb23::emu::mbc::camera;
+
+/// One grayscale picture the size of the Pocket Camera's sensor.
+pub type Picture = Box<[u8; camera::WIDTH * camera::HEIGHT]>;
+
+/// A webcam captured through `ffmpeg`, which also scales and grays its frames for the sensor.
+pub struct Webcam {
+    ffmpeg: Child,
+    frames: Mutex<Receiver<Picture>>,
+}
+
+impl Webcam {
+    /// Starts capturing from a V4L2 device, e.g. `/dev/video0`.
+    pub fn open(device: &Path) -> Result<Self, String> {
+        let scale = format!("scale={}:{}", camera::WIDTH, camera::HEIGHT);
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-loglevel", "error", "-f", "v4l2", "-i"])
+            .arg(device)
+            .args(["-vf", &scale, "-pix_fmt", "gray", "-f", "rawvideo", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to start ffmpeg for {}: {e}", device.display()))?;
+        let mut stdout = ffmpeg.stdout.take().unwrap();
+        // a frame the emulator hasn't taken yet stays put and newer ones are dropped, so what
+        // the sensor sees is never more than a frame behind
+        let (tx, rx) = mpsc::sync_channel(1);
+        thread::spawn(move || loop {
+            let mut picture: Picture = Box::new([0; camera::WIDTH * camera::HEIGHT]);
+            // ffmpeg says why on stderr, and it's also how we hear it was killed
+            if stdout.read_exact(&mut picture[..]).is_err() {
+                return;
+            }
+            if let Err(mpsc::TrySendError::Disconnected(_)) = tx.try_send(picture) {
+                return;
+            }
+        });
+        Ok(Self {
+            ffmpeg,
+            frames: Mutex::new(rx),
+        })
+    }
+
+    /// The newest frame since the last call, if one has come in.
+    pub fn poll(&self) -> Option<Picture> {
+        self.frames.lock().unwrap().try_iter().last()
+    }
+}
+
+impl Drop for Webcam {
+    fn drop(&mut self) {
+        let _ = self.ffmpeg.kill();
+        let _ = self.ffmpeg.wait();
+    }
+}
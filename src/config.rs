@@ -0,0 +1,290 @@
+use std::{
+    env,
+    fmt::{self, Display, Formatter},
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Dmg,
+    Sgb,
+    Cgb,
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(Self::Dmg),
+            "sgb" => Ok(Self::Sgb),
+            "cgb" => Ok(Self::Cgb),
+            _ => Err(format!(
+                "unknown model `{s}` (expected `dmg`, `sgb` or `cgb`)"
+            )),
+        }
+    }
+}
+
+impl Display for Model {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dmg => write!(f, "dmg"),
+            Self::Sgb => write!(f, "sgb"),
+            Self::Cgb => write!(f, "cgb"),
+        }
+    }
+}
+
+/// CGB CPU revisions that differ in ways test ROMs can observe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revision {
+    Cgb0,
+    CgbE,
+}
+
+impl FromStr for Revision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "0" | "cgb0" => Ok(Self::Cgb0),
+            "e" | "cgbe" => Ok(Self::CgbE),
+            _ => Err(format!(
+                "unknown revision `{s}` (expected `cgb0` or `cgbe`)"
+            )),
+        }
+    }
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cgb0 => write!(f, "cgb0"),
+            Self::CgbE => write!(f, "cgbe"),
+        }
+    }
+}
+
+/// Whose registers to start a cart with when the boot ROM is skipped. Every model's boot
+/// ROM leaves its own fingerprint behind, and games that care which model they're on
+/// read it from A (and B, and F) rather than poking at the hardware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootProfile {
+    Dmg0,
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb,
+}
+
+impl BootProfile {
+    /// What `model` leaves behind with its usual boot ROM.
+    pub fn of(model: Model) -> Self {
+        match model {
+            Model::Dmg => Self::Dmg,
+            Model::Sgb => Self::Sgb,
+            Model::Cgb => Self::Cgb,
+        }
+    }
+}
+
+impl FromStr for BootProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg0" => Ok(Self::Dmg0),
+            "dmg" => Ok(Self::Dmg),
+            "mgb" => Ok(Self::Mgb),
+            "sgb" => Ok(Self::Sgb),
+            "sgb2" => Ok(Self::Sgb2),
+            "cgb" => Ok(Self::Cgb),
+            "agb" => Ok(Self::Agb),
+            _ => Err(format!(
+                "unknown boot profile `{s}` (expected `dmg0`, `dmg`, `mgb`, `sgb`, `sgb2`, `cgb` or `agb`)"
+            )),
+        }
+    }
+}
+
+impl Display for BootProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dmg0 => write!(f, "dmg0"),
+            Self::Dmg => write!(f, "dmg"),
+            Self::Mgb => write!(f, "mgb"),
+            Self::Sgb => write!(f, "sgb"),
+            Self::Sgb2 => write!(f, "sgb2"),
+            Self::Cgb => write!(f, "cgb"),
+            Self::Agb => write!(f, "agb"),
+        }
+    }
+}
+
+/// Emulator and frontend settings, stored on disk as `key = value` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub model: Model,
+    /// Only matters when `model` is `cgb`
+    pub revision: Revision,
+    /// RGBA colors for DMG shades 0 (lightest) through 3 (darkest)
+    pub palette: [u32; 4],
+    pub scale: u32,
+    /// How much of the last frame lingers on screen, like a DMG LCD's slow pixels: 0 for
+    /// none, up to just under 1
+    pub ghosting: f32,
+    /// Emulation speed relative to real hardware, or 0 to run unthrottled
+    pub speed: f64,
+    pub audio: bool,
+    pub volume: f32,
+    pub sample_rate: u32,
+    pub boot: Option<PathBuf>,
+    /// Whose registers to start with when there's no `boot` ROM, or the model's own
+    pub boot_profile: Option<BootProfile>,
+    /// Decides the garbage memory powers on with, so runs can be reproduced exactly
+    pub seed: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            model: Model::Dmg,
+            revision: Revision::CgbE,
+            palette: [0xFFFFFFFF, 0xAAAAAAFF, 0x555555FF, 0x000000FF],
+            scale: 8,
+            ghosting: 0.0,
+            speed: 1.0,
+            audio: true,
+            volume: 0.1,
+            sample_rate: 22050,
+            boot: None,
+            boot_profile: None,
+            seed: 0,
+        }
+    }
+}
+
+impl Settings {
+    /// `$XDG_CONFIG_HOME/gb23/settings`, falling back to `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(dir.join("gb23").join("settings"))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        text.parse()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write!(File::create(path)?, "{self}")
+    }
+}
+
+impl FromStr for Settings {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut settings = Self::default();
+        for (i, line) in s.lines().enumerate() {
+            let err =
+                |msg: String| io::Error::new(ErrorKind::InvalidData, format!("{}: {msg}", i + 1));
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| err(format!("expected `key = value`, found `{line}`")))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = |e: &dyn Display| err(format!("invalid value for `{key}`: {e}"));
+            match key {
+                "model" => settings.model = value.parse().map_err(|e| invalid(&e))?,
+                "revision" => settings.revision = value.parse().map_err(|e| invalid(&e))?,
+                "palette" => {
+                    let colors = value
+                        .split_whitespace()
+                        .map(|color| u32::from_str_radix(color, 16))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| invalid(&e))?;
+                    settings.palette = colors
+                        .try_into()
+                        .map_err(|_| invalid(&"expected 4 hex colors"))?;
+                }
+                "scale" => {
+                    let scale: u32 = value.parse().map_err(|e| invalid(&e))?;
+                    if scale == 0 {
+                        return Err(invalid(&"expected 1 or more"));
+                    }
+                    settings.scale = scale;
+                }
+                "ghosting" => {
+                    let ghosting: f32 = value.parse().map_err(|e| invalid(&e))?;
+                    if !(0.0..1.0).contains(&ghosting) {
+                        return Err(invalid(&"expected 0 up to 1"));
+                    }
+                    settings.ghosting = ghosting;
+                }
+                "speed" => {
+                    let speed: f64 = value.parse().map_err(|e| invalid(&e))?;
+                    // anything slower would have frames take longer than time can count up to
+                    if (speed != 0.0) && !(speed.is_finite() && (speed >= 0.01)) {
+                        return Err(invalid(&"expected 0 (unthrottled), or 0.01 and up"));
+                    }
+                    settings.speed = speed;
+                }
+                "audio" => settings.audio = value.parse().map_err(|e| invalid(&e))?,
+                "volume" => settings.volume = value.parse().map_err(|e| invalid(&e))?,
+                "sample_rate" => settings.sample_rate = value.parse().map_err(|e| invalid(&e))?,
+                "boot" => settings.boot = (!value.is_empty()).then(|| PathBuf::from(value)),
+                "boot_profile" => {
+                    settings.boot_profile = (!value.is_empty())
+                        .then(|| value.parse())
+                        .transpose()
+                        .map_err(|e| invalid(&e))?
+                }
+                "seed" => settings.seed = value.parse().map_err(|e| invalid(&e))?,
+                _ => return Err(err(format!("unknown setting `{key}`"))),
+            }
+        }
+        Ok(settings)
+    }
+}
+
+impl Display for Settings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let [c0, c1, c2, c3] = self.palette;
+        writeln!(f, "model = {}", self.model)?;
+        writeln!(f, "revision = {}", self.revision)?;
+        writeln!(f, "palette = {c0:08X} {c1:08X} {c2:08X} {c3:08X}")?;
+        writeln!(f, "scale = {}", self.scale)?;
+        writeln!(f, "ghosting = {}", self.ghosting)?;
+        writeln!(f, "speed = {}", self.speed)?;
+        writeln!(f, "audio = {}", self.audio)?;
+        writeln!(f, "volume = {}", self.volume)?;
+        writeln!(f, "sample_rate = {}", self.sample_rate)?;
+        match &self.boot {
+            Some(boot) => writeln!(f, "boot = {}", boot.display())?,
+            None => writeln!(f, "boot =")?,
+        }
+        match &self.boot_profile {
+            Some(profile) => writeln!(f, "boot_profile = {profile}")?,
+            None => writeln!(f, "boot_profile =")?,
+        }
+        writeln!(f, "seed = {}", self.seed)
+    }
+}
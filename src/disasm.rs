@@ -0,0 +1,165 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    sync::OnceLock,
+};
+
+use crate::emu::cpu::OPCODES;
+
+/// Where execution can go after an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Next,
+    Jump(u16),
+    Branch(u16),
+    Call(u16),
+    // ret, reti, jp hl and illegal opcodes never fall through
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: [u8; 3],
+    pub len: usize,
+    pub flow: Flow,
+    template: &'static str,
+}
+
+const REGS: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+
+#[rustfmt::skip]
+const CB_TEMPLATES: [&str; 64] = [
+    "rlc b", "rlc c", "rlc d", "rlc e", "rlc h", "rlc l", "rlc [hl]", "rlc a",
+    "rrc b", "rrc c", "rrc d", "rrc e", "rrc h", "rrc l", "rrc [hl]", "rrc a",
+    "rl b", "rl c", "rl d", "rl e", "rl h", "rl l", "rl [hl]", "rl a",
+    "rr b", "rr c", "rr d", "rr e", "rr h", "rr l", "rr [hl]", "rr a",
+    "sla b", "sla c", "sla d", "sla e", "sla h", "sla l", "sla [hl]", "sla a",
+    "sra b", "sra c", "sra d", "sra e", "sra h", "sra l", "sra [hl]", "sra a",
+    "swap b", "swap c", "swap d", "swap e", "swap h", "swap l", "swap [hl]", "swap a",
+    "srl b", "srl c", "srl d", "srl e", "srl h", "srl l", "srl [hl]", "srl a",
+];
+
+fn cb_text(cb: u8) -> String {
+    let reg = REGS[(cb & 0x07) as usize];
+    let bit = (cb >> 3) & 0x07;
+    match cb >> 6 {
+        0 => CB_TEMPLATES[cb as usize].into(),
+        1 => format!("bit {bit}, {reg}"),
+        2 => format!("res {bit}, {reg}"),
+        _ => format!("set {bit}, {reg}"),
+    }
+}
+
+// every template in OPCODES and every CB-prefixed instruction, the latter as $CBxx, built the
+// first time the assembler asks
+fn encodings() -> &'static HashMap<String, u16> {
+    static ENCODINGS: OnceLock<HashMap<String, u16>> = OnceLock::new();
+    ENCODINGS.get_or_init(|| {
+        let ops = (0..=0xFF)
+            .filter(|&opcode| !OPCODES[opcode as usize].template.is_empty())
+            .map(|opcode| (OPCODES[opcode as usize].template.to_string(), opcode));
+        let cbs = (0..=0xFF).map(|cb| (cb_text(cb as u8), 0xCB00 | cb));
+        ops.chain(cbs).collect()
+    })
+}
+
+/// The opcode for a template like `ld a, {n8}`, the reverse of what `decode` uses to format.
+pub fn opcode(template: &str) -> Option<u8> {
+    encodings()
+        .get(template)
+        .filter(|&&code| code <= 0xFF)
+        .map(|&code| code as u8)
+}
+
+/// The byte after the $CB prefix for an instruction like `bit 7, h`.
+pub fn cb_suffix(text: &str) -> Option<u8> {
+    encodings()
+        .get(text)
+        .filter(|&&code| code > 0xFF)
+        .map(|&code| code as u8)
+}
+
+/// Decodes the instruction at the start of `bytes`, which was loaded from `addr`.
+/// Operand bytes past the end of the slice read as zero.
+pub fn decode(bytes: &[u8], addr: u16) -> Instruction {
+    let byte = |i: usize| bytes.get(i).copied().unwrap_or(0);
+    let opcode = byte(0);
+    let template = OPCODES[opcode as usize].template;
+    let len = OPCODES[opcode as usize].len as usize;
+    let imm16 = u16::from_le_bytes([byte(1), byte(2)]);
+    let target = addr.wrapping_add(2).wrapping_add(byte(1) as i8 as u16);
+    let flow = match opcode {
+        0x18 => Flow::Jump(target),
+        0x20 | 0x28 | 0x30 | 0x38 => Flow::Branch(target),
+        0xC3 => Flow::Jump(imm16),
+        0xC2 | 0xCA | 0xD2 | 0xDA => Flow::Branch(imm16),
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => Flow::Call(imm16),
+        0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => Flow::Call((opcode & 0x38) as u16),
+        0xC9 | 0xD9 | 0xE9 => Flow::End,
+        _ if template.is_empty() && (opcode != 0xCB) => Flow::End,
+        _ => Flow::Next,
+    };
+    let mut instr_bytes = [0; 3];
+    for (i, b) in instr_bytes.iter_mut().enumerate().take(len) {
+        *b = byte(i);
+    }
+    Instruction {
+        addr,
+        bytes: instr_bytes,
+        len,
+        flow,
+        template,
+    }
+}
+
+impl Instruction {
+    /// The address a jump, branch or call goes to, if it has one.
+    pub fn target(&self) -> Option<u16> {
+        match self.flow {
+            Flow::Jump(addr) | Flow::Branch(addr) | Flow::Call(addr) => Some(addr),
+            Flow::Next | Flow::End => None,
+        }
+    }
+
+    /// Formats the instruction, naming jump targets and absolute addresses with `label` where
+    /// it has a name for them.
+    pub fn format<F: Fn(u16) -> Option<String>>(&self, label: F) -> String {
+        let opcode = self.bytes[0];
+        if opcode == 0xCB {
+            return cb_text(self.bytes[1]);
+        }
+        if self.template.is_empty() {
+            return format!("db ${opcode:02X}");
+        }
+        let imm16 = u16::from_le_bytes([self.bytes[1], self.bytes[2]]);
+        let addr = |addr: u16| label(addr).unwrap_or_else(|| format!("${addr:04X}"));
+        let mut text = self.template.to_string();
+        if text.contains("{n8}") {
+            text = text.replace("{n8}", &format!("${:02X}", self.bytes[1]));
+        } else if text.contains("{a8}") {
+            text = text.replace("{a8}", &format!("$FF{:02X}", self.bytes[1]));
+        } else if text.contains("{e8}") {
+            let e8 = self.bytes[1] as i8;
+            if (e8 < 0) && text.contains("+ {e8}") {
+                text = text.replace("+ {e8}", "- {e8}");
+            } else if e8 < 0 {
+                text = text.replace("{e8}", "-{e8}");
+            }
+            text = text.replace("{e8}", &e8.unsigned_abs().to_string());
+        } else if text.contains("{r8}") {
+            text = text.replace("{r8}", &addr(self.target().unwrap()));
+        } else if text.contains("{n16}") {
+            text = text.replace("{n16}", &format!("${imm16:04X}"));
+        } else if text.contains("{a16}") {
+            text = text.replace("{a16}", &addr(imm16));
+        }
+        text
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(|_| None))
+    }
+}
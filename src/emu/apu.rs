@@ -1 +1,795 @@
-pub struct Apu {}
+use std::hash::{Hash, Hasher};
+
+use super::{
+    audio::AudioSink,
+    bus::Port,
+    state::{Snapshot, State},
+};
+
+const CPU_HZ: usize = 4194304;
+
+// samples are handed over in batches, a dynamic call per sample would add up
+const BATCH: usize = 256;
+
+const DUTIES: [u8; 4] = [0b00000001, 0b10000001, 0b10000111, 0b01111110];
+
+// NRx2 on the square and noise channels
+#[derive(Default, Hash)]
+struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, value: u8) {
+        self.initial = value >> 4;
+        self.increase = (value & 0x08) != 0;
+        self.period = value & 0x07;
+    }
+
+    fn read(&self) -> u8 {
+        (self.initial << 4) | ((self.increase as u8) << 3) | self.period
+    }
+
+    // the upper 5 bits all zero turns the DAC off, and the channel along with it
+    fn dac(&self) -> bool {
+        (self.read() & 0xF8) != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.increase && (self.volume < 15) {
+                self.volume += 1;
+            } else if !self.increase && (self.volume > 0) {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+// what all four channels share: on or off, and the length counter that turns them off
+#[derive(Default, Hash)]
+struct Length {
+    enabled: bool,
+    counter: u16,
+}
+
+impl Length {
+    fn clock(&mut self, on: &mut bool) {
+        if self.enabled && (self.counter > 0) {
+            self.counter -= 1;
+            if self.counter == 0 {
+                *on = false;
+            }
+        }
+    }
+
+    // NRx4, with lengths enabled during a step that doesn't clock them getting an extra
+    // clock right away
+    fn write(&mut self, value: u8, max: u16, extra: bool, on: &mut bool) {
+        let enabling = !self.enabled && ((value & 0x40) != 0);
+        self.enabled = (value & 0x40) != 0;
+        if enabling && extra {
+            self.clock(on);
+        }
+        if (value & 0x80) != 0 && (self.counter == 0) {
+            self.counter = max;
+            if self.enabled && extra {
+                self.counter -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Default, Hash)]
+struct Square {
+    on: bool,
+    length: Length,
+    envelope: Envelope,
+    duty: u8,
+    step: u8,
+    freq: u16,
+    timer: usize,
+    // channel 1 only
+    sweep: Sweep,
+}
+
+#[derive(Default, Hash)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+    enabled: bool,
+    shadow: u16,
+    timer: u8,
+}
+
+impl Square {
+    fn period(&self) -> usize {
+        (2048 - self.freq as usize) * 4
+    }
+
+    fn trigger(&mut self) {
+        self.on = self.envelope.dac();
+        self.timer = self.period();
+        self.envelope.trigger();
+        let sweep = &mut self.sweep;
+        sweep.shadow = self.freq;
+        sweep.timer = if sweep.period == 0 { 8 } else { sweep.period };
+        sweep.enabled = (sweep.period != 0) || (sweep.shift != 0);
+        if sweep.shift != 0 {
+            self.sweep_freq();
+        }
+    }
+
+    // the next frequency, turning the channel off when it overflows
+    fn sweep_freq(&mut self) -> u16 {
+        let delta = self.sweep.shadow >> self.sweep.shift;
+        let freq = if self.sweep.negate {
+            self.sweep.shadow.wrapping_sub(delta)
+        } else {
+            self.sweep.shadow + delta
+        };
+        if freq > 2047 {
+            self.on = false;
+        }
+        freq
+    }
+
+    fn clock_sweep(&mut self) {
+        self.sweep.timer = self.sweep.timer.saturating_sub(1);
+        if self.sweep.timer > 0 {
+            return;
+        }
+        self.sweep.timer = if self.sweep.period == 0 {
+            8
+        } else {
+            self.sweep.period
+        };
+        if self.sweep.enabled && (self.sweep.period != 0) {
+            let freq = self.sweep_freq();
+            if (freq <= 2047) && (self.sweep.shift != 0) {
+                self.freq = freq;
+                self.sweep.shadow = freq;
+                // checked again straight away, but not written back
+                self.sweep_freq();
+            }
+        }
+    }
+
+    fn advance(&mut self, mut cycles: usize) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.step = (self.step + 1) & 0x07;
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        let high = (DUTIES[self.duty as usize] >> (7 - self.step)) & 0x01;
+        if self.on {
+            high * self.envelope.volume
+        } else {
+            0
+        }
+    }
+}
+
+#[derive(Default, Hash)]
+struct Wave {
+    on: bool,
+    dac: bool,
+    length: Length,
+    volume: u8,
+    freq: u16,
+    position: u8,
+    timer: usize,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    fn period(&self) -> usize {
+        (2048 - self.freq as usize) * 2
+    }
+
+    fn trigger(&mut self) {
+        self.on = self.dac;
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    fn advance(&mut self, mut cycles: usize) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0x1F;
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        if !self.on || (self.volume == 0) {
+            return 0;
+        }
+        let byte = self.ram[(self.position / 2) as usize];
+        // high nibble first
+        let sample = if (self.position & 0x01) == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+        sample >> (self.volume - 1)
+    }
+}
+
+#[derive(Default, Hash)]
+struct Noise {
+    on: bool,
+    length: Length,
+    envelope: Envelope,
+    nr43: u8,
+    lfsr: u16,
+    timer: usize,
+}
+
+impl Noise {
+    fn period(&self) -> usize {
+        let divisor = match self.nr43 & 0x07 {
+            0 => 8,
+            r => (r as usize) * 16,
+        };
+        divisor << (self.nr43 >> 4)
+    }
+
+    fn trigger(&mut self) {
+        self.on = self.envelope.dac();
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    fn advance(&mut self, mut cycles: usize) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            let bit = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+            self.lfsr = (self.lfsr >> 1) | (bit << 14);
+            // 7-bit mode
+            if (self.nr43 & 0x08) != 0 {
+                self.lfsr = (self.lfsr & !0x40) | (bit << 6);
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    fn output(&self) -> u8 {
+        if self.on && ((self.lfsr & 0x01) == 0) {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+}
+
+/// What one channel is up to, for debug views.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChannelState {
+    /// Playing, rather than stopped by its length, sweep or DAC.
+    pub on: bool,
+    pub dac: bool,
+    /// The frequency as written to the registers: 11 bits from NRx3 and NRx4, or NR43 as
+    /// is for the noise channel.
+    pub freq: u16,
+    /// The pitch in Hz, or how often the noise channel shifts its LFSR.
+    pub hz: f32,
+    /// The envelope's volume, 0-15, or the wave channel's output level, 0-3.
+    pub volume: u8,
+    /// The duty cycle, 0-3, on the square channels.
+    pub duty: Option<u8>,
+    /// Sweeps until the envelope's next step, and its period, on all but the wave channel.
+    pub envelope: Option<(u8, u8)>,
+    pub length: u16,
+    pub length_enabled: bool,
+}
+
+/// All four channels and the wave RAM, for debug views.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ApuState {
+    pub on: bool,
+    pub channels: [ChannelState; 4],
+    pub wave_ram: [u8; 16],
+}
+
+pub struct Apu {
+    sample_rate: usize,
+    // CPU cycles times the sample rate, so no rounding error creeps in
+    cycles: usize,
+    samples: Vec<[f32; 2]>,
+    // the output capacitors' charge, and how much of it is kept each sample
+    capacitors: [f32; 2],
+    charge_factor: f32,
+    // channels left out of the mix, bit 0 for channel 1
+    muted: u8,
+    cgb: bool,
+    on: bool,
+    // the frame sequencer step to run on the next DIV-APU clock
+    step: u8,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+}
+
+impl Apu {
+    pub fn new(cgb: bool, sample_rate: u32) -> Self {
+        // per CPU cycle, CGB's capacitors drain a good deal quicker than DMG's
+        let per_cycle: f32 = if cgb { 0.998943 } else { 0.999958 };
+        Self {
+            sample_rate: sample_rate as usize,
+            cycles: 0,
+            samples: Vec::with_capacity(BATCH),
+            capacitors: [0.0; 2],
+            charge_factor: per_cycle.powf(CPU_HZ as f32 / sample_rate as f32),
+            muted: 0,
+            cgb,
+            on: false,
+            step: 0,
+            square1: Square::default(),
+            square2: Square::default(),
+            wave: Wave::default(),
+            noise: Noise::default(),
+            nr50: 0,
+            nr51: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cycles = 0;
+        self.samples.clear();
+        let ram = self.wave.ram;
+        *self = Self {
+            samples: std::mem::take(&mut self.samples),
+            muted: self.muted,
+            ..Self::new(self.cgb, self.sample_rate as u32)
+        };
+        // wave RAM powers on with whatever was there
+        self.wave.ram = ram;
+    }
+
+    pub fn state(&self) -> ApuState {
+        let square = |square: &Square| ChannelState {
+            on: square.on,
+            dac: square.envelope.dac(),
+            freq: square.freq,
+            // 8 steps to a cycle of the duty pattern
+            hz: CPU_HZ as f32 / (square.period() * 8) as f32,
+            volume: square.envelope.volume,
+            duty: Some(square.duty),
+            envelope: Some((square.envelope.timer, square.envelope.period)),
+            length: square.length.counter,
+            length_enabled: square.length.enabled,
+        };
+        let (wave, noise) = (&self.wave, &self.noise);
+        ApuState {
+            on: self.on,
+            channels: [
+                square(&self.square1),
+                square(&self.square2),
+                ChannelState {
+                    on: wave.on,
+                    dac: wave.dac,
+                    freq: wave.freq,
+                    // and 32 samples to a cycle of the wave
+                    hz: CPU_HZ as f32 / (wave.period() * 32) as f32,
+                    volume: wave.volume,
+                    duty: None,
+                    envelope: None,
+                    length: wave.length.counter,
+                    length_enabled: wave.length.enabled,
+                },
+                ChannelState {
+                    on: noise.on,
+                    dac: noise.envelope.dac(),
+                    freq: noise.nr43 as u16,
+                    hz: CPU_HZ as f32 / noise.period() as f32,
+                    volume: noise.envelope.volume,
+                    duty: None,
+                    envelope: Some((noise.envelope.timer, noise.envelope.period)),
+                    length: noise.length.counter,
+                    length_enabled: noise.length.enabled,
+                },
+            ],
+            wave_ram: wave.ram,
+        }
+    }
+
+    #[inline]
+    pub fn muted(&self) -> u8 {
+        self.muted
+    }
+
+    #[inline]
+    pub fn set_muted(&mut self, muted: u8) {
+        self.muted = muted & 0x0F;
+    }
+
+    pub fn tick(&mut self, cycles: usize, sink: &mut dyn AudioSink) {
+        if self.on {
+            self.square1.advance(cycles);
+            self.square2.advance(cycles);
+            self.wave.advance(cycles);
+            self.noise.advance(cycles);
+        }
+        self.cycles += cycles * self.sample_rate;
+        while self.cycles >= CPU_HZ {
+            self.cycles -= CPU_HZ;
+            let mixed = self.mix();
+            let sample = self.high_pass(mixed);
+            self.samples.push(sample);
+            if self.samples.len() == BATCH {
+                sink.samples(&self.samples);
+                self.samples.clear();
+            }
+        }
+    }
+
+    /// Runs the next frame sequencer step, on each falling edge of DIV's bit 4 (bit 5 in
+    /// double speed): lengths on even steps, sweep on 2 and 6 and envelopes on 7.
+    pub fn step_sequencer(&mut self) {
+        if !self.on {
+            return;
+        }
+        if (self.step & 0x01) == 0 {
+            self.square1.length.clock(&mut self.square1.on);
+            self.square2.length.clock(&mut self.square2.on);
+            self.wave.length.clock(&mut self.wave.on);
+            self.noise.length.clock(&mut self.noise.on);
+        }
+        if (self.step == 2) || (self.step == 6) {
+            self.square1.clock_sweep();
+        }
+        if self.step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+        self.step = (self.step + 1) & 0x07;
+    }
+
+    // each channel's 0-15 level through its DAC, then panned and scaled by the master volume.
+    // A DAC that's on puts out -1.0 for silence while one that's off puts out nothing, so
+    // switching one clicks, as it does on hardware
+    fn mix(&self) -> [f32; 2] {
+        if !self.on {
+            return [0.0, 0.0];
+        }
+        let dac = |level: u8, on: bool| {
+            if on {
+                (level as f32 / 7.5) - 1.0
+            } else {
+                0.0
+            }
+        };
+        let outputs = [
+            dac(self.square1.output(), self.square1.envelope.dac()),
+            dac(self.square2.output(), self.square2.envelope.dac()),
+            dac(self.wave.output(), self.wave.dac),
+            dac(self.noise.output(), self.noise.envelope.dac()),
+        ];
+        let mut mixed = [0.0; 2];
+        for (i, output) in outputs.iter().enumerate() {
+            if (self.muted & (1 << i)) != 0 {
+                continue;
+            }
+            // NR51's high nibble is the left side
+            if (self.nr51 & (0x10 << i)) != 0 {
+                mixed[0] += output;
+            }
+            if (self.nr51 & (0x01 << i)) != 0 {
+                mixed[1] += output;
+            }
+        }
+        let left = (((self.nr50 >> 4) & 0x07) + 1) as f32 / 8.0;
+        let right = ((self.nr50 & 0x07) + 1) as f32 / 8.0;
+        [mixed[0] * left / 4.0, mixed[1] * right / 4.0]
+    }
+
+    // the capacitors on the way out take away whatever DC offset the DACs leave, but they
+    // only charge while one of the DACs is on
+    fn high_pass(&mut self, mixed: [f32; 2]) -> [f32; 2] {
+        if !self.dacs_on() {
+            return [0.0, 0.0];
+        }
+        let mut out = [0.0; 2];
+        for ((out, capacitor), input) in out.iter_mut().zip(&mut self.capacitors).zip(mixed) {
+            *out = input - *capacitor;
+            *capacitor = input - (*out * self.charge_factor);
+        }
+        out
+    }
+
+    fn dacs_on(&self) -> bool {
+        self.on
+            && (self.square1.envelope.dac()
+                || self.square2.envelope.dac()
+                || self.wave.dac
+                || self.noise.envelope.dac())
+    }
+
+    /// The digital outputs of two channels, one per nibble, as CGB's PCM12 and PCM34 show.
+    pub fn pcm(&self, addr: u16) -> u8 {
+        if addr == Port::PCM12 {
+            (self.square2.output() << 4) | self.square1.output()
+        } else {
+            (self.noise.output() << 4) | self.wave.output()
+        }
+    }
+
+    // a length enabled now gets an extra clock if the sequencer won't clock it next
+    fn extra_length_clock(&self) -> bool {
+        (self.step & 0x01) != 0
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        let (s1, s2, wave, noise) = (&self.square1, &self.square2, &self.wave, &self.noise);
+        match addr {
+            Port::NR10 => (s1.sweep.period << 4) | ((s1.sweep.negate as u8) << 3) | s1.sweep.shift,
+            Port::NR11 => s1.duty << 6,
+            Port::NR12 => s1.envelope.read(),
+            Port::NR14 => (s1.length.enabled as u8) << 6,
+            Port::NR21 => s2.duty << 6,
+            Port::NR22 => s2.envelope.read(),
+            Port::NR24 => (s2.length.enabled as u8) << 6,
+            Port::NR30 => (wave.dac as u8) << 7,
+            Port::NR32 => wave.volume << 5,
+            Port::NR34 => (wave.length.enabled as u8) << 6,
+            Port::NR42 => noise.envelope.read(),
+            Port::NR43 => noise.nr43,
+            Port::NR44 => (noise.length.enabled as u8) << 6,
+            Port::NR50 => self.nr50,
+            Port::NR51 => self.nr51,
+            Port::NR52 => {
+                ((self.on as u8) << 7)
+                    | ((noise.on as u8) << 3)
+                    | ((wave.on as u8) << 2)
+                    | ((s2.on as u8) << 1)
+                    | (s1.on as u8)
+            }
+            0xFF30..=0xFF3F => wave.ram[(addr - Port::WAVE) as usize],
+            // write only
+            _ => 0x00,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if let 0xFF30..=0xFF3F = addr {
+            self.wave.ram[(addr - Port::WAVE) as usize] = value;
+            return;
+        }
+        if addr == Port::NR52 {
+            let on = (value & 0x80) != 0;
+            if self.on && !on {
+                // turning off clears every register, though DMG keeps the lengths
+                let lengths = [
+                    self.square1.length.counter,
+                    self.square2.length.counter,
+                    self.wave.length.counter,
+                    self.noise.length.counter,
+                ];
+                self.reset();
+                if !self.cgb {
+                    self.square1.length.counter = lengths[0];
+                    self.square2.length.counter = lengths[1];
+                    self.wave.length.counter = lengths[2];
+                    self.noise.length.counter = lengths[3];
+                }
+            } else if !self.on && on {
+                self.step = 0;
+            }
+            self.on = on;
+            return;
+        }
+        if !self.on {
+            // DMG still takes lengths while off
+            if !self.cgb {
+                match addr {
+                    Port::NR11 => self.square1.length.counter = 64 - (value & 0x3F) as u16,
+                    Port::NR21 => self.square2.length.counter = 64 - (value & 0x3F) as u16,
+                    Port::NR31 => self.wave.length.counter = 256 - value as u16,
+                    Port::NR41 => self.noise.length.counter = 64 - (value & 0x3F) as u16,
+                    _ => {}
+                }
+            }
+            return;
+        }
+        let extra = self.extra_length_clock();
+        match addr {
+            Port::NR10 => {
+                let sweep = &mut self.square1.sweep;
+                sweep.period = (value >> 4) & 0x07;
+                sweep.negate = (value & 0x08) != 0;
+                sweep.shift = value & 0x07;
+            }
+            Port::NR11 | Port::NR21 => {
+                let square = if addr == Port::NR11 {
+                    &mut self.square1
+                } else {
+                    &mut self.square2
+                };
+                square.duty = value >> 6;
+                square.length.counter = 64 - (value & 0x3F) as u16;
+            }
+            Port::NR12 | Port::NR22 => {
+                let square = if addr == Port::NR12 {
+                    &mut self.square1
+                } else {
+                    &mut self.square2
+                };
+                square.envelope.write(value);
+                if !square.envelope.dac() {
+                    square.on = false;
+                }
+            }
+            Port::NR13 | Port::NR23 => {
+                let square = if addr == Port::NR13 {
+                    &mut self.square1
+                } else {
+                    &mut self.square2
+                };
+                square.freq = (square.freq & 0x700) | value as u16;
+            }
+            Port::NR14 | Port::NR24 => {
+                let square = if addr == Port::NR14 {
+                    &mut self.square1
+                } else {
+                    &mut self.square2
+                };
+                square.freq = (square.freq & 0xFF) | (((value & 0x07) as u16) << 8);
+                square.length.write(value, 64, extra, &mut square.on);
+                if (value & 0x80) != 0 {
+                    square.trigger();
+                }
+            }
+            Port::NR30 => {
+                self.wave.dac = (value & 0x80) != 0;
+                if !self.wave.dac {
+                    self.wave.on = false;
+                }
+            }
+            Port::NR31 => self.wave.length.counter = 256 - value as u16,
+            Port::NR32 => self.wave.volume = (value >> 5) & 0x03,
+            Port::NR33 => self.wave.freq = (self.wave.freq & 0x700) | value as u16,
+            Port::NR34 => {
+                let wave = &mut self.wave;
+                wave.freq = (wave.freq & 0xFF) | (((value & 0x07) as u16) << 8);
+                wave.length.write(value, 256, extra, &mut wave.on);
+                if (value & 0x80) != 0 {
+                    wave.trigger();
+                }
+            }
+            Port::NR41 => self.noise.length.counter = 64 - (value & 0x3F) as u16,
+            Port::NR42 => {
+                self.noise.envelope.write(value);
+                if !self.noise.envelope.dac() {
+                    self.noise.on = false;
+                }
+            }
+            Port::NR43 => self.noise.nr43 = value,
+            Port::NR44 => {
+                let noise = &mut self.noise;
+                noise.length.write(value, 64, extra, &mut noise.on);
+                if (value & 0x80) != 0 {
+                    noise.trigger();
+                }
+            }
+            Port::NR50 => self.nr50 = value,
+            Port::NR51 => self.nr51 = value,
+            _ => {}
+        }
+    }
+}
+
+// everything but the samples on their way out
+impl Hash for Apu {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.on, self.step, self.nr50, self.nr51).hash(state);
+        self.square1.hash(state);
+        self.square2.hash(state);
+        self.wave.hash(state);
+        self.noise.hash(state);
+    }
+}
+
+impl Snapshot for Apu {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.on.snapshot(state);
+        self.step.snapshot(state);
+        self.nr50.snapshot(state);
+        self.nr51.snapshot(state);
+        self.square1.snapshot(state);
+        self.square2.snapshot(state);
+        self.wave.snapshot(state);
+        self.noise.snapshot(state);
+    }
+}
+
+impl Snapshot for Envelope {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.initial.snapshot(state);
+        self.increase.snapshot(state);
+        self.period.snapshot(state);
+        self.volume.snapshot(state);
+        self.timer.snapshot(state);
+    }
+}
+
+impl Snapshot for Length {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.enabled.snapshot(state);
+        self.counter.snapshot(state);
+    }
+}
+
+impl Snapshot for Square {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.on.snapshot(state);
+        self.length.snapshot(state);
+        self.envelope.snapshot(state);
+        self.duty.snapshot(state);
+        self.step.snapshot(state);
+        self.freq.snapshot(state);
+        self.timer.snapshot(state);
+        self.sweep.snapshot(state);
+    }
+}
+
+impl Snapshot for Sweep {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.period.snapshot(state);
+        self.negate.snapshot(state);
+        self.shift.snapshot(state);
+        self.enabled.snapshot(state);
+        self.shadow.snapshot(state);
+        self.timer.snapshot(state);
+    }
+}
+
+impl Snapshot for Wave {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.on.snapshot(state);
+        self.dac.snapshot(state);
+        self.length.snapshot(state);
+        self.volume.snapshot(state);
+        self.freq.snapshot(state);
+        self.position.snapshot(state);
+        self.timer.snapshot(state);
+        self.ram.snapshot(state);
+    }
+}
+
+impl Snapshot for Noise {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.on.snapshot(state);
+        self.length.snapshot(state);
+        self.envelope.snapshot(state);
+        self.nr43.snapshot(state);
+        self.lfsr.snapshot(state);
+        self.timer.snapshot(state);
+    }
+}
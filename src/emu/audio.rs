@@ -0,0 +1,94 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Where the sound goes: the speakers, a file on disk, or nowhere at all for headless runs.
+pub trait AudioSink {
+    /// The next stereo samples (left, right) from the APU, each between -1.0 and 1.0.
+    fn samples(&mut self, samples: &[[f32; 2]]);
+}
+
+/// Throws everything away.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn samples(&mut self, _samples: &[[f32; 2]]) {}
+}
+
+// several sinks listening to the same run, e.g. the speakers while recording
+impl AudioSink for Vec<Box<dyn AudioSink>> {
+    fn samples(&mut self, samples: &[[f32; 2]]) {
+        for sink in self {
+            sink.samples(samples);
+        }
+    }
+}
+
+/// Records everything into a 16-bit stereo WAV file. The sizes in the header are only
+/// filled in once the writer is dropped.
+pub struct WavWriter {
+    out: BufWriter<File>,
+    len: u32,
+    failed: bool,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // PCM, 2 channels
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        // bytes per second, bytes per sample frame, bits per sample
+        out.write_all(&(sample_rate * 4).to_le_bytes())?;
+        out.write_all(&4u16.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            out,
+            len: 0,
+            failed: false,
+        })
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + self.len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.len.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn samples(&mut self, samples: &[[f32; 2]]) {
+        if self.failed {
+            return;
+        }
+        for sample in samples.as_flattened() {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            if let Err(e) = self.out.write_all(&sample.to_le_bytes()) {
+                // most likely the disk is full, which won't get better by trying again
+                tracing::error!("failed to write audio, not recording any more: {e}");
+                self.failed = true;
+                return;
+            }
+            self.len += 2;
+        }
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            tracing::error!("failed to finish WAV file: {e}");
+        }
+    }
+}
@@ -1,3 +1,7 @@
+use std::{any, hash::Hasher};
+
+use super::{error::CoreError, state::State};
+
 pub enum Port {}
 
 impl Port {
@@ -23,6 +27,24 @@ impl Port {
     pub const NR23: u16 = 0xFF18;
     pub const NR24: u16 = 0xFF19;
 
+    pub const NR30: u16 = 0xFF1A;
+    pub const NR31: u16 = 0xFF1B;
+    pub const NR32: u16 = 0xFF1C;
+    pub const NR33: u16 = 0xFF1D;
+    pub const NR34: u16 = 0xFF1E;
+
+    pub const NR41: u16 = 0xFF20;
+    pub const NR42: u16 = 0xFF21;
+    pub const NR43: u16 = 0xFF22;
+    pub const NR44: u16 = 0xFF23;
+
+    pub const NR50: u16 = 0xFF24;
+    pub const NR51: u16 = 0xFF25;
+    pub const NR52: u16 = 0xFF26;
+
+    // 16 bytes of wave RAM, 2 samples each
+    pub const WAVE: u16 = 0xFF30;
+
     pub const LCDC: u16 = 0xFF40;
     pub const STAT: u16 = 0xFF41;
     pub const SCY: u16 = 0xFF42;
@@ -36,6 +58,7 @@ impl Port {
     pub const WY: u16 = 0xFF4A;
     pub const WX: u16 = 0xFF4B;
 
+    pub const KEY0: u16 = 0xFF4C;
     pub const KEY1: u16 = 0xFF4D;
     pub const VBK: u16 = 0xFF4F;
     pub const BOOT: u16 = 0xFF50;
@@ -45,40 +68,352 @@ impl Port {
     pub const HMDA3: u16 = 0xFF53;
     pub const HMDA4: u16 = 0xFF54;
     pub const HMDA5: u16 = 0xFF55;
+    // CGB infrared
+    pub const RP: u16 = 0xFF56;
 
     pub const BCPS: u16 = 0xFF68;
     pub const BCPD: u16 = 0xFF69;
     pub const OCPS: u16 = 0xFF6A;
     pub const OCPD: u16 = 0xFF6B;
+    pub const OPRI: u16 = 0xFF6C;
     pub const SVBK: u16 = 0xFF70;
 
+    // undocumented CGB registers
+    pub const FF72: u16 = 0xFF72;
+    pub const FF73: u16 = 0xFF73;
+    pub const FF74: u16 = 0xFF74;
+    pub const FF75: u16 = 0xFF75;
+    pub const PCM12: u16 = 0xFF76;
+    pub const PCM34: u16 = 0xFF77;
+
     pub const IE: u16 = 0xFFFF;
+
+    /// Every port, for tools that show them by name or decode their bits, and for the bus to
+    /// know which of their bits are there.
+    pub const INFO: &'static [PortInfo] = &[
+        PortInfo::new(
+            Self::P1,
+            "P1",
+            &[
+                (0x20, "buttons", SELECTED),
+                (0x10, "d-pad", SELECTED),
+                (0x0F, "lines", &[]),
+            ],
+        )
+        .masks(0x3F, 0x30),
+        PortInfo::new(Self::SB, "SB", &[]),
+        PortInfo::new(
+            Self::SC,
+            "SC",
+            &[
+                (0x80, "transfer", OFF_ON),
+                (0x02, "speed", &["normal", "fast"]),
+                (0x01, "clock", &["external", "internal"]),
+            ],
+        )
+        .masks(0x83, 0x83),
+        PortInfo::new(Self::DIV, "DIV", &[]),
+        PortInfo::new(Self::TIMA, "TIMA", &[]),
+        PortInfo::new(Self::TMA, "TMA", &[]),
+        PortInfo::new(
+            Self::TAC,
+            "TAC",
+            &[
+                (0x04, "timer", OFF_ON),
+                (0x03, "clock", &["4096Hz", "262144Hz", "65536Hz", "16384Hz"]),
+            ],
+        )
+        .masks(0x07, 0x07),
+        PortInfo::new(Self::IF, "IF", INTERRUPTS).masks(0x1F, 0x1F),
+        PortInfo::new(Self::NR10, "NR10", &[]).masks(0x7F, 0xFF),
+        PortInfo::new(Self::NR11, "NR11", &[]).masks(0xC0, 0xFF),
+        PortInfo::new(Self::NR12, "NR12", &[]),
+        PortInfo::new(Self::NR13, "NR13", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::NR14, "NR14", &[]).masks(0x40, 0xFF),
+        PortInfo::new(Self::NR21, "NR21", &[]).masks(0xC0, 0xFF),
+        PortInfo::new(Self::NR22, "NR22", &[]),
+        PortInfo::new(Self::NR23, "NR23", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::NR24, "NR24", &[]).masks(0x40, 0xFF),
+        PortInfo::new(Self::NR30, "NR30", &[(0x80, "DAC", OFF_ON)]).masks(0x80, 0xFF),
+        PortInfo::new(Self::NR31, "NR31", &[]).masks(0x00, 0xFF),
+        PortInfo::new(
+            Self::NR32,
+            "NR32",
+            &[(0x60, "volume", &["mute", "100%", "50%", "25%"])],
+        )
+        .masks(0x60, 0xFF),
+        PortInfo::new(Self::NR33, "NR33", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::NR34, "NR34", &[]).masks(0x40, 0xFF),
+        PortInfo::new(Self::NR41, "NR41", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::NR42, "NR42", &[]),
+        PortInfo::new(Self::NR43, "NR43", &[]),
+        PortInfo::new(Self::NR44, "NR44", &[]).masks(0x40, 0xFF),
+        PortInfo::new(Self::NR50, "NR50", &[]),
+        PortInfo::new(Self::NR51, "NR51", &[]),
+        PortInfo::new(
+            Self::NR52,
+            "NR52",
+            &[
+                (0x80, "APU", OFF_ON),
+                (0x08, "CH4", OFF_ON),
+                (0x04, "CH3", OFF_ON),
+                (0x02, "CH2", OFF_ON),
+                (0x01, "CH1", OFF_ON),
+            ],
+        )
+        .masks(0x8F, 0x80),
+        PortInfo::new(
+            Self::LCDC,
+            "LCDC",
+            &[
+                (0x80, "display", OFF_ON),
+                (0x40, "window map", &["$9800", "$9C00"]),
+                (0x20, "window", OFF_ON),
+                (0x10, "tiles", &["$8800", "$8000"]),
+                (0x08, "BG map", &["$9800", "$9C00"]),
+                (0x04, "OBJ", &["8x8", "8x16"]),
+                (0x02, "OBJ", OFF_ON),
+                (0x01, "BG", OFF_ON),
+            ],
+        ),
+        PortInfo::new(
+            Self::STAT,
+            "STAT",
+            &[
+                (0x40, "LYC IRQ", OFF_ON),
+                (0x20, "OAM IRQ", OFF_ON),
+                (0x10, "VBlank IRQ", OFF_ON),
+                (0x08, "HBlank IRQ", OFF_ON),
+                (0x04, "LY=LYC", &["no", "yes"]),
+                (0x03, "mode", &["HBlank", "VBlank", "OAM", "drawing"]),
+            ],
+        )
+        .masks(0x7F, 0x78),
+        PortInfo::new(Self::SCY, "SCY", &[]),
+        PortInfo::new(Self::SCX, "SCX", &[]),
+        PortInfo::new(Self::LY, "LY", &[]).masks(0xFF, 0x00),
+        PortInfo::new(Self::LYC, "LYC", &[]),
+        PortInfo::new(Self::DMA, "DMA", &[]),
+        PortInfo::new(Self::BGP, "BGP", SHADES),
+        PortInfo::new(Self::OBP0, "OBP0", SHADES),
+        PortInfo::new(Self::OBP1, "OBP1", SHADES),
+        PortInfo::new(Self::WY, "WY", &[]),
+        PortInfo::new(Self::WX, "WX", &[]),
+        PortInfo::new(Self::KEY0, "KEY0", &[(0x04, "DMG compatibility", OFF_ON)]).masks(0x00, 0x0C),
+        PortInfo::new(
+            Self::KEY1,
+            "KEY1",
+            &[
+                (0x80, "speed", &["normal", "double"]),
+                (0x01, "switch", &["no", "armed"]),
+            ],
+        )
+        .masks(0x81, 0x01),
+        PortInfo::new(Self::VBK, "VBK", &[(0x01, "bank", &[])]).masks(0x01, 0x01),
+        PortInfo::new(
+            Self::BOOT,
+            "BOOT",
+            &[(0x01, "boot ROM", &["mapped", "unmapped"])],
+        )
+        .masks(0x01, 0xFF),
+        PortInfo::new(Self::HMDA1, "HMDA1", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::HMDA2, "HMDA2", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::HMDA3, "HMDA3", &[]).masks(0x00, 0xFF),
+        PortInfo::new(Self::HMDA4, "HMDA4", &[]).masks(0x00, 0xFF),
+        PortInfo::new(
+            Self::HMDA5,
+            "HMDA5",
+            &[
+                (0x80, "mode", &["general", "HBlank"]),
+                (0x7F, "length", &[]),
+            ],
+        ),
+        PortInfo::new(
+            Self::RP,
+            "RP",
+            &[
+                (0xC0, "read", &[]),
+                (0x02, "signal", &["yes", "no"]),
+                (0x01, "LED", OFF_ON),
+            ],
+        )
+        .masks(0xC3, 0xC1),
+        PortInfo::new(Self::BCPS, "BCPS", PALETTE_INDEX).masks(0xBF, 0xBF),
+        PortInfo::new(Self::BCPD, "BCPD", &[]),
+        PortInfo::new(Self::OCPS, "OCPS", PALETTE_INDEX).masks(0xBF, 0xBF),
+        PortInfo::new(Self::OCPD, "OCPD", &[]),
+        PortInfo::new(Self::OPRI, "OPRI", &[(0x01, "priority", &["OAM", "X"])]).masks(0x01, 0x01),
+        PortInfo::new(Self::SVBK, "SVBK", &[(0x07, "bank", &[])]).masks(0x07, 0x07),
+        PortInfo::new(Self::FF72, "FF72", &[]),
+        PortInfo::new(Self::FF73, "FF73", &[]),
+        PortInfo::new(Self::FF74, "FF74", &[]),
+        PortInfo::new(Self::FF75, "FF75", &[]).masks(0x70, 0x70),
+        PortInfo::new(Self::PCM12, "PCM12", &[]).masks(0xFF, 0x00),
+        PortInfo::new(Self::PCM34, "PCM34", &[]).masks(0xFF, 0x00),
+        PortInfo::new(Self::IE, "IE", INTERRUPTS),
+    ];
+
+    // $FF00-$FF7F, looked up on every IO access so they're laid out flat. Nothing answers
+    // where there's no port, so all of those bits read as 1 and writes go nowhere
+    const IO_MASKS: [(u8, u8); 0x80] = {
+        let mut masks = [(0x00, 0x00); 0x80];
+        let mut i = 0;
+        while i < Self::INFO.len() {
+            let info = &Self::INFO[i];
+            if info.addr < 0xFF80 {
+                masks[(info.addr - 0xFF00) as usize] = (info.read_mask, info.write_mask);
+            }
+            i += 1;
+        }
+        let mut addr = Self::WAVE;
+        while addr < (Self::WAVE + 16) {
+            masks[(addr - 0xFF00) as usize] = (0xFF, 0xFF);
+            addr += 1;
+        }
+        masks
+    };
+
+    pub fn info(addr: u16) -> Option<&'static PortInfo> {
+        Self::INFO.iter().find(|info| info.addr == addr)
+    }
+
+    /// `FF56 (RP)` for a port with a name, just `FF7E` otherwise.
+    pub fn label(addr: u16) -> String {
+        match Self::info(addr) {
+            Some(info) if info.name != format!("{addr:04X}") => {
+                format!("{addr:04X} ({})", info.name)
+            }
+            _ => format!("{addr:04X}"),
+        }
+    }
+
+    /// The bits of an IO port ($FF00-$FF7F) that are really there when read, and that a
+    /// write can change. The rest read back as 1.
+    #[inline]
+    pub fn io_masks(addr: u16) -> (u8, u8) {
+        Self::IO_MASKS[(addr - 0xFF00) as usize]
+    }
+}
+
+const OFF_ON: &[&str] = &["off", "on"];
+// P1 selects with a 0 bit
+const SELECTED: &[&str] = &["selected", "-"];
+const INTERRUPTS: Fields = &[
+    (0x10, "joypad", OFF_ON),
+    (0x08, "serial", OFF_ON),
+    (0x04, "timer", OFF_ON),
+    (0x02, "STAT", OFF_ON),
+    (0x01, "VBlank", OFF_ON),
+];
+const SHADES: Fields = &[
+    (0xC0, "color 3", &[]),
+    (0x30, "color 2", &[]),
+    (0x0C, "color 1", &[]),
+    (0x03, "color 0", &[]),
+];
+const PALETTE_INDEX: Fields = &[(0x80, "increment", OFF_ON), (0x3F, "index", &[])];
+
+// (mask, label, a name for each value of the field), values without names are shown as numbers
+type Fields = &'static [(u8, &'static str, &'static [&'static str])];
+
+pub struct PortInfo {
+    pub addr: u16,
+    pub name: &'static str,
+    /// Bits that read back what's in the port, the others always read as 1
+    pub read_mask: u8,
+    /// Bits a write can change
+    pub write_mask: u8,
+    fields: Fields,
+}
+
+impl PortInfo {
+    const fn new(addr: u16, name: &'static str, fields: Fields) -> Self {
+        Self {
+            addr,
+            name,
+            read_mask: 0xFF,
+            write_mask: 0xFF,
+            fields,
+        }
+    }
+
+    const fn masks(self, read_mask: u8, write_mask: u8) -> Self {
+        Self {
+            read_mask,
+            write_mask,
+            ..self
+        }
+    }
+
+    /// Spells out the bits of a value read from this port, e.g. `display on, OBJ 8x16`.
+    pub fn describe(&self, value: u8) -> String {
+        self.fields
+            .iter()
+            .map(|&(mask, label, names)| {
+                let field = (value & mask) >> mask.trailing_zeros();
+                match names.get(field as usize) {
+                    Some(name) => format!("{label} {name}"),
+                    None => format!("{label} {field:X}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// nothing answers at `addr`, which is a bug in whatever routed it here
+fn unmapped<T: ?Sized>(addr: u16) {
+    let device = any::type_name::<T>();
+    tracing::warn!("{}", CoreError::Unmapped { device, addr });
 }
 
 pub trait Bus {
-    fn lcd_mut(&mut self) -> &mut [[u32; 160]; 144] {
-        unreachable!()
+    // a bus nobody's watching the screen through can let lines go
+    fn scanline(&mut self, _ly: u8, _line: &[u32; 160]) {}
+
+    fn read(&mut self, addr: u16) -> u8 {
+        unmapped::<Self>(addr);
+        0xFF
     }
 
-    fn read(&mut self, _addr: u16) -> u8 {
-        unreachable!()
+    fn write(&mut self, addr: u16, _value: u8) {
+        unmapped::<Self>(addr);
     }
 
-    fn write(&mut self, _addr: u16, _value: u8) {
-        unreachable!()
+    /// A read of an opcode or its operands, which is just a read unless something is keeping
+    /// track of what's code.
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.read(addr)
     }
 }
 
 pub trait BusDevice<B: Bus> {
     fn reset(&mut self, bus: &mut B);
 
-    fn read(&mut self, _addr: u16) -> u8 {
-        unreachable!()
+    fn read(&mut self, addr: u16) -> u8 {
+        unmapped::<Self>(addr);
+        0xFF
     }
 
-    fn write(&mut self, _addr: u16, _value: u8) {
-        unreachable!()
+    fn write(&mut self, addr: u16, _value: u8) {
+        unmapped::<Self>(addr);
     }
 
     fn tick(&mut self, bus: &mut B) -> usize;
+
+    /// Lets `cycles` normal speed cycles go by, for devices that keep time of their own.
+    fn advance(&mut self, _cycles: usize) {}
+
+    /// Where in the ROM a read of `addr` lands with the banks as they are, for cartridges.
+    fn rom_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    /// Feeds whatever decides what the device does next into `state`, for
+    /// [`Emu::state_hash`](super::Emu::state_hash).
+    fn hash_state(&self, _state: &mut dyn Hasher) {}
+
+    /// Saves or loads the same state [`BusDevice::hash_state`] hashes, for
+    /// [`Emu::save_state`](super::Emu::save_state).
+    fn snapshot_state(&mut self, _state: &mut State<'_>) {}
 }
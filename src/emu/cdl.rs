@@ -0,0 +1,47 @@
+//! Code/data logging: what each byte of the ROM was used for
+
+/// One byte of flags per ROM byte, as FCEUX and Mesen keep them: bit 0 for code (opcodes
+/// and their operands), bit 1 for data the CPU read, and bit 2 for the source of an OAM
+/// DMA. Bytes never touched stay 0.
+#[derive(Clone, Default)]
+pub struct Cdl {
+    flags: Vec<u8>,
+}
+
+impl Cdl {
+    pub const CODE: u8 = 0x01;
+    pub const DATA: u8 = 0x02;
+    pub const DMA: u8 = 0x04;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Picks up a log saved by [`Cdl::to_bytes`], to add another session to it.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            flags: bytes.to_vec(),
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, offset: usize, flag: u8) {
+        // the log only grows as far as the ROM has been seen, it's sized up on the way out
+        if offset >= self.flags.len() {
+            self.flags.resize(offset + 1, 0);
+        }
+        self.flags[offset] |= flag;
+    }
+
+    #[inline]
+    pub fn get(&self, offset: usize) -> u8 {
+        self.flags.get(offset).copied().unwrap_or(0)
+    }
+
+    /// The log as a file, one byte for each of the ROM's `len` bytes.
+    pub fn to_bytes(&self, len: usize) -> Vec<u8> {
+        let mut bytes = self.flags.clone();
+        bytes.resize(len, 0);
+        bytes
+    }
+}
@@ -1,8 +1,13 @@
 //! SM83 (GBZ80) emulation
 
-use super::bus::{Bus, BusDevice, Port};
+use std::{mem, str::FromStr};
 
-#[derive(Default)]
+use super::{
+    bus::{Bus, BusDevice, Port},
+    state::{Snapshot, State},
+};
+
+#[derive(Default, Hash)]
 pub struct Cpu {
     pc: u16,
     sp: u16,
@@ -12,8 +17,29 @@ pub struct Cpu {
     hl: [u8; 2],
 
     ime: bool,
+    // EI only enables interrupts once the instruction after it is done
+    ime_next: bool,
     stopped: bool,
     halted: bool,
+    // HALT with interrupts off and one already pending doesn't halt, instead the next
+    // opcode fetch forgets to move PC along
+    halt_bug: bool,
+}
+
+impl Snapshot for Cpu {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.pc.snapshot(state);
+        self.sp.snapshot(state);
+        self.af.snapshot(state);
+        self.bc.snapshot(state);
+        self.de.snapshot(state);
+        self.hl.snapshot(state);
+        self.ime.snapshot(state);
+        self.ime_next.snapshot(state);
+        self.stopped.snapshot(state);
+        self.halted.snapshot(state);
+        self.halt_bug.snapshot(state);
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -38,6 +64,40 @@ pub enum Register {
     L,
 }
 
+impl FromStr for WideRegister {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "PC" => Ok(Self::PC),
+            "SP" => Ok(Self::SP),
+            "AF" => Ok(Self::AF),
+            "BC" => Ok(Self::BC),
+            "DE" => Ok(Self::DE),
+            "HL" => Ok(Self::HL),
+            _ => Err(format!("unknown register `{s}`")),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(Self::A),
+            "F" => Ok(Self::F),
+            "B" => Ok(Self::B),
+            "C" => Ok(Self::C),
+            "D" => Ok(Self::D),
+            "E" => Ok(Self::E),
+            "H" => Ok(Self::H),
+            "L" => Ok(Self::L),
+            _ => Err(format!("unknown register `{s}`")),
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum Flag {
     Zero = 0x80,
@@ -46,6 +106,57 @@ pub enum Flag {
     Carry = 0x10,
 }
 
+/// An opcode's assembly, size and how long it runs, from [`OPCODES`] or [`cb_opcode`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Opcode {
+    /// How it's written, like `ld bc, {n16}`, with the operand as one of `{n8}`, `{n16}`,
+    /// `{a8}` (an address in $FF00-$FFFF), `{a16}`, `{e8}` (a signed offset) or `{r8}` (a
+    /// relative jump's target). Empty for the illegal opcodes and the $CB prefix, and for
+    /// CB-prefixed ones, which the disassembler spells out itself.
+    pub template: &'static str,
+    /// In bytes, the opcode's own included.
+    pub len: u8,
+    /// In CPU cycles, for a conditional jump, call or return when it isn't taken.
+    pub cycles: u8,
+    /// In CPU cycles when a conditional jump, call or return is taken, the same as `cycles`
+    /// for everything else.
+    pub taken_cycles: u8,
+}
+
+impl Opcode {
+    const fn new(template: &'static str, len: u8, cycles: u8, taken_cycles: u8) -> Self {
+        Self {
+            template,
+            len,
+            cycles,
+            taken_cycles,
+        }
+    }
+}
+
+/// The second byte of a CB-prefixed instruction, taking the prefix with it.
+pub const fn cb_opcode(cb: u8) -> Opcode {
+    // (HL) is read, changed and written back, except by BIT which only reads it
+    let cycles = match (cb & 0x07, cb) {
+        (0x06, 0x40..=0x7F) => 12,
+        (0x06, _) => 16,
+        _ => 8,
+    };
+    Opcode::new("", 2, cycles, cycles)
+}
+
+/// What one [`Cpu::step`] did.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StepInfo {
+    /// The instruction's opcode, or `None` for a step that ran none: halted or stopped
+    /// time, or dispatching an interrupt. CB prefixed instructions give `$CB`.
+    pub opcode: Option<u8>,
+    /// Where PC was before the step.
+    pub pc_before: u16,
+    /// How long the step took, in CPU cycles.
+    pub cycles: usize,
+}
+
 #[derive(Copy, Clone)]
 enum Condition {
     Zero,
@@ -66,7 +177,7 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn set_flag(&mut self, flag: Flag, value: bool) {
+    pub fn set_flag(&mut self, flag: Flag, value: bool) {
         if value {
             self.af[0] |= flag as u8;
         } else {
@@ -76,11 +187,20 @@ impl Cpu {
 
     #[inline(always)]
     fn fetch<B: Bus>(&mut self, bus: &mut B) -> u8 {
-        let value = bus.read(self.pc);
+        let value = bus.fetch(self.pc);
         self.pc = self.pc.wrapping_add(1);
         value
     }
 
+    #[inline(always)]
+    fn fetch_opcode<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        if self.halt_bug {
+            self.halt_bug = false;
+            return bus.fetch(self.pc);
+        }
+        self.fetch(bus)
+    }
+
     #[inline(always)]
     fn fetch_wide<B: Bus>(&mut self, bus: &mut B) -> u16 {
         (self.fetch(bus) as u16) | ((self.fetch(bus) as u16) << 8)
@@ -104,7 +224,8 @@ impl Cpu {
     pub fn set_register(&mut self, reg: Register, value: u8) {
         match reg {
             Register::A => self.af[1] = value,
-            Register::F => self.af[0] = value,
+            // the low nibble of F doesn't exist
+            Register::F => self.af[0] = value & 0xF0,
             Register::B => self.bc[1] = value,
             Register::C => self.bc[0] = value,
             Register::D => self.de[1] = value,
@@ -145,6 +266,36 @@ impl Cpu {
         }
     }
 
+    #[inline]
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    #[inline]
+    pub fn set_ime(&mut self, ime: bool) {
+        self.ime = ime;
+    }
+
+    #[inline]
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    #[inline]
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    #[inline]
+    pub fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    #[inline]
+    pub fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
     #[inline(always)]
     fn nop(&mut self) -> usize {
         4
@@ -190,24 +341,38 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn inc(&mut self, reg: Register) -> usize {
-        let value = self.register(reg);
+    fn inc_value(&mut self, value: u8) -> u8 {
         let result = value.wrapping_add(1);
-        self.set_register(reg, result);
         self.set_flag(Flag::Zero, result == 0x00);
-        self.set_flag(Flag::HalfCarry, ((result ^ value) & 0x10) != 0);
         self.set_flag(Flag::Negative, false);
-        4
+        // the low nibble carries only when it was all ones. C is left alone
+        self.set_flag(Flag::HalfCarry, (value & 0x0F) == 0x0F);
+        result
     }
 
     #[inline(always)]
-    fn dec(&mut self, reg: Register) -> usize {
+    fn inc(&mut self, reg: Register) -> usize {
         let value = self.register(reg);
-        let result = value.wrapping_sub(1);
+        let result = self.inc_value(value);
         self.set_register(reg, result);
+        4
+    }
+
+    #[inline(always)]
+    fn dec_value(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
         self.set_flag(Flag::Zero, result == 0x00);
-        self.set_flag(Flag::HalfCarry, ((result ^ value) & 0x10) != 0);
         self.set_flag(Flag::Negative, true);
+        // the low nibble borrows only when it was all zeros. C is left alone
+        self.set_flag(Flag::HalfCarry, (value & 0x0F) == 0x00);
+        result
+    }
+
+    #[inline(always)]
+    fn dec(&mut self, reg: Register) -> usize {
+        let value = self.register(reg);
+        let result = self.dec_value(value);
+        self.set_register(reg, result);
         4
     }
 
@@ -476,9 +641,10 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn stop<B: Bus>(&mut self, bus: &mut B) -> usize {
+    fn stop(&mut self) -> usize {
+        // what happens next (and whether the byte after is skipped) depends on the buttons,
+        // interrupts and a speed switch, which the machine around us sorts out
         self.stopped = true;
-        self.fetch(bus);
         4
     }
 
@@ -547,6 +713,7 @@ impl Cpu {
     fn daa(&mut self) -> usize {
         let value = self.register(Register::A);
         let mut result = value;
+        // after a subtraction only the flags say what to undo, A can't be out of range
         if self.flag(Flag::Negative) {
             if self.flag(Flag::HalfCarry) {
                 result = result.wrapping_sub(0x06);
@@ -565,10 +732,8 @@ impl Cpu {
         }
         self.set_register(Register::A, result);
         self.set_flag(Flag::Zero, result == 0x00);
-        // TODO should I be reseting H?
+        // H is always spent, while C is only ever set: a carry out of the tens stays one
         self.set_flag(Flag::HalfCarry, false);
-        // TODO Do I always do this?
-        // self.set_flag(Flag::Carry, self.flag(Flag::Carry) || (value > 0x99));
         4
     }
 
@@ -636,11 +801,8 @@ impl Cpu {
     fn inc_hl_indirect<B: Bus>(&mut self, bus: &mut B) -> usize {
         let addr = self.wide_register(WideRegister::HL);
         let value = bus.read(addr);
-        let result = value.wrapping_add(1);
+        let result = self.inc_value(value);
         bus.write(addr, result);
-        self.set_flag(Flag::Zero, result == 0x00);
-        self.set_flag(Flag::Negative, false);
-        self.set_flag(Flag::HalfCarry, ((result ^ value) & 0x10) != 0);
         12
     }
 
@@ -648,11 +810,8 @@ impl Cpu {
     fn dec_hl_indirect<B: Bus>(&mut self, bus: &mut B) -> usize {
         let addr = self.wide_register(WideRegister::HL);
         let value = bus.read(addr);
-        let result = value.wrapping_sub(1);
+        let result = self.dec_value(value);
         bus.write(addr, result);
-        self.set_flag(Flag::Zero, result == 0x00);
-        self.set_flag(Flag::Negative, true);
-        self.set_flag(Flag::HalfCarry, ((result ^ value) & 0x10) != 0);
         12
     }
 
@@ -680,20 +839,34 @@ impl Cpu {
     }
 
     #[inline(always)]
-    fn halt(&mut self) -> usize {
-        self.halted = true;
+    fn halt<B: Bus>(&mut self, bus: &mut B) -> usize {
+        let pending = (bus.read(Port::IE) & bus.read(Port::IF) & 0x1F) != 0;
+        if !pending {
+            self.halted = true;
+        } else if !self.ime {
+            self.halt_bug = true;
+        }
+        // with interrupts on, the pending one is taken straight away instead
         4
     }
 
     #[inline(always)]
     fn add_value(&mut self, value: u8, carry: bool) {
         let a = self.register(Register::A);
-        let (result, carry) = a.carrying_add(value, carry);
-        self.set_register(Register::A, result as u8);
+        let carry = carry as u8;
+        let result = a.wrapping_add(value).wrapping_add(carry);
+        self.set_register(Register::A, result);
         self.set_flag(Flag::Zero, result == 0x00);
         self.set_flag(Flag::Negative, false);
-        self.set_flag(Flag::HalfCarry, ((a ^ value ^ result) & 0x10) != 0);
-        self.set_flag(Flag::Carry, carry);
+        // the carry in counts towards both
+        self.set_flag(
+            Flag::HalfCarry,
+            ((a & 0x0F) + (value & 0x0F) + carry) > 0x0F,
+        );
+        self.set_flag(
+            Flag::Carry,
+            ((a as u16) + (value as u16) + (carry as u16)) > 0xFF,
+        );
     }
 
     #[inline(always)]
@@ -730,13 +903,22 @@ impl Cpu {
 
     #[inline(always)]
     fn sub_value(&mut self, value: u8, carry: bool) {
+        let result = self.sub_flags(value, carry);
+        self.set_register(Register::A, result);
+    }
+
+    // A - value - carry and the flags that leaves, which is all CP keeps
+    #[inline(always)]
+    fn sub_flags(&mut self, value: u8, carry: bool) -> u8 {
         let a = self.register(Register::A);
-        let (result, carry) = a.borrowing_sub(value, carry);
-        self.set_register(Register::A, result as u8);
+        let carry = carry as u8;
+        let result = a.wrapping_sub(value).wrapping_sub(carry);
         self.set_flag(Flag::Zero, result == 0x00);
         self.set_flag(Flag::Negative, true);
-        self.set_flag(Flag::HalfCarry, ((a ^ value ^ result) & 0x10) != 0);
-        self.set_flag(Flag::Carry, carry);
+        // the borrow in counts towards both
+        self.set_flag(Flag::HalfCarry, (a & 0x0F) < ((value & 0x0F) + carry));
+        self.set_flag(Flag::Carry, (a as u16) < ((value as u16) + (carry as u16)));
+        result
     }
 
     #[inline(always)]
@@ -872,12 +1054,7 @@ impl Cpu {
 
     #[inline(always)]
     fn compare_value(&mut self, value: u8) {
-        let a = self.register(Register::A);
-        let (result, carry) = a.overflowing_sub(value);
-        self.set_flag(Flag::Zero, result == 0x00);
-        self.set_flag(Flag::Negative, true);
-        self.set_flag(Flag::HalfCarry, ((a ^ value ^ result) & 0x10) != 0);
-        self.set_flag(Flag::Carry, carry);
+        self.sub_flags(value, false);
     }
 
     #[inline(always)]
@@ -1075,6 +1252,7 @@ impl Cpu {
     #[inline(always)]
     fn di(&mut self) -> usize {
         self.ime = false;
+        self.ime_next = false;
         4
     }
 
@@ -1097,7 +1275,7 @@ impl Cpu {
 
     #[inline(always)]
     fn ei(&mut self) -> usize {
-        self.ime = true;
+        self.ime_next = true;
         4
     }
 
@@ -1154,7 +1332,8 @@ impl Cpu {
         let addr = self.wide_register(WideRegister::HL);
         let value = bus.read(addr);
         self.bit_value(bit, value);
-        16
+        // nothing is written back, so it's a read short of the other (HL) ops
+        12
     }
 
     #[inline(always)]
@@ -1483,18 +1662,48 @@ impl<B: Bus> BusDevice<B> for Cpu {
     fn reset(&mut self, _bus: &mut B) {
         self.pc = 0x0000;
         self.ime = false;
+        self.ime_next = false;
         self.stopped = false;
         self.halted = false;
+        self.halt_bug = false;
     }
 
     fn tick(&mut self, bus: &mut B) -> usize {
+        self.step(bus).cycles
+    }
+}
+
+impl Cpu {
+    /// Runs one instruction, interrupt dispatch, or cycle of halted or stopped time.
+    #[inline]
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> StepInfo {
+        self.step_with(bus, |_, _, _| {})
+    }
+
+    /// [`Cpu::step`], calling `pre` with the CPU, the PC and the opcode once an instruction
+    /// is fetched but before it runs. Only PC has moved on by then.
+    pub fn step_with<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        mut pre: impl FnMut(&Cpu, u16, u8),
+    ) -> StepInfo {
+        let pc_before = self.pc;
+        let idle = |cycles| StepInfo {
+            opcode: None,
+            pc_before,
+            cycles,
+        };
+        if self.stopped {
+            return idle(4);
+        }
         let iflags = bus.read(Port::IF);
         let imasked = bus.read(Port::IE) & iflags;
         if self.halted {
-            if imasked == 0 {
-                return 4;
+            // waking up takes a cycle of its own, before the interrupt (if IME) is taken
+            if imasked != 0 {
+                self.halted = false;
             }
-            self.halted = false;
+            return idle(4);
         }
         // handle interrupts
         if self.ime {
@@ -1516,282 +1725,341 @@ impl<B: Bus> BusDevice<B> for Cpu {
                     bus.write(Port::IF, iflags ^ 0x10);
                 }
                 self.ime = false;
-                return 20;
+                self.ime_next = false;
+                return idle(20);
             }
         }
-        let opcode = self.fetch(bus);
-        match opcode {
-            0x00 => self.nop(),
-            0x01 => self.load_wide_immediate(bus, WideRegister::BC),
-            0x02 => self.store_register_indirect(bus, WideRegister::BC, Register::A),
-            0x03 => self.inc_wide(WideRegister::BC),
-            0x04 => self.inc(Register::B),
-            0x05 => self.dec(Register::B),
-            0x06 => self.load_immediate(bus, Register::B),
-            0x07 => self.rlca(),
-            0x08 => self.write_stack_immediate(bus),
-            0x09 => self.add_wide(WideRegister::BC),
-            0x0A => self.load_register_indirect(bus, WideRegister::BC, Register::A),
-            0x0B => self.dec_wide(WideRegister::BC),
-            0x0C => self.inc(Register::C),
-            0x0D => self.dec(Register::C),
-            0x0E => self.load_immediate(bus, Register::C),
-            0x0F => self.rrca(),
-
-            0x10 => self.stop(bus),
-            0x11 => self.load_wide_immediate(bus, WideRegister::DE),
-            0x12 => self.store_register_indirect(bus, WideRegister::DE, Register::A),
-            0x13 => self.inc_wide(WideRegister::DE),
-            0x14 => self.inc(Register::D),
-            0x15 => self.dec(Register::D),
-            0x16 => self.load_immediate(bus, Register::D),
-            0x17 => self.rla(),
-            0x18 => self.jr(bus),
-            0x19 => self.add_wide(WideRegister::DE),
-            0x1A => self.load_register_indirect(bus, WideRegister::DE, Register::A),
-            0x1B => self.dec_wide(WideRegister::DE),
-            0x1C => self.inc(Register::E),
-            0x1D => self.dec(Register::E),
-            0x1E => self.load_immediate(bus, Register::E),
-            0x1F => self.rra(),
-
-            0x20 => self.jr_condition(bus, Condition::NotZero),
-            0x21 => self.load_wide_immediate(bus, WideRegister::HL),
-            0x22 => self.store_a_hli_indirect(bus),
-            0x23 => self.inc_wide(WideRegister::HL),
-            0x24 => self.inc(Register::H),
-            0x25 => self.dec(Register::H),
-            0x26 => self.load_immediate(bus, Register::H),
-            0x27 => self.daa(),
-            0x28 => self.jr_condition(bus, Condition::Zero),
-            0x29 => self.add_wide(WideRegister::HL),
-            0x2A => self.load_a_hli_indirect(bus),
-            0x2B => self.dec_wide(WideRegister::HL),
-            0x2C => self.inc(Register::L),
-            0x2D => self.dec(Register::L),
-            0x2E => self.load_immediate(bus, Register::L),
-            0x2F => self.cpl(),
-
-            0x30 => self.jr_condition(bus, Condition::NotCarry),
-            0x31 => self.load_wide_immediate(bus, WideRegister::SP),
-            0x32 => self.store_a_hld_indirect(bus),
-            0x33 => self.inc_wide(WideRegister::SP),
-            0x34 => self.inc_hl_indirect(bus),
-            0x35 => self.dec_hl_indirect(bus),
-            0x36 => self.store_immediate_hl_indirect(bus),
-            0x37 => self.scf(),
-            0x38 => self.jr_condition(bus, Condition::Carry),
-            0x39 => self.add_wide(WideRegister::SP),
-            0x3A => self.load_a_hld_indirect(bus),
-            0x3B => self.dec_wide(WideRegister::SP),
-            0x3C => self.inc(Register::A),
-            0x3D => self.dec(Register::A),
-            0x3E => self.load_immediate(bus, Register::A),
-            0x3F => self.ccf(),
-
-            0x40 => self.copy(Register::B, Register::B),
-            0x41 => self.copy(Register::B, Register::C),
-            0x42 => self.copy(Register::B, Register::D),
-            0x43 => self.copy(Register::B, Register::E),
-            0x44 => self.copy(Register::B, Register::H),
-            0x45 => self.copy(Register::B, Register::L),
-            0x46 => self.load_register_indirect(bus, WideRegister::HL, Register::B),
-            0x47 => self.copy(Register::B, Register::A),
-            0x48 => self.copy(Register::C, Register::B),
-            0x49 => self.copy(Register::C, Register::C),
-            0x4A => self.copy(Register::C, Register::D),
-            0x4B => self.copy(Register::C, Register::E),
-            0x4C => self.copy(Register::C, Register::H),
-            0x4D => self.copy(Register::C, Register::L),
-            0x4E => self.load_register_indirect(bus, WideRegister::HL, Register::C),
-            0x4F => self.copy(Register::C, Register::A),
-
-            0x50 => self.copy(Register::D, Register::B),
-            0x51 => self.copy(Register::D, Register::C),
-            0x52 => self.copy(Register::D, Register::D),
-            0x53 => self.copy(Register::D, Register::E),
-            0x54 => self.copy(Register::D, Register::H),
-            0x55 => self.copy(Register::D, Register::L),
-            0x56 => self.load_register_indirect(bus, WideRegister::HL, Register::D),
-            0x57 => self.copy(Register::D, Register::A),
-            0x58 => self.copy(Register::E, Register::B),
-            0x59 => self.copy(Register::E, Register::C),
-            0x5A => self.copy(Register::E, Register::D),
-            0x5B => self.copy(Register::E, Register::E),
-            0x5C => self.copy(Register::E, Register::H),
-            0x5D => self.copy(Register::E, Register::L),
-            0x5E => self.load_register_indirect(bus, WideRegister::HL, Register::E),
-            0x5F => self.copy(Register::E, Register::A),
-
-            0x60 => self.copy(Register::H, Register::B),
-            0x61 => self.copy(Register::H, Register::C),
-            0x62 => self.copy(Register::H, Register::D),
-            0x63 => self.copy(Register::H, Register::E),
-            0x64 => self.copy(Register::H, Register::H),
-            0x65 => self.copy(Register::H, Register::L),
-            0x66 => self.load_register_indirect(bus, WideRegister::HL, Register::H),
-            0x67 => self.copy(Register::H, Register::A),
-            0x68 => self.copy(Register::L, Register::B),
-            0x69 => self.copy(Register::L, Register::C),
-            0x6A => self.copy(Register::L, Register::D),
-            0x6B => self.copy(Register::L, Register::E),
-            0x6C => self.copy(Register::L, Register::H),
-            0x6D => self.copy(Register::L, Register::L),
-            0x6E => self.load_register_indirect(bus, WideRegister::HL, Register::L),
-            0x6F => self.copy(Register::L, Register::A),
-
-            0x70 => self.store_register_indirect(bus, WideRegister::HL, Register::B),
-            0x71 => self.store_register_indirect(bus, WideRegister::HL, Register::C),
-            0x72 => self.store_register_indirect(bus, WideRegister::HL, Register::D),
-            0x73 => self.store_register_indirect(bus, WideRegister::HL, Register::E),
-            0x74 => self.store_register_indirect(bus, WideRegister::HL, Register::H),
-            0x75 => self.store_register_indirect(bus, WideRegister::HL, Register::L),
-            0x76 => self.halt(),
-            0x77 => self.store_register_indirect(bus, WideRegister::HL, Register::A),
-            0x78 => self.copy(Register::A, Register::B),
-            0x79 => self.copy(Register::A, Register::C),
-            0x7A => self.copy(Register::A, Register::D),
-            0x7B => self.copy(Register::A, Register::E),
-            0x7C => self.copy(Register::A, Register::H),
-            0x7D => self.copy(Register::A, Register::L),
-            0x7E => self.load_register_indirect(bus, WideRegister::HL, Register::A),
-            0x7F => self.copy(Register::A, Register::A),
-
-            0x80 => self.add(Register::B),
-            0x81 => self.add(Register::C),
-            0x82 => self.add(Register::D),
-            0x83 => self.add(Register::E),
-            0x84 => self.add(Register::H),
-            0x85 => self.add(Register::L),
-            0x86 => self.add_hl_indirect(bus),
-            0x87 => self.add(Register::A),
-            0x88 => self.add_carry(Register::B),
-            0x89 => self.add_carry(Register::C),
-            0x8A => self.add_carry(Register::D),
-            0x8B => self.add_carry(Register::E),
-            0x8C => self.add_carry(Register::H),
-            0x8D => self.add_carry(Register::L),
-            0x8E => self.add_carry_hl_indirect(bus),
-            0x8F => self.add_carry(Register::A),
-
-            0x90 => self.sub(Register::B),
-            0x91 => self.sub(Register::C),
-            0x92 => self.sub(Register::D),
-            0x93 => self.sub(Register::E),
-            0x94 => self.sub(Register::H),
-            0x95 => self.sub(Register::L),
-            0x96 => self.sub_hl_indirect(bus),
-            0x97 => self.sub(Register::A),
-            0x98 => self.sub_carry(Register::B),
-            0x99 => self.sub_carry(Register::C),
-            0x9A => self.sub_carry(Register::D),
-            0x9B => self.sub_carry(Register::E),
-            0x9C => self.sub_carry(Register::H),
-            0x9D => self.sub_carry(Register::L),
-            0x9E => self.sub_carry_hl_indirect(bus),
-            0x9F => self.sub_carry(Register::A),
-
-            0xA0 => self.and(Register::B),
-            0xA1 => self.and(Register::C),
-            0xA2 => self.and(Register::D),
-            0xA3 => self.and(Register::E),
-            0xA4 => self.and(Register::H),
-            0xA5 => self.and(Register::L),
-            0xA6 => self.and_hl_indirect(bus),
-            0xA7 => self.and(Register::A),
-            0xA8 => self.xor(Register::B),
-            0xA9 => self.xor(Register::C),
-            0xAA => self.xor(Register::D),
-            0xAB => self.xor(Register::E),
-            0xAC => self.xor(Register::H),
-            0xAD => self.xor(Register::L),
-            0xAE => self.xor_hl_indirect(bus),
-            0xAF => self.xor(Register::A),
-
-            0xB0 => self.or(Register::B),
-            0xB1 => self.or(Register::C),
-            0xB2 => self.or(Register::D),
-            0xB3 => self.or(Register::E),
-            0xB4 => self.or(Register::H),
-            0xB5 => self.or(Register::L),
-            0xB6 => self.or_hl_indirect(bus),
-            0xB7 => self.or(Register::A),
-            0xB8 => self.compare(Register::B),
-            0xB9 => self.compare(Register::C),
-            0xBA => self.compare(Register::D),
-            0xBB => self.compare(Register::E),
-            0xBC => self.compare(Register::H),
-            0xBD => self.compare(Register::L),
-            0xBE => self.compare_hl_indirect(bus),
-            0xBF => self.compare(Register::A),
-
-            0xC0 => self.ret_condition(bus, Condition::NotZero),
-            0xC1 => self.pop(bus, WideRegister::BC),
-            0xC2 => self.jmp_condition(bus, Condition::NotZero),
-            0xC3 => self.jmp(bus),
-            0xC4 => self.call_condition(bus, Condition::NotZero),
-            0xC5 => self.push(bus, WideRegister::BC),
-            0xC6 => self.add_immediate(bus),
-            0xC7 => self.rst(bus, 0x0000),
-            0xC8 => self.ret_condition(bus, Condition::Zero),
-            0xC9 => self.ret(bus),
-            0xCA => self.jmp_condition(bus, Condition::Zero),
-            0xCB => self.cb(bus),
-            0xCC => self.call_condition(bus, Condition::Zero),
-            0xCD => self.call(bus),
-            0xCE => self.add_carry_immediate(bus),
-            0xCF => self.rst(bus, 0x0008),
-
-            0xD0 => self.ret_condition(bus, Condition::NotCarry),
-            0xD1 => self.pop(bus, WideRegister::DE),
-            0xD2 => self.jmp_condition(bus, Condition::NotCarry),
-            0xD3 => 4,
-            0xD4 => self.call_condition(bus, Condition::NotCarry),
-            0xD5 => self.push(bus, WideRegister::DE),
-            0xD6 => self.sub_immediate(bus),
-            0xD7 => self.rst(bus, 0x0010),
-            0xD8 => self.ret_condition(bus, Condition::Carry),
-            0xD9 => self.reti(bus),
-            0xDA => self.jmp_condition(bus, Condition::Carry),
-            0xDB => 4,
-            0xDC => self.call_condition(bus, Condition::Carry),
-            0xDD => 4,
-            0xDE => self.sub_carry_immediate(bus),
-            0xDF => self.rst(bus, 0x0018),
-
-            0xE0 => self.store_high_indirect(bus),
-            0xE1 => self.pop(bus, WideRegister::HL),
-            0xE2 => self.store_high_c_indirect(bus),
-            0xE3 => 4,
-            0xE4 => 4,
-            0xE5 => self.push(bus, WideRegister::HL),
-            0xE6 => self.and_immediate(bus),
-            0xE7 => self.rst(bus, 0x0020),
-            0xE8 => self.add_sp(bus),
-            0xE9 => self.jmp_hl(),
-            0xEA => self.store_indirect(bus),
-            0xEB => 4,
-            0xEC => 4,
-            0xED => 4,
-            0xEE => self.xor_immediate(bus),
-            0xEF => self.rst(bus, 0x0028),
-
-            0xF0 => self.load_high_indirect(bus),
-            0xF1 => self.pop(bus, WideRegister::AF),
-            0xF2 => self.load_high_c_indirect(bus),
-            0xF3 => self.di(),
-            0xF4 => 4,
-            0xF5 => self.push(bus, WideRegister::AF),
-            0xF6 => self.or_immediate(bus),
-            0xF7 => self.rst(bus, 0x0030),
-            0xF8 => self.load_sp_indirect(bus),
-            0xF9 => self.copy_wide(WideRegister::SP, WideRegister::HL),
-            0xFA => self.load_indirect(bus),
-            0xFB => self.ei(),
-            0xFC => 4,
-            0xFD => 4,
-            0xFE => self.compare_immediate(bus),
-            0xFF => self.rst(bus, 0x0038),
+        let opcode = self.fetch_opcode(bus);
+        pre(self, pc_before, opcode);
+        let ime_next = mem::take(&mut self.ime_next);
+        let cycles = self.execute(bus, opcode);
+        // an EI before this instruction takes effect now, unless this was a DI
+        if ime_next && (opcode != 0xF3) {
+            self.ime = true;
+        }
+        StepInfo {
+            opcode: Some(opcode),
+            pc_before,
+            cycles,
         }
     }
 }
+
+// Every unprefixed opcode as `opcode: template, len, cycles [/ taken cycles] => what it
+// runs`, which becomes both the match `Cpu::step` dispatches with and the `OPCODES` table,
+// so the two can't disagree. The disassembler and assembler work from the same table. A
+// match compiles to a jump table with the handlers inlined into it. A table of function
+// pointers measured no better (benches/tick.rs): either way an instruction is mostly spent
+// on the bus, not on getting to its handler
+macro_rules! opcodes {
+    (
+        $this:tt, $bus:ident;
+        $(
+            $opcode:literal: $template:literal, $len:literal, $cycles:literal $(/ $taken:literal)?
+                => $run:expr,
+        )*
+    ) => {
+        impl Cpu {
+            #[inline(always)]
+            fn execute<B: Bus>(&mut $this, $bus: &mut B, opcode: u8) -> usize {
+                match opcode {
+                    $($opcode => $run,)*
+                }
+            }
+        }
+
+        /// The size and timing of each unprefixed opcode, as the CPU runs them. The illegal
+        /// ones lock the CPU up on hardware, here they take 4 cycles and do nothing. $CB
+        /// is 0 cycles, the byte after it decides with [`cb_opcode`].
+        pub const OPCODES: [Opcode; 256] = {
+            let mut opcodes = [Opcode::new("", 0, 0, 0); 256];
+            $(
+                opcodes[$opcode] =
+                    Opcode::new($template, $len, $cycles, opcodes!(@taken $cycles $($taken)?));
+            )*
+            opcodes
+        };
+    };
+    (@taken $cycles:literal) => {
+        $cycles
+    };
+    (@taken $cycles:literal $taken:literal) => {
+        $taken
+    };
+}
+
+#[rustfmt::skip]
+opcodes! {
+    self, bus;
+    0x00: "nop", 1, 4 => self.nop(),
+    0x01: "ld bc, {n16}", 3, 12 => self.load_wide_immediate(bus, WideRegister::BC),
+    0x02: "ld [bc], a", 1, 8 => self.store_register_indirect(bus, WideRegister::BC, Register::A),
+    0x03: "inc bc", 1, 8 => self.inc_wide(WideRegister::BC),
+    0x04: "inc b", 1, 4 => self.inc(Register::B),
+    0x05: "dec b", 1, 4 => self.dec(Register::B),
+    0x06: "ld b, {n8}", 2, 8 => self.load_immediate(bus, Register::B),
+    0x07: "rlca", 1, 4 => self.rlca(),
+    0x08: "ld [{a16}], sp", 3, 20 => self.write_stack_immediate(bus),
+    0x09: "add hl, bc", 1, 8 => self.add_wide(WideRegister::BC),
+    0x0A: "ld a, [bc]", 1, 8 => self.load_register_indirect(bus, WideRegister::BC, Register::A),
+    0x0B: "dec bc", 1, 8 => self.dec_wide(WideRegister::BC),
+    0x0C: "inc c", 1, 4 => self.inc(Register::C),
+    0x0D: "dec c", 1, 4 => self.dec(Register::C),
+    0x0E: "ld c, {n8}", 2, 8 => self.load_immediate(bus, Register::C),
+    0x0F: "rrca", 1, 4 => self.rrca(),
+
+    0x10: "stop", 2, 4 => self.stop(),
+    0x11: "ld de, {n16}", 3, 12 => self.load_wide_immediate(bus, WideRegister::DE),
+    0x12: "ld [de], a", 1, 8 => self.store_register_indirect(bus, WideRegister::DE, Register::A),
+    0x13: "inc de", 1, 8 => self.inc_wide(WideRegister::DE),
+    0x14: "inc d", 1, 4 => self.inc(Register::D),
+    0x15: "dec d", 1, 4 => self.dec(Register::D),
+    0x16: "ld d, {n8}", 2, 8 => self.load_immediate(bus, Register::D),
+    0x17: "rla", 1, 4 => self.rla(),
+    0x18: "jr {r8}", 2, 12 => self.jr(bus),
+    0x19: "add hl, de", 1, 8 => self.add_wide(WideRegister::DE),
+    0x1A: "ld a, [de]", 1, 8 => self.load_register_indirect(bus, WideRegister::DE, Register::A),
+    0x1B: "dec de", 1, 8 => self.dec_wide(WideRegister::DE),
+    0x1C: "inc e", 1, 4 => self.inc(Register::E),
+    0x1D: "dec e", 1, 4 => self.dec(Register::E),
+    0x1E: "ld e, {n8}", 2, 8 => self.load_immediate(bus, Register::E),
+    0x1F: "rra", 1, 4 => self.rra(),
+
+    0x20: "jr nz, {r8}", 2, 8 / 12 => self.jr_condition(bus, Condition::NotZero),
+    0x21: "ld hl, {n16}", 3, 12 => self.load_wide_immediate(bus, WideRegister::HL),
+    0x22: "ld [hl+], a", 1, 8 => self.store_a_hli_indirect(bus),
+    0x23: "inc hl", 1, 8 => self.inc_wide(WideRegister::HL),
+    0x24: "inc h", 1, 4 => self.inc(Register::H),
+    0x25: "dec h", 1, 4 => self.dec(Register::H),
+    0x26: "ld h, {n8}", 2, 8 => self.load_immediate(bus, Register::H),
+    0x27: "daa", 1, 4 => self.daa(),
+    0x28: "jr z, {r8}", 2, 8 / 12 => self.jr_condition(bus, Condition::Zero),
+    0x29: "add hl, hl", 1, 8 => self.add_wide(WideRegister::HL),
+    0x2A: "ld a, [hl+]", 1, 8 => self.load_a_hli_indirect(bus),
+    0x2B: "dec hl", 1, 8 => self.dec_wide(WideRegister::HL),
+    0x2C: "inc l", 1, 4 => self.inc(Register::L),
+    0x2D: "dec l", 1, 4 => self.dec(Register::L),
+    0x2E: "ld l, {n8}", 2, 8 => self.load_immediate(bus, Register::L),
+    0x2F: "cpl", 1, 4 => self.cpl(),
+
+    0x30: "jr nc, {r8}", 2, 8 / 12 => self.jr_condition(bus, Condition::NotCarry),
+    0x31: "ld sp, {n16}", 3, 12 => self.load_wide_immediate(bus, WideRegister::SP),
+    0x32: "ld [hl-], a", 1, 8 => self.store_a_hld_indirect(bus),
+    0x33: "inc sp", 1, 8 => self.inc_wide(WideRegister::SP),
+    0x34: "inc [hl]", 1, 12 => self.inc_hl_indirect(bus),
+    0x35: "dec [hl]", 1, 12 => self.dec_hl_indirect(bus),
+    0x36: "ld [hl], {n8}", 2, 12 => self.store_immediate_hl_indirect(bus),
+    0x37: "scf", 1, 4 => self.scf(),
+    0x38: "jr c, {r8}", 2, 8 / 12 => self.jr_condition(bus, Condition::Carry),
+    0x39: "add hl, sp", 1, 8 => self.add_wide(WideRegister::SP),
+    0x3A: "ld a, [hl-]", 1, 8 => self.load_a_hld_indirect(bus),
+    0x3B: "dec sp", 1, 8 => self.dec_wide(WideRegister::SP),
+    0x3C: "inc a", 1, 4 => self.inc(Register::A),
+    0x3D: "dec a", 1, 4 => self.dec(Register::A),
+    0x3E: "ld a, {n8}", 2, 8 => self.load_immediate(bus, Register::A),
+    0x3F: "ccf", 1, 4 => self.ccf(),
+
+    0x40: "ld b, b", 1, 4 => self.copy(Register::B, Register::B),
+    0x41: "ld b, c", 1, 4 => self.copy(Register::B, Register::C),
+    0x42: "ld b, d", 1, 4 => self.copy(Register::B, Register::D),
+    0x43: "ld b, e", 1, 4 => self.copy(Register::B, Register::E),
+    0x44: "ld b, h", 1, 4 => self.copy(Register::B, Register::H),
+    0x45: "ld b, l", 1, 4 => self.copy(Register::B, Register::L),
+    0x46: "ld b, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::B),
+    0x47: "ld b, a", 1, 4 => self.copy(Register::B, Register::A),
+    0x48: "ld c, b", 1, 4 => self.copy(Register::C, Register::B),
+    0x49: "ld c, c", 1, 4 => self.copy(Register::C, Register::C),
+    0x4A: "ld c, d", 1, 4 => self.copy(Register::C, Register::D),
+    0x4B: "ld c, e", 1, 4 => self.copy(Register::C, Register::E),
+    0x4C: "ld c, h", 1, 4 => self.copy(Register::C, Register::H),
+    0x4D: "ld c, l", 1, 4 => self.copy(Register::C, Register::L),
+    0x4E: "ld c, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::C),
+    0x4F: "ld c, a", 1, 4 => self.copy(Register::C, Register::A),
+
+    0x50: "ld d, b", 1, 4 => self.copy(Register::D, Register::B),
+    0x51: "ld d, c", 1, 4 => self.copy(Register::D, Register::C),
+    0x52: "ld d, d", 1, 4 => self.copy(Register::D, Register::D),
+    0x53: "ld d, e", 1, 4 => self.copy(Register::D, Register::E),
+    0x54: "ld d, h", 1, 4 => self.copy(Register::D, Register::H),
+    0x55: "ld d, l", 1, 4 => self.copy(Register::D, Register::L),
+    0x56: "ld d, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::D),
+    0x57: "ld d, a", 1, 4 => self.copy(Register::D, Register::A),
+    0x58: "ld e, b", 1, 4 => self.copy(Register::E, Register::B),
+    0x59: "ld e, c", 1, 4 => self.copy(Register::E, Register::C),
+    0x5A: "ld e, d", 1, 4 => self.copy(Register::E, Register::D),
+    0x5B: "ld e, e", 1, 4 => self.copy(Register::E, Register::E),
+    0x5C: "ld e, h", 1, 4 => self.copy(Register::E, Register::H),
+    0x5D: "ld e, l", 1, 4 => self.copy(Register::E, Register::L),
+    0x5E: "ld e, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::E),
+    0x5F: "ld e, a", 1, 4 => self.copy(Register::E, Register::A),
+
+    0x60: "ld h, b", 1, 4 => self.copy(Register::H, Register::B),
+    0x61: "ld h, c", 1, 4 => self.copy(Register::H, Register::C),
+    0x62: "ld h, d", 1, 4 => self.copy(Register::H, Register::D),
+    0x63: "ld h, e", 1, 4 => self.copy(Register::H, Register::E),
+    0x64: "ld h, h", 1, 4 => self.copy(Register::H, Register::H),
+    0x65: "ld h, l", 1, 4 => self.copy(Register::H, Register::L),
+    0x66: "ld h, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::H),
+    0x67: "ld h, a", 1, 4 => self.copy(Register::H, Register::A),
+    0x68: "ld l, b", 1, 4 => self.copy(Register::L, Register::B),
+    0x69: "ld l, c", 1, 4 => self.copy(Register::L, Register::C),
+    0x6A: "ld l, d", 1, 4 => self.copy(Register::L, Register::D),
+    0x6B: "ld l, e", 1, 4 => self.copy(Register::L, Register::E),
+    0x6C: "ld l, h", 1, 4 => self.copy(Register::L, Register::H),
+    0x6D: "ld l, l", 1, 4 => self.copy(Register::L, Register::L),
+    0x6E: "ld l, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::L),
+    0x6F: "ld l, a", 1, 4 => self.copy(Register::L, Register::A),
+
+    0x70: "ld [hl], b", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::B),
+    0x71: "ld [hl], c", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::C),
+    0x72: "ld [hl], d", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::D),
+    0x73: "ld [hl], e", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::E),
+    0x74: "ld [hl], h", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::H),
+    0x75: "ld [hl], l", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::L),
+    0x76: "halt", 1, 4 => self.halt(bus),
+    0x77: "ld [hl], a", 1, 8 => self.store_register_indirect(bus, WideRegister::HL, Register::A),
+    0x78: "ld a, b", 1, 4 => self.copy(Register::A, Register::B),
+    0x79: "ld a, c", 1, 4 => self.copy(Register::A, Register::C),
+    0x7A: "ld a, d", 1, 4 => self.copy(Register::A, Register::D),
+    0x7B: "ld a, e", 1, 4 => self.copy(Register::A, Register::E),
+    0x7C: "ld a, h", 1, 4 => self.copy(Register::A, Register::H),
+    0x7D: "ld a, l", 1, 4 => self.copy(Register::A, Register::L),
+    0x7E: "ld a, [hl]", 1, 8 => self.load_register_indirect(bus, WideRegister::HL, Register::A),
+    0x7F: "ld a, a", 1, 4 => self.copy(Register::A, Register::A),
+
+    0x80: "add a, b", 1, 4 => self.add(Register::B),
+    0x81: "add a, c", 1, 4 => self.add(Register::C),
+    0x82: "add a, d", 1, 4 => self.add(Register::D),
+    0x83: "add a, e", 1, 4 => self.add(Register::E),
+    0x84: "add a, h", 1, 4 => self.add(Register::H),
+    0x85: "add a, l", 1, 4 => self.add(Register::L),
+    0x86: "add a, [hl]", 1, 8 => self.add_hl_indirect(bus),
+    0x87: "add a, a", 1, 4 => self.add(Register::A),
+    0x88: "adc a, b", 1, 4 => self.add_carry(Register::B),
+    0x89: "adc a, c", 1, 4 => self.add_carry(Register::C),
+    0x8A: "adc a, d", 1, 4 => self.add_carry(Register::D),
+    0x8B: "adc a, e", 1, 4 => self.add_carry(Register::E),
+    0x8C: "adc a, h", 1, 4 => self.add_carry(Register::H),
+    0x8D: "adc a, l", 1, 4 => self.add_carry(Register::L),
+    0x8E: "adc a, [hl]", 1, 8 => self.add_carry_hl_indirect(bus),
+    0x8F: "adc a, a", 1, 4 => self.add_carry(Register::A),
+
+    0x90: "sub b", 1, 4 => self.sub(Register::B),
+    0x91: "sub c", 1, 4 => self.sub(Register::C),
+    0x92: "sub d", 1, 4 => self.sub(Register::D),
+    0x93: "sub e", 1, 4 => self.sub(Register::E),
+    0x94: "sub h", 1, 4 => self.sub(Register::H),
+    0x95: "sub l", 1, 4 => self.sub(Register::L),
+    0x96: "sub [hl]", 1, 8 => self.sub_hl_indirect(bus),
+    0x97: "sub a", 1, 4 => self.sub(Register::A),
+    0x98: "sbc a, b", 1, 4 => self.sub_carry(Register::B),
+    0x99: "sbc a, c", 1, 4 => self.sub_carry(Register::C),
+    0x9A: "sbc a, d", 1, 4 => self.sub_carry(Register::D),
+    0x9B: "sbc a, e", 1, 4 => self.sub_carry(Register::E),
+    0x9C: "sbc a, h", 1, 4 => self.sub_carry(Register::H),
+    0x9D: "sbc a, l", 1, 4 => self.sub_carry(Register::L),
+    0x9E: "sbc a, [hl]", 1, 8 => self.sub_carry_hl_indirect(bus),
+    0x9F: "sbc a, a", 1, 4 => self.sub_carry(Register::A),
+
+    0xA0: "and b", 1, 4 => self.and(Register::B),
+    0xA1: "and c", 1, 4 => self.and(Register::C),
+    0xA2: "and d", 1, 4 => self.and(Register::D),
+    0xA3: "and e", 1, 4 => self.and(Register::E),
+    0xA4: "and h", 1, 4 => self.and(Register::H),
+    0xA5: "and l", 1, 4 => self.and(Register::L),
+    0xA6: "and [hl]", 1, 8 => self.and_hl_indirect(bus),
+    0xA7: "and a", 1, 4 => self.and(Register::A),
+    0xA8: "xor b", 1, 4 => self.xor(Register::B),
+    0xA9: "xor c", 1, 4 => self.xor(Register::C),
+    0xAA: "xor d", 1, 4 => self.xor(Register::D),
+    0xAB: "xor e", 1, 4 => self.xor(Register::E),
+    0xAC: "xor h", 1, 4 => self.xor(Register::H),
+    0xAD: "xor l", 1, 4 => self.xor(Register::L),
+    0xAE: "xor [hl]", 1, 8 => self.xor_hl_indirect(bus),
+    0xAF: "xor a", 1, 4 => self.xor(Register::A),
+
+    0xB0: "or b", 1, 4 => self.or(Register::B),
+    0xB1: "or c", 1, 4 => self.or(Register::C),
+    0xB2: "or d", 1, 4 => self.or(Register::D),
+    0xB3: "or e", 1, 4 => self.or(Register::E),
+    0xB4: "or h", 1, 4 => self.or(Register::H),
+    0xB5: "or l", 1, 4 => self.or(Register::L),
+    0xB6: "or [hl]", 1, 8 => self.or_hl_indirect(bus),
+    0xB7: "or a", 1, 4 => self.or(Register::A),
+    0xB8: "cp b", 1, 4 => self.compare(Register::B),
+    0xB9: "cp c", 1, 4 => self.compare(Register::C),
+    0xBA: "cp d", 1, 4 => self.compare(Register::D),
+    0xBB: "cp e", 1, 4 => self.compare(Register::E),
+    0xBC: "cp h", 1, 4 => self.compare(Register::H),
+    0xBD: "cp l", 1, 4 => self.compare(Register::L),
+    0xBE: "cp [hl]", 1, 8 => self.compare_hl_indirect(bus),
+    0xBF: "cp a", 1, 4 => self.compare(Register::A),
+
+    0xC0: "ret nz", 1, 8 / 20 => self.ret_condition(bus, Condition::NotZero),
+    0xC1: "pop bc", 1, 12 => self.pop(bus, WideRegister::BC),
+    0xC2: "jp nz, {a16}", 3, 12 / 16 => self.jmp_condition(bus, Condition::NotZero),
+    0xC3: "jp {a16}", 3, 16 => self.jmp(bus),
+    0xC4: "call nz, {a16}", 3, 12 / 24 => self.call_condition(bus, Condition::NotZero),
+    0xC5: "push bc", 1, 16 => self.push(bus, WideRegister::BC),
+    0xC6: "add a, {n8}", 2, 8 => self.add_immediate(bus),
+    0xC7: "rst $00", 1, 16 => self.rst(bus, 0x0000),
+    0xC8: "ret z", 1, 8 / 20 => self.ret_condition(bus, Condition::Zero),
+    0xC9: "ret", 1, 16 => self.ret(bus),
+    0xCA: "jp z, {a16}", 3, 12 / 16 => self.jmp_condition(bus, Condition::Zero),
+    0xCB: "", 2, 0 => self.cb(bus),
+    0xCC: "call z, {a16}", 3, 12 / 24 => self.call_condition(bus, Condition::Zero),
+    0xCD: "call {a16}", 3, 24 => self.call(bus),
+    0xCE: "adc a, {n8}", 2, 8 => self.add_carry_immediate(bus),
+    0xCF: "rst $08", 1, 16 => self.rst(bus, 0x0008),
+
+    0xD0: "ret nc", 1, 8 / 20 => self.ret_condition(bus, Condition::NotCarry),
+    0xD1: "pop de", 1, 12 => self.pop(bus, WideRegister::DE),
+    0xD2: "jp nc, {a16}", 3, 12 / 16 => self.jmp_condition(bus, Condition::NotCarry),
+    0xD3: "", 1, 4 => 4,
+    0xD4: "call nc, {a16}", 3, 12 / 24 => self.call_condition(bus, Condition::NotCarry),
+    0xD5: "push de", 1, 16 => self.push(bus, WideRegister::DE),
+    0xD6: "sub {n8}", 2, 8 => self.sub_immediate(bus),
+    0xD7: "rst $10", 1, 16 => self.rst(bus, 0x0010),
+    0xD8: "ret c", 1, 8 / 20 => self.ret_condition(bus, Condition::Carry),
+    0xD9: "reti", 1, 16 => self.reti(bus),
+    0xDA: "jp c, {a16}", 3, 12 / 16 => self.jmp_condition(bus, Condition::Carry),
+    0xDB: "", 1, 4 => 4,
+    0xDC: "call c, {a16}", 3, 12 / 24 => self.call_condition(bus, Condition::Carry),
+    0xDD: "", 1, 4 => 4,
+    0xDE: "sbc a, {n8}", 2, 8 => self.sub_carry_immediate(bus),
+    0xDF: "rst $18", 1, 16 => self.rst(bus, 0x0018),
+
+    0xE0: "ldh [{a8}], a", 2, 12 => self.store_high_indirect(bus),
+    0xE1: "pop hl", 1, 12 => self.pop(bus, WideRegister::HL),
+    0xE2: "ldh [c], a", 1, 8 => self.store_high_c_indirect(bus),
+    0xE3: "", 1, 4 => 4,
+    0xE4: "", 1, 4 => 4,
+    0xE5: "push hl", 1, 16 => self.push(bus, WideRegister::HL),
+    0xE6: "and {n8}", 2, 8 => self.and_immediate(bus),
+    0xE7: "rst $20", 1, 16 => self.rst(bus, 0x0020),
+    0xE8: "add sp, {e8}", 2, 16 => self.add_sp(bus),
+    0xE9: "jp hl", 1, 4 => self.jmp_hl(),
+    0xEA: "ld [{a16}], a", 3, 16 => self.store_indirect(bus),
+    0xEB: "", 1, 4 => 4,
+    0xEC: "", 1, 4 => 4,
+    0xED: "", 1, 4 => 4,
+    0xEE: "xor {n8}", 2, 8 => self.xor_immediate(bus),
+    0xEF: "rst $28", 1, 16 => self.rst(bus, 0x0028),
+
+    0xF0: "ldh a, [{a8}]", 2, 12 => self.load_high_indirect(bus),
+    0xF1: "pop af", 1, 12 => self.pop(bus, WideRegister::AF),
+    0xF2: "ldh a, [c]", 1, 8 => self.load_high_c_indirect(bus),
+    0xF3: "di", 1, 4 => self.di(),
+    0xF4: "", 1, 4 => 4,
+    0xF5: "push af", 1, 16 => self.push(bus, WideRegister::AF),
+    0xF6: "or {n8}", 2, 8 => self.or_immediate(bus),
+    0xF7: "rst $30", 1, 16 => self.rst(bus, 0x0030),
+    0xF8: "ld hl, sp + {e8}", 2, 12 => self.load_sp_indirect(bus),
+    0xF9: "ld sp, hl", 1, 8 => self.copy_wide(WideRegister::SP, WideRegister::HL),
+    0xFA: "ld a, [{a16}]", 3, 16 => self.load_indirect(bus),
+    0xFB: "ei", 1, 4 => self.ei(),
+    0xFC: "", 1, 4 => 4,
+    0xFD: "", 1, 4 => 4,
+    0xFE: "cp {n8}", 2, 8 => self.compare_immediate(bus),
+    0xFF: "rst $38", 1, 16 => self.rst(bus, 0x0038),
+}
@@ -0,0 +1,73 @@
+//! What the core can't do, reported instead of panicking
+
+use std::fmt::{self, Display, Formatter};
+
+use super::bus::Port;
+
+/// Something the emulator doesn't handle. The machine carries on as best it can: reads come
+/// back as open bus and writes go nowhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreError {
+    /// A port the hardware has but we don't emulate yet was read.
+    UnimplementedRead(u16),
+    /// A port the hardware has but we don't emulate yet was written.
+    UnimplementedWrite(u16, u8),
+    /// A device was handed an address it has nothing at, a bug in how the bus routes them.
+    Unmapped { device: &'static str, addr: u16 },
+}
+
+impl CoreError {
+    // the same problem, whatever value was written
+    fn same_as(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::UnimplementedWrite(a, _), Self::UnimplementedWrite(b, _)) => a == b,
+            _ => self == other,
+        }
+    }
+}
+
+impl Display for CoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::UnimplementedRead(addr) => {
+                write!(f, "game read unimplemented port {}", Port::label(addr))
+            }
+            Self::UnimplementedWrite(addr, value) => {
+                write!(
+                    f,
+                    "game wrote {value:02X} to unimplemented port {}",
+                    Port::label(addr)
+                )
+            }
+            Self::Unmapped { device, addr } => write!(f, "{device} has nothing at {addr:04X}"),
+        }
+    }
+}
+
+/// Every different [`CoreError`] so far and how many times it happened, in the order they
+/// first did. Each is logged as a warning the first time only, games tend to hit the same
+/// port over and over.
+#[derive(Default)]
+pub struct Errors {
+    seen: Vec<(CoreError, usize)>,
+}
+
+impl Errors {
+    pub fn report(&mut self, error: CoreError) {
+        match self.seen.iter_mut().find(|(seen, _)| seen.same_as(&error)) {
+            Some((_, count)) => *count += 1,
+            None => {
+                tracing::warn!("{error}");
+                self.seen.push((error, 1));
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&CoreError, usize)> {
+        self.seen.iter().map(|(error, count)| (error, *count))
+    }
+
+    pub fn clear(&mut self) {
+        self.seen.clear();
+    }
+}
@@ -0,0 +1,104 @@
+//! Counting how often each address is read, written and executed
+
+use super::bus::{Bus, Port};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read = 0,
+    Write = 1,
+    Execute = 2,
+}
+
+/// How often the CPU read, wrote and executed each address. Reads include fetching the
+/// instructions themselves. Counts saturate rather than wrap, a busy loop can run for a
+/// long time.
+pub struct Heatmap {
+    counts: Vec<[u32; 3]>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heatmap {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![[0; 3]; 0x10000],
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, addr: u16, access: Access) {
+        let count = &mut self.counts[addr as usize][access as usize];
+        *count = count.saturating_add(1);
+    }
+
+    #[inline]
+    pub fn count(&self, addr: u16, access: Access) -> u32 {
+        self.counts[addr as usize][access as usize]
+    }
+
+    /// Reads, writes and executions of `addr`.
+    #[inline]
+    pub fn counts(&self, addr: u16) -> [u32; 3] {
+        self.counts[addr as usize]
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.fill([0; 3]);
+    }
+
+    /// The `n` busiest addresses with their counts, busiest first.
+    pub fn hottest(&self, n: usize) -> Vec<(u16, [u32; 3])> {
+        let mut hot = (0..=0xFFFF)
+            .map(|addr| (addr, self.counts(addr)))
+            .filter(|(_, counts)| counts.iter().any(|&count| count != 0))
+            .collect::<Vec<_>>();
+        // ties go to the lower address, so the listing doesn't shuffle between runs
+        hot.sort_by_key(|&(addr, counts)| {
+            let total = counts.iter().map(|&count| count as u64).sum::<u64>();
+            (u64::MAX - total, addr)
+        });
+        hot.truncate(n);
+        hot
+    }
+}
+
+/// A bus that counts every access on its way through to the real one.
+pub struct Instrumented<'a, B> {
+    bus: &'a mut B,
+    heatmap: &'a mut Heatmap,
+}
+
+impl<'a, B: Bus> Instrumented<'a, B> {
+    pub fn new(bus: &'a mut B, heatmap: &'a mut Heatmap) -> Self {
+        Self { bus, heatmap }
+    }
+}
+
+impl<'a, B: Bus> Bus for Instrumented<'a, B> {
+    fn scanline(&mut self, ly: u8, line: &[u32; 160]) {
+        self.bus.scanline(ly, line);
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        // the CPU looks at IE and IF before every instruction, which would drown out
+        // whatever the program itself does with them
+        if (addr != Port::IE) && (addr != Port::IF) {
+            self.heatmap.record(addr, Access::Read);
+        }
+        self.bus.read(addr)
+    }
+
+    fn fetch(&mut self, addr: u16) -> u8 {
+        self.heatmap.record(addr, Access::Read);
+        self.bus.fetch(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.heatmap.record(addr, Access::Write);
+        self.bus.write(addr, value);
+    }
+}
@@ -0,0 +1,85 @@
+//! Logging the CPU's accesses to chosen IO ports, for working out what a game does with them
+
+use std::str::FromStr;
+
+use super::bus::Port;
+
+/// The IO ports ($FF00-$FF7F, and IE) whose reads and writes are logged at debug level,
+/// with the frame, line and PC they happened at. Parsed from a list like
+/// `FF40,FF46-FF4B` or `LCDC,BGP-WX`, ports by address or by name.
+#[derive(Clone, Default)]
+pub struct IoLog {
+    io: u128,
+    ie: bool,
+    // where the machine is, kept up by the emulator for the log lines
+    pub(super) frame: u64,
+    pub(super) pc: u16,
+}
+
+impl IoLog {
+    #[inline]
+    pub fn contains(&self, addr: u16) -> bool {
+        match addr {
+            0xFF00..=0xFF7F => (self.io & (1 << (addr - 0xFF00))) != 0,
+            Port::IE => self.ie,
+            _ => false,
+        }
+    }
+
+    fn insert(&mut self, addr: u16) {
+        match addr {
+            0xFF00..=0xFF7F => self.io |= 1 << (addr - 0xFF00),
+            _ => self.ie = true,
+        }
+    }
+
+    /// Frames finished since logging started.
+    #[inline]
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+// `FF40`, `$FF40` or `LCDC`
+fn parse_port(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let addr = match Port::INFO
+        .iter()
+        .find(|info| info.name.eq_ignore_ascii_case(s))
+    {
+        Some(info) => info.addr,
+        None => u16::from_str_radix(s.trim_start_matches('$'), 16)
+            .map_err(|_| format!("`{s}` isn't a port name or address"))?,
+    };
+    match addr {
+        0xFF00..=0xFF7F | Port::IE => Ok(addr),
+        _ => Err(format!("{addr:04X} isn't an IO port")),
+    }
+}
+
+impl FromStr for IoLog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut log = Self::default();
+        for part in s.split(',') {
+            let (start, end) = match part.split_once('-') {
+                Some((start, end)) => (parse_port(start)?, parse_port(end)?),
+                None => {
+                    let addr = parse_port(part)?;
+                    (addr, addr)
+                }
+            };
+            if start > end {
+                return Err(format!("`{}` runs backwards", part.trim()));
+            }
+            for addr in start..=end {
+                // IE is the only port past $FF7F, a range up to it skips HRAM
+                if (addr < 0xFF80) || (addr == Port::IE) {
+                    log.insert(addr);
+                }
+            }
+        }
+        Ok(log)
+    }
+}
@@ -0,0 +1,280 @@
+use std::hash::{Hash, Hasher};
+
+use crate::emu::{
+    bus::{Bus, BusDevice},
+    state::{Snapshot, State},
+};
+
+/// The width of the sensor's picture, in pixels.
+pub const WIDTH: usize = 128;
+/// The height of the sensor's picture, in pixels.
+pub const HEIGHT: usize = 112;
+
+// $A000-$A035, repeated every $80 bytes while they're mapped
+const REGISTERS: usize = 0x36;
+// the start of the 4x4 matrix of 3 thresholds each, one per shade
+const DITHER: usize = 0x06;
+// where a finished capture lands in RAM bank 0, as 16x14 tiles
+const PICTURE: usize = 0x0100;
+
+/// The Pocket Camera's MAC-GBD mapper, with the sensor's registers banked in over RAM.
+pub struct Camera<'a> {
+    rom: Vec<&'a [u8]>,
+    sram: Vec<&'a mut [u8]>,
+    rom_bank: u8,
+    // $4000-$5FFF, bit 4 maps the registers instead of a RAM bank
+    ram_select: u8,
+    sram_enable: bool,
+    registers: [u8; REGISTERS],
+    // normal speed cycles until the capture in progress lands in RAM
+    capture_cycles: usize,
+    // grayscale, 0 is black
+    image: Box<[u8; WIDTH * HEIGHT]>,
+}
+
+impl<'a> Camera<'a> {
+    pub fn new(rom: &'a [u8], sram: &'a mut [u8]) -> Self {
+        Self {
+            rom: rom.chunks(16384).collect(),
+            sram: sram.chunks_mut(8192).collect(),
+            rom_bank: 1,
+            ram_select: 0,
+            sram_enable: false,
+            registers: [0; REGISTERS],
+            capture_cycles: 0,
+            // a flat gray until there's something to point it at
+            image: Box::new([0x80; WIDTH * HEIGHT]),
+        }
+    }
+
+    /// What the sensor sees from now on, a [`WIDTH`] by [`HEIGHT`] grayscale picture where 0
+    /// is black.
+    pub fn set_image(&mut self, image: &[u8; WIDTH * HEIGHT]) {
+        self.image.copy_from_slice(image);
+    }
+
+    /// The bank currently mapped at $4000-$7FFF.
+    #[inline]
+    pub fn rom_bank(&self) -> usize {
+        self.rom_bank as usize
+    }
+
+    /// The bank currently mapped at $A000-$BFFF, unless the registers are.
+    #[inline]
+    pub fn sram_bank(&self) -> usize {
+        (self.ram_select & 0x0F) as usize
+    }
+
+    /// Whether a capture is still in progress.
+    #[inline]
+    pub fn capturing(&self) -> bool {
+        (self.registers[0] & 0x01) != 0
+    }
+
+    #[inline]
+    fn registers_mapped(&self) -> bool {
+        (self.ram_select & 0x10) != 0
+    }
+
+    fn write_register(&mut self, reg: usize, value: u8) {
+        if reg >= REGISTERS {
+            return;
+        }
+        if reg == 0 {
+            let start = !self.capturing() && ((value & 0x01) != 0);
+            self.registers[0] = value & 0x07;
+            if start {
+                self.capture_cycles = self.capture_time();
+            } else if (value & 0x01) == 0 {
+                // clearing the bit gives up on the capture
+                self.capture_cycles = 0;
+            }
+        } else {
+            self.registers[reg] = value;
+        }
+    }
+
+    // Pan Docs' capture time in 1 MiHz cycles, which is 4 of ours each. Clearing N takes
+    // another 512, and each step of exposure 16 more
+    fn capture_time(&self) -> usize {
+        let n = (self.registers[1] & 0x80) != 0;
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as usize;
+        (32446 + if n { 0 } else { 512 } + (16 * exposure)) * 4
+    }
+
+    // what the sensor hands over for one pixel after exposure and inversion, 0 is black.
+    // Past the edges the nearest pixel is used again
+    fn sensed(&self, x: isize, y: isize) -> i32 {
+        let x = x.clamp(0, (WIDTH - 1) as isize) as usize;
+        let y = y.clamp(0, (HEIGHT - 1) as isize) as usize;
+        let exposure = u16::from_be_bytes([self.registers[2], self.registers[3]]) as i32;
+        // an exposure of $1000 passes the light through as is
+        let value = ((self.image[y * WIDTH + x] as i32) * exposure / 0x1000).min(255);
+        if (self.registers[4] & 0x08) != 0 {
+            255 - value
+        } else {
+            value
+        }
+    }
+
+    // the shade a pixel is dithered to, 0 is white
+    fn shade(&self, x: usize, y: usize) -> u8 {
+        let (sx, sy) = (x as isize, y as isize);
+        let mut value = self.sensed(sx, sy);
+        // 2D edge enhancement sharpens against all 4 neighbours, the ratio is in quarters.
+        // Gain and the voltage references are left out, they only shift what exposure does
+        if (self.registers[1] & 0xE0) == 0xE0 {
+            const RATIO: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+            let ratio = RATIO[((self.registers[4] >> 4) & 0x07) as usize];
+            let edges = (4 * value)
+                - self.sensed(sx - 1, sy)
+                - self.sensed(sx + 1, sy)
+                - self.sensed(sx, sy - 1)
+                - self.sensed(sx, sy + 1);
+            value += edges * ratio / 4;
+        }
+        let matrix = DITHER + (((y & 3) * 4) + (x & 3)) * 3;
+        let thresholds = &self.registers[matrix..(matrix + 3)];
+        match value {
+            v if v < thresholds[0] as i32 => 3,
+            v if v < thresholds[1] as i32 => 2,
+            v if v < thresholds[2] as i32 => 1,
+            _ => 0,
+        }
+    }
+
+    fn capture(&mut self) {
+        let mut tiles = [0; (WIDTH / 8) * (HEIGHT / 8) * 16];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let shade = self.shade(x, y);
+                let row = ((((y / 8) * (WIDTH / 8)) + (x / 8)) * 16) + ((y % 8) * 2);
+                let bit = 0x80 >> (x % 8);
+                if (shade & 0x01) != 0 {
+                    tiles[row] |= bit;
+                }
+                if (shade & 0x02) != 0 {
+                    tiles[row + 1] |= bit;
+                }
+            }
+        }
+        // a short save may not reach the picture, or only some of it
+        if let Some(dst) = self
+            .sram
+            .first_mut()
+            .and_then(|bank| bank.get_mut(PICTURE..))
+        {
+            let len = dst.len().min(tiles.len());
+            dst[..len].copy_from_slice(&tiles[..len]);
+        }
+    }
+}
+
+impl<'a, B: Bus> BusDevice<B> for Camera<'a> {
+    fn reset(&mut self, _bus: &mut B) {
+        self.rom_bank = 1;
+        self.ram_select = 0;
+        self.sram_enable = false;
+        self.registers = [0; REGISTERS];
+        self.capture_cycles = 0;
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let byte = match addr {
+            0x0000..=0x3FFF => self.rom.first().and_then(|bank| bank.get(addr as usize)),
+            0x4000..=0x7FFF => self
+                .rom
+                .get(self.rom_bank())
+                .and_then(|bank| bank.get((addr - 0x4000) as usize)),
+            // only the register that says whether a capture is done can be read back
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                return match (addr & 0x7F) as usize {
+                    0 => self.registers[0],
+                    _ => 0x00,
+                };
+            }
+            // unlike the MBCs, RAM reads work whether it's enabled or not
+            0xA000..=0xBFFF => self
+                .sram
+                .get(self.sram_bank())
+                .and_then(|bank| bank.get((addr - 0xA000) as usize)),
+            _ => None,
+        };
+        byte.copied().unwrap_or(0xFF)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.sram_enable = (value & 0x0F) == 0x0A,
+            // all 6 bits, 0 included, so bank 0 can be mapped twice
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => self.ram_select = value & 0x1F,
+            0xA000..=0xBFFF if self.registers_mapped() => {
+                self.write_register((addr & 0x7F) as usize, value)
+            }
+            0xA000..=0xBFFF if self.sram_enable => {
+                let bank = self.sram_bank();
+                if let Some(byte) = self
+                    .sram
+                    .get_mut(bank)
+                    .and_then(|bank| bank.get_mut((addr - 0xA000) as usize))
+                {
+                    *byte = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, _bus: &mut B) -> usize {
+        0
+    }
+
+    fn rom_offset(&self, addr: u16) -> Option<usize> {
+        let (bank, offset) = match addr {
+            0x0000..=0x3FFF => (0, addr as usize),
+            0x4000..=0x7FFF => (self.rom_bank(), (addr - 0x4000) as usize),
+            _ => return None,
+        };
+        self.rom
+            .get(bank)
+            .filter(|bank| offset < bank.len())
+            .map(|_| bank * 0x4000 + offset)
+    }
+
+    fn advance(&mut self, cycles: usize) {
+        if self.capture_cycles == 0 {
+            return;
+        }
+        self.capture_cycles = self.capture_cycles.saturating_sub(cycles);
+        if self.capture_cycles == 0 {
+            self.capture();
+            self.registers[0] &= !0x01;
+        }
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) {
+        (
+            self.rom_bank,
+            self.ram_select,
+            self.sram_enable,
+            self.registers,
+            self.capture_cycles,
+        )
+            .hash(&mut state);
+        self.image.hash(&mut state);
+        self.sram.hash(&mut state);
+    }
+
+    // not the picture, that's whatever the camera is pointed at now
+    fn snapshot_state(&mut self, state: &mut State<'_>) {
+        self.rom_bank.snapshot(state);
+        self.ram_select.snapshot(state);
+        self.sram_enable.snapshot(state);
+        self.registers.snapshot(state);
+        self.capture_cycles.snapshot(state);
+        for bank in &mut self.sram {
+            state.bytes(bank);
+        }
+    }
+}
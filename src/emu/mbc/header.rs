@@ -0,0 +1,165 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgbSupport {
+    None,
+    Compatible,
+    Only,
+}
+
+/// The cartridge header at $0100-$014F.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub title: String,
+    pub manufacturer: Option<String>,
+    pub cgb: CgbSupport,
+    pub licensee: String,
+    pub sgb: bool,
+    pub cart_type: u8,
+    pub rom_size: Option<usize>,
+    pub ram_size: Option<usize>,
+    pub japanese: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    computed_header_checksum: u8,
+    computed_global_checksum: u16,
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        if rom.len() < 0x150 {
+            return None;
+        }
+        let cgb = match rom[0x143] {
+            0xC0 => CgbSupport::Only,
+            0x80 => CgbSupport::Compatible,
+            _ => CgbSupport::None,
+        };
+        // newer carts steal the end of the title for the manufacturer code and CGB flag
+        let manufacturer = &rom[0x13F..0x143];
+        let manufacturer = (manufacturer
+            .iter()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+            && (cgb != CgbSupport::None))
+            .then(|| String::from_utf8_lossy(manufacturer).into_owned());
+        let title_end = match (cgb, &manufacturer) {
+            (_, Some(_)) => 0x13F,
+            (CgbSupport::None, None) => 0x144,
+            _ => 0x143,
+        };
+        let title = rom[0x134..title_end]
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| if c.is_ascii_graphic() { c as char } else { ' ' })
+            .collect::<String>();
+        let licensee = if rom[0x14B] == 0x33 {
+            String::from_utf8_lossy(&rom[0x144..0x146]).into_owned()
+        } else {
+            format!("{:02X}", rom[0x14B])
+        };
+        let rom_size = match rom[0x148] {
+            n @ 0x00..=0x08 => Some(0x8000 << n),
+            0x52 => Some(72 * 0x4000),
+            0x53 => Some(80 * 0x4000),
+            0x54 => Some(96 * 0x4000),
+            _ => None,
+        };
+        let ram_size = match rom[0x149] {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        };
+        let computed_header_checksum = rom[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |x, &b| x.wrapping_sub(b).wrapping_sub(1));
+        let computed_global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| (i != 0x14E) && (i != 0x14F))
+            .fold(0u16, |x, (_, &b)| x.wrapping_add(b as u16));
+        Some(Self {
+            title,
+            manufacturer,
+            cgb,
+            licensee,
+            sgb: rom[0x146] == 0x03,
+            cart_type: rom[0x147],
+            rom_size,
+            ram_size,
+            japanese: rom[0x14A] == 0x00,
+            version: rom[0x14C],
+            header_checksum: rom[0x14D],
+            global_checksum: u16::from_be_bytes([rom[0x14E], rom[0x14F]]),
+            computed_header_checksum,
+            computed_global_checksum,
+        })
+    }
+
+    /// The boot ROM refuses to start a cart that fails this one.
+    pub fn header_checksum_ok(&self) -> bool {
+        self.header_checksum == self.computed_header_checksum
+    }
+
+    /// Nothing on hardware checks this, but a mismatch usually means a bad dump.
+    pub fn global_checksum_ok(&self) -> bool {
+        self.global_checksum == self.computed_global_checksum
+    }
+
+    /// Whether the cart keeps its RAM (or clock) powered with a battery, so it's saved.
+    pub fn battery(&self) -> bool {
+        matches!(
+            self.cart_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFC | 0xFF
+        )
+    }
+
+    /// Whether the cart has MBC3's real time clock.
+    pub fn rtc(&self) -> bool {
+        matches!(self.cart_type, 0x0F | 0x10)
+    }
+
+    /// The bytes of RAM in a save, which MBC2 has built in rather than in the header.
+    pub fn save_size(&self) -> usize {
+        match self.cart_type {
+            0x05 | 0x06 => 512,
+            _ => self.ram_size.unwrap_or(0),
+        }
+    }
+
+    pub fn mapper(&self) -> &'static str {
+        match self.cart_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "UNKNOWN",
+        }
+    }
+}
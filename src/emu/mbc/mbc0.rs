@@ -1,4 +1,9 @@
-use crate::emu::bus::{Bus, BusDevice};
+use std::hash::{Hash, Hasher};
+
+use crate::emu::{
+    bus::{Bus, BusDevice},
+    state::State,
+};
 
 pub struct Mbc0<'a> {
     rom: &'a [u8],
@@ -16,20 +21,39 @@ impl<'a, B: Bus> BusDevice<B> for Mbc0<'a> {
 
     fn read(&mut self, addr: u16) -> u8 {
         match addr {
-            0x0000..=0x7FFF => self.rom[addr as usize],
-            //0xA000..=0xBFFF => self.sram[(addr - 0xA000) as usize],
+            // anything past the end of a short ROM reads as open bus
+            0x0000..=0x7FFF => self.rom.get(addr as usize).copied().unwrap_or(0xFF),
+            // there's no mapper to enable RAM with, whatever RAM there is is always there
+            0xA000..=0xBFFF => self
+                .sram
+                .get((addr - 0xA000) as usize)
+                .copied()
+                .unwrap_or(0xFF),
             _ => 0xFF,
         }
     }
 
     fn write(&mut self, addr: u16, value: u8) {
-        match addr {
-            //0xA000..=0xBFFF => self.sram[(addr - 0xA000) as usize] = value,
-            _ => {}
+        if let 0xA000..=0xBFFF = addr {
+            if let Some(byte) = self.sram.get_mut((addr - 0xA000) as usize) {
+                *byte = value;
+            }
         }
     }
 
     fn tick(&mut self, _bus: &mut B) -> usize {
         0
     }
+
+    fn rom_offset(&self, addr: u16) -> Option<usize> {
+        Some(addr as usize).filter(|&offset| offset < self.rom.len())
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) {
+        self.sram.hash(&mut state);
+    }
+
+    fn snapshot_state(&mut self, state: &mut State<'_>) {
+        state.bytes(self.sram);
+    }
 }
@@ -1,12 +1,21 @@
-use crate::emu::bus::{Bus, BusDevice};
+use std::hash::{Hash, Hasher};
+
+use crate::emu::{
+    bus::{Bus, BusDevice},
+    state::{Snapshot, State},
+};
 
 pub struct Mbc1<'a> {
     rom: Vec<&'a [u8]>,
     sram: Vec<&'a mut [u8]>,
-    rom_bank: u8,
-    sram_bank: u8,
+    // $2000-$3FFF, the low ROM bank bits
+    bank1: u8,
+    // $4000-$5FFF, the high ROM bank bits or the RAM bank
+    bank2: u8,
     bank_mode: u8,
     sram_enable: bool,
+    // MBC1M wires BANK2 one bit lower, leaving BANK1 only 4 bits
+    multicart: bool,
 }
 
 impl<'a> Mbc1<'a> {
@@ -14,63 +23,137 @@ impl<'a> Mbc1<'a> {
         Self {
             rom: rom.chunks(16384).collect(),
             sram: sram.chunks_mut(8192).collect(),
-            rom_bank: 0,
-            sram_bank: 0,
+            bank1: 1,
+            bank2: 0,
             bank_mode: 0,
             sram_enable: false,
+            multicart: false,
+        }
+    }
+
+    /// An MBC1M multicart, where each game is its own 256 KiB and BANK2 picks which.
+    pub fn multicart(rom: &'a [u8], sram: &'a mut [u8]) -> Self {
+        Self {
+            multicart: true,
+            ..Self::new(rom, sram)
+        }
+    }
+
+    /// A multicart if `rom` looks like one, otherwise a plain MBC1.
+    pub fn detect(rom: &'a [u8], sram: &'a mut [u8]) -> Self {
+        if Self::is_multicart(rom) {
+            Self::multicart(rom, sram)
+        } else {
+            Self::new(rom, sram)
+        }
+    }
+
+    /// Whether `rom` looks like an MBC1M multicart: 1 MiB, with another game's header
+    /// (going by the boot logo) at the start of one of the other 256 KiB quarters. Plain
+    /// MBC1 carts have no reason to put one there.
+    pub fn is_multicart(rom: &[u8]) -> bool {
+        // the start of the logo is plenty to tell it from anything else
+        const LOGO: [u8; 8] = [0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B];
+        if rom.len() != 0x100000 {
+            return false;
+        }
+        [0x40000, 0x80000, 0xC0000]
+            .iter()
+            .any(|&game| rom[(game + 0x0104)..(game + 0x010C)] == LOGO)
+    }
+
+    /// The bank currently mapped at $4000-$7FFF.
+    #[inline]
+    pub fn rom_bank(&self) -> usize {
+        self.high_bits() | self.low_bits()
+    }
+
+    /// The bank currently mapped at $A000-$BFFF.
+    #[inline]
+    pub fn sram_bank(&self) -> usize {
+        if self.bank_mode == 1 {
+            (self.bank2 as usize) & bank_mask(self.sram.len())
+        } else {
+            0
+        }
+    }
+
+    /// The bank currently mapped at $0000-$3FFF, which mode 1 banks too on big carts.
+    #[inline]
+    pub fn rom0_bank(&self) -> usize {
+        if self.bank_mode == 1 {
+            self.high_bits()
+        } else {
+            0
         }
     }
+
+    #[inline]
+    fn high_bits(&self) -> usize {
+        let shift = if self.multicart { 4 } else { 5 };
+        ((self.bank2 as usize) << shift) & bank_mask(self.rom.len())
+    }
+
+    #[inline]
+    fn low_bits(&self) -> usize {
+        let mask = if self.multicart { 0x0F } else { 0x1F };
+        (self.bank1 as usize) & mask & bank_mask(self.rom.len())
+    }
+}
+
+// the bank bits that have a chip address line behind them, anything past the end of a
+// truncated dump is left to read as open bus
+fn bank_mask(banks: usize) -> usize {
+    banks.next_power_of_two() - 1
 }
 
 impl<'a, B: Bus> BusDevice<B> for Mbc1<'a> {
     fn reset(&mut self, _bus: &mut B) {
-        self.rom_bank = 0;
-        self.sram_bank = 0;
+        self.bank1 = 1;
+        self.bank2 = 0;
         self.bank_mode = 0;
         self.sram_enable = false;
     }
 
     fn read(&mut self, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0x3FFF => self.rom[0][addr as usize],
-            0x4000..=0x7FFF => self.rom[self.rom_bank as usize][(addr - 0x4000) as usize],
-            0xA000..=0xBFFF => self.sram[self.sram_bank as usize][(addr - 0xA000) as usize],
-            _ => 0xFF,
-        }
+        // truncated dumps and missing RAM read as open bus rather than taking us down
+        let byte = match addr {
+            0x0000..=0x3FFF => self
+                .rom
+                .get(self.rom0_bank())
+                .and_then(|bank| bank.get(addr as usize)),
+            0x4000..=0x7FFF => self
+                .rom
+                .get(self.rom_bank())
+                .and_then(|bank| bank.get((addr - 0x4000) as usize)),
+            // disabled RAM isn't driving the bus, which keeps saves safe from stray writes
+            0xA000..=0xBFFF if self.sram_enable => self
+                .sram
+                .get(self.sram_bank())
+                .and_then(|bank| bank.get((addr - 0xA000) as usize)),
+            _ => None,
+        };
+        byte.copied().unwrap_or(0xFF)
     }
 
     fn write(&mut self, addr: u16, value: u8) {
         match addr {
-            0x0000..=0x1FFF => self.sram_enable = value != 0,
-            0x2000..=0x3FFF => {
-                let lo = value & 0x1F;
-                // quirk to translate bank 0 (and some others) one bank up
-                let lo = match lo {
-                    0x00 => 0x01,
-                    0x20 => 0x21,
-                    0x40 => 0x41,
-                    0x60 => 0x61,
-                    _ => lo,
-                };
-                self.rom_bank = (self.rom_bank & 0xE0) | lo;
-                // make sure bank wraps around actual rom size
-                self.rom_bank &= (self.rom.len() - 1) as u8;
-            }
-            0x4000..=0x5FFF => {
-                if self.bank_mode == 0 {
-                    let hi = (value & 0x03) << 5;
-                    self.rom_bank = (self.rom_bank & 0x1F) | hi;
-                    // make sure bank wraps around actual rom size
-                    self.rom_bank &= (self.rom.len() - 1) as u8;
-                } else {
-                    self.sram_bank = value & 0x03;
-                    // make sure bank wraps around actual ram size
-                    self.sram_bank &= (self.sram.len() - 1) as u8;
-                }
-            }
+            // only $A in the low nibble enables, anything else disables
+            0x0000..=0x1FFF => self.sram_enable = (value & 0x0F) == 0x0A,
+            // 0 can't be picked, it's bumped to 1 before any masking. So a small cart can
+            // still map bank 0 here with e.g. $10, as can a multicart
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
             0x6000..=0x7FFF => self.bank_mode = value & 0x01,
             0xA000..=0xBFFF if self.sram_enable => {
-                self.sram[self.sram_bank as usize][(addr - 0xA000) as usize] = value
+                let bank = self.sram_bank();
+                if let Some(byte) = self
+                    .sram
+                    .get_mut(bank)
+                    .and_then(|bank| bank.get_mut((addr - 0xA000) as usize))
+                {
+                    *byte = value;
+                }
             }
             _ => {}
         }
@@ -79,4 +162,38 @@ impl<'a, B: Bus> BusDevice<B> for Mbc1<'a> {
     fn tick(&mut self, _bus: &mut B) -> usize {
         0
     }
+
+    fn rom_offset(&self, addr: u16) -> Option<usize> {
+        let (bank, offset) = match addr {
+            0x0000..=0x3FFF => (self.rom0_bank(), addr as usize),
+            0x4000..=0x7FFF => (self.rom_bank(), (addr - 0x4000) as usize),
+            _ => return None,
+        };
+        self.rom
+            .get(bank)
+            .filter(|bank| offset < bank.len())
+            .map(|_| bank * 0x4000 + offset)
+    }
+
+    fn hash_state(&self, mut state: &mut dyn Hasher) {
+        (
+            self.bank1,
+            self.bank2,
+            self.bank_mode,
+            self.sram_enable,
+            self.multicart,
+        )
+            .hash(&mut state);
+        self.sram.hash(&mut state);
+    }
+
+    fn snapshot_state(&mut self, state: &mut State<'_>) {
+        self.bank1.snapshot(state);
+        self.bank2.snapshot(state);
+        self.bank_mode.snapshot(state);
+        self.sram_enable.snapshot(state);
+        for bank in &mut self.sram {
+            state.bytes(bank);
+        }
+    }
 }
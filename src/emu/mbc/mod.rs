@@ -1,2 +1,5 @@
+pub mod camera;
+pub mod header;
 pub mod mbc0;
 pub mod mbc1;
+pub mod sav;
@@ -0,0 +1,102 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// RAM always comes in multiples of this (MBC2's is exactly this), so anything left over is
+// an RTC footer
+const RAM_UNIT: usize = 512;
+
+/// MBC3's clock as BGB and VBA store it after the RAM in a save: seconds, minutes, hours,
+/// and the day counter's low and high bytes, then the same again as last latched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rtc {
+    pub registers: [u8; 5],
+    pub latched: [u8; 5],
+    /// When the save was written, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl Rtc {
+    /// The footer's usual size, with a 64-bit timestamp. Older VBA builds wrote a 32-bit
+    /// one, leaving it 44 bytes.
+    pub const LEN: usize = 48;
+
+    /// A stopped clock at zero, as of now.
+    pub fn new() -> Self {
+        Self {
+            registers: [0; 5],
+            latched: [0; 5],
+            timestamp: now(),
+        }
+    }
+
+    fn parse(footer: &[u8]) -> Self {
+        let word = |i: usize| footer[i * 4];
+        let timestamp = match footer.len() {
+            Self::LEN => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            _ => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+        };
+        Self {
+            registers: [word(0), word(1), word(2), word(3), word(4)],
+            latched: [word(5), word(6), word(7), word(8), word(9)],
+            timestamp,
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        // each register takes a little-endian word of its own
+        for &register in self.registers.iter().chain(&self.latched) {
+            out.extend_from_slice(&(register as u32).to_le_bytes());
+        }
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+/// A battery save as other emulators write them: the cart's RAM as is, then the clock for
+/// carts that have one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Save {
+    pub sram: Vec<u8>,
+    pub rtc: Option<Rtc>,
+}
+
+impl Save {
+    /// Splits a `.sav` file into RAM and clock, taking either size of RTC footer.
+    pub fn parse(data: &[u8]) -> Self {
+        let footer = match data.len() % RAM_UNIT {
+            44 | 48 => data.len() % RAM_UNIT,
+            _ => 0,
+        };
+        let (sram, footer) = data.split_at(data.len() - footer);
+        Self {
+            sram: sram.to_vec(),
+            rtc: (!footer.is_empty()).then(|| Rtc::parse(footer)),
+        }
+    }
+
+    /// Cuts the RAM down or pads it out with zeroes to `size` bytes. Emulators disagree on
+    /// how much to save for carts with less than a full bank, so saves from elsewhere often
+    /// need it.
+    pub fn resize(&mut self, size: usize) {
+        self.sram.resize(size, 0x00);
+    }
+
+    /// The `.sav` file: the RAM, then a 48-byte RTC footer if there's a clock.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.sram.clone();
+        if let Some(rtc) = &self.rtc {
+            rtc.write(&mut out);
+        }
+        out
+    }
+}
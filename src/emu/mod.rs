@@ -1,110 +1,271 @@
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+};
+
 use self::{
+    apu::Apu,
+    audio::AudioSink,
     bus::{Bus, BusDevice, Port},
-    cpu::Cpu,
+    cdl::Cdl,
+    cpu::{Cpu, StepInfo, WideRegister},
+    error::{CoreError, Errors},
+    heatmap::{Access, Heatmap, Instrumented},
+    iolog::IoLog,
     ppu::Ppu,
+    serial::SerialOutput,
+    sgb::Sgb,
+    state::{Snapshot, State},
+    video::{NullSink, VideoSink},
 };
+use crate::config::{Model, Revision, Settings};
 
 mod apu;
+pub mod audio;
 pub mod bus;
+pub mod cdl;
 pub mod cpu;
+pub mod error;
+pub mod heatmap;
+pub mod iolog;
 pub mod mbc;
-mod ppu;
+pub mod ppu;
+pub mod serial;
+pub mod sgb;
+pub mod state;
+pub mod video;
+
+pub use apu::{ApuState, ChannelState};
 
 pub struct Emu<M, P, I> {
-    boot_data: Vec<u8>,
-    vblanked: bool,
     cpu: Cpu,
-    mbc: M,
     ppu: P,
-    input: I,
-    lcd: [[u32; 160]; 144],
-    wram: [[u8; 4096]; 8],
-    hram: [u8; 256],
-    iflags: u8,
-    boot: u8,
-    svbk: u8,
-    sc: u8,
-    div: u8,
-    tima: u8,
-    tma: u8,
-    tac: u8,
-    ie: u8,
+    chipset: Chipset<M, I>,
     div_counter: usize,
     tima_counter: usize,
+    // cycles left before the CPU wakes from the HALT a speed switch leaves it in
+    speed_switch: usize,
+    // only while someone's looking, it costs on every access
+    heatmap: Option<Box<Heatmap>>,
 }
 
+// how long a speed switch keeps the CPU out when no interrupt cuts it short
+const SPEED_SWITCH_CYCLES: usize = 0x20000;
+
+// bumped whenever what goes into a save state changes
+const STATE_MAGIC: &[u8] = b"GB23STATE";
+const STATE_VERSION: u8 = 1;
+
 impl<M: BusDevice<NoopView>, I: BusDevice<NoopView>> Emu<M, Ppu, I> {
-    pub fn new(boot_data: Vec<u8>, mbc: M, input: I) -> Self {
+    pub fn new(settings: &Settings, boot_data: Vec<u8>, mut mbc: M, input: I) -> Self {
         let cpu = Cpu::new();
-        let ppu = Ppu::new();
+        let cgb_mode = boot_cgb_mode(settings.model, &boot_data, &mut mbc);
+        let sgb = (settings.model == Model::Sgb).then(|| {
+            // same check the SGB BIOS does before it listens for packets
+            let enabled = (mbc.read(0x0146) == 0x03) && (mbc.read(0x014B) == 0x33);
+            Sgb::new(enabled, settings.palette)
+        });
+        // the SGB colors the screen itself, so the PPU only hands it shades
+        let palette = if sgb.is_some() {
+            [0, 1, 2, 3]
+        } else {
+            settings.palette
+        };
+        let mut ppu = Ppu::new(settings.model, palette, settings.seed);
+        ppu.set_x_priority(!cgb_mode);
         let lcd = [[0; 160]; 144];
         Self {
-            boot_data,
-            vblanked: false,
             cpu,
-            mbc,
             ppu,
-            input,
-            lcd,
-            wram: [[0xFF; 4096]; 8],
-            hram: [0xFF; 256],
-            iflags: 0,
-            boot: 0,
-            svbk: 0,
-            sc: 0,
-            div: 0,
-            tima: 0,
-            tma: 0,
-            tac: 0,
-            ie: 0,
+            chipset: Chipset {
+                model: settings.model,
+                revision: settings.revision,
+                cgb_mode,
+                boot_data,
+                vblanked: false,
+                ppu_cycles: 0,
+                mbc,
+                input,
+                sgb,
+                sgb_screen: None,
+                cdl: None,
+                errors: Errors::default(),
+                io_log: None,
+                lcd,
+                video: Box::new(NullSink),
+                apu: Apu::new(settings.model == Model::Cgb, settings.sample_rate),
+                audio: Box::new(audio::NullSink),
+                ly_stub: None,
+                wram: [[0xFF; 4096]; 8],
+                hram: [0xFF; 127],
+                iflags: 0,
+                boot: 0,
+                svbk: 0,
+                opri: 0,
+                key0: 0,
+                key1: 0,
+                double_speed: false,
+                undoc: [0; 4],
+                sb: 0,
+                sc: 0,
+                serial_cycles: 0,
+                serial_waiting: false,
+                serial_out: SerialOutput::default(),
+                linked: false,
+                div: 0,
+                tima: 0,
+                tma: 0,
+                tac: 0,
+                ie: 0,
+            },
             div_counter: 0,
             tima_counter: 0,
+            speed_switch: 0,
+            heatmap: None,
         }
     }
 
     pub fn reset(&mut self) {
         let (cpu, mut cpu_view) = self.cpu_view();
         cpu.reset(&mut cpu_view);
-        let (ppu, mut ppu_view) = self.ppu_view();
-        ppu.reset(&mut ppu_view);
-        self.input.reset(&mut NoopView {});
-        self.mbc.reset(&mut NoopView {});
-        self.vblanked = false;
-        self.iflags = 0;
-        self.svbk = 0;
-        self.sc = 0;
-        self.div = 0;
-        self.tima = 0;
-        self.tma = 0;
-        self.tac = 0;
-        self.ie = 0;
+        let (ppu, ppu_view) = self.ppu_view();
+        ppu.reset(ppu_view);
+        let chipset = &mut self.chipset;
+        chipset.input.reset(&mut NoopView {});
+        chipset.mbc.reset(&mut NoopView {});
+        if let Some(sgb) = &mut chipset.sgb {
+            sgb.reset();
+        }
+        chipset.apu.reset();
+        chipset.iflags = 0;
+        chipset.svbk = 0;
+        chipset.opri = 0;
+        chipset.key0 = 0;
+        chipset.key1 = 0;
+        chipset.double_speed = false;
+        chipset.cgb_mode = boot_cgb_mode(chipset.model, &chipset.boot_data, &mut chipset.mbc);
+        self.ppu.set_x_priority(chipset.x_priority());
+        chipset.undoc = [0; 4];
+        chipset.sb = 0;
+        chipset.sc = 0;
+        chipset.serial_cycles = 0;
+        chipset.serial_waiting = false;
+        chipset.div = 0;
+        chipset.tima = 0;
+        chipset.tma = 0;
+        chipset.tac = 0;
+        chipset.ie = 0;
+        chipset.vblanked = false;
+        chipset.ppu_cycles = 0;
         self.div_counter = 0;
         self.tima_counter = 0;
+        self.speed_switch = 0;
     }
 
+    /// Runs one instruction (or interrupt dispatch, or a halted or stopped stretch) and
+    /// returns how long it took in normal speed cycles, so twice as many CPU cycles can
+    /// pass in the same time in double speed.
     pub fn tick(&mut self) -> usize {
-        let (cpu, mut cpu_view) = self.cpu_view();
-        let cycles = cpu.tick(&mut cpu_view);
-        // TODO: mbc tick?
-        let (ppu, mut ppu_view) = self.ppu_view();
-        let mut vblank = 0;
-        for _ in 0..cycles {
-            vblank += ppu.tick(&mut ppu_view);
-        }
-        if vblank != 0 {
-            self.vblanked = true;
+        self.step().cycles
+    }
+
+    /// [`Emu::tick`], saying what the CPU ran. Its cycles are normal speed cycles too.
+    #[inline]
+    pub fn step(&mut self) -> StepInfo {
+        self.step_with(|_, _, _| {})
+    }
+
+    /// [`Emu::step`], with a hook run before the instruction as [`Cpu::step_with`] does.
+    pub fn step_with(&mut self, pre: impl FnMut(&Cpu, u16, u8)) -> StepInfo {
+        let was_stopped = self.cpu.stopped();
+        let Self {
+            ref mut cpu,
+            ref mut ppu,
+            ref mut chipset,
+            ref mut heatmap,
+            ..
+        } = self;
+        if let Some(log) = &mut chipset.io_log {
+            log.pc = cpu.wide_register(WideRegister::PC);
         }
-        self.input.tick(&mut NoopView {});
-        // timers
-        self.div_counter += cycles;
-        // TODO: verify this value needs to be 1024 vs 256
-        if self.div_counter >= 1024 {
-            self.div_counter -= 1024;
-            self.div = self.div.wrapping_add(1);
-        }
-        if (self.tac & 0x04) != 0 {
-            self.tima_counter += cycles;
-            let freq = match self.tac & 0x03 {
+        let mut cpu_view = CpuView {
+            ppu,
+            chipset,
+            logged: true,
+        };
+        let mut step = match heatmap {
+            Some(heatmap) => {
+                let step = cpu.step_with(&mut Instrumented::new(&mut cpu_view, heatmap), pre);
+                if step.opcode.is_some() {
+                    heatmap.record(step.pc_before, Access::Execute);
+                }
+                step
+            }
+            None => cpu.step_with(&mut cpu_view, pre),
+        };
+        step.cycles = self.run_chipset(was_stopped, step.cycles);
+        step
+    }
+
+    // everything else catching up with `cpu_cycles` of the CPU, returning how long that was
+    // in normal speed cycles
+    fn run_chipset(&mut self, was_stopped: bool, cpu_cycles: usize) -> usize {
+        if self.cpu.stopped() {
+            if was_stopped {
+                self.wake(cpu_cycles);
+            } else {
+                self.stop();
+            }
+        }
+        // the PPU and APU keep to normal speed whatever the CPU does
+        let chipset = &mut self.chipset;
+        let cycles = if chipset.double_speed {
+            cpu_cycles / 2
+        } else {
+            cpu_cycles
+        };
+        // the PPU is only caught up once it has something observable to do
+        chipset.ppu_cycles += cycles;
+        if chipset.ppu_cycles > self.ppu.idle_dots() {
+            chipset.sync_ppu(&mut self.ppu);
+        }
+        chipset.input.tick(&mut NoopView {});
+        chipset.mbc.advance(cycles);
+        chipset.apu.tick(cycles, &mut *chipset.audio);
+        // serial, only clocked from here when we're the side driving the clock
+        if ((chipset.sc & 0x81) == 0x81) && !chipset.serial_waiting {
+            chipset.serial_cycles += cpu_cycles;
+            // CGB's fast clock shifts 32 times quicker
+            let period = if chipset.cgb_mode && ((chipset.sc & 0x02) != 0) {
+                128
+            } else {
+                4096
+            };
+            if chipset.serial_cycles >= period {
+                chipset.serial_cycles = 0;
+                if chipset.linked {
+                    chipset.serial_waiting = true;
+                } else {
+                    // nothing plugged in, the line floats high
+                    chipset.finish_transfer(0xFF);
+                }
+            }
+        }
+        // timers, which STOP stops along with the CPU
+        if self.cpu.stopped() {
+            return cycles;
+        }
+        // DIV counts at 16384Hz, twice that in double speed
+        self.div_counter += cpu_cycles;
+        while self.div_counter >= 256 {
+            self.div_counter -= 256;
+            let div = chipset.div;
+            chipset.div = div.wrapping_add(1);
+            chipset.clock_div_apu(div);
+        }
+        if (chipset.tac & 0x04) != 0 {
+            self.tima_counter += cpu_cycles;
+            let freq = match chipset.tac & 0x03 {
                 0x00 => 4096,
                 0x01 => 262144,
                 0x02 => 65536,
@@ -113,13 +274,13 @@ impl<M: BusDevice<NoopView>, I: BusDevice<NoopView>> Emu<M, Ppu, I> {
             };
             let period = 4194304 / freq;
             while self.tima_counter >= period {
-                let (result, carry) = self.tima.overflowing_add(1);
+                let (result, carry) = chipset.tima.overflowing_add(1);
                 // timer interrupt
                 if carry {
-                    self.iflags |= 0x04;
-                    self.tima = self.tma;
+                    chipset.iflags |= 0x04;
+                    chipset.tima = chipset.tma;
                 } else {
-                    self.tima = result;
+                    chipset.tima = result;
                 }
                 self.tima_counter = self.tima_counter.wrapping_sub(period);
             }
@@ -127,21 +288,343 @@ impl<M: BusDevice<NoopView>, I: BusDevice<NoopView>> Emu<M, Ppu, I> {
         cycles
     }
 
+    // STOP stops until a button is pressed, switches CPU speed, or does neither and acts
+    // like HALT (or a NOP), going by the buttons, interrupts and KEY1 as Pan Docs lays out
+    fn stop(&mut self) {
+        let chipset = &mut self.chipset;
+        let pending = (chipset.ie & chipset.iflags & 0x1F) != 0;
+        let held = (chipset.input.read(Port::P1) & 0x0F) != 0x0F;
+        // without an interrupt to take, the byte after STOP is skipped
+        if !pending {
+            let pc = self.cpu.wide_register(WideRegister::PC);
+            self.cpu
+                .set_wide_register(WideRegister::PC, pc.wrapping_add(1));
+        }
+        if held {
+            self.cpu.set_stopped(false);
+            self.cpu.set_halted(!pending);
+            return;
+        }
+        let div = mem::take(&mut chipset.div);
+        chipset.clock_div_apu(div);
+        self.div_counter = 0;
+        if chipset.cgb_mode && ((chipset.key1 & 0x01) != 0) {
+            chipset.double_speed = !chipset.double_speed;
+            chipset.key1 = 0;
+            // real hardware glitches if IME is set too, there's no telling how
+            if pending {
+                self.cpu.set_stopped(false);
+            } else {
+                self.speed_switch = SPEED_SWITCH_CYCLES;
+            }
+        }
+    }
+
+    // what ends a STOP: the speed switch finishing (or an interrupt cutting it short), or
+    // otherwise a button
+    fn wake(&mut self, cycles: usize) {
+        let chipset = &mut self.chipset;
+        if self.speed_switch > 0 {
+            self.speed_switch = self.speed_switch.saturating_sub(cycles);
+            if (self.speed_switch == 0) || ((chipset.ie & chipset.iflags & 0x1F) != 0) {
+                self.speed_switch = 0;
+                self.cpu.set_stopped(false);
+            }
+        } else if (chipset.input.read(Port::P1) & 0x0F) != 0x0F {
+            self.cpu.set_stopped(false);
+        }
+    }
+
     #[inline]
     pub fn vblanked(&mut self) -> bool {
-        let value = self.vblanked;
-        self.vblanked = false;
-        value
+        mem::take(&mut self.chipset.vblanked)
     }
 
     #[inline]
     pub fn lcd(&self) -> &[[u32; 160]; 144] {
-        &self.lcd
+        &self.chipset.lcd
+    }
+
+    /// Sends every scanline and frame from here on to `sink`, handing back the one before.
+    pub fn set_video_sink(&mut self, sink: Box<dyn VideoSink>) -> Box<dyn VideoSink> {
+        mem::replace(&mut self.chipset.video, sink)
+    }
+
+    /// A hash of everything that decides what the machine does from here on: CPU, memory,
+    /// PPU, APU, timers and cartridge state, but not the picture or sound already made. Two runs
+    /// from the same ROM, settings and inputs hash the same after every frame, so comparing
+    /// hashes catches a desync (in a recorded movie, or between two linked machines) on the
+    /// frame it happens rather than once it shows. Hashes are stable across runs and builds
+    /// on the same platform.
+    pub fn state_hash(&mut self) -> u64 {
+        // a PPU that lags behind is the same machine as one that's caught up
+        self.chipset.sync_ppu(&mut self.ppu);
+        let mut state = StateHasher::default();
+        self.cpu.hash(&mut state);
+        self.ppu.hash(&mut state);
+        (self.div_counter, self.tima_counter, self.speed_switch).hash(&mut state);
+        let chipset = &self.chipset;
+        chipset.mbc.hash_state(&mut state);
+        chipset.input.hash_state(&mut state);
+        chipset.sgb.hash(&mut state);
+        (chipset.cgb_mode, chipset.wram, chipset.hram).hash(&mut state);
+        [
+            chipset.iflags,
+            chipset.boot,
+            chipset.svbk,
+            chipset.opri,
+            chipset.key0,
+            chipset.key1,
+            chipset.sb,
+            chipset.sc,
+            chipset.div,
+            chipset.tima,
+            chipset.tma,
+            chipset.tac,
+            chipset.ie,
+        ]
+        .hash(&mut state);
+        chipset.undoc.hash(&mut state);
+        chipset.apu.hash(&mut state);
+        (
+            chipset.serial_cycles,
+            chipset.serial_waiting,
+            chipset.double_speed,
+        )
+            .hash(&mut state);
+        state.finish()
+    }
+
+    /// Everything [`Emu::state_hash`] covers, as bytes to hand back to [`Emu::load_state`]
+    /// later. The cart's RAM comes along, so loading a state takes back saves made since.
+    pub fn save_state(&mut self) -> Vec<u8> {
+        let mut state = State::save();
+        state.bytes(&mut STATE_MAGIC.to_vec());
+        state.bytes(&mut [STATE_VERSION, self.chipset.model as u8]);
+        self.snapshot(&mut state);
+        state.finish().unwrap()
+    }
+
+    /// Puts the machine back how [`Emu::save_state`] left it. States only load into the
+    /// same model they were saved from, and one that doesn't load leaves the machine as it
+    /// was. Nothing checks the cart is the same, keeping states apart is up to the caller.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let data = data
+            .strip_prefix(STATE_MAGIC)
+            .ok_or("not a gb23 save state")?;
+        match *data {
+            [STATE_VERSION, model, ..] if model == (self.chipset.model as u8) => {}
+            [STATE_VERSION, ..] => return Err("save state is for another model".to_string()),
+            _ => return Err("save state is from another version of gb23".to_string()),
+        }
+        let backup = self.save_state();
+        let mut state = State::load(&data[2..]);
+        self.snapshot(&mut state);
+        if let Err(e) = state.finish() {
+            self.load_state(&backup).unwrap();
+            return Err(e);
+        }
+        self.chipset.vblanked = false;
+        Ok(())
+    }
+
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        // a PPU that lags behind would have cycles owed to it that don't get saved
+        self.chipset.sync_ppu(&mut self.ppu);
+        self.cpu.snapshot(state);
+        self.ppu.snapshot(state);
+        self.div_counter.snapshot(state);
+        self.tima_counter.snapshot(state);
+        self.speed_switch.snapshot(state);
+        let chipset = &mut self.chipset;
+        chipset.mbc.snapshot_state(state);
+        chipset.input.snapshot_state(state);
+        // whether there is one comes with the model
+        if let Some(sgb) = &mut chipset.sgb {
+            sgb.snapshot(state);
+        }
+        chipset.cgb_mode.snapshot(state);
+        chipset.wram.snapshot(state);
+        chipset.hram.snapshot(state);
+        for reg in [
+            &mut chipset.iflags,
+            &mut chipset.boot,
+            &mut chipset.svbk,
+            &mut chipset.opri,
+            &mut chipset.key0,
+            &mut chipset.key1,
+            &mut chipset.sb,
+            &mut chipset.sc,
+            &mut chipset.div,
+            &mut chipset.tima,
+            &mut chipset.tma,
+            &mut chipset.tac,
+            &mut chipset.ie,
+        ] {
+            reg.snapshot(state);
+        }
+        chipset.undoc.snapshot(state);
+        chipset.apu.snapshot(state);
+        chipset.serial_cycles.snapshot(state);
+        chipset.serial_waiting.snapshot(state);
+        chipset.double_speed.snapshot(state);
+    }
+
+    /// Plugs in (or pulls out) a link cable. Once plugged in, a transfer on our own clock
+    /// waits for the other end in [`Emu::serial_exchange`] rather than finishing on its own
+    /// with nothing but $FF shifted in.
+    pub fn set_linked(&mut self, linked: bool) {
+        self.chipset.linked = linked;
+    }
+
+    /// The byte we have finished clocking out over a link cable, waiting on the other end
+    /// to hand back its own through [`Emu::serial_exchange`].
+    pub fn serial_pending(&self) -> Option<u8> {
+        self.chipset.serial_waiting.then_some(self.chipset.sb)
+    }
+
+    /// Swaps `byte` for the one in SB as if 8 clocks came down the cable, finishing the
+    /// transfer. A machine not waiting on a transfer doesn't shift and the other end sees $FF.
+    pub fn serial_exchange(&mut self, byte: u8) -> u8 {
+        let chipset = &mut self.chipset;
+        if (chipset.sc & 0x80) == 0 {
+            return 0xFF;
+        }
+        let out = chipset.sb;
+        chipset.finish_transfer(byte);
+        out
+    }
+
+    /// What the game has sent out the serial port on its own clock.
+    #[inline]
+    pub fn serial_output(&self) -> &SerialOutput {
+        &self.chipset.serial_out
+    }
+
+    #[inline]
+    pub fn serial_output_mut(&mut self) -> &mut SerialOutput {
+        &mut self.chipset.serial_out
+    }
+
+    /// Makes LY always read as `ly` (or the PPU's real line again with `None`). Reference
+    /// traces like Gameboy Doctor's are logged this way, so waits for vblank don't matter.
+    pub fn stub_ly(&mut self, ly: Option<u8>) {
+        self.chipset.ly_stub = ly;
+    }
+
+    /// Sends every sample from here on to `sink`, at the configured sample rate, handing
+    /// back the one before.
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) -> Box<dyn AudioSink> {
+        mem::replace(&mut self.chipset.audio, sink)
+    }
+
+    /// A look at all four sound channels, for debug views.
+    #[inline]
+    pub fn apu_state(&self) -> ApuState {
+        self.chipset.apu.state()
+    }
+
+    /// The APU channels left out of what's heard, bit 0 for channel 1 up to bit 3 for
+    /// channel 4.
+    #[inline]
+    pub fn muted_channels(&self) -> u8 {
+        self.chipset.apu.muted()
+    }
+
+    /// Leaves the channels set in `muted` out of what's heard, as a debugging aid. The
+    /// channels still run as before, it only changes the sound.
+    #[inline]
+    pub fn set_muted_channels(&mut self, muted: u8) {
+        self.chipset.apu.set_muted(muted);
+    }
+
+    /// Whether the picture is tinted by layer, see [`Ppu::set_tint`].
+    #[inline]
+    pub fn tint(&self) -> bool {
+        self.ppu.tint()
+    }
+
+    /// Tints the picture by the layer each pixel came from, as a debugging aid. Not on an
+    /// SGB, which colors the screen from the PPU's shades itself.
+    #[inline]
+    pub fn set_tint(&mut self, tint: bool) {
+        self.ppu.set_tint(tint && self.chipset.sgb.is_none());
+    }
+
+    /// The [`Layers`](ppu::Layers) left out of the picture, see [`Ppu::set_hidden_layers`].
+    #[inline]
+    pub fn hidden_layers(&self) -> u8 {
+        self.ppu.hidden_layers()
+    }
+
+    /// Leaves the [`Layers`](ppu::Layers) set in `hidden` out of the picture whatever LCDC
+    /// says, as a debugging aid.
+    #[inline]
+    pub fn set_hidden_layers(&mut self, hidden: u8) {
+        self.ppu.set_hidden_layers(hidden);
+    }
+
+    #[inline]
+    pub fn mbc(&self) -> &M {
+        &self.chipset.mbc
+    }
+
+    #[inline]
+    pub fn mbc_mut(&mut self) -> &mut M {
+        &mut self.chipset.mbc
+    }
+
+    /// Starts or stops counting the CPU's accesses, which slows every one of them down.
+    /// Stopping forgets the counts.
+    pub fn set_heatmap(&mut self, enabled: bool) {
+        if enabled != self.heatmap.is_some() {
+            self.heatmap = enabled.then(Box::default);
+        }
+    }
+
+    #[inline]
+    pub fn heatmap(&self) -> Option<&Heatmap> {
+        self.heatmap.as_deref()
+    }
+
+    #[inline]
+    pub fn heatmap_mut(&mut self) -> Option<&mut Heatmap> {
+        self.heatmap.as_deref_mut()
+    }
+
+    /// Starts logging what the ROM is used for on top of `cdl`, or stops with `None`.
+    pub fn set_cdl(&mut self, cdl: Option<Cdl>) {
+        self.chipset.cdl = cdl.map(Box::new);
+    }
+
+    #[inline]
+    pub fn cdl(&self) -> Option<&Cdl> {
+        self.chipset.cdl.as_deref()
+    }
+
+    /// Starts logging the CPU's accesses to the ports in `log`, or stops with `None`.
+    pub fn set_io_log(&mut self, log: Option<IoLog>) {
+        self.chipset.io_log = log.map(Box::new);
+    }
+
+    /// What the game has done so far that we don't emulate.
+    #[inline]
+    pub fn errors(&self) -> &Errors {
+        &self.chipset.errors
+    }
+
+    pub fn clear_errors(&mut self) {
+        self.chipset.errors.clear();
+    }
+
+    #[inline]
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.chipset.sgb.as_ref()
     }
 
     #[inline]
     pub fn input_mut(&mut self) -> &mut I {
-        &mut self.input
+        &mut self.chipset.input
     }
 
     #[inline]
@@ -149,228 +632,537 @@ impl<M: BusDevice<NoopView>, I: BusDevice<NoopView>> Emu<M, Ppu, I> {
         &self.cpu
     }
 
+    /// The PPU, caught up to the CPU first since it normally lags behind.
+    pub fn ppu(&mut self) -> &Ppu {
+        self.chipset.sync_ppu(&mut self.ppu);
+        &self.ppu
+    }
+
+    /// Fills `buf` with memory starting at `addr` as the CPU sees it, wrapping past $FFFF.
+    pub fn read_range(&mut self, addr: u16, buf: &mut [u8]) {
+        let (_, mut cpu_view) = self.cpu_view();
+        cpu_view.read_range(addr, buf);
+    }
+
     #[inline(always)]
     pub fn cpu_view(&mut self) -> (&mut Cpu, CpuView<M, Ppu, I>) {
         let Self {
-            ref boot_data,
             ref mut cpu,
-            ref mut mbc,
             ref mut ppu,
-            ref mut input,
-            ref mut wram,
-            ref mut hram,
-            ref mut iflags,
-            ref mut boot,
-            ref mut svbk,
-            ref mut ie,
-            ref mut sc,
-            ref mut div,
-            ref mut tima,
-            ref mut tma,
-            ref mut tac,
+            ref mut chipset,
             ..
         } = self;
         (
             cpu,
             CpuView {
-                boot_data,
-                mbc,
                 ppu,
-                input,
-                wram,
-                hram,
-                iflags,
-                boot,
-                svbk,
-                sc,
-                div,
-                tima,
-                tma,
-                tac,
-                ie,
+                chipset,
+                logged: false,
             },
         )
     }
 
     #[inline(always)]
-    fn ppu_view(&mut self) -> (&mut Ppu, PpuView<M>) {
-        let Self {
-            ref mut lcd,
-            ref boot_data,
-            ref mut mbc,
-            ref mut ppu,
-            ref mut wram,
-            ref mut iflags,
-            ref mut boot,
-            ref mut svbk,
-            ..
-        } = self;
-        (
-            ppu,
-            PpuView {
-                lcd,
-                boot_data,
-                mbc,
-                wram,
-                iflags,
-                boot,
-                svbk,
-            },
-        )
+    fn ppu_view(&mut self) -> (&mut Ppu, &mut Chipset<M, I>) {
+        (&mut self.ppu, &mut self.chipset)
+    }
+}
+
+/// Everything on the bus that isn't the CPU or PPU, owned in one place so the
+/// per-tick views only have to borrow a couple of pointers.
+pub struct Chipset<M, I> {
+    model: Model,
+    revision: Revision,
+    // a CGB running a CGB cart, rather than in DMG compatibility mode
+    cgb_mode: bool,
+    boot_data: Vec<u8>,
+    vblanked: bool,
+    ppu_cycles: usize,
+    mbc: M,
+    input: I,
+    sgb: Option<Sgb>,
+    // the SGB's picture, border and all
+    sgb_screen: Option<Box<[[u32; 256]; 224]>>,
+    cdl: Option<Box<Cdl>>,
+    errors: Errors,
+    io_log: Option<Box<IoLog>>,
+    // the SGB wants the whole screen at once, so lines are kept here too
+    lcd: [[u32; 160]; 144],
+    video: Box<dyn VideoSink>,
+    apu: Apu,
+    audio: Box<dyn AudioSink>,
+    // what LY reads as instead of the PPU's line, for comparing against reference traces
+    ly_stub: Option<u8>,
+    wram: [[u8; 4096]; 8],
+    hram: [u8; 127],
+    iflags: u8,
+    boot: u8,
+    svbk: u8,
+    opri: u8,
+    key0: u8,
+    // bit 0 arms a speed switch for the next STOP
+    key1: u8,
+    double_speed: bool,
+    undoc: [u8; 4],
+    sb: u8,
+    sc: u8,
+    serial_cycles: usize,
+    // shifted out on our own clock, waiting for the other end to answer
+    serial_waiting: bool,
+    serial_out: SerialOutput,
+    linked: bool,
+    div: u8,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    ie: u8,
+}
+
+impl<M, I> Chipset<M, I> {
+    // ports this model has that don't do anything here yet
+    #[inline]
+    fn unimplemented(&self, addr: u16) -> bool {
+        match addr {
+            Port::HMDA1..=Port::HMDA5 | Port::BCPS..=Port::OCPD => self.cgb_mode,
+            Port::RP => self.model == Model::Cgb,
+            _ => false,
+        }
+    }
+
+    // CGB games pick the object priority mode with OPRI, DMG games always get DMG's
+    #[inline]
+    fn x_priority(&self) -> bool {
+        !self.cgb_mode || ((self.opri & 0x01) != 0)
+    }
+
+    /// The WRAM bank and offset into it for an address in $C000-$FDFF. $D000 (and its
+    /// shadow at $F000) is whichever bank SVBK picks, where 0 picks 1 like 1 does.
+    #[inline]
+    fn wram_index(&self, addr: u16) -> (usize, usize) {
+        let offset = ((addr - 0xC000) & 0x1FFF) as usize;
+        if offset < 0x1000 {
+            (0, offset)
+        } else {
+            ((self.svbk as usize).max(1), offset - 0x1000)
+        }
+    }
+
+    // the APU's frame sequencer steps whenever DIV's bit 4 (bit 5 in double speed) falls,
+    // which resetting DIV can make happen early
+    fn clock_div_apu(&mut self, old_div: u8) {
+        let bit = if self.double_speed { 0x20 } else { 0x10 };
+        if ((old_div & bit) != 0) && ((self.div & bit) == 0) {
+            self.apu.step_sequencer();
+        }
+    }
+
+    fn finish_transfer(&mut self, byte: u8) {
+        self.sb = byte;
+        self.sc &= 0x7F;
+        self.serial_cycles = 0;
+        self.serial_waiting = false;
+        self.iflags |= 0x08;
+    }
+}
+
+impl<M: BusDevice<NoopView>, I> Chipset<M, I> {
+    // a cartridge ROM read, which goes in the code/data log as `usage` if one is running
+    #[inline]
+    fn read_rom(&mut self, addr: u16, usage: u8) -> u8 {
+        if let Some(cdl) = &mut self.cdl {
+            if let Some(offset) = self.mbc.rom_offset(addr) {
+                cdl.record(offset, usage);
+            }
+        }
+        self.mbc.read(addr)
+    }
+
+    #[inline]
+    fn sync_ppu(&mut self, ppu: &mut Ppu) {
+        let cycles = mem::take(&mut self.ppu_cycles);
+        if ppu.advance(self, cycles) != 0 {
+            self.vblanked = true;
+            if let Some(log) = &mut self.io_log {
+                log.frame += 1;
+            }
+            if let Some(sgb) = &mut self.sgb {
+                let mut tiles = [0; 4096];
+                ppu.screen_tiles(&mut tiles);
+                sgb.vblank(&tiles, &self.lcd);
+                let screen = self
+                    .sgb_screen
+                    .get_or_insert_with(|| Box::new([[0; 256]; 224]));
+                sgb.render(&self.lcd, screen);
+                self.video.frame(256, screen.as_flattened());
+            } else {
+                self.video.frame(160, self.lcd.as_flattened());
+            }
+        }
     }
 }
 
 pub struct CpuView<'a, M, P, I> {
-    boot_data: &'a [u8],
-    mbc: &'a mut M,
     ppu: &'a mut P,
-    input: &'a mut I,
-    wram: &'a mut [[u8; 4096]; 8],
-    hram: &'a mut [u8; 256],
-    iflags: &'a mut u8,
-    boot: &'a mut u8,
-    svbk: &'a mut u8,
-    sc: &'a mut u8,
-    div: &'a mut u8,
-    tima: &'a mut u8,
-    tma: &'a mut u8,
-    tac: &'a mut u8,
-    ie: &'a mut u8,
+    chipset: &'a mut Chipset<M, I>,
+    // whether reads go in the code/data log and unimplemented ports are reported, only the
+    // CPU's own accesses are, not the debugger's
+    logged: bool,
+}
+
+impl<'a, M: BusDevice<NoopView>, I: BusDevice<NoopView>> CpuView<'a, M, Ppu, I> {
+    /// Like `read` over a whole range, but plain RAM is copied a run at a time.
+    pub fn read_range(&mut self, mut addr: u16, buf: &mut [u8]) {
+        let mut i = 0;
+        while i < buf.len() {
+            let chipset = &*self.chipset;
+            let run = match addr {
+                0xC000..=0xFDFF => {
+                    let (bank, offset) = chipset.wram_index(addr);
+                    // the echo stops short of the end of the bank, where OAM starts
+                    let end = if addr >= 0xF000 { 0xE00 } else { 0x1000 };
+                    Some(&chipset.wram[bank][offset..end])
+                }
+                0xFF80..=0xFFFE => Some(&chipset.hram[((addr - 0xFF80) as usize)..]),
+                _ => None,
+            };
+            let len = if let Some(run) = run {
+                let len = run.len().min(buf.len() - i);
+                buf[i..(i + len)].copy_from_slice(&run[..len]);
+                len
+            } else {
+                buf[i] = self.read(addr);
+                1
+            };
+            i += len;
+            addr = addr.wrapping_add(len as u16);
+        }
+    }
+
+    // a debug line for the CPU's access to `addr`, if it's one of the ports being logged
+    #[inline]
+    fn log_io(&mut self, addr: u16, value: u8, write: bool) {
+        let chipset = &mut *self.chipset;
+        if !self.logged
+            || !chipset
+                .io_log
+                .as_ref()
+                .is_some_and(|log| log.contains(addr))
+        {
+            return;
+        }
+        // the line has to be up to date
+        chipset.sync_ppu(self.ppu);
+        let log = chipset.io_log.as_ref().unwrap();
+        let access = if write {
+            format!("wrote {value:02X} to")
+        } else {
+            format!("read {value:02X} from")
+        };
+        tracing::debug!(
+            "frame {} line {:3} PC={:04X} {access} {}",
+            log.frame,
+            self.ppu.ly(),
+            log.pc,
+            Port::label(addr)
+        );
+    }
+
+    // $FF00-$FF7F, the IO ports. Only the bits `Port::io_masks` says are there get from
+    // here to the port and back, so the arms below don't have to mask
+    fn read_io(&mut self, addr: u16) -> u8 {
+        let (read_mask, _) = Port::io_masks(addr);
+        let chipset = &mut *self.chipset;
+        if self.logged && chipset.unimplemented(addr) {
+            chipset.errors.report(CoreError::UnimplementedRead(addr));
+        }
+        let value = match addr {
+            Port::P1 => {
+                let p1 = chipset.input.read(addr);
+                chipset.sgb.as_ref().map_or(p1, |sgb| sgb.read(p1))
+            }
+            Port::SB => chipset.sb,
+            // the fast clock bit is only there on CGB
+            Port::SC if !chipset.cgb_mode => chipset.sc | 0x02,
+            Port::SC => chipset.sc,
+            Port::DIV => chipset.div,
+            Port::TIMA => chipset.tima,
+            Port::TMA => chipset.tma,
+            Port::TAC => chipset.tac,
+            Port::IF => chipset.iflags,
+            Port::KEY1 if chipset.cgb_mode => ((chipset.double_speed as u8) << 7) | chipset.key1,
+            Port::BOOT => chipset.boot,
+            Port::LY if chipset.ly_stub.is_some() => chipset.ly_stub.unwrap(),
+            // PPU IO ports
+            Port::LCDC..=Port::WX
+            | Port::VBK
+            | Port::HMDA1..=Port::HMDA5
+            | Port::BCPS..=Port::OCPD => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::read(self.ppu, addr)
+            }
+            Port::SVBK if chipset.cgb_mode => chipset.svbk,
+            // nothing on DMG, SGB or MGB answers these
+            Port::OPRI if chipset.model == Model::Cgb => chipset.opri,
+            Port::FF72 | Port::FF73 if chipset.model == Model::Cgb => {
+                chipset.undoc[(addr - Port::FF72) as usize]
+            }
+            Port::FF74 if chipset.cgb_mode => chipset.undoc[2],
+            Port::FF75 if chipset.model == Model::Cgb => chipset.undoc[3],
+            Port::NR10..=Port::NR52 | Port::WAVE..=0xFF3F => chipset.apu.read(addr),
+            Port::PCM12 | Port::PCM34 if chipset.model == Model::Cgb => chipset.apu.pcm(addr),
+            _ => 0xFF,
+        };
+        value | !read_mask
+    }
+
+    fn write_io(&mut self, addr: u16, value: u8) {
+        let (_, write_mask) = Port::io_masks(addr);
+        let value = value & write_mask;
+        let chipset = &mut *self.chipset;
+        if self.logged && chipset.unimplemented(addr) {
+            chipset
+                .errors
+                .report(CoreError::UnimplementedWrite(addr, value));
+        }
+        match addr {
+            Port::P1 => {
+                if let Some(sgb) = &mut chipset.sgb {
+                    sgb.write(value);
+                }
+                chipset.input.write(addr, value)
+            }
+            Port::SB => chipset.sb = value,
+            Port::SC => {
+                chipset.sc = if chipset.cgb_mode {
+                    value
+                } else {
+                    value & 0x81
+                };
+                // a write starts (or cancels) a transfer from scratch
+                chipset.serial_cycles = 0;
+                chipset.serial_waiting = false;
+                // on our own clock, whether or not anything's plugged in to hear it
+                if (value & 0x81) == 0x81 {
+                    chipset.serial_out.push(chipset.sb);
+                }
+            }
+            Port::DIV => {
+                let div = mem::take(&mut chipset.div);
+                chipset.clock_div_apu(div);
+            }
+            Port::TIMA => chipset.tima = value,
+            Port::TMA => chipset.tma = value,
+            Port::TAC => chipset.tac = value,
+            Port::IF => chipset.iflags = value,
+            // the boot ROM picks CGB or DMG compatibility mode for the cart, then it's locked
+            Port::KEY0 if (chipset.model == Model::Cgb) && (chipset.boot == 0) => {
+                chipset.key0 = value;
+                chipset.cgb_mode = (value & 0x04) == 0;
+                chipset.sync_ppu(self.ppu);
+                self.ppu.set_x_priority(chipset.x_priority());
+            }
+            Port::KEY1 if chipset.cgb_mode => chipset.key1 = value,
+            Port::BOOT => chipset.boot = value,
+            // PPU IO ports
+            Port::LCDC..=Port::WX
+            | Port::VBK
+            | Port::HMDA1..=Port::HMDA5
+            | Port::BCPS..=Port::OCPD => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::write(self.ppu, addr, value)
+            }
+            Port::SVBK if chipset.cgb_mode => chipset.svbk = value,
+            // later revisions lock the priority mode once the boot ROM has picked it
+            Port::OPRI
+                if (chipset.model == Model::Cgb)
+                    && ((chipset.boot == 0)
+                        || !chipset.cgb_mode
+                        || (chipset.revision == Revision::Cgb0)) =>
+            {
+                chipset.opri = value;
+                chipset.sync_ppu(self.ppu);
+                self.ppu.set_x_priority(chipset.x_priority());
+            }
+            Port::FF72 | Port::FF73 if chipset.model == Model::Cgb => {
+                chipset.undoc[(addr - Port::FF72) as usize] = value
+            }
+            Port::FF74 if chipset.cgb_mode => chipset.undoc[2] = value,
+            Port::FF75 if chipset.model == Model::Cgb => chipset.undoc[3] = value,
+            Port::NR10..=Port::NR52 | Port::WAVE..=0xFF3F => chipset.apu.write(addr, value),
+            _ => {}
+        }
+    }
 }
 
 impl<'a, M: BusDevice<NoopView>, I: BusDevice<NoopView>> Bus for CpuView<'a, M, Ppu, I> {
     fn read(&mut self, addr: u16) -> u8 {
+        let chipset = &mut *self.chipset;
         match addr {
             // BIOS
-            0x0000..=0x00FF if *self.boot == 0 => self.boot_data[addr as usize],
+            0x0000..=0x00FF if chipset.boot == 0 => chipset.boot_data[addr as usize],
             // cart
-            0x0000..=0x7FFF => self.mbc.read(addr),
+            0x0000..=0x7FFF if self.logged => chipset.read_rom(addr, Cdl::DATA),
+            0x0000..=0x7FFF => chipset.mbc.read(addr),
             // VRAM
-            0x8000..=0x9FFF => <Ppu as BusDevice<PpuView<M>>>::read(self.ppu, addr),
+            0x8000..=0x9FFF => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::read(self.ppu, addr)
+            }
             // cart
-            0xA000..=0xBFFF => self.mbc.read(addr),
-            // WRAM
-            0xC000..=0xCFFF => self.wram[0][(addr - 0xC000) as usize],
-            0xD000..=0xDFFF if *self.svbk < 2 => self.wram[1][(addr - 0xD000) as usize],
-            0xD000..=0xDFFF => self.wram[*self.svbk as usize][(addr - 0xD000) as usize],
-            // shadow area
-            0xE000..=0xEFFF => self.wram[0][(addr - 0xE000) as usize],
-            0xF000..=0xFDFF if *self.svbk < 2 => self.wram[1][(addr - 0xF000) as usize],
-            0xF000..=0xFDFF => self.wram[*self.svbk as usize][(addr - 0xF000) as usize],
+            0xA000..=0xBFFF => chipset.mbc.read(addr),
+            // WRAM and its shadow
+            0xC000..=0xFDFF => {
+                let (bank, offset) = chipset.wram_index(addr);
+                chipset.wram[bank][offset]
+            }
             // OAM
-            0xFE00..=0xFE9F => <Ppu as BusDevice<PpuView<M>>>::read(self.ppu, addr),
+            0xFE00..=0xFE9F => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::read(self.ppu, addr)
+            }
             // reserved
             0xFEA0..=0xFEFF => 0xFF,
-            Port::P1 => self.input.read(addr),
-            Port::SB => 0x00, //todo!(),
-            Port::SC => *self.sc,
-            Port::DIV => *self.div,
-            Port::TIMA => *self.tima,
-            Port::TMA => *self.tma,
-            Port::TAC => *self.tac,
-            Port::IF => *self.iflags,
-            Port::KEY1 => todo!(),
-            Port::BOOT => *self.boot,
-            // PPU IO ports
-            Port::LCDC..=Port::WX
-            | Port::VBK
-            | Port::HMDA1..=Port::HMDA5
-            | Port::BCPS..=Port::OCPD => <Ppu as BusDevice<PpuView<M>>>::read(self.ppu, addr),
-            // 0xFF56 => // IR port
-            Port::SVBK => *self.svbk,
+            0xFF00..=0xFF7F => {
+                let value = self.read_io(addr);
+                self.log_io(addr, value, false);
+                value
+            }
             // HRAM
-            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
-            Port::IE => *self.ie,
-            _ => 0xFF, // TODO
+            0xFF80..=0xFFFE => chipset.hram[(addr - 0xFF80) as usize],
+            Port::IE => {
+                let value = chipset.ie;
+                self.log_io(addr, value, false);
+                value
+            }
+        }
+    }
+
+    fn fetch(&mut self, addr: u16) -> u8 {
+        let chipset = &mut *self.chipset;
+        match addr {
+            0x0000..=0x00FF if chipset.boot == 0 => chipset.boot_data[addr as usize],
+            0x0000..=0x7FFF if self.logged => chipset.read_rom(addr, Cdl::CODE),
+            _ => self.read(addr),
         }
     }
 
     fn write(&mut self, addr: u16, value: u8) {
+        let chipset = &mut *self.chipset;
         match addr {
             // cart
-            0x0000..=0x7FFF => self.mbc.write(addr, value),
+            0x0000..=0x7FFF => chipset.mbc.write(addr, value),
             // VRAM
-            0x8000..=0x9FFF => <Ppu as BusDevice<PpuView<M>>>::write(self.ppu, addr, value),
+            0x8000..=0x9FFF => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::write(self.ppu, addr, value)
+            }
             // cart
-            0xA000..=0xBFFF => self.mbc.write(addr, value),
-            // WRAM
-            0xC000..=0xCFFF => self.wram[0][(addr - 0xC000) as usize] = value,
-            0xD000..=0xDFFF if *self.svbk < 2 => self.wram[1][(addr - 0xD000) as usize] = value,
-            0xD000..=0xDFFF => self.wram[*self.svbk as usize][(addr - 0xD000) as usize] = value,
-            // shadow area
-            0xE000..=0xEFFF => self.wram[0][(addr - 0xE000) as usize] = value,
-            0xF000..=0xFDFF if *self.svbk < 2 => self.wram[1][(addr - 0xF000) as usize] = value,
-            0xF000..=0xFDFF => self.wram[*self.svbk as usize][(addr - 0xF000) as usize] = value,
+            0xA000..=0xBFFF => chipset.mbc.write(addr, value),
+            // WRAM and its shadow
+            0xC000..=0xFDFF => {
+                let (bank, offset) = chipset.wram_index(addr);
+                chipset.wram[bank][offset] = value
+            }
             // OAM
-            0xFE00..=0xFE9F => <Ppu as BusDevice<PpuView<M>>>::write(self.ppu, addr, value),
+            0xFE00..=0xFE9F => {
+                chipset.sync_ppu(self.ppu);
+                <Ppu as BusDevice<Chipset<M, I>>>::write(self.ppu, addr, value)
+            }
             // reserved
             0xFEA0..=0xFEFF => {}
-            Port::P1 => self.input.write(addr, value),
-            Port::SB => eprint!("{}", value as char),
-            Port::SC => *self.sc = value & 0x03,
-            Port::DIV => *self.div = 0,
-            Port::TIMA => *self.tima = value,
-            Port::TMA => *self.tma = value,
-            Port::TAC => *self.tac = value & 0x07,
-            Port::IF => *self.iflags = value & 0x1F,
-            Port::KEY1 => todo!(),
-            Port::BOOT => *self.boot = value,
-            // PPU IO ports
-            Port::LCDC..=Port::WX
-            | Port::VBK
-            | Port::HMDA1..=Port::HMDA5
-            | Port::BCPS..=Port::OCPD => {
-                <Ppu as BusDevice<PpuView<M>>>::write(self.ppu, addr, value)
+            0xFF00..=0xFF7F => {
+                self.log_io(addr, value, true);
+                self.write_io(addr, value)
             }
-            // 0xFF56 => // IR port
-            Port::SVBK => *self.svbk = value & 0x07,
             // HRAM
-            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = value,
-            Port::IE => *self.ie = value & 0x1F,
-            _ => {} // TODO
+            0xFF80..=0xFFFE => chipset.hram[(addr - 0xFF80) as usize] = value,
+            Port::IE => {
+                self.log_io(addr, value, true);
+                self.chipset.ie = value & 0x1F
+            }
         }
     }
 }
 
-pub struct NoopView {}
+// a word at a time multiply and rotate like FxHash, cheap enough to run every frame. Not
+// std's DefaultHasher since the hashes have to match from one run (and build) to the next
+#[derive(Default)]
+struct StateHasher(u64);
 
-impl Bus for NoopView {}
+impl StateHasher {
+    #[inline(always)]
+    fn add(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517CC1B727220A95);
+    }
+}
+
+impl Hasher for StateHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.add(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for &b in words.remainder() {
+            self.add(b as u64);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        // murmur's finalizer, so states a bit apart don't hash a bit apart
+        let mut x = self.0;
+        x = (x ^ (x >> 33)).wrapping_mul(0xFF51AFD7ED558CCD);
+        x = (x ^ (x >> 33)).wrapping_mul(0xC4CEB9FE1A85EC53);
+        x ^ (x >> 33)
+    }
+}
 
-pub struct PpuView<'a, M> {
-    lcd: &'a mut [[u32; 160]; 144],
-    boot_data: &'a [u8],
-    mbc: &'a mut M,
-    wram: &'a mut [[u8; 4096]; 8],
-    iflags: &'a mut u8,
-    boot: &'a mut u8,
-    svbk: &'a mut u8,
+// a boot ROM starts out in CGB mode and switches to DMG compatibility through KEY0 once it
+// has looked at the cart, without one we pick the same way it would
+fn boot_cgb_mode<M: BusDevice<NoopView>>(model: Model, boot_data: &[u8], mbc: &mut M) -> bool {
+    (model == Model::Cgb) && (!boot_data.is_empty() || ((mbc.read(0x0143) & 0x80) != 0))
 }
 
-impl<'a, M: BusDevice<NoopView>> Bus for PpuView<'a, M> {
+pub struct NoopView {}
+
+impl Bus for NoopView {}
+
+impl<M: BusDevice<NoopView>, I> Bus for Chipset<M, I> {
     #[inline]
-    fn lcd_mut(&mut self) -> &mut [[u32; 160]; 144] {
-        self.lcd
+    fn scanline(&mut self, ly: u8, line: &[u32; 160]) {
+        self.lcd[ly as usize] = *line;
+        self.video.scanline(ly, line);
     }
 
     fn read(&mut self, addr: u16) -> u8 {
         match addr {
             // BIOS
-            0x0000..=0x00FF if *self.boot == 0 => self.boot_data[addr as usize],
-            // cart
-            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.read(addr),
-            // WRAM
-            0xC000..=0xCFFF => self.wram[0][(addr - 0xC000) as usize],
-            0xD000..=0xDFFF if *self.svbk < 2 => self.wram[1][(addr - 0xD000) as usize],
-            0xD000..=0xDFFF => self.wram[*self.svbk as usize][(addr - 0xD000) as usize],
-            Port::IF => *self.iflags,
-            _ => unreachable!(),
+            0x0000..=0x00FF if self.boot == 0 => self.boot_data[addr as usize],
+            // cart, which only OAM DMA reads from here
+            0x0000..=0x7FFF => self.read_rom(addr, Cdl::DMA),
+            0xA000..=0xBFFF => self.mbc.read(addr),
+            // WRAM and its shadow
+            0xC000..=0xFDFF => {
+                let (bank, offset) = self.wram_index(addr);
+                self.wram[bank][offset]
+            }
+            Port::IF => self.iflags,
+            _ => {
+                self.errors.report(CoreError::Unmapped {
+                    device: "chipset",
+                    addr,
+                });
+                0xFF
+            }
         }
     }
 
     fn write(&mut self, addr: u16, value: u8) {
         match addr {
-            Port::IF => *self.iflags = value,
-            _ => unreachable!(),
+            Port::IF => self.iflags = value,
+            _ => self.errors.report(CoreError::Unmapped {
+                device: "chipset",
+                addr,
+            }),
         }
     }
 }
@@ -1,8 +1,98 @@
-use sdl2::libc;
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+};
 
-use super::bus::{Bus, BusDevice, Port};
+use super::{
+    bus::{Bus, BusDevice, Port},
+    error::CoreError,
+    state::{Snapshot, State},
+};
+use crate::config::Model;
+
+/// The layers the PPU draws, as their LCDC enable bits.
+pub enum Layers {}
+
+impl Layers {
+    pub const BG: u8 = 0x01;
+    pub const OBJ: u8 = 0x02;
+    pub const WINDOW: u8 = 0x20;
+
+    pub const NAMES: [(&'static str, u8); 3] = [
+        ("bg", Self::BG),
+        ("window", Self::WINDOW),
+        ("obj", Self::OBJ),
+    ];
+
+    /// `bg`, `window` or `obj`, any case.
+    pub fn parse(name: &str) -> Option<u8> {
+        Self::NAMES
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|&(_, layer)| layer)
+    }
+
+    /// `bg obj`, or `none`.
+    pub fn describe(layers: u8) -> String {
+        if (layers & (Self::BG | Self::OBJ | Self::WINDOW)) == 0 {
+            return "none".to_string();
+        }
+        Self::NAMES
+            .iter()
+            .filter(|(_, layer)| (layers & layer) != 0)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// What a line is drawn from, set up by hand rather than by a running machine: VRAM bank 0
+/// from $8000, OAM, and the registers that matter. For testing the renderer on its own.
+#[derive(Clone)]
+pub struct LineState {
+    pub vram: [u8; 0x2000],
+    pub oam: [u8; 0xA0],
+    pub lcdc: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    /// Orders overlapping objects by X, as DMG does, rather than by OAM index
+    pub x_priority: bool,
+}
+
+// as the DMG boot ROM leaves things, with the logo and everything else cleared away
+impl Default for LineState {
+    fn default() -> Self {
+        Self {
+            vram: [0; 0x2000],
+            oam: [0; 0xA0],
+            lcdc: 0x91,
+            scy: 0,
+            scx: 0,
+            wy: 0,
+            wx: 0,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            x_priority: true,
+        }
+    }
+}
 
 pub struct Ppu {
+    model: Model,
+    palette: [u32; 4],
+    // for the garbage VRAM powers on with
+    seed: u64,
+    // overlapping objects are ordered by X then OAM index, rather than OAM index alone
+    x_priority: bool,
+    // debugging views, not part of the machine
+    tint: bool,
+    hidden: u8,
     z_buffer: [[u8; 160]; 144],
     chr_data: [[u8; 6144]; 2],
     bg_data1: [[u8; 1024]; 2],
@@ -10,6 +100,8 @@ pub struct Ppu {
     objs: [u8; 40 * 4],
     dot: usize,
     dma_counter: usize,
+    stat_irq: bool,
+    line0_matched: bool,
     lcdc: u8,
     stat: u8,
     scy: u8,
@@ -35,8 +127,14 @@ pub struct Ppu {
 }
 
 impl Ppu {
-    pub fn new() -> Self {
+    pub fn new(model: Model, palette: [u32; 4], seed: u64) -> Self {
         Self {
+            model,
+            palette,
+            seed,
+            x_priority: model != Model::Cgb,
+            tint: false,
+            hidden: 0,
             z_buffer: [[0; 160]; 144],
             chr_data: [[0xFF; 6144]; 2],
             bg_data1: [[0xFF; 1024]; 2],
@@ -44,6 +142,8 @@ impl Ppu {
             objs: [0xFF; 40 * 4],
             dot: 0,
             dma_counter: 0,
+            stat_irq: false,
+            line0_matched: false,
             lcdc: 0,
             stat: 0,
             scy: 0,
@@ -79,14 +179,7 @@ impl Ppu {
             3 => ((self.bgp & 0xC0) >> 6, 0x80),
             _ => unreachable!(),
         };
-        let color = match index {
-            0 => 0xFFFFFFFF,
-            1 => 0xAAAAAAFF,
-            2 => 0x555555FF,
-            3 => 0x000000FF,
-            _ => unreachable!(),
-        };
-        (color, z)
+        (self.palette[index as usize], z)
     }
 
     #[inline]
@@ -107,19 +200,169 @@ impl Ppu {
             _ => unreachable!(),
         };
         let z = if (attr & 0x80) == 0 { 0xFF } else { 0x7F };
-        match index {
-            0 => (0xFFFFFFFF, z),
-            1 => (0xAAAAAAFF, z),
-            2 => (0x555555FF, z),
-            3 => (0x000000FF, z),
-            _ => unreachable!(),
+        (self.palette[index as usize], z)
+    }
+
+    /// The line being drawn, unlike the LY port this doesn't read 0 early on line 153.
+    #[inline]
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    #[inline]
+    pub fn dot(&self) -> usize {
+        self.dot
+    }
+
+    /// 0 = hblank, 1 = vblank, 2 = OAM scan, 3 = drawing
+    #[inline]
+    pub fn mode(&self) -> u8 {
+        self.stat & 0x03
+    }
+
+    /// Orders overlapping objects by X like DMG (and CGB running DMG games) does, or by
+    /// their place in OAM like CGB games get.
+    #[inline]
+    pub fn set_x_priority(&mut self, x_priority: bool) {
+        self.x_priority = x_priority;
+    }
+
+    /// Tints every pixel by the layer it came from, BG blue, window green and objects red,
+    /// and outlines the objects dropped from lines with more than 10 on them. For seeing
+    /// what a game draws where, and why it's in front of or behind something else.
+    #[inline]
+    pub fn set_tint(&mut self, tint: bool) {
+        self.tint = tint;
+    }
+
+    #[inline]
+    pub fn tint(&self) -> bool {
+        self.tint
+    }
+
+    /// Leaves the [`Layers`] set in `hidden` out of the picture whatever LCDC says, for
+    /// picking apart what a game draws where. A hidden BG shows as shade 0, as it does when
+    /// a DMG game turns it off.
+    #[inline]
+    pub fn set_hidden_layers(&mut self, hidden: u8) {
+        self.hidden = hidden & (Layers::BG | Layers::OBJ | Layers::WINDOW);
+    }
+
+    #[inline]
+    pub fn hidden_layers(&self) -> u8 {
+        self.hidden
+    }
+
+    /// Draws line `ly` (0 to 143) from `state` into `line`, using `palette` for the 4
+    /// shades, the same as the PPU would in the middle of a frame.
+    pub fn render_line(state: &LineState, palette: [u32; 4], ly: u8, line: &mut [u32; 160]) {
+        let mut ppu = Self::new(Model::Dmg, palette, 0);
+        ppu.chr_data[0].copy_from_slice(&state.vram[..0x1800]);
+        ppu.bg_data1[0].copy_from_slice(&state.vram[0x1800..0x1C00]);
+        ppu.bg_data2[0].copy_from_slice(&state.vram[0x1C00..]);
+        ppu.objs = state.oam;
+        ppu.lcdc = state.lcdc;
+        ppu.scy = state.scy;
+        ppu.scx = state.scx;
+        ppu.wy = state.wy;
+        ppu.wx = state.wx;
+        ppu.bgp = state.bgp;
+        ppu.obp0 = state.obp0;
+        ppu.obp1 = state.obp1;
+        ppu.x_priority = state.x_priority;
+        ppu.ly = ly;
+        ppu.draw_line(line);
+    }
+
+    /// Bytes left to copy for the OAM DMA in progress, 0 if there is none.
+    #[inline]
+    pub fn dma_remaining(&self) -> usize {
+        self.dma_counter
+    }
+
+    /// Number of dots the PPU can skip over without anything observable happening.
+    #[inline]
+    pub fn idle_dots(&self) -> usize {
+        // DMA and LCD-off still need to run dot by dot, and a pending STAT write interrupt
+        // has to be raised right away
+        if (self.dma_counter > 0) || ((self.lcdc & 0x80) == 0) || self.stat_irq {
+            return 0;
+        }
+        // mode switches happen on dots 0, 80, and 370 of visible lines, line 153 compares
+        // LYC against 0 on dot 8, and every line ends on dot 455
+        let next = match self.dot {
+            0 => return 0,
+            80 | 370 if self.ly < 144 => return 0,
+            8 if self.ly == 153 => return 0,
+            1..=79 if self.ly < 144 => 80,
+            81..=369 if self.ly < 144 => 370,
+            1..=7 if self.ly == 153 => 8,
+            _ => 455,
+        };
+        next - self.dot
+    }
+
+    fn compare_lyc<B: Bus>(&mut self, bus: &mut B, ly: u8, fire: bool) {
+        if ly != self.lyc {
+            self.stat &= !0x04;
+            return;
+        }
+        self.stat |= 0x04;
+        // if LYC interrupt enabled, set the stat flag
+        if fire && ((self.stat & 0x40) != 0) {
+            let iflags = bus.read(Port::IF);
+            bus.write(Port::IF, iflags | 0x02);
+        }
+    }
+
+    /// Catch up on `cycles` dots, skipping over idle stretches in bulk.
+    /// Returns non-zero if vblank started along the way.
+    pub fn advance<B: Bus>(&mut self, bus: &mut B, mut cycles: usize) -> usize {
+        let mut vblank = 0;
+        while cycles > 0 {
+            let idle = self.idle_dots().min(cycles);
+            if idle > 0 {
+                self.dot += idle;
+                cycles -= idle;
+                continue;
+            }
+            vblank += <Self as BusDevice<B>>::tick(self, bus);
+            cycles -= 1;
+        }
+        vblank
+    }
+
+    /// The tile data behind the first 256 tiles of the background map, in screen order.
+    /// This is what the SGB sees when a game shows VRAM for a transfer (scroll is ignored).
+    pub fn screen_tiles(&self, out: &mut [u8; 4096]) {
+        let bg_data = if (self.lcdc & 0x08) == 0 {
+            &self.bg_data1
+        } else {
+            &self.bg_data2
+        };
+        for (i, tile) in out.chunks_exact_mut(16).enumerate() {
+            let chr_idx = bg_data[0][((i / 20) * 32) + (i % 20)];
+            let chr_data_offset = if (self.lcdc & 0x10) != 0 {
+                chr_idx as usize * 16
+            } else {
+                0x1000usize.wrapping_add_signed(chr_idx as i8 as isize * 16)
+            };
+            tile.copy_from_slice(&self.chr_data[0][chr_data_offset..(chr_data_offset + 16)]);
         }
     }
 
     fn draw_line(&mut self, line: &mut [u32; 160]) {
         // reset z-buffer
         self.z_buffer[self.ly as usize].fill(0);
-        {
+        // which layer each pixel came from, for the tint
+        let mut layers = [Layer::Bg; 160];
+        // objects past the first 10 on the line, which the PPU never draws
+        let mut dropped = [0; 30];
+        let mut dropped_count = 0;
+        let lcdc = self.lcdc & !self.hidden;
+        if (self.hidden & Layers::BG) != 0 {
+            line.fill(self.palette[0]);
+        } else {
             let bg_data = if (self.lcdc & 0x08) == 0 {
                 &self.bg_data1
             } else {
@@ -155,26 +398,44 @@ impl Ppu {
                 }
             }
         }
+        let height = if (self.lcdc & 0x04) != 0 { 16 } else { 8 };
         // sprites?
-        if (self.lcdc & 0x02) != 0 {
-            let height = if (self.lcdc & 0x04) != 0 { 16 } else { 8 };
-            // TODO change this so we search OAM for the first 10 objs
-            // on the current line and then iterate over them. the search only looks at Y
-            // sprites offscreen in X still count against it
-            // Also want to sort them since sprite priority is based on lowest X coord
-            for obj in self.objs.chunks(4) {
-                // this is the OAM filter algorithm:
+        if (lcdc & Layers::OBJ) != 0 {
+            // the first 10 objects on the line in OAM order, whether they're offscreen in X
+            // or not, then whichever comes first wins where they overlap
+            let mut selected = [0; 10];
+            let mut count = 0;
+            for (i, obj) in self.objs.chunks(4).enumerate() {
                 let y = obj[0];
                 if ((self.ly + 16) < y) || ((self.ly + 16 - height) >= y) {
                     continue;
                 }
+                if count < selected.len() {
+                    selected[count] = i;
+                    count += 1;
+                } else if self.tint {
+                    dropped[dropped_count] = i;
+                    dropped_count += 1;
+                } else {
+                    break;
+                }
+            }
+            let selected = &mut selected[..count];
+            if self.x_priority {
+                // stable, so the same X falls back to OAM order
+                selected.sort_by_key(|&i| self.objs[(i * 4) + 1]);
+            }
+            // a pixel belongs to the first object with a color there, even one hidden
+            // behind the BG
+            let mut covered = [false; 160];
+            for &i in selected.iter() {
+                let obj = &self.objs[(i * 4)..((i * 4) + 4)];
                 // sprite origins are in the bottom right on gameboy
                 // we translate it to make the math simpler
-                let y = y.wrapping_sub(16);
-                // TODO i think there is a bug here. In 16 height mode,
-                // the index of the chr's final bit should always be masked out
-                // to zero. I think if I do that it will fix some subtle sprite bugs
-                let chr_idx = obj[2] as usize;
+                let y = obj[0].wrapping_sub(16);
+                // tall objects are an even tile and the odd one after it, whichever of the
+                // two the index says
+                let chr_idx = if height == 16 { obj[2] & 0xFE } else { obj[2] } as usize;
                 let attr = obj[3];
                 // y offset within the sprite intersecting with ly
                 let obj_y = self.ly.wrapping_sub(y) % height;
@@ -203,19 +464,21 @@ impl Ppu {
                     let bitlo = ((lo & ((0x80 >> i) as u8)) != 0) as u8;
                     let bithi = ((hi & ((0x80 >> i) as u8)) != 0) as u8;
                     let bits = (bithi << 1) | bitlo;
+                    if (bits == 0) || covered[dot] {
+                        continue;
+                    }
+                    covered[dot] = true;
                     let (color, z) = self.obj_color(bits, attr);
                     if z >= self.z_buffer[self.ly as usize][dot] {
                         self.z_buffer[self.ly as usize][dot] = z;
                         line[dot] = color;
+                        layers[dot] = Layer::Obj;
                     }
                 }
             }
         }
         // window?
-        if (self.lcdc & 0x20) != 0 {
-            if self.ly < self.wy {
-                return;
-            }
+        if ((lcdc & Layers::WINDOW) != 0) && (self.ly >= self.wy) {
             let win_data = if (self.lcdc & 0x40) == 0 {
                 &self.bg_data1
             } else {
@@ -257,26 +520,147 @@ impl Ppu {
                 if z >= self.z_buffer[self.ly as usize][dot] {
                     self.z_buffer[self.ly as usize][dot] = z;
                     line[dot] = color;
+                    layers[dot] = Layer::Window;
+                }
+            }
+        }
+        if self.tint {
+            for (pixel, layer) in line.iter_mut().zip(layers) {
+                *pixel = tint(*pixel, layer.tint());
+            }
+            for &i in &dropped[..dropped_count] {
+                let obj = &self.objs[(i * 4)..((i * 4) + 4)];
+                let obj_y = self.ly.wrapping_sub(obj[0].wrapping_sub(16));
+                let x = obj[1].wrapping_sub(8) as usize;
+                // the top and bottom rows all the way across, the sides on the rows between
+                let edge = (obj_y == 0) || (obj_y == height - 1);
+                for i in 0..8 {
+                    let dot = (i as usize).wrapping_add(x) % 256;
+                    if (dot < 160) && (edge || (i == 0) || (i == 7)) {
+                        line[dot] = DROPPED_OUTLINE;
+                    }
                 }
             }
         }
     }
 }
 
-impl<B: Bus> BusDevice<B> for Ppu {
-    fn reset(&mut self, _bus: &mut B) {
-        // TODO: use real random API
-        for b in self.chr_data[0].iter_mut() {
-            *b = unsafe { libc::rand() as u8 };
+#[derive(Clone, Copy)]
+enum Layer {
+    Bg,
+    Window,
+    Obj,
+}
+
+impl Layer {
+    #[inline]
+    fn tint(self) -> u32 {
+        match self {
+            Self::Bg => 0x3050FFFF,
+            Self::Window => 0x30C040FF,
+            Self::Obj => 0xFF3030FF,
         }
-        for b in self.bg_data1[0].iter_mut() {
-            *b = unsafe { libc::rand() as u8 };
+    }
+}
+
+const DROPPED_OUTLINE: u32 = 0xFF00FFFF;
+
+// halfway between a pixel and the tint, keeping the pixel's alpha
+#[inline]
+fn tint(pixel: u32, tint: u32) -> u32 {
+    let [r0, g0, b0, a] = pixel.to_be_bytes();
+    let [r1, g1, b1, _] = tint.to_be_bytes();
+    let mix = |c0: u8, c1: u8| ((c0 as u16 + c1 as u16) / 2) as u8;
+    u32::from_be_bytes([mix(r0, r1), mix(g0, g1), mix(b0, b1), a])
+}
+
+// everything but the picture itself, which is redrawn from this state anyway
+impl Hash for Ppu {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.chr_data.hash(state);
+        self.bg_data1.hash(state);
+        self.bg_data2.hash(state);
+        self.objs.hash(state);
+        (
+            self.dot,
+            self.dma_counter,
+            self.stat_irq,
+            self.line0_matched,
+            self.x_priority,
+        )
+            .hash(state);
+        [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.dma, self.bgp,
+            self.obp0, self.obp1, self.wy, self.wx, self.vbk,
+        ]
+        .hash(state);
+        [
+            self.hdma1, self.hdma2, self.hdma3, self.hdma4, self.hdma5, self.bcps, self.bcpd,
+            self.ocps, self.ocpd,
+        ]
+        .hash(state);
+    }
+}
+
+// the same as goes into the hash
+impl Snapshot for Ppu {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.chr_data.snapshot(state);
+        self.bg_data1.snapshot(state);
+        self.bg_data2.snapshot(state);
+        self.objs.snapshot(state);
+        self.dot.snapshot(state);
+        self.dma_counter.snapshot(state);
+        self.stat_irq.snapshot(state);
+        self.line0_matched.snapshot(state);
+        self.x_priority.snapshot(state);
+        for reg in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.dma,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+            &mut self.vbk,
+            &mut self.hdma1,
+            &mut self.hdma2,
+            &mut self.hdma3,
+            &mut self.hdma4,
+            &mut self.hdma5,
+            &mut self.bcps,
+            &mut self.bcpd,
+            &mut self.ocps,
+            &mut self.ocpd,
+        ] {
+            reg.snapshot(state);
         }
-        for b in self.bg_data2[0].iter_mut() {
-            *b = unsafe { libc::rand() as u8 };
+    }
+}
+
+impl<B: Bus> BusDevice<B> for Ppu {
+    fn reset(&mut self, _bus: &mut B) {
+        // xorshift, so the same seed always powers on with the same garbage
+        let mut x = (self.seed ^ 0x9E3779B97F4A7C15).max(1);
+        let vram = self.chr_data[0]
+            .iter_mut()
+            .chain(self.bg_data1[0].iter_mut())
+            .chain(self.bg_data2[0].iter_mut());
+        for b in vram {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *b = x as u8;
         }
         self.dot = 0;
         self.dma_counter = 0;
+        self.stat_irq = false;
+        self.line0_matched = false;
         self.lcdc = 0;
         self.stat = 0;
         self.scy = 0;
@@ -311,6 +695,8 @@ impl<B: Bus> BusDevice<B> for Ppu {
             Port::STAT => self.stat,
             Port::SCY => self.scy,
             Port::SCX => self.scx,
+            // line 153 only reads as 153 for its first few dots, then 0 until line 0 proper
+            Port::LY if (self.ly == 153) && (self.dot >= 4) => 0,
             Port::LY => self.ly,
             Port::LYC => self.lyc,
             Port::DMA => self.dma,
@@ -329,7 +715,16 @@ impl<B: Bus> BusDevice<B> for Ppu {
             Port::BCPD => self.bcpd, // TODO: palettes are an array that increments
             Port::OCPS => self.ocps,
             Port::OCPD => self.ocpd,
-            _ => unreachable!(),
+            _ => {
+                tracing::warn!(
+                    "{}",
+                    CoreError::Unmapped {
+                        device: "PPU",
+                        addr
+                    }
+                );
+                0xFF
+            }
         }
     }
 
@@ -348,6 +743,14 @@ impl<B: Bus> BusDevice<B> for Ppu {
                     value
                 };
                 self.stat = (value & 0x7C) | (self.stat & 0x03);
+                // DMG and SGB briefly see every STAT source enabled during the write, so being
+                // in hblank, vblank, or on the LYC line fires an interrupt. Some games rely on it
+                if (self.model != Model::Cgb)
+                    && ((self.lcdc & 0x80) != 0)
+                    && ((self.ly == self.lyc) || matches!(self.stat & 0x03, 0x00 | 0x01))
+                {
+                    self.stat_irq = true;
+                }
             }
             Port::SCY => self.scy = value,
             Port::SCX => self.scx = value,
@@ -363,20 +766,24 @@ impl<B: Bus> BusDevice<B> for Ppu {
             Port::WY => self.wy = value,
             Port::WX => self.wx = value,
             Port::VBK => self.vbk = value & 0x01,
-            Port::HMDA1 => {} //todo!(),
-            Port::HMDA2 => {} // todo!(),
-            Port::HMDA3 => {} //todo!(),
-            Port::HMDA4 => {} // todo!(),
-            Port::HMDA5 => {} // todo!(),
-            Port::BCPS => {}  //todo!(),
-            Port::BCPD => {}  //todo!(),
-            Port::OCPS => {}  //todo!(),
-            Port::OCPD => {}  // todo!(),
-            _ => unreachable!(),
+            // not emulated yet, the bus reports games using them
+            Port::HMDA1..=Port::HMDA5 | Port::BCPS..=Port::OCPD => {}
+            _ => tracing::warn!(
+                "{}",
+                CoreError::Unmapped {
+                    device: "PPU",
+                    addr
+                }
+            ),
         }
     }
 
     fn tick(&mut self, bus: &mut B) -> usize {
+        if self.stat_irq {
+            self.stat_irq = false;
+            let iflags = bus.read(Port::IF);
+            bus.write(Port::IF, iflags | 0x02);
+        }
         // dma active?
         if self.dma_counter > 0 {
             self.dma_counter -= 1;
@@ -392,19 +799,16 @@ impl<B: Bus> BusDevice<B> for Ppu {
             self.stat &= !0x03;
             self.ly = 0;
             self.dot = 0;
+            self.line0_matched = false;
             return 0;
         }
         if self.dot == 0 {
-            if self.ly == self.lyc {
-                self.stat |= 0x04;
-                // if LYC interrupt enabled, set the stat flag
-                if (self.stat & 0x40) != 0 {
-                    let iflags = bus.read(Port::IF);
-                    bus.write(Port::IF, iflags | 0x02);
-                }
-            } else {
-                self.stat &= !0x03;
-            }
+            // LYC=0 already matched late in line 153, so line 0 itself doesn't fire again
+            let fire = !mem::take(&mut self.line0_matched);
+            self.compare_lyc(bus, self.ly, fire);
+        } else if (self.ly == 153) && (self.dot == 8) {
+            self.compare_lyc(bus, 0, true);
+            self.line0_matched = true;
         }
         // before vblank
         if self.ly < 144 {
@@ -421,7 +825,9 @@ impl<B: Bus> BusDevice<B> for Ppu {
             } else if self.dot == 80 {
                 // switch to mode 3
                 self.stat = (self.stat & 0xFC) | 0x03;
-                self.draw_line(&mut bus.lcd_mut()[self.ly as usize]);
+                let mut line = [0; 160];
+                self.draw_line(&mut line);
+                bus.scanline(self.ly, &line);
             // hblank mode
             } else if self.dot == 370 {
                 // hblank mode
@@ -460,7 +866,7 @@ impl<B: Bus> BusDevice<B> for Ppu {
         if self.dot == 456 {
             self.dot = 0;
             self.ly += 1;
-            if self.ly == 155 {
+            if self.ly == 154 {
                 self.ly = 0;
             }
         }
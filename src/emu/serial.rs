@@ -0,0 +1,98 @@
+//! What the game sends out the serial port, which is how test ROMs like blargg's report
+
+use std::{collections::VecDeque, mem};
+
+/// Every byte the game starts a transfer with, kept for looking back over, and cut up into
+/// lines for printing as they finish. A line the same as the one before it is counted
+/// rather than handed out again, and past [`SerialOutput::MAX_PENDING`] lines not yet taken
+/// the rest are dropped, so a game stuck printing doesn't drown everything else out.
+#[derive(Default)]
+pub struct SerialOutput {
+    // the latest bytes, the oldest dropped past `CAPACITY`
+    text: VecDeque<u8>,
+    // the line being sent
+    line: Vec<u8>,
+    // finished lines waiting to be taken
+    pending: Vec<String>,
+    // the last line finished, and how many times it's been sent again since
+    last: Option<String>,
+    repeats: usize,
+    dropped: usize,
+}
+
+impl SerialOutput {
+    const CAPACITY: usize = 64 * 1024;
+    // a line that never ends is handed out in pieces this long
+    const MAX_LINE: usize = 256;
+    pub const MAX_PENDING: usize = 100;
+
+    pub fn push(&mut self, byte: u8) {
+        if self.text.len() == Self::CAPACITY {
+            self.text.pop_front();
+        }
+        self.text.push_back(byte);
+        match byte {
+            b'\n' => self.end_line(),
+            b'\r' => {}
+            _ => {
+                self.line.push(byte);
+                if self.line.len() >= Self::MAX_LINE {
+                    self.end_line();
+                }
+            }
+        }
+    }
+
+    fn end_line(&mut self) {
+        let line = String::from_utf8_lossy(&self.line).into_owned();
+        self.line.clear();
+        if self.last.as_ref() == Some(&line) {
+            self.repeats += 1;
+            return;
+        }
+        self.flush_repeats();
+        self.queue(line.clone());
+        self.last = Some(line);
+    }
+
+    fn flush_repeats(&mut self) {
+        match self.repeats {
+            0 => {}
+            1 => self.queue("(last line repeated once more)".to_string()),
+            n => self.queue(format!("(last line repeated {n} more times)")),
+        }
+        self.repeats = 0;
+    }
+
+    fn queue(&mut self, line: String) {
+        if self.pending.len() < Self::MAX_PENDING {
+            self.pending.push(line);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// The lines finished since the last time they were taken, and how many times the last
+    /// of them has been sent again.
+    pub fn take_lines(&mut self) -> Vec<String> {
+        // or output ending on a repeated line would never say so
+        self.flush_repeats();
+        let mut lines = mem::take(&mut self.pending);
+        if self.dropped != 0 {
+            lines.push(format!("({} more lines dropped)", self.dropped));
+            self.dropped = 0;
+        }
+        lines
+    }
+
+    /// Everything kept, the line still being sent included.
+    pub fn text(&self) -> String {
+        let (front, back) = self.text.as_slices();
+        String::from_utf8_lossy(&[front, back].concat()).into_owned()
+    }
+
+    /// Forgets everything sent so far.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
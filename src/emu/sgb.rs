@@ -0,0 +1,354 @@
+use std::mem;
+
+use super::state::{Snapshot, State};
+
+/// Super Game Boy state: command packets arrive over the joypad port, and the SNES side
+/// colors the screen and draws a border around it.
+#[derive(Hash)]
+pub struct Sgb {
+    // the SGB BIOS ignores packets from carts without the SGB header flag
+    enabled: bool,
+    initial: [u32; 4],
+    lines: u8,
+    bit: Option<usize>,
+    packet: [u8; 16],
+    command: Vec<u8>,
+    players: u8,
+    player: u8,
+    mask: Mask,
+    frozen: Option<Box<[[u32; 160]; 144]>>,
+    transfer: Option<Transfer>,
+    palettes: [[u32; 4]; 4],
+    attrs: [[u8; 20]; 18],
+    chr_data: [u8; 8192],
+    border_map: [u8; 2048],
+    border_palettes: [[u32; 16]; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Mask {
+    None,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Transfer {
+    Chr(usize),
+    Pct,
+}
+
+enum Command {}
+
+impl Command {
+    const PAL01: u8 = 0x00;
+    const PAL23: u8 = 0x01;
+    const PAL03: u8 = 0x02;
+    const PAL12: u8 = 0x03;
+    const ATTR_BLK: u8 = 0x04;
+    const MLT_REQ: u8 = 0x11;
+    const CHR_TRN: u8 = 0x13;
+    const PCT_TRN: u8 = 0x14;
+    const MASK_EN: u8 = 0x17;
+}
+
+// SNES colors are 15-bit BGR
+#[inline]
+fn rgba(lo: u8, hi: u8) -> u32 {
+    let color = u16::from_le_bytes([lo, hi]);
+    let expand = |c: u16| (((c & 0x1F) << 3) | ((c & 0x1F) >> 2)) as u32;
+    (expand(color) << 24) | (expand(color >> 5) << 16) | (expand(color >> 10) << 8) | 0xFF
+}
+
+impl Sgb {
+    pub fn new(enabled: bool, palette: [u32; 4]) -> Self {
+        Self {
+            enabled,
+            initial: palette,
+            lines: 0x30,
+            bit: None,
+            packet: [0; 16],
+            command: Vec::new(),
+            players: 1,
+            player: 0,
+            mask: Mask::None,
+            frozen: None,
+            transfer: None,
+            palettes: [palette; 4],
+            attrs: [[0; 20]; 18],
+            chr_data: [0; 8192],
+            border_map: [0; 2048],
+            border_palettes: [[0; 16]; 4],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.enabled, self.initial);
+    }
+
+    /// Replaces the low nibble of P1 with the current joypad ID while multiplayer is enabled
+    /// and neither button group is selected.
+    #[inline]
+    pub fn read(&self, p1: u8) -> u8 {
+        if (self.players > 1) && ((self.lines & 0x30) == 0x30) {
+            (p1 & 0xF0) | (0x0F - self.player)
+        } else {
+            p1
+        }
+    }
+
+    /// Watches P1 writes for packet bits. Pulling both lines low starts a packet, then each
+    /// bit is P14 (0) or P15 (1) pulsed low, LSB first, with a 0 stop bit after all 128.
+    pub fn write(&mut self, value: u8) {
+        let lines = value & 0x30;
+        let prev = mem::replace(&mut self.lines, lines);
+        if !self.enabled {
+            return;
+        }
+        match lines {
+            0x00 => {
+                self.bit = Some(0);
+                self.packet.fill(0);
+            }
+            0x10 | 0x20 if prev == 0x30 => {
+                let Some(bit) = self.bit else {
+                    return;
+                };
+                if bit == 128 {
+                    self.bit = None;
+                    self.packet();
+                    return;
+                }
+                if lines == 0x10 {
+                    self.packet[bit / 8] |= 1 << (bit % 8);
+                }
+                self.bit = Some(bit + 1);
+            }
+            // the joypad ID advances whenever P15 goes back high outside of a packet
+            0x30 if self.bit.is_none() && ((prev & 0x20) == 0) && (self.players > 1) => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => {}
+        }
+    }
+
+    fn packet(&mut self) {
+        // the first packet of a command holds the command and how many packets it spans
+        if self.command.is_empty() && ((self.packet[0] & 0x07) == 0) {
+            return;
+        }
+        self.command.extend_from_slice(&self.packet);
+        let len = (self.command[0] & 0x07) as usize;
+        if self.command.len() < (len * 16) {
+            return;
+        }
+        let command = mem::take(&mut self.command);
+        self.command(&command);
+    }
+
+    fn command(&mut self, data: &[u8]) {
+        let color = |i: usize| rgba(data[i], data[i + 1]);
+        match data[0] >> 3 {
+            cmd @ (Command::PAL01 | Command::PAL23 | Command::PAL03 | Command::PAL12) => {
+                let (a, b) = match cmd {
+                    Command::PAL01 => (0, 1),
+                    Command::PAL23 => (2, 3),
+                    Command::PAL03 => (0, 3),
+                    _ => (1, 2),
+                };
+                // color 0 is shared by every palette
+                for palette in self.palettes.iter_mut() {
+                    palette[0] = color(1);
+                }
+                for i in 0..3 {
+                    self.palettes[a][i + 1] = color(3 + (i * 2));
+                    self.palettes[b][i + 1] = color(9 + (i * 2));
+                }
+            }
+            Command::ATTR_BLK => {
+                let count = (data[1] as usize).min(18);
+                for set in data[2..].chunks_exact(6).take(count) {
+                    self.attr_blk(set);
+                }
+            }
+            Command::MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    0x01 => 2,
+                    0x03 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            Command::CHR_TRN => self.transfer = Some(Transfer::Chr((data[1] & 0x01) as usize)),
+            Command::PCT_TRN => self.transfer = Some(Transfer::Pct),
+            Command::MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    0x01 => Mask::Freeze,
+                    0x02 => Mask::Black,
+                    0x03 => Mask::Color0,
+                    _ => Mask::None,
+                };
+                self.frozen = None;
+            }
+            cmd => tracing::debug!("unimplemented SGB command {cmd:02X}"),
+        }
+    }
+
+    fn attr_blk(&mut self, set: &[u8]) {
+        let (control, palettes) = (set[0] & 0x07, set[1]);
+        let [x1, y1, x2, y2] = [set[2], set[3], set[4], set[5]].map(|n| n as usize);
+        let inside = palettes & 0x03;
+        let outside = (palettes >> 4) & 0x03;
+        // with only one of inside or outside set, the surrounding line goes along with it
+        let line = match control {
+            0x01 => Some(inside),
+            0x04 => Some(outside),
+            _ if (control & 0x02) != 0 => Some((palettes >> 2) & 0x03),
+            _ => None,
+        };
+        for (y, row) in self.attrs.iter_mut().enumerate() {
+            for (x, attr) in row.iter_mut().enumerate() {
+                let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                let on_line = within && ((x == x1) || (x == x2) || (y == y1) || (y == y2));
+                let change = if on_line {
+                    line
+                } else if within {
+                    ((control & 0x01) != 0).then_some(inside)
+                } else {
+                    ((control & 0x04) != 0).then_some(outside)
+                };
+                if let Some(palette) = change {
+                    *attr = palette;
+                }
+            }
+        }
+    }
+
+    /// Finishes a pending VRAM transfer and latches the frozen frame. `screen_tiles` is the
+    /// 4KiB of tile data the game is showing, in screen order.
+    pub fn vblank(&mut self, screen_tiles: &[u8; 4096], lcd: &[[u32; 160]; 144]) {
+        if (self.mask == Mask::Freeze) && self.frozen.is_none() {
+            self.frozen = Some(Box::new(*lcd));
+        }
+        match self.transfer.take() {
+            Some(Transfer::Chr(half)) => {
+                self.chr_data[(half * 4096)..((half + 1) * 4096)].copy_from_slice(screen_tiles);
+            }
+            Some(Transfer::Pct) => {
+                self.border_map.copy_from_slice(&screen_tiles[..2048]);
+                for (i, palette) in self.border_palettes.iter_mut().enumerate() {
+                    for (j, color) in palette.iter_mut().enumerate() {
+                        let offset = 2048 + (i * 32) + (j * 2);
+                        *color = rgba(screen_tiles[offset], screen_tiles[offset + 1]);
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn border_color(&self, x: usize, y: usize) -> Option<u32> {
+        let entry = ((y / 8) * 32) + (x / 8);
+        let (tile, attr) = (self.border_map[entry * 2], self.border_map[(entry * 2) + 1]);
+        let palette = ((attr >> 2) & 0x03) as usize;
+        let tx = if (attr & 0x40) == 0 {
+            x % 8
+        } else {
+            7 - (x % 8)
+        };
+        let ty = if (attr & 0x80) == 0 {
+            y % 8
+        } else {
+            7 - (y % 8)
+        };
+        // SNES tiles are 4bpp, with planes 0/1 interleaved in the first 16 bytes and 2/3 after
+        let data = &self.chr_data[(tile as usize * 32)..];
+        let bit = 0x80 >> tx;
+        let index = [
+            data[ty * 2],
+            data[(ty * 2) + 1],
+            data[16 + (ty * 2)],
+            data[17 + (ty * 2)],
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |index, (plane, &b)| {
+            index | ((((b & bit) != 0) as usize) << plane)
+        });
+        // color 0 lets the backdrop through
+        (index != 0).then(|| self.border_palettes[palette][index])
+    }
+
+    /// Composes the 256x224 SNES picture: the border, with the game screen colored by
+    /// the attribute map in the middle. `lcd` holds shade indices rather than colors.
+    pub fn render(&self, lcd: &[[u32; 160]; 144], out: &mut [[u32; 256]; 224]) {
+        let backdrop = self.palettes[0][0];
+        let lcd = self.frozen.as_deref().unwrap_or(lcd);
+        for (y, row) in out.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let (sx, sy) = (x.wrapping_sub(48), y.wrapping_sub(40));
+                *pixel = if (sx < 160) && (sy < 144) {
+                    match self.mask {
+                        Mask::Black => 0x000000FF,
+                        Mask::Color0 => backdrop,
+                        Mask::None | Mask::Freeze => {
+                            let palette = self.attrs[sy / 8][sx / 8] as usize;
+                            self.palettes[palette][(lcd[sy][sx] & 0x03) as usize]
+                        }
+                    }
+                } else {
+                    self.border_color(x, y).unwrap_or(backdrop)
+                };
+            }
+        }
+    }
+}
+
+impl Snapshot for Sgb {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        self.enabled.snapshot(state);
+        self.initial.snapshot(state);
+        self.lines.snapshot(state);
+        self.bit.snapshot(state);
+        self.packet.snapshot(state);
+        self.command.snapshot(state);
+        self.players.snapshot(state);
+        self.player.snapshot(state);
+        let mut mask = self.mask as u8;
+        mask.snapshot(state);
+        self.mask = match mask {
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            _ => Mask::None,
+        };
+        let mut frozen = self.frozen.is_some();
+        frozen.snapshot(state);
+        if frozen {
+            self.frozen
+                .get_or_insert_with(|| Box::new([[0; 160]; 144]))
+                .snapshot(state);
+        } else {
+            self.frozen = None;
+        }
+        // a CHR_TRN carries which half of the tiles it's for
+        let (mut kind, mut half) = match self.transfer {
+            None => (0u8, 0),
+            Some(Transfer::Chr(half)) => (1, half),
+            Some(Transfer::Pct) => (2, 0),
+        };
+        kind.snapshot(state);
+        half.snapshot(state);
+        self.transfer = match kind {
+            1 => Some(Transfer::Chr(half)),
+            2 => Some(Transfer::Pct),
+            _ => None,
+        };
+        self.palettes.snapshot(state);
+        self.attrs.snapshot(state);
+        self.chr_data.snapshot(state);
+        self.border_map.snapshot(state);
+        self.border_palettes.snapshot(state);
+    }
+}
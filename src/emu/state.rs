@@ -0,0 +1,168 @@
+//! Save states: everything [`Emu::state_hash`](super::Emu::state_hash) covers, as bytes
+
+/// Which way a [`Snapshot`] is going: out into a buffer, or back in from one.
+pub enum State<'a> {
+    Save(Vec<u8>),
+    Load {
+        data: &'a [u8],
+        pos: usize,
+        // ran out of data somewhere along the way
+        short: bool,
+    },
+}
+
+/// Something that can be saved and loaded. The one `snapshot` does both, going over the same
+/// fields in the same order either way, so saving and loading can't drift apart.
+pub trait Snapshot {
+    fn snapshot(&mut self, state: &mut State<'_>);
+}
+
+impl<'a> State<'a> {
+    pub fn save() -> Self {
+        Self::Save(Vec::new())
+    }
+
+    pub fn load(data: &'a [u8]) -> Self {
+        Self::Load {
+            data,
+            pos: 0,
+            short: false,
+        }
+    }
+
+    /// Writes `bytes` out, or overwrites them with the next ones in.
+    pub fn bytes(&mut self, bytes: &mut [u8]) {
+        match self {
+            Self::Save(out) => out.extend_from_slice(bytes),
+            Self::Load { data, pos, short } => match data.get(*pos..(*pos + bytes.len())) {
+                Some(src) => {
+                    bytes.copy_from_slice(src);
+                    *pos += bytes.len();
+                }
+                None => *short = true,
+            },
+        }
+    }
+
+    /// The saved bytes, or on the way in whether every byte was used up exactly.
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Save(out) => Ok(out),
+            Self::Load { short: true, .. } => Err("save state is cut short".to_string()),
+            Self::Load { data, pos, .. } if pos != data.len() => {
+                Err("save state has junk on the end".to_string())
+            }
+            Self::Load { .. } => Ok(Vec::new()),
+        }
+    }
+
+    fn cut_short(&mut self) {
+        if let Self::Load { short, .. } = self {
+            *short = true;
+        }
+    }
+
+    // how many bytes are left to load, for lengths that can't be trusted
+    fn remaining(&self) -> usize {
+        match self {
+            Self::Save(_) => usize::MAX,
+            Self::Load { data, pos, .. } => data.len().saturating_sub(*pos),
+        }
+    }
+}
+
+impl Snapshot for u8 {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        state.bytes(std::slice::from_mut(self));
+    }
+}
+
+impl Snapshot for bool {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut byte = *self as u8;
+        byte.snapshot(state);
+        *self = byte != 0;
+    }
+}
+
+impl Snapshot for u16 {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut bytes = self.to_le_bytes();
+        state.bytes(&mut bytes);
+        *self = Self::from_le_bytes(bytes);
+    }
+}
+
+impl Snapshot for u32 {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut bytes = self.to_le_bytes();
+        state.bytes(&mut bytes);
+        *self = Self::from_le_bytes(bytes);
+    }
+}
+
+impl Snapshot for u64 {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut bytes = self.to_le_bytes();
+        state.bytes(&mut bytes);
+        *self = Self::from_le_bytes(bytes);
+    }
+}
+
+// always 64 bits, so a state moves between 32 and 64 bit builds
+impl Snapshot for usize {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut value = *self as u64;
+        value.snapshot(state);
+        *self = value as usize;
+    }
+}
+
+impl Snapshot for f32 {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut bits = self.to_bits();
+        bits.snapshot(state);
+        *self = Self::from_bits(bits);
+    }
+}
+
+impl<T: Snapshot, const N: usize> Snapshot for [T; N] {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        for item in self {
+            item.snapshot(state);
+        }
+    }
+}
+
+impl<T: Snapshot + ?Sized> Snapshot for Box<T> {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        (**self).snapshot(state);
+    }
+}
+
+impl Snapshot for Vec<u8> {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut len = self.len();
+        len.snapshot(state);
+        if len > state.remaining() {
+            // a bogus length, rather than trying to allocate it
+            self.clear();
+            state.cut_short();
+            return;
+        }
+        self.resize(len, 0);
+        state.bytes(self);
+    }
+}
+
+impl<T: Snapshot + Default> Snapshot for Option<T> {
+    fn snapshot(&mut self, state: &mut State<'_>) {
+        let mut some = self.is_some();
+        some.snapshot(state);
+        if some {
+            self.get_or_insert_with(T::default).snapshot(state);
+        } else {
+            *self = None;
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::cmp::Ordering;
+
+/// Where the pictures go: a window, files on disk, or nowhere at all for headless runs.
+pub trait VideoSink {
+    /// A line of the game screen, as soon as the PPU has drawn it.
+    fn scanline(&mut self, _ly: u8, _line: &[u32; 160]) {}
+
+    /// A finished picture at the start of vblank, `width` pixels wide. That's the game
+    /// screen, or on an SGB the screen inside its border.
+    fn frame(&mut self, width: usize, pixels: &[u32]);
+}
+
+/// Throws everything away.
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn frame(&mut self, _width: usize, _pixels: &[u32]) {}
+}
+
+// several sinks watching the same run, e.g. the window while dumping frames
+impl VideoSink for Vec<Box<dyn VideoSink>> {
+    fn scanline(&mut self, ly: u8, line: &[u32; 160]) {
+        for sink in self {
+            sink.scanline(ly, line);
+        }
+    }
+
+    fn frame(&mut self, width: usize, pixels: &[u32]) {
+        for sink in self {
+            sink.frame(width, pixels);
+        }
+    }
+}
+
+/// Blends each frame into the last one shown, the way a DMG's LCD is slow to let go of a
+/// picture. Games that flicker sprites on and off every other frame lean on that to look
+/// see-through. `strength` is how much of the last picture lingers, 0 to leave frames alone
+/// and close to 1 for a long smear. Scanlines go through untouched.
+pub struct Ghosting {
+    sink: Box<dyn VideoSink>,
+    // how much of the last picture stays, out of 256
+    strength: u32,
+    last: Vec<u32>,
+}
+
+impl Ghosting {
+    pub fn new(sink: Box<dyn VideoSink>, strength: f32) -> Self {
+        Self {
+            sink,
+            strength: (strength.clamp(0.0, 1.0) * 256.0).round().min(255.0) as u32,
+            last: Vec::new(),
+        }
+    }
+}
+
+// one channel part of the way from what was shown to what's drawn now, `k` out of 256 staying
+#[inline]
+fn fade(k: u32, shown: u8, drawn: u8) -> u8 {
+    let blended = ((shown as u32 * k + drawn as u32 * (256 - k) + 128) >> 8) as u8;
+    // rounding would leave it stuck one short forever
+    match blended.cmp(&shown) {
+        Ordering::Equal if drawn > shown => shown + 1,
+        Ordering::Equal if drawn < shown => shown - 1,
+        _ => blended,
+    }
+}
+
+impl VideoSink for Ghosting {
+    fn scanline(&mut self, ly: u8, line: &[u32; 160]) {
+        self.sink.scanline(ly, line);
+    }
+
+    fn frame(&mut self, width: usize, pixels: &[u32]) {
+        // nothing to blend with at first, or once an SGB border comes or goes
+        if (self.strength == 0) || (self.last.len() != pixels.len()) {
+            self.last = pixels.to_vec();
+        } else {
+            let k = self.strength;
+            for (shown, &drawn) in self.last.iter_mut().zip(pixels) {
+                let [r0, g0, b0, _] = shown.to_be_bytes();
+                let [r1, g1, b1, a] = drawn.to_be_bytes();
+                *shown = u32::from_be_bytes([fade(k, r0, r1), fade(k, g0, g1), fade(k, b0, b1), a]);
+            }
+        }
+        self.sink.frame(width, &self.last);
+    }
+}
+
+/// A picture as plain RGBA8, 4 bytes a pixel in that order whatever the platform, row after
+/// row with no padding. The PPU makes pixels as `u32`s laid out `0xRRGGBBAA`, which is only
+/// the same bytes in memory on a big-endian machine, so this is what to hand anything that
+/// wants bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    width: usize,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl Frame {
+    /// Copies `pixels` as the PPU makes them (`0xRRGGBBAA`), `width` pixels a row.
+    pub fn from_pixels(width: usize, pixels: &[u32]) -> Self {
+        Self {
+            width,
+            pixels: pixels.iter().map(|pixel| pixel.to_be_bytes()).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.pixels.len() / self.width
+    }
+
+    /// The pixel at `x`, `y` as red, green, blue and alpha.
+    #[inline]
+    pub fn rgba(&self, x: usize, y: usize) -> [u8; 4] {
+        self.pixels[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn set_rgba(&mut self, x: usize, y: usize, rgba: [u8; 4]) {
+        self.pixels[y * self.width + x] = rgba;
+    }
+
+    /// Every row, top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &[[u8; 4]]> {
+        self.pixels.chunks(self.width)
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [[u8; 4]]> {
+        self.pixels.chunks_mut(self.width)
+    }
+
+    /// The whole picture as RGBA8 bytes, e.g. for an SDL `RGBA32` texture.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.pixels.as_flattened()
+    }
+
+    /// The bytes in a row of `as_bytes`.
+    #[inline]
+    pub fn pitch(&self) -> usize {
+        self.width * 4
+    }
+
+    /// RGB8 bytes, alpha dropped, as most image formats store them.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&[r, g, b, _]| [r, g, b])
+            .collect()
+    }
+
+    /// BGRA8 bytes, as Windows bitmaps and many GPU surfaces want them.
+    pub fn to_bgra8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&[r, g, b, a]| [b, g, r, a])
+            .collect()
+    }
+
+    /// Back to the PPU's `0xRRGGBBAA` pixels.
+    pub fn to_pixels(&self) -> Vec<u32> {
+        self.pixels
+            .iter()
+            .map(|&rgba| u32::from_be_bytes(rgba))
+            .collect()
+    }
+}
@@ -1,3 +1,5 @@
-#![feature(bigint_helper_methods)]
-
+pub mod asm;
+pub mod config;
+pub mod disasm;
 pub mod emu;
+pub mod patch;
@@ -0,0 +1,347 @@
+//! IPS and BPS patches, for handing out ROM hacks without the ROM they're made from
+
+// an IPS record can't start here, its offset would read as the end of the patch
+const IPS_EOF: usize = 0x454F46;
+// IPS offsets are 3 bytes, records are at most this long
+const IPS_MAX_OFFSET: usize = 0xFFFFFF;
+const IPS_MAX_RECORD: usize = 0xFFFF;
+// runs of the same byte at least this long are cheaper as an RLE record of their own, even
+// with the record that has to pick up after them
+const IPS_RLE_RUN: usize = 14;
+// the biggest cart there is (MBC5, 512 banks), a BPS target past this is a bad patch
+const BPS_MAX_TARGET: usize = 8 * 1024 * 1024;
+
+/// The patch formats we can write, picked by a patch file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ips,
+    Bps,
+}
+
+impl Format {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "ips" => Some(Self::Ips),
+            "bps" => Some(Self::Bps),
+            _ => None,
+        }
+    }
+
+    /// A patch that turns `base` into `target`.
+    pub fn diff(self, base: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Ips => ips(base, target),
+            Self::Bps => Ok(bps(base, target)),
+        }
+    }
+}
+
+/// An IPS patch turning `base` into `target`. Nearby changes share a record when that's
+/// shorter than starting a new one, and long runs of one byte are RLE records. A `target`
+/// shorter than `base` gets the common truncation extension at the end.
+pub fn ips(base: &[u8], target: &[u8]) -> Result<Vec<u8>, String> {
+    if target.len() > (IPS_MAX_OFFSET + 1) {
+        return Err(format!(
+            "IPS patches only reach 16 MiB, the ROM is {} bytes",
+            target.len()
+        ));
+    }
+    let differs = |i: usize| base.get(i) != Some(&target[i]);
+    let mut out = b"PATCH".to_vec();
+    let mut i = 0;
+    while i < target.len() {
+        if !differs(i) {
+            i += 1;
+            continue;
+        }
+        let mut start = i;
+        if start == IPS_EOF {
+            // the byte before is the same either way, so it can come along
+            start -= 1;
+        }
+        // a gap of up to a record header's worth of unchanged bytes is cheaper to copy over
+        let mut end = i + 1;
+        let mut gap = 0;
+        let mut j = end;
+        while (j < target.len()) && (j - start < IPS_MAX_RECORD) && (gap <= 5) {
+            if differs(j) {
+                end = j + 1;
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+            j += 1;
+        }
+        ips_records(&mut out, start, &target[start..end]);
+        i = end;
+    }
+    out.extend_from_slice(b"EOF");
+    if target.len() < base.len() {
+        out.extend_from_slice(&(target.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(out)
+}
+
+// one stretch of changes, as plain and RLE records
+fn ips_records(out: &mut Vec<u8>, offset: usize, data: &[u8]) {
+    let header = |out: &mut Vec<u8>, offset: usize, len: u16| {
+        out.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(&len.to_be_bytes());
+    };
+    let mut literal = 0;
+    let mut i = 0;
+    while i < data.len() {
+        let run = data[i..].iter().take_while(|&&b| b == data[i]).count();
+        // neither record may start where it would look like the end of the patch
+        let rle = ((run >= IPS_RLE_RUN) || ((run == data.len()) && (run > 3)))
+            && ((offset + i) != IPS_EOF)
+            && ((offset + i + run) != IPS_EOF);
+        if !rle {
+            i += run;
+            continue;
+        }
+        if literal < i {
+            header(out, offset + literal, (i - literal) as u16);
+            out.extend_from_slice(&data[literal..i]);
+        }
+        header(out, offset + i, 0);
+        out.extend_from_slice(&(run as u16).to_be_bytes());
+        out.push(data[i]);
+        i += run;
+        literal = i;
+    }
+    if literal < data.len() {
+        header(out, offset + literal, (data.len() - literal) as u16);
+        out.extend_from_slice(&data[literal..]);
+    }
+}
+
+/// A BPS patch turning `base` into `target`, copying whatever lines up with `base`, spelling
+/// out the rest, and repeating bytes for runs of one value.
+pub fn bps(base: &[u8], target: &[u8]) -> Vec<u8> {
+    const SOURCE_READ: usize = 0;
+    const TARGET_READ: usize = 1;
+    const TARGET_COPY: usize = 3;
+    // shorter matches and runs cost about as much to copy as to spell out
+    const MIN_MATCH: usize = 4;
+    const MIN_RUN: usize = 8;
+
+    let mut out = b"BPS1".to_vec();
+    bps_number(&mut out, base.len());
+    bps_number(&mut out, target.len());
+    // no metadata
+    bps_number(&mut out, 0);
+    let matches = |i: usize| {
+        (i..target.len())
+            .take_while(|&j| base.get(j) == Some(&target[j]))
+            .count()
+    };
+    let run = |i: usize| target[i..].iter().take_while(|&&b| b == target[i]).count();
+    let mut target_rel = 0;
+    let mut i = 0;
+    while i < target.len() {
+        let len = matches(i);
+        if len >= MIN_MATCH {
+            bps_number(&mut out, ((len - 1) << 2) | SOURCE_READ);
+            i += len;
+            continue;
+        }
+        let start = i;
+        while (i < target.len()) && (matches(i) < MIN_MATCH) && (run(i) < MIN_RUN) {
+            i += 1;
+        }
+        let repeat = (i < target.len()) && (matches(i) < MIN_MATCH);
+        if repeat {
+            // the first byte of a run is spelled out, the rest copy it over and over
+            i += 1;
+        }
+        bps_number(&mut out, ((i - start - 1) << 2) | TARGET_READ);
+        out.extend_from_slice(&target[start..i]);
+        if repeat {
+            let len = run(i - 1) - 1;
+            let from = i - 1;
+            bps_number(&mut out, ((len - 1) << 2) | TARGET_COPY);
+            if from < target_rel {
+                bps_number(&mut out, ((target_rel - from) << 1) | 1);
+            } else {
+                bps_number(&mut out, (from - target_rel) << 1);
+            }
+            target_rel = from + len;
+            i += len;
+        }
+    }
+    out.extend_from_slice(&crc32(base).to_le_bytes());
+    out.extend_from_slice(&crc32(target).to_le_bytes());
+    let crc = crc32(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+// BPS's variable length numbers, 7 bits at a time with the top bit marking the last
+fn bps_number(out: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let bits = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(0x80 | bits);
+            return;
+        }
+        out.push(bits);
+        value -= 1;
+    }
+}
+
+/// Applies an IPS or BPS patch to `base`, telling them apart by how they start. BPS patches
+/// carry CRCs of both ROMs and themselves, so a patch for some other ROM is caught.
+pub fn apply(patch: &[u8], base: &[u8]) -> Result<Vec<u8>, String> {
+    if let Some(records) = patch.strip_prefix(b"PATCH") {
+        apply_ips(records, base).ok_or_else(|| "IPS patch is cut short".to_string())
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(patch, base)
+    } else {
+        Err("not an IPS or BPS patch".to_string())
+    }
+}
+
+fn apply_ips(records: &[u8], base: &[u8]) -> Option<Vec<u8>> {
+    let mut out = base.to_vec();
+    let mut pos = 0;
+    let mut take = |len: usize| {
+        let bytes = records.get(pos..(pos + len))?;
+        pos += len;
+        Some(bytes)
+    };
+    let number = |bytes: &[u8]| bytes.iter().fold(0, |n, &b| (n << 8) | (b as usize));
+    loop {
+        let offset = number(take(3)?);
+        if offset == IPS_EOF {
+            break;
+        }
+        let len = number(take(2)?);
+        let (len, data) = if len == 0 {
+            // RLE, a length and the one byte to fill it with
+            let len = number(take(2)?);
+            (len, take(1)?.repeat(len))
+        } else {
+            (len, take(len)?.to_vec())
+        };
+        if out.len() < (offset + len) {
+            out.resize(offset + len, 0);
+        }
+        out[offset..(offset + len)].copy_from_slice(&data);
+    }
+    // the truncation extension
+    if let Some(len) = take(3) {
+        out.truncate(number(len));
+    }
+    Some(out)
+}
+
+fn apply_bps(patch: &[u8], base: &[u8]) -> Result<Vec<u8>, String> {
+    let corrupt = || "BPS patch is corrupt".to_string();
+    if patch.len() < 16 {
+        return Err(corrupt());
+    }
+    let (body, footer) = patch.split_at(patch.len() - 12);
+    let crc = |i: usize| u32::from_le_bytes(footer[(i * 4)..(i * 4 + 4)].try_into().unwrap());
+    if crc32(&patch[..(patch.len() - 4)]) != crc(2) {
+        return Err(corrupt());
+    }
+    if crc32(base) != crc(0) {
+        return Err("BPS patch is for a different ROM".to_string());
+    }
+    let mut pos = 4;
+    let source_len = bps_read_number(body, &mut pos).ok_or_else(corrupt)?;
+    let target_len = bps_read_number(body, &mut pos).ok_or_else(corrupt)?;
+    let metadata_len = bps_read_number(body, &mut pos).ok_or_else(corrupt)?;
+    pos = pos.checked_add(metadata_len).ok_or_else(corrupt)?;
+    if source_len != base.len() {
+        return Err("BPS patch is for a different ROM".to_string());
+    }
+    if target_len > BPS_MAX_TARGET {
+        return Err(corrupt());
+    }
+    let mut out = Vec::with_capacity(target_len);
+    let (mut source_rel, mut target_rel) = (0usize, 0usize);
+    // copies move a running offset along, by a signed amount each time
+    let relative = |at: &mut usize, pos: &mut usize| -> Option<()> {
+        let n = bps_read_number(body, pos)?;
+        *at = if (n & 1) != 0 {
+            at.checked_sub(n >> 1)?
+        } else {
+            at.checked_add(n >> 1)?
+        };
+        Some(())
+    };
+    while pos < body.len() {
+        let n = bps_read_number(body, &mut pos).ok_or_else(corrupt)?;
+        let len = (n >> 2) + 1;
+        // every action adds `len` bytes, none may go past the target
+        if len > (target_len - out.len()) {
+            return Err(corrupt());
+        }
+        let span = |start: usize| Some(start..start.checked_add(len)?);
+        match n & 3 {
+            // source read, from where the output is up to
+            0 => {
+                let at = out.len();
+                out.extend_from_slice(span(at).and_then(|r| base.get(r)).ok_or_else(corrupt)?);
+            }
+            // target read, the bytes are in the patch
+            1 => {
+                let range = span(pos).ok_or_else(corrupt)?;
+                pos = range.end;
+                out.extend_from_slice(body.get(range).ok_or_else(corrupt)?);
+            }
+            // source copy
+            2 => {
+                relative(&mut source_rel, &mut pos).ok_or_else(corrupt)?;
+                let range = span(source_rel).ok_or_else(corrupt)?;
+                source_rel = range.end;
+                out.extend_from_slice(base.get(range).ok_or_else(corrupt)?);
+            }
+            // target copy, a byte at a time since it may overlap what it's writing
+            _ => {
+                relative(&mut target_rel, &mut pos).ok_or_else(corrupt)?;
+                for _ in 0..len {
+                    let byte = *out.get(target_rel).ok_or_else(corrupt)?;
+                    out.push(byte);
+                    target_rel += 1;
+                }
+            }
+        }
+    }
+    if (out.len() != target_len) || (crc32(&out) != crc(1)) {
+        return Err(corrupt());
+    }
+    Ok(out)
+}
+
+fn bps_read_number(bytes: &[u8], pos: &mut usize) -> Option<usize> {
+    let (mut value, mut shift) = (0usize, 1usize);
+    loop {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value = value.checked_add(((byte & 0x7F) as usize).checked_mul(shift)?)?;
+        if (byte & 0x80) != 0 {
+            return Some(value);
+        }
+        shift = shift.checked_mul(0x80)?;
+        value = value.checked_add(shift)?;
+    }
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if (crc & 1) != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use gb23::asm::{Asm, Dialect, Lexer, Radix, DEFAULT_MACRO_DEPTH};
+
+// what assembling a source gives, whether or not it worked
+struct Build {
+    result: Result<(), String>,
+    rom: Vec<u8>,
+    warnings: Vec<String>,
+    map: String,
+}
+
+// assembles `src` as if it were test.asm, the way gb23-asm does
+fn build_with_depth(src: &str, macro_depth: usize) -> Build {
+    let lexer = Lexer::new("test.asm", src.as_bytes(), Dialect::Gb23).unwrap();
+    let mut rom = Vec::new();
+    let mut asm = Asm::new(
+        lexer,
+        Path::new("."),
+        Box::new(&mut rom),
+        macro_depth,
+        Dialect::Gb23,
+    );
+    let result = asm
+        .first_pass()
+        .and_then(|()| asm.second_pass())
+        .map_err(|e| e.to_string());
+    let warnings = asm.warnings().to_vec();
+    let mut map = Vec::new();
+    asm.write_map(&mut map, Radix::Dollar, Radix::Dollar)
+        .unwrap();
+    drop(asm);
+    Build {
+        result,
+        rom,
+        warnings,
+        map: String::from_utf8(map).unwrap(),
+    }
+}
+
+fn build(src: &str) -> Build {
+    build_with_depth(src, DEFAULT_MACRO_DEPTH)
+}
+
+fn error(src: &str) -> String {
+    build(src).result.unwrap_err()
+}
+
+#[test]
+fn signed_bytes_that_fit_are_fine() {
+    let build = build("    DB -1, -128, 255\n    DW -1\n");
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(build.rom, [0xFF, 0x80, 0xFF, 0xFF, 0xFF]);
+    assert!(build.warnings.is_empty(), "{:?}", build.warnings);
+}
+
+#[test]
+fn bytes_that_dont_fit_are_truncated_with_a_warning() {
+    let build = build("    NOP\n    DB 256, -129\n");
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(build.rom, [0x00, 0x00, 0x7F]);
+    assert_eq!(
+        build.warnings,
+        [
+            "test.asm:2: expression $100 truncated to 1 byte, use LOW() or HIGH() if intended",
+            "test.asm:2: expression -$81 truncated to 1 byte, use LOW() or HIGH() if intended",
+        ]
+    );
+}
+
+#[test]
+fn low_and_high_truncate_quietly() {
+    let build = build("    DB LOW($1234), HIGH($1234)\n");
+    assert_eq!(build.rom, [0x34, 0x12]);
+    assert!(build.warnings.is_empty(), "{:?}", build.warnings);
+}
+
+#[test]
+fn wrapping_past_32_bits_warns() {
+    let build = build("    DB ($7FFFFFFF + 1) >> 31\n");
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(
+        build.warnings,
+        ["test.asm:1: expression overflows 32 bits, the result wrapped around"]
+    );
+}
+
+#[test]
+fn division_by_zero_is_an_error() {
+    assert_eq!(error("    DB 1 / 0\n"), "test.asm:1: division by zero");
+    assert_eq!(
+        error("    NOP\n    DB 1 % (2 - 2)\n"),
+        "test.asm:2: division by zero"
+    );
+}
+
+#[test]
+fn shifts_out_of_range_are_errors() {
+    assert_eq!(
+        error("    DW 1 << 32\n"),
+        "test.asm:1: shift by 32 out of range 0-31"
+    );
+    assert_eq!(
+        error("    DW 1 >> -1\n"),
+        "test.asm:1: shift by -1 out of range 0-31"
+    );
+    assert_eq!(build("    DW 1 << 31 ~> 31\n").rom, [0x01, 0x00]);
+}
+
+#[test]
+fn unary_operators_chain() {
+    let build = build("    DB - -3, + -1, 2 - -1 * 2, ~$F0 & $FF, !5, -~0\n    LD HL, SP + -3\n");
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(build.rom, [3, 0xFF, 4, 0x0F, 0, 1, 0xF8, 0xFD]);
+}
+
+#[test]
+fn malformed_expressions_are_errors() {
+    assert_eq!(error("    DB 1 +\n"), "test.asm:1: expected value");
+    assert_eq!(error("    DB (1, 2)\n"), "test.asm:1: expected )");
+    assert_eq!(error("    DB 1 ~ 0\n"), "test.asm:1: expected operator");
+}
+
+#[test]
+fn macro_arguments_can_be_expressions() {
+    let src = "\
+sum MACRO
+    DB \\1 + \\2
+END
+load MACRO
+    LD A, \\1
+END
+    sum(2 + 3, (1 + 1) * 4)
+    sum(there - here, 1)
+here
+    load([$C000 + 2])
+there
+";
+    let build = build(src);
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(build.rom, [13, 4, 0xFA, 0x02, 0xC0]);
+}
+
+#[test]
+fn macros_can_call_macros_defined_later() {
+    let src = "\
+outer MACRO
+    inner(\\1 + 1)
+    DB \\1
+END
+    outer(1)
+inner MACRO
+    DB \\1
+END
+";
+    let build = build(src);
+    assert_eq!(build.result, Ok(()));
+    assert_eq!(build.rom, [2, 1]);
+}
+
+#[test]
+fn macro_expansion_depth_is_limited() {
+    let src = "\
+forever MACRO
+    DB \\1
+    forever(\\1 + 1)
+END
+    NOP
+    forever(1)
+";
+    assert_eq!(
+        build_with_depth(src, 3).result.unwrap_err(),
+        "test.asm:6:forever: macro expansion too deep: forever -> forever -> forever -> forever"
+    );
+    assert!(build(src)
+        .result
+        .unwrap_err()
+        .contains("macro expansion too deep"));
+}
+
+#[test]
+fn purged_macros_cant_be_called() {
+    let src = "\
+byte MACRO
+    DB 1
+END
+    byte()
+    PURGE byte
+    byte()
+";
+    assert_eq!(error(src), "test.asm:6: macro byte was purged");
+    // but the name is free to be used again
+    let src = "\
+byte MACRO
+    DB 1
+END
+    byte()
+    PURGE byte
+byte = 2
+    DB byte
+";
+    assert_eq!(build(src).rom, [1, 2]);
+}
+
+#[test]
+fn floating_romx_goes_in_the_first_bank_with_room() {
+    // ALIGN pads with zeroes, which makes segments of $3000, $2000 and $800 bytes
+    let src = "\
+    SEGMENT \"ROMX\", 1
+fixed
+    DB 0
+    ALIGN $2000
+    DB 0
+    ALIGN $1000
+    SEGMENT \"ROMX\"
+big
+    DB 0
+    ALIGN $2000
+    SEGMENT \"ROMX\"
+small
+    DB 0
+    ALIGN $800
+";
+    let build = build(src);
+    assert_eq!(build.result, Ok(()));
+    // bank 1 has $1000 bytes left, too few for the first and enough for the second
+    assert_eq!(
+        build.map,
+        "\
+ROM01: $4000-$6FFF, 12288 bytes
+  $4000 fixed
+ROMX #1 (bank 1, from test.asm:11): $7000-$77FF, 2048 bytes
+  $7000 small
+ROMX #0 (bank 2, from test.asm:7): $4000-$5FFF, 8192 bytes
+  $4000 big
+constants:
+"
+    );
+}
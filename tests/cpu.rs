@@ -0,0 +1,395 @@
+use gb23::emu::{
+    bus::{Bus, BusDevice},
+    cpu::{cb_opcode, Cpu, Flag, Register, StepInfo, WideRegister, OPCODES},
+};
+
+// nothing but 64 KiB of RAM, with IE and IF left at 0 so nothing interrupts
+struct FlatBus([u8; 0x10000]);
+
+impl Bus for FlatBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.0[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.0[addr as usize] = value;
+    }
+}
+
+// cycles per opcode as Pan Docs lists them, conditional ones when not taken. 0 is the CB
+// prefix and the opcodes that lock up the CPU, which have no timing to speak of
+#[rustfmt::skip]
+const TIMINGS: [usize; 256] = [
+    4, 12,  8,  8,  4,  4,  8,  4, 20,  8,  8,  8,  4,  4,  8,  4,
+    4, 12,  8,  8,  4,  4,  8,  4, 12,  8,  8,  8,  4,  4,  8,  4,
+    8, 12,  8,  8,  4,  4,  8,  4,  8,  8,  8,  8,  4,  4,  8,  4,
+    8, 12,  8,  8, 12, 12, 12,  4,  8,  8,  8,  8,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    8,  8,  8,  8,  8,  8,  4,  8,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    4,  4,  4,  4,  4,  4,  8,  4,  4,  4,  4,  4,  4,  4,  8,  4,
+    8, 12, 12, 16, 12, 16,  8, 16,  8, 16, 12,  0, 12, 24,  8, 16,
+    8, 12, 12,  0, 12, 16,  8, 16,  8, 16, 12,  0, 12,  0,  8, 16,
+   12, 12,  8,  0,  0, 16,  8, 16, 16,  4, 16,  0,  0,  0,  8, 16,
+   12, 12,  8,  4,  0, 16,  8, 16, 12,  8, 16,  4,  0,  0,  8, 16,
+];
+
+// the conditional jumps, calls and returns when they're taken
+const TAKEN: [(u8, usize); 16] = [
+    (0x20, 12),
+    (0x28, 12),
+    (0x30, 12),
+    (0x38, 12),
+    (0xC0, 20),
+    (0xC8, 20),
+    (0xD0, 20),
+    (0xD8, 20),
+    (0xC2, 16),
+    (0xCA, 16),
+    (0xD2, 16),
+    (0xDA, 16),
+    (0xC4, 24),
+    (0xCC, 24),
+    (0xD4, 24),
+    (0xDC, 24),
+];
+
+// runs one instruction out of WRAM, with the flags set up to take a conditional branch
+// or not, and returns how long it took
+fn cycles(code: &[u8], taken: bool) -> usize {
+    let mut bus = FlatBus([0; 0x10000]);
+    bus.0[0xC000..(0xC000 + code.len())].copy_from_slice(code);
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    cpu.set_wide_register(WideRegister::PC, 0xC000);
+    cpu.set_wide_register(WideRegister::SP, 0xDFF0);
+    cpu.set_wide_register(WideRegister::HL, 0xD000);
+    let opcode = code[0];
+    if TAKEN.iter().any(|&(branch, _)| branch == opcode) {
+        // bit 4 picks the flag, bit 3 whether it has to be set
+        let flag = if (opcode & 0x10) != 0 {
+            Flag::Carry
+        } else {
+            Flag::Zero
+        };
+        cpu.set_flag(flag, ((opcode & 0x08) != 0) == taken);
+    }
+    cpu.tick(&mut bus)
+}
+
+#[test]
+fn unprefixed_timings() {
+    for (opcode, &expected) in TIMINGS.iter().enumerate() {
+        if expected == 0 {
+            continue;
+        }
+        let opcode = opcode as u8;
+        assert_eq!(
+            cycles(&[opcode, 0x00, 0xC0], false),
+            expected,
+            "opcode ${opcode:02X}"
+        );
+    }
+}
+
+#[test]
+fn taken_branch_timings() {
+    for (opcode, expected) in TAKEN {
+        assert_eq!(
+            cycles(&[opcode, 0x00, 0xC0], true),
+            expected,
+            "opcode ${opcode:02X} taken"
+        );
+    }
+}
+
+#[test]
+fn cb_timings() {
+    for opcode in 0x00..=0xFF {
+        // (HL) is read, changed and written back, except by BIT which only reads it
+        let expected = match (opcode & 0x07, opcode) {
+            (0x06, 0x40..=0x7F) => 12,
+            (0x06, _) => 16,
+            _ => 8,
+        };
+        assert_eq!(
+            cycles(&[0xCB, opcode], false),
+            expected,
+            "opcode $CB ${opcode:02X}"
+        );
+    }
+}
+
+#[test]
+fn opcode_table_matches_timings() {
+    for (opcode, &expected) in TIMINGS.iter().enumerate() {
+        if expected != 0 {
+            assert_eq!(
+                OPCODES[opcode].cycles as usize, expected,
+                "opcode ${opcode:02X}"
+            );
+        }
+    }
+    for (opcode, expected) in TAKEN {
+        assert_eq!(
+            OPCODES[opcode as usize].taken_cycles as usize, expected,
+            "opcode ${opcode:02X} taken"
+        );
+    }
+    for opcode in 0x00..=0xFF {
+        assert_eq!(
+            cb_opcode(opcode).cycles as usize,
+            cycles(&[0xCB, opcode], false),
+            "opcode $CB ${opcode:02X}"
+        );
+    }
+}
+
+#[test]
+fn opcode_table_lengths() {
+    for (opcode, info) in OPCODES.iter().enumerate() {
+        let opcode = opcode as u8;
+        // everything that doesn't go somewhere else (or stop) moves on by its length
+        let jumps = matches!(
+            opcode,
+            0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0x76 | 0xE9
+        ) || ((opcode & 0xC7) == 0xC7)
+            || matches!(opcode & 0xE7, 0xC0 | 0xC2 | 0xC4)
+            || matches!(opcode, 0xC3 | 0xC9 | 0xCD | 0xD9);
+        // the illegal opcodes lock up
+        let illegal = (TIMINGS[opcode as usize] == 0) && (opcode != 0xCB);
+        if jumps || illegal {
+            continue;
+        }
+        let mut bus = FlatBus([0; 0x10000]);
+        bus.0[0xC000..0xC003].copy_from_slice(&[opcode, 0x00, 0xD0]);
+        let mut cpu = Cpu::new();
+        cpu.reset(&mut bus);
+        cpu.set_wide_register(WideRegister::PC, 0xC000);
+        cpu.set_wide_register(WideRegister::SP, 0xDFF0);
+        cpu.tick(&mut bus);
+        assert_eq!(
+            cpu.wide_register(WideRegister::PC),
+            0xC000 + info.len as u16,
+            "opcode ${opcode:02X}"
+        );
+    }
+}
+
+// runs a single byte opcode on A and B with the flags in F, and returns A and F after
+fn alu(opcode: u8, a: u8, b: u8, f: u8) -> (u8, u8) {
+    let mut bus = FlatBus([0; 0x10000]);
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    alu_on(&mut cpu, &mut bus, opcode, a, b, f)
+}
+
+fn alu_on(cpu: &mut Cpu, bus: &mut FlatBus, opcode: u8, a: u8, b: u8, f: u8) -> (u8, u8) {
+    bus.0[0xC000] = opcode;
+    cpu.set_wide_register(WideRegister::PC, 0xC000);
+    cpu.set_wide_register(WideRegister::AF, u16::from_be_bytes([a, f]));
+    cpu.set_register(Register::B, b);
+    cpu.tick(bus);
+    (cpu.register(Register::A), cpu.register(Register::F))
+}
+
+fn flags(z: bool, n: bool, h: bool, c: bool) -> u8 {
+    ((z as u8) << 7) | ((n as u8) << 6) | ((h as u8) << 5) | ((c as u8) << 4)
+}
+
+// every A, every B and both carries in, checked against Pan Docs' flags worked out
+// the long way
+fn check_alu(opcode: u8, name: &str, expected: impl Fn(u8, u8, bool) -> (u8, u8)) {
+    let mut bus = FlatBus([0; 0x10000]);
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    for a in 0..=0xFF {
+        for b in 0..=0xFF {
+            for carry in [false, true] {
+                // the other flags going in mustn't matter
+                let f = if carry { 0xF0 } else { 0xE0 };
+                assert_eq!(
+                    alu_on(&mut cpu, &mut bus, opcode, a, b, f),
+                    expected(a, b, carry),
+                    "{name} A=${a:02X} B=${b:02X} C={}",
+                    carry as u8
+                );
+            }
+        }
+    }
+}
+
+fn add(a: u8, b: u8, carry: bool) -> (u8, u8) {
+    let sum = (a as u16) + (b as u16) + (carry as u16);
+    let half = (a & 0x0F) + (b & 0x0F) + (carry as u8);
+    let result = sum as u8;
+    (result, flags(result == 0, false, half > 0x0F, sum > 0xFF))
+}
+
+fn sub(a: u8, b: u8, carry: bool) -> (u8, u8) {
+    let difference = (a as i16) - (b as i16) - (carry as i16);
+    let half = ((a & 0x0F) as i16) - ((b & 0x0F) as i16) - (carry as i16);
+    let result = difference as u8;
+    (result, flags(result == 0, true, half < 0, difference < 0))
+}
+
+#[test]
+fn add_flags() {
+    check_alu(0x80, "ADD A,B", |a, b, _| add(a, b, false));
+}
+
+#[test]
+fn adc_flags() {
+    check_alu(0x88, "ADC A,B", add);
+}
+
+#[test]
+fn sub_flags() {
+    check_alu(0x90, "SUB A,B", |a, b, _| sub(a, b, false));
+}
+
+#[test]
+fn sbc_flags() {
+    check_alu(0x98, "SBC A,B", sub);
+}
+
+#[test]
+fn cp_flags() {
+    check_alu(0xB8, "CP A,B", |a, b, _| (a, sub(a, b, false).1));
+}
+
+#[test]
+fn inc_dec_flags() {
+    for value in 0..=0xFFu8 {
+        for f in (0x00..=0xF0).step_by(0x10) {
+            // C is kept as it was
+            let carry = f & 0x10;
+            let result = value.wrapping_add(1);
+            let expected = flags(result == 0, false, (value & 0x0F) == 0x0F, false) | carry;
+            assert_eq!(
+                alu(0x3C, value, 0, f),
+                (result, expected),
+                "INC A=${value:02X} F=${f:02X}"
+            );
+            let result = value.wrapping_sub(1);
+            let expected = flags(result == 0, true, (value & 0x0F) == 0x00, false) | carry;
+            assert_eq!(
+                alu(0x3D, value, 0, f),
+                (result, expected),
+                "DEC A=${value:02X} F=${f:02X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn daa_flags() {
+    for a in 0..=0xFFu8 {
+        for f in (0x00..=0xF0).step_by(0x10) {
+            let (n, h, c) = ((f & 0x40) != 0, (f & 0x20) != 0, (f & 0x10) != 0);
+            let mut adjust = 0;
+            let mut carry = c;
+            if h || (!n && ((a & 0x0F) > 0x09)) {
+                adjust |= 0x06;
+            }
+            if c || (!n && (a > 0x99)) {
+                adjust |= 0x60;
+                carry = true;
+            }
+            let result = if n {
+                a.wrapping_sub(adjust)
+            } else {
+                a.wrapping_add(adjust)
+            };
+            assert_eq!(
+                alu(0x27, a, 0, f),
+                (result, flags(result == 0, n, false, carry)),
+                "DAA A=${a:02X} F=${f:02X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn daa_fixes_up_bcd() {
+    // every pair of 2 digit BCD numbers, added and subtracted, with the carry as the 100s
+    for x in 0..100u8 {
+        for y in 0..100u8 {
+            let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+            let (sum, f) = alu(0x80, bcd(x), bcd(y), 0x00);
+            let (sum, f) = alu(0x27, sum, 0, f);
+            assert_eq!(sum, bcd((x + y) % 100), "{x} + {y}");
+            assert_eq!((f & 0x10) != 0, (x + y) >= 100, "{x} + {y} carry");
+            let (difference, f) = alu(0x90, bcd(x), bcd(y), 0x00);
+            let (difference, f) = alu(0x27, difference, 0, f);
+            assert_eq!(
+                difference,
+                bcd(((x as i16 - y as i16).rem_euclid(100)) as u8),
+                "{x} - {y}"
+            );
+            assert_eq!((f & 0x10) != 0, x < y, "{x} - {y} borrow");
+        }
+    }
+}
+
+#[test]
+fn step_reports_what_ran() {
+    let mut bus = FlatBus([0; 0x10000]);
+    // LD A,$42; HALT
+    bus.0[0xC000..0xC003].copy_from_slice(&[0x3E, 0x42, 0x76]);
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    cpu.set_wide_register(WideRegister::PC, 0xC000);
+    let mut seen = Vec::new();
+    let step = cpu.step_with(&mut bus, |cpu, pc, opcode| {
+        // nothing has run yet
+        seen.push((pc, opcode, cpu.register(Register::A)));
+    });
+    assert_eq!(seen, [(0xC000, 0x3E, 0x00)]);
+    assert_eq!(
+        step,
+        StepInfo {
+            opcode: Some(0x3E),
+            pc_before: 0xC000,
+            cycles: 8,
+        }
+    );
+    assert_eq!(cpu.register(Register::A), 0x42);
+    assert_eq!(cpu.step(&mut bus).opcode, Some(0x76));
+    // halted, so no more instructions and no more hook calls
+    let step = cpu.step_with(&mut bus, |_, _, _| panic!("nothing to run"));
+    assert_eq!(
+        step,
+        StepInfo {
+            opcode: None,
+            pc_before: 0xC003,
+            cycles: 4,
+        }
+    );
+}
+
+#[test]
+fn step_reports_interrupt_dispatch() {
+    let mut bus = FlatBus([0; 0x10000]);
+    bus.0[0xFFFF] = 0x04;
+    bus.0[0xFF0F] = 0x04;
+    let mut cpu = Cpu::new();
+    cpu.reset(&mut bus);
+    cpu.set_wide_register(WideRegister::PC, 0xC000);
+    cpu.set_wide_register(WideRegister::SP, 0xDFF0);
+    cpu.set_ime(true);
+    let step = cpu.step(&mut bus);
+    assert_eq!(
+        step,
+        StepInfo {
+            opcode: None,
+            pc_before: 0xC000,
+            cycles: 20,
+        }
+    );
+    assert_eq!(cpu.wide_register(WideRegister::PC), 0x0050);
+}
@@ -0,0 +1,148 @@
+use gb23::{
+    config::{Model, Settings},
+    emu::{
+        bus::{Bus, BusDevice, Port},
+        cpu::WideRegister,
+        mbc::mbc1::Mbc1,
+        ppu::Ppu,
+        Emu,
+    },
+};
+
+struct NoInput;
+
+impl<B: Bus> BusDevice<B> for NoInput {
+    fn reset(&mut self, _bus: &mut B) {}
+
+    fn read(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    fn tick(&mut self, _bus: &mut B) -> usize {
+        0
+    }
+}
+
+// mixes the power-on VRAM with DIV into itself, forever, so any difference in either
+// spreads all over the state
+const PROGRAM: &[u8] = &[
+    0x21, 0x00, 0x80, // ld hl, $8000
+    0xF0, 0x04, //       ldh a, [DIV]
+    0xAE, //             xor [hl]
+    0x22, //             ld [hl+], a
+    0xCB, 0x74, //       bit 6, h
+    0x28, 0xF8, //       jr z, $0103
+    0x18, 0xF3, //       jr $0100
+];
+
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..(0x100 + PROGRAM.len())].copy_from_slice(PROGRAM);
+    rom
+}
+
+fn boot<'a>(settings: &Settings, rom: &'a [u8], sram: &'a mut [u8]) -> Emu<Mbc1<'a>, Ppu, NoInput> {
+    let mut emu = Emu::new(settings, Vec::new(), Mbc1::new(rom, sram), NoInput);
+    emu.reset();
+    let (cpu, mut cpu_view) = emu.cpu_view();
+    cpu.set_wide_register(WideRegister::PC, 0x100);
+    cpu_view.write(Port::BOOT, 0x01);
+    cpu_view.write(Port::LCDC, 0x81);
+    emu
+}
+
+fn run_frames(emu: &mut Emu<Mbc1<'_>, Ppu, NoInput>, frames: usize) -> Vec<u64> {
+    let mut hashes = Vec::new();
+    while hashes.len() < frames {
+        emu.tick();
+        if emu.vblanked() {
+            hashes.push(emu.state_hash());
+        }
+    }
+    hashes
+}
+
+fn hashes(settings: &Settings, frames: usize) -> Vec<u64> {
+    let rom = rom();
+    let mut sram = vec![0; 0x2000];
+    run_frames(&mut boot(settings, &rom, &mut sram), frames)
+}
+
+#[test]
+fn same_run_same_hashes() {
+    for model in [Model::Dmg, Model::Sgb, Model::Cgb] {
+        let settings = Settings {
+            model,
+            seed: 1234,
+            ..Settings::default()
+        };
+        let first = hashes(&settings, 120);
+        assert_eq!(first, hashes(&settings, 120));
+        // and the hash actually follows the machine along
+        assert!(first.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+}
+
+#[test]
+fn seed_decides_power_on_state() {
+    let settings = Settings {
+        seed: 1,
+        ..Settings::default()
+    };
+    let other = Settings {
+        seed: 2,
+        ..Settings::default()
+    };
+    assert_ne!(hashes(&settings, 1), hashes(&other, 1));
+}
+
+#[test]
+fn save_state_picks_up_where_it_left_off() {
+    let rom = rom();
+    for model in [Model::Dmg, Model::Sgb, Model::Cgb] {
+        let settings = Settings {
+            model,
+            seed: 1234,
+            ..Settings::default()
+        };
+        let mut sram = vec![0; 0x2000];
+        let mut emu = boot(&settings, &rom, &mut sram);
+        run_frames(&mut emu, 30);
+        let state = emu.save_state();
+        let after = run_frames(&mut emu, 30);
+
+        // a machine that powered on differently still ends up the same
+        let other = Settings {
+            seed: 5678,
+            ..settings.clone()
+        };
+        let mut other_sram = vec![0; 0x2000];
+        let mut other_emu = boot(&other, &rom, &mut other_sram);
+        other_emu.load_state(&state).unwrap();
+        assert_eq!(after, run_frames(&mut other_emu, 30));
+    }
+}
+
+#[test]
+fn bad_save_state_leaves_machine_alone() {
+    let rom = rom();
+    let mut sram = vec![0; 0x2000];
+    let mut emu = boot(&Settings::default(), &rom, &mut sram);
+    run_frames(&mut emu, 10);
+    let state = emu.save_state();
+    run_frames(&mut emu, 10);
+    let hash = emu.state_hash();
+    assert!(emu.load_state(&state[..(state.len() - 1)]).is_err());
+    assert!(emu.load_state(b"not a state").is_err());
+    assert_eq!(hash, emu.state_hash());
+
+    let cgb = Settings {
+        model: Model::Cgb,
+        ..Settings::default()
+    };
+    let mut cgb_sram = vec![0; 0x2000];
+    let mut cgb_emu = boot(&cgb, &rom, &mut cgb_sram);
+    assert!(cgb_emu.load_state(&state).is_err());
+}
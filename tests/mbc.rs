@@ -0,0 +1,482 @@
+use gb23::emu::{
+    bus::BusDevice,
+    mbc::{
+        camera::{self, Camera},
+        mbc0::Mbc0,
+        mbc1::Mbc1,
+    },
+    NoopView,
+};
+
+mod common;
+
+use common::Rng;
+
+fn read(mbc: &mut impl BusDevice<NoopView>, addr: u16) -> u8 {
+    mbc.read(addr)
+}
+
+fn write(mbc: &mut impl BusDevice<NoopView>, addr: u16, value: u8) {
+    mbc.write(addr, value)
+}
+
+// every byte of a bank is its bank number, so a read says which bank is mapped
+fn banked_rom(banks: usize) -> Vec<u8> {
+    (0..banks * 0x4000).map(|i| (i / 0x4000) as u8).collect()
+}
+
+// the same for RAM, with its 8 KiB banks
+fn banked_sram(banks: usize) -> Vec<u8> {
+    (0..banks * 0x2000).map(|i| (i / 0x2000) as u8).collect()
+}
+
+// pokes random bank registers and addresses, and checks cart space only ever reads
+// back bytes from the image or open bus
+fn fuzz(mbc: &mut impl BusDevice<NoopView>, rom: &[u8], rng: &mut Rng) {
+    mbc.reset(&mut NoopView {});
+    for _ in 0..2000 {
+        let addr = rng.next() as u16;
+        if (rng.next() & 1) != 0 {
+            write(mbc, addr, rng.next() as u8);
+        } else {
+            let value = read(mbc, addr);
+            if addr < 0x4000 {
+                assert_eq!(value, rom.get(addr as usize).copied().unwrap_or(0xFF));
+            }
+        }
+    }
+}
+
+#[test]
+fn mbc0_random_roms() {
+    let mut rng = Rng(0x9E3779B97F4A7C15);
+    for _ in 0..200 {
+        let len = rng.below(0x10000);
+        let rom = rng.bytes(len);
+        let len = rng.below(0x4000);
+        let mut sram = rng.bytes(len);
+        fuzz(&mut Mbc0::new(&rom, &mut sram), &rom, &mut rng);
+    }
+}
+
+#[test]
+fn mbc1_random_roms() {
+    let mut rng = Rng(0x2545F4914F6CDD1D);
+    for _ in 0..200 {
+        // mostly not a whole number of banks, like a truncated dump
+        let len = rng.below(0x4000 * 9);
+        let rom = rng.bytes(len);
+        let len = rng.below(0x2000 * 5);
+        let mut sram = rng.bytes(len);
+        fuzz(&mut Mbc1::new(&rom, &mut sram), &rom, &mut rng);
+    }
+}
+
+#[test]
+fn camera_random_roms() {
+    let mut rng = Rng(0xD1B54A32D192ED03);
+    for _ in 0..50 {
+        let len = rng.below(0x4000 * 65);
+        let rom = rng.bytes(len);
+        let len = rng.below(0x2000 * 17);
+        let mut sram = rng.bytes(len);
+        fuzz(&mut Camera::new(&rom, &mut sram), &rom, &mut rng);
+    }
+}
+
+#[test]
+fn mbc1_truncated_bank_reads_open_bus() {
+    let rom: Vec<u8> = (0..0x5000).map(|i| i as u8).collect();
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x2000, 0x01);
+    assert_eq!(read(&mut mbc, 0x4000), rom[0x4000]);
+    assert_eq!(read(&mut mbc, 0x4FFF), rom[0x4FFF]);
+    assert_eq!(read(&mut mbc, 0x5000), 0xFF);
+    assert_eq!(read(&mut mbc, 0x7FFF), 0xFF);
+}
+
+#[test]
+fn mbc1_without_sram_reads_open_bus() {
+    let rom = vec![0; 0x8000];
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x0000, 0x0A);
+    write(&mut mbc, 0x6000, 0x01);
+    write(&mut mbc, 0x4000, 0x03);
+    write(&mut mbc, 0xA000, 0x12);
+    assert_eq!(read(&mut mbc, 0xA000), 0xFF);
+}
+
+#[test]
+fn mbc1_512k_ignores_bank2_for_rom() {
+    let rom = banked_rom(32);
+    let mut sram = vec![0; 0x8000];
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    assert_eq!(read(&mut mbc, 0x4000), 1);
+    for (value, bank) in [(0x00, 1), (0x01, 1), (0x1F, 31), (0x20, 1), (0x35, 21)] {
+        write(&mut mbc, 0x2000, value);
+        assert_eq!(read(&mut mbc, 0x4000), bank, "${value:02X}");
+    }
+    // the upper bits have no ROM behind them, in either mode they only bank RAM
+    write(&mut mbc, 0x4000, 0x03);
+    assert_eq!(read(&mut mbc, 0x4000), 21);
+    write(&mut mbc, 0x6000, 0x01);
+    assert_eq!(read(&mut mbc, 0x0000), 0);
+    assert_eq!(read(&mut mbc, 0x4000), 21);
+    write(&mut mbc, 0x0000, 0x0A);
+    write(&mut mbc, 0xA000, 0x33);
+    write(&mut mbc, 0x6000, 0x00);
+    assert_eq!(read(&mut mbc, 0xA000), 0x00);
+    write(&mut mbc, 0x6000, 0x01);
+    assert_eq!(read(&mut mbc, 0xA000), 0x33);
+    drop(mbc);
+    assert_eq!(sram[0x6000], 0x33);
+}
+
+#[test]
+fn mbc1_2m_banks_rom0_in_mode_1() {
+    let rom = banked_rom(128);
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x4000, 0x03);
+    write(&mut mbc, 0x2000, 0x00);
+    // 0 is bumped to 1 before the upper bits go on, so $20, $40 and $60 can't be reached
+    assert_eq!(read(&mut mbc, 0x4000), 0x61);
+    assert_eq!(read(&mut mbc, 0x0000), 0x00);
+    write(&mut mbc, 0x6000, 0x01);
+    assert_eq!(read(&mut mbc, 0x0000), 0x60);
+    assert_eq!(read(&mut mbc, 0x3FFF), 0x60);
+    assert_eq!(read(&mut mbc, 0x4000), 0x61);
+    write(&mut mbc, 0x2000, 0x1F);
+    write(&mut mbc, 0x4000, 0x02);
+    assert_eq!(read(&mut mbc, 0x0000), 0x40);
+    assert_eq!(read(&mut mbc, 0x7FFF), 0x5F);
+    write(&mut mbc, 0x6000, 0x00);
+    assert_eq!(read(&mut mbc, 0x0000), 0x00);
+    assert_eq!(read(&mut mbc, 0x4000), 0x5F);
+}
+
+#[test]
+fn mbc1_1m_wraps_upper_bits() {
+    let rom = banked_rom(64);
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x2000, 0x05);
+    write(&mut mbc, 0x4000, 0x03);
+    assert_eq!(read(&mut mbc, 0x4000), 0x25);
+    write(&mut mbc, 0x6000, 0x01);
+    assert_eq!(read(&mut mbc, 0x0000), 0x20);
+}
+
+#[test]
+fn mbc1m_shifts_bank2_by_4() {
+    let rom = banked_rom(64);
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::multicart(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x4000, 0x02);
+    write(&mut mbc, 0x2000, 0x12);
+    assert_eq!(read(&mut mbc, 0x4000), 0x22);
+    // BANK1's top bit isn't wired, but still counts when bumping 0 to 1
+    write(&mut mbc, 0x2000, 0x10);
+    assert_eq!(read(&mut mbc, 0x4000), 0x20);
+    write(&mut mbc, 0x2000, 0x00);
+    assert_eq!(read(&mut mbc, 0x4000), 0x21);
+    write(&mut mbc, 0x6000, 0x01);
+    for (game, bank) in [(0, 0x00), (1, 0x10), (2, 0x20), (3, 0x30)] {
+        write(&mut mbc, 0x4000, game);
+        assert_eq!(read(&mut mbc, 0x0000), bank);
+        assert_eq!(read(&mut mbc, 0x4000), bank + 1);
+    }
+}
+
+#[test]
+fn mbc1_sram_needs_enabling() {
+    let rom = vec![0; 0x8000];
+    let mut sram = vec![0x55; 0x2000];
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    // disabled after reset, so reads float and writes go nowhere
+    assert_eq!(read(&mut mbc, 0xA000), 0xFF);
+    write(&mut mbc, 0xA000, 0x12);
+    write(&mut mbc, 0x0000, 0x0A);
+    assert_eq!(read(&mut mbc, 0xA000), 0x55);
+    write(&mut mbc, 0xA000, 0x12);
+    assert_eq!(read(&mut mbc, 0xA000), 0x12);
+    // only the low nibble counts
+    write(&mut mbc, 0x1FFF, 0x0B);
+    assert_eq!(read(&mut mbc, 0xA000), 0xFF);
+    write(&mut mbc, 0xA000, 0x34);
+    write(&mut mbc, 0x1000, 0xFA);
+    assert_eq!(read(&mut mbc, 0xA000), 0x12);
+}
+
+#[test]
+fn mbc0_sram_is_always_enabled() {
+    let rom = vec![0; 0x8000];
+    let mut sram = vec![0x55; 0x2000];
+    let mut mbc = Mbc0::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    assert_eq!(read(&mut mbc, 0xBFFF), 0x55);
+    write(&mut mbc, 0xA000, 0x12);
+    assert_eq!(read(&mut mbc, 0xA000), 0x12);
+    let mut sram = vec![0x55; 0x800];
+    let mut mbc = Mbc0::new(&rom, &mut sram);
+    assert_eq!(read(&mut mbc, 0xA800), 0xFF);
+}
+
+#[test]
+fn empty_rom_reads_open_bus() {
+    let mut sram = Vec::new();
+    let mut mbc = Mbc1::new(&[], &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x2000, 0x05);
+    for addr in [0x0000, 0x0100, 0x3FFF, 0x4000, 0x7FFF] {
+        assert_eq!(read(&mut mbc, addr), 0xFF);
+    }
+    let mut mbc = Mbc0::new(&[], &mut sram);
+    assert_eq!(read(&mut mbc, 0x0100), 0xFF);
+}
+
+#[test]
+fn mbc0_ignores_register_writes() {
+    let rom = banked_rom(2);
+    let mut sram = Vec::new();
+    let mut mbc = Mbc0::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    for addr in [0x0000, 0x2000, 0x4000, 0x6000, 0x7FFF] {
+        write(&mut mbc, addr, 0x0A);
+    }
+    assert_eq!(read(&mut mbc, 0x0000), 0);
+    assert_eq!(read(&mut mbc, 0x4000), 1);
+    assert_eq!(read(&mut mbc, 0xA000), 0xFF);
+}
+
+#[test]
+fn mbc1_registers_mirror_across_their_ranges() {
+    let rom = banked_rom(128);
+    let mut sram = banked_sram(4);
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    for (addr, bank) in [(0x2000, 0x02), (0x2ABC, 0x03), (0x3FFF, 0x04)] {
+        write(&mut mbc, addr, bank);
+        assert_eq!(read(&mut mbc, 0x4000), bank);
+    }
+    write(&mut mbc, 0x5FFF, 0x01);
+    assert_eq!(read(&mut mbc, 0x4000), 0x24);
+    write(&mut mbc, 0x7FFF, 0x01);
+    assert_eq!(read(&mut mbc, 0x0000), 0x20);
+    write(&mut mbc, 0x1FFF, 0x0A);
+    assert_eq!(read(&mut mbc, 0xBFFF), 1);
+    // RAM has 8 KiB to itself, nothing repeats within $A000-$BFFF
+    assert_eq!(read(&mut mbc, 0xA000), 1);
+}
+
+#[test]
+fn mbc1_ignores_unwired_bits() {
+    let rom = banked_rom(128);
+    let mut sram = banked_sram(4);
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x2000, 0xE3);
+    assert_eq!(read(&mut mbc, 0x4000), 0x03);
+    write(&mut mbc, 0x4000, 0xFE);
+    assert_eq!(read(&mut mbc, 0x4000), 0x43);
+    write(&mut mbc, 0x6000, 0xFE);
+    assert_eq!(read(&mut mbc, 0x0000), 0x00);
+    write(&mut mbc, 0x6000, 0xFF);
+    assert_eq!(read(&mut mbc, 0x0000), 0x40);
+}
+
+#[test]
+fn mbc1_banks_sram_only_in_mode_1() {
+    let rom = banked_rom(4);
+    let mut sram = banked_sram(4);
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x0000, 0x0A);
+    for bank in 0..4 {
+        write(&mut mbc, 0x4000, bank);
+        write(&mut mbc, 0x6000, 0x00);
+        assert_eq!(read(&mut mbc, 0xA000), 0);
+        write(&mut mbc, 0x6000, 0x01);
+        assert_eq!(read(&mut mbc, 0xA000), bank);
+        assert_eq!(read(&mut mbc, 0xBFFF), bank);
+    }
+}
+
+#[test]
+fn mbc1_small_sram_ignores_bank_bits() {
+    let rom = banked_rom(4);
+    let mut sram = vec![0x55; 0x2000];
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x0000, 0x0A);
+    write(&mut mbc, 0x6000, 0x01);
+    write(&mut mbc, 0x4000, 0x03);
+    assert_eq!(read(&mut mbc, 0xA000), 0x55);
+    write(&mut mbc, 0xA123, 0x12);
+    write(&mut mbc, 0x4000, 0x00);
+    assert_eq!(read(&mut mbc, 0xA123), 0x12);
+}
+
+#[test]
+fn mbc1_reset_restores_power_on_banks() {
+    let rom = banked_rom(128);
+    let mut sram = banked_sram(4);
+    let mut mbc = Mbc1::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x0000, 0x0A);
+    write(&mut mbc, 0x2000, 0x07);
+    write(&mut mbc, 0x4000, 0x03);
+    write(&mut mbc, 0x6000, 0x01);
+    mbc.reset(&mut NoopView {});
+    assert_eq!(read(&mut mbc, 0x0000), 0);
+    assert_eq!(read(&mut mbc, 0x4000), 1);
+    assert_eq!(read(&mut mbc, 0xA000), 0xFF);
+}
+
+#[test]
+fn camera_maps_every_rom_bank() {
+    let rom = banked_rom(64);
+    let mut sram = banked_sram(16);
+    let mut mbc = Camera::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    assert_eq!(read(&mut mbc, 0x4000), 1);
+    // unlike MBC1, 0 isn't bumped and 6 bits are wired
+    for (value, bank) in [(0x00, 0), (0x21, 0x21), (0x3F, 0x3F), (0xC5, 0x05)] {
+        write(&mut mbc, 0x3FFF, value);
+        assert_eq!(read(&mut mbc, 0x4000), bank, "${value:02X}");
+        assert_eq!(read(&mut mbc, 0x0000), 0);
+    }
+}
+
+#[test]
+fn camera_banks_16_ram_banks() {
+    let rom = banked_rom(64);
+    let mut sram = banked_sram(16);
+    let mut mbc = Camera::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    // reads work with RAM disabled, only writes are kept out
+    for bank in 0..16 {
+        write(&mut mbc, 0x4000, bank);
+        assert_eq!(read(&mut mbc, 0xA000), bank);
+        assert_eq!(read(&mut mbc, 0xBFFF), bank);
+    }
+    write(&mut mbc, 0xA000, 0x99);
+    assert_eq!(read(&mut mbc, 0xA000), 15);
+    write(&mut mbc, 0x0000, 0x0A);
+    write(&mut mbc, 0xA000, 0x99);
+    assert_eq!(read(&mut mbc, 0xA000), 0x99);
+    write(&mut mbc, 0x0000, 0x00);
+    write(&mut mbc, 0xA000, 0x11);
+    assert_eq!(read(&mut mbc, 0xA000), 0x99);
+    drop(mbc);
+    assert_eq!(sram[15 * 0x2000], 0x99);
+}
+
+#[test]
+fn camera_registers_mirror_and_read_as_0() {
+    let rom = banked_rom(64);
+    let mut sram = banked_sram(16);
+    let mut mbc = Camera::new(&rom, &mut sram);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x4000, 0x13);
+    // only $A000 reads back, and only its low 3 bits
+    write(&mut mbc, 0xA000, 0xF6);
+    assert_eq!(read(&mut mbc, 0xA000), 0x06);
+    assert_eq!(read(&mut mbc, 0xA080), 0x06);
+    assert_eq!(read(&mut mbc, 0xBF80), 0x06);
+    write(&mut mbc, 0xA001, 0xFF);
+    assert_eq!(read(&mut mbc, 0xA001), 0x00);
+    assert_eq!(read(&mut mbc, 0xA07F), 0x00);
+    // the registers don't write through to the RAM bank under them
+    write(&mut mbc, 0x4000, 0x03);
+    assert_eq!(read(&mut mbc, 0xA000), 3);
+    assert_eq!(read(&mut mbc, 0xA001), 3);
+}
+
+#[test]
+fn camera_capture_lands_in_bank_0() {
+    let rom = banked_rom(64);
+    let mut sram = vec![0; 0x20000];
+    let mut mbc = Camera::new(&rom, &mut sram);
+    // black on the left half, white on the right
+    let mut image = [0; camera::WIDTH * camera::HEIGHT];
+    for row in image.chunks_mut(camera::WIDTH) {
+        row[(camera::WIDTH / 2)..].fill(0xFF);
+    }
+    mbc.set_image(&image);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x4000, 0x10);
+    // N set, and an exposure of $1000 to pass the image through untouched
+    write(&mut mbc, 0xA001, 0x80);
+    write(&mut mbc, 0xA002, 0x10);
+    for i in 0..16 {
+        write(&mut mbc, 0xA006 + i * 3, 0x40);
+        write(&mut mbc, 0xA007 + i * 3, 0x80);
+        write(&mut mbc, 0xA008 + i * 3, 0xC0);
+    }
+    write(&mut mbc, 0xA000, 0x01);
+    let cycles = (32446 + 16 * 0x1000) * 4;
+    BusDevice::<NoopView>::advance(&mut mbc, cycles - 1);
+    assert_eq!(read(&mut mbc, 0xA000), 0x01);
+    BusDevice::<NoopView>::advance(&mut mbc, 1);
+    assert_eq!(read(&mut mbc, 0xA000), 0x00);
+    write(&mut mbc, 0x4000, 0x00);
+    // the first tile row, 16 tiles of 2 bytes per line
+    for tile in 0..16 {
+        let expected = if tile < 8 { 0xFF } else { 0x00 };
+        for byte in 0..16 {
+            let addr = 0xA100 + tile * 16 + byte;
+            assert_eq!(read(&mut mbc, addr), expected, "${addr:04X}");
+        }
+    }
+    assert_eq!(read(&mut mbc, 0xA0FF), 0x00);
+    assert_eq!(read(&mut mbc, 0xAF00), 0x00);
+}
+
+#[test]
+fn camera_capture_can_be_cancelled() {
+    let rom = banked_rom(64);
+    let mut sram = vec![0; 0x20000];
+    let mut mbc = Camera::new(&rom, &mut sram);
+    mbc.set_image(&[0; camera::WIDTH * camera::HEIGHT]);
+    mbc.reset(&mut NoopView {});
+    write(&mut mbc, 0x4000, 0x10);
+    for i in 0..48 {
+        write(&mut mbc, 0xA006 + i, 0x80);
+    }
+    write(&mut mbc, 0xA000, 0x01);
+    BusDevice::<NoopView>::advance(&mut mbc, 1000);
+    write(&mut mbc, 0xA000, 0x00);
+    BusDevice::<NoopView>::advance(&mut mbc, 1_000_000);
+    write(&mut mbc, 0x4000, 0x00);
+    assert_eq!(read(&mut mbc, 0xA100), 0x00);
+}
+
+#[test]
+fn camera_capture_fits_whatever_ram_there_is() {
+    let rom = banked_rom(64);
+    for len in [0, 0x80, 0x100, 0x180] {
+        let mut sram = vec![0; len];
+        let mut mbc = Camera::new(&rom, &mut sram);
+        mbc.set_image(&[0; camera::WIDTH * camera::HEIGHT]);
+        mbc.reset(&mut NoopView {});
+        write(&mut mbc, 0x4000, 0x10);
+        for i in 0..48 {
+            write(&mut mbc, 0xA006 + i, 0x80);
+        }
+        write(&mut mbc, 0xA000, 0x01);
+        BusDevice::<NoopView>::advance(&mut mbc, 10_000_000);
+        drop(mbc);
+        // black is shade 3, so whatever reached the picture is all ones
+        assert!(sram.iter().skip(0x100).all(|&b| b == 0xFF), "{len:X} bytes");
+    }
+}
@@ -0,0 +1,409 @@
+use gb23::{
+    config::{Model, Settings},
+    emu::{
+        bus::{Bus, BusDevice, Port},
+        cdl::Cdl,
+        cpu::WideRegister,
+        error::CoreError,
+        heatmap::Access,
+        iolog::IoLog,
+        mbc::mbc1::Mbc1,
+        ppu::Ppu,
+        serial::SerialOutput,
+        Emu,
+    },
+};
+
+struct NoInput;
+
+impl<B: Bus> BusDevice<B> for NoInput {
+    fn reset(&mut self, _bus: &mut B) {}
+
+    fn read(&mut self, _addr: u16) -> u8 {
+        0xFF
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+
+    fn tick(&mut self, _bus: &mut B) -> usize {
+        0
+    }
+}
+
+// a powered on machine with the boot ROM already out of the way
+fn emu<'a>(model: Model, rom: &'a [u8], sram: &'a mut [u8]) -> Emu<Mbc1<'a>, Ppu, NoInput> {
+    let settings = Settings {
+        model,
+        ..Settings::default()
+    };
+    let mut emu = Emu::new(&settings, Vec::new(), Mbc1::new(rom, sram), NoInput);
+    emu.reset();
+    emu.cpu_view().1.write(Port::BOOT, 0x01);
+    emu
+}
+
+fn read(emu: &mut Emu<Mbc1<'_>, Ppu, NoInput>, addr: u16) -> u8 {
+    emu.cpu_view().1.read(addr)
+}
+
+fn write(emu: &mut Emu<Mbc1<'_>, Ppu, NoInput>, addr: u16, value: u8) {
+    emu.cpu_view().1.write(addr, value)
+}
+
+#[test]
+fn hram_is_127_bytes_under_ie() {
+    let rom = vec![0; 0x8000];
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    for addr in 0xFF80..=0xFFFE {
+        write(&mut emu, addr, (addr as u8) ^ 0x5A);
+    }
+    write(&mut emu, Port::IE, 0x00);
+    for addr in 0xFF80..=0xFFFE {
+        assert_eq!(read(&mut emu, addr), (addr as u8) ^ 0x5A, "${addr:04X}");
+    }
+    let mut hram = [0; 127];
+    emu.read_range(0xFF80, &mut hram);
+    assert!(hram
+        .iter()
+        .zip(0xFF80..=0xFFFE)
+        .all(|(&value, addr)| value == ((addr as u8) ^ 0x5A)));
+    assert_eq!(read(&mut emu, Port::IE), 0x00);
+}
+
+#[test]
+fn ie_is_not_hram() {
+    let rom = vec![0; 0x8000];
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    write(&mut emu, 0xFFFE, 0x00);
+    for value in 0..=0xFF {
+        write(&mut emu, Port::IE, value);
+        assert_eq!(read(&mut emu, Port::IE), value & 0x1F);
+        assert_eq!(read(&mut emu, 0xFFFE), 0x00);
+    }
+}
+
+#[test]
+fn read_range_wraps_past_ie() {
+    let mut rom = vec![0; 0x8000];
+    rom[0] = 0xC3;
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    write(&mut emu, 0xFFFD, 0x12);
+    write(&mut emu, 0xFFFE, 0x34);
+    write(&mut emu, Port::IE, 0x05);
+    let mut buf = [0; 4];
+    emu.read_range(0xFFFD, &mut buf);
+    assert_eq!(buf, [0x12, 0x34, 0x05, 0xC3]);
+}
+
+#[test]
+fn io_writes_stay_in_io() {
+    let rom = vec![0; 0x8000];
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    for addr in 0xFF80..=0xFFFE {
+        write(&mut emu, addr, addr as u8);
+    }
+    write(&mut emu, Port::IE, 0x1F);
+    for addr in 0xFF00..=0xFF7F {
+        // one maps the (missing) boot ROM back in, the other starts a copy
+        if (addr != Port::BOOT) && (addr != Port::DMA) {
+            write(&mut emu, addr, 0x00);
+        }
+    }
+    for addr in 0xFF80..=0xFFFE {
+        assert_eq!(read(&mut emu, addr), addr as u8, "${addr:04X}");
+    }
+    assert_eq!(read(&mut emu, Port::IE), 0x1F);
+    // nothing is wired up where there is no port, apart from wave RAM
+    for addr in 0xFF00..=0xFF7F {
+        if Port::info(addr).is_none() && !(Port::WAVE..Port::WAVE + 16).contains(&addr) {
+            assert_eq!(read(&mut emu, addr), 0xFF, "${addr:04X}");
+        }
+    }
+}
+
+#[test]
+fn every_address_decodes() {
+    let rom = vec![0; 0x8000];
+    for model in [Model::Dmg, Model::Sgb, Model::Cgb] {
+        let mut sram = vec![0; 0x2000];
+        let mut emu = emu(model, &rom, &mut sram);
+        for addr in 0x0000..=0xFFFF {
+            read(&mut emu, addr);
+        }
+        // past the cartridge, which takes writes below $8000 as bank switches
+        for addr in 0x8000..=0xFFFF {
+            if (addr != Port::BOOT) && (addr != Port::DMA) {
+                let value = read(&mut emu, addr);
+                write(&mut emu, addr, value);
+            }
+        }
+    }
+}
+
+// a cart that asks for CGB mode
+fn cgb_rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0143] = 0x80;
+    rom
+}
+
+#[test]
+fn svbk_picks_the_d000_bank() {
+    let rom = cgb_rom();
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Cgb, &rom, &mut sram);
+    write(&mut emu, 0xC000, 0xAA);
+    for bank in 0..8 {
+        write(&mut emu, Port::SVBK, bank);
+        write(&mut emu, 0xD000, 0x10 + bank);
+        write(&mut emu, 0xDFFF, 0x20 + bank);
+    }
+    for bank in 0..8 {
+        write(&mut emu, Port::SVBK, bank);
+        assert_eq!(read(&mut emu, Port::SVBK), 0xF8 | bank);
+        // bank 0 can't be picked, it's bank 1 instead
+        let picked = bank.max(1);
+        assert_eq!(read(&mut emu, 0xD000), 0x10 + picked, "SVBK {bank}");
+        assert_eq!(read(&mut emu, 0xDFFF), 0x20 + picked, "SVBK {bank}");
+        assert_eq!(read(&mut emu, 0xC000), 0xAA, "SVBK {bank}");
+    }
+    // only the bank bits are there
+    write(&mut emu, Port::SVBK, 0xFB);
+    assert_eq!(read(&mut emu, Port::SVBK), 0xFB);
+    assert_eq!(read(&mut emu, 0xD000), 0x13);
+}
+
+#[test]
+fn echo_follows_svbk() {
+    let rom = cgb_rom();
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Cgb, &rom, &mut sram);
+    for bank in 0..8 {
+        write(&mut emu, Port::SVBK, bank);
+        // writes through the echo land in WRAM, and the other way around
+        write(&mut emu, 0xE123, 0x30 + bank);
+        assert_eq!(read(&mut emu, 0xC123), 0x30 + bank);
+        write(&mut emu, 0xD456, 0x40 + bank);
+        assert_eq!(read(&mut emu, 0xF456), 0x40 + bank);
+        write(&mut emu, 0xFDFF, 0x50 + bank);
+        assert_eq!(read(&mut emu, 0xDDFF), 0x50 + bank);
+    }
+    for bank in 0..8 {
+        write(&mut emu, Port::SVBK, bank);
+        let picked = bank.max(1);
+        // $E000-$EFFF is always bank 0, like $C000
+        assert_eq!(read(&mut emu, 0xE123), 0x37, "SVBK {bank}");
+        assert_eq!(read(&mut emu, 0xF456), 0x40 + picked, "SVBK {bank}");
+        let mut echo = [0; 0x1E00];
+        let mut wram = [0; 0x1E00];
+        emu.read_range(0xE000, &mut echo);
+        emu.read_range(0xC000, &mut wram);
+        assert_eq!(echo, wram, "SVBK {bank}");
+    }
+    // OAM starts where the echo stops
+    let mut buf = [0; 2];
+    emu.read_range(0xFDFF, &mut buf);
+    assert_eq!(buf[0], 0x57);
+    assert_eq!(buf[1], read(&mut emu, 0xFE00));
+}
+
+#[test]
+fn svbk_is_cgb_mode_only() {
+    for (model, rom) in [(Model::Dmg, cgb_rom()), (Model::Cgb, vec![0; 0x8000])] {
+        let mut sram = vec![0; 0x2000];
+        let mut emu = emu(model, &rom, &mut sram);
+        write(&mut emu, 0xD000, 0x11);
+        write(&mut emu, Port::SVBK, 0x03);
+        assert_eq!(read(&mut emu, Port::SVBK), 0xFF, "{model}");
+        assert_eq!(read(&mut emu, 0xD000), 0x11, "{model}");
+        assert_eq!(read(&mut emu, 0xF000), 0x11, "{model}");
+    }
+}
+
+#[test]
+fn unused_io_bits_read_as_one() {
+    let rom = cgb_rom();
+    for model in [Model::Dmg, Model::Cgb] {
+        let mut sram = vec![0; 0x2000];
+        let mut emu = emu(model, &rom, &mut sram);
+        for addr in 0xFF00..=0xFF7F {
+            let (read_mask, _) = Port::io_masks(addr);
+            if (addr != Port::BOOT) && (addr != Port::DMA) {
+                write(&mut emu, addr, 0x00);
+            }
+            let value = read(&mut emu, addr);
+            assert_eq!(value & !read_mask, !read_mask, "{model} ${addr:04X}");
+        }
+    }
+}
+
+#[test]
+fn heatmap_counts_cpu_accesses() {
+    let mut rom = vec![0; 0x8000];
+    // loop: ld a,[$C000]; inc a; ld [$C000],a; jr loop
+    rom[0x0100..0x0109].copy_from_slice(&[0xFA, 0x00, 0xC0, 0x3C, 0xEA, 0x00, 0xC0, 0x18, 0xF7]);
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    emu.cpu_view().0.set_wide_register(WideRegister::PC, 0x0100);
+    emu.set_heatmap(true);
+    for _ in 0..40 {
+        emu.step();
+    }
+    let heatmap = emu.heatmap().unwrap();
+    assert_eq!(heatmap.counts(0xC000), [10, 10, 0]);
+    // every fetch is a read too
+    assert_eq!(heatmap.counts(0x0100), [10, 0, 10]);
+    assert_eq!(heatmap.counts(0x0101), [10, 0, 0]);
+    assert_eq!(heatmap.count(0x0103, Access::Execute), 10);
+    // the CPU polling for interrupts isn't the program reading them
+    assert_eq!(heatmap.counts(Port::IF), [0, 0, 0]);
+    assert_eq!(
+        heatmap.hottest(2),
+        [(0x0100, [10, 0, 10]), (0x0103, [10, 0, 10])]
+    );
+    emu.set_heatmap(false);
+    assert!(emu.heatmap().is_none());
+}
+
+#[test]
+fn cdl_logs_what_rom_bytes_are_for() {
+    let mut rom = vec![0; 0x8000];
+    // loop: ld a,[$0200]; jr loop
+    rom[0x0100..0x0105].copy_from_slice(&[0xFA, 0x00, 0x02, 0x18, 0xFB]);
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    emu.cpu_view().0.set_wide_register(WideRegister::PC, 0x0100);
+    emu.set_cdl(Some(Cdl::new()));
+    write(&mut emu, Port::DMA, 0x03);
+    // the debugger looking around isn't the program using anything
+    read(&mut emu, 0x0400);
+    for _ in 0..100 {
+        emu.step();
+    }
+    let log = emu.cdl().unwrap().to_bytes(rom.len());
+    assert_eq!(log.len(), rom.len());
+    assert_eq!(log[0x0100..0x0105], [Cdl::CODE; 5]);
+    assert_eq!(log[0x0105], 0);
+    assert_eq!(log[0x0200], Cdl::DATA);
+    assert_eq!(log[0x0201], 0);
+    assert!(log[0x0300..0x03A0].iter().all(|&flags| flags == Cdl::DMA));
+    assert_eq!(log[0x03A0], 0);
+    assert_eq!(log[0x0400], 0);
+}
+
+#[test]
+fn unimplemented_ports_are_reported_not_fatal() {
+    let mut rom = cgb_rom();
+    rom[0x0100..0x010A].copy_from_slice(&[
+        0x3E, 0x01, //       ld a, $01
+        0xE0, 0x56, //       ldh [RP], a
+        0xE0, 0x56, //       ldh [RP], a
+        0xF0, 0x68, //       ldh a, [BCPS]
+        0x18, 0xFE, //       jr @
+    ]);
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Cgb, &rom, &mut sram);
+    // the debugger poking around isn't the game's doing
+    read(&mut emu, Port::RP);
+    emu.cpu_view().0.set_wide_register(WideRegister::PC, 0x0100);
+    for _ in 0..5 {
+        emu.step();
+    }
+    let errors = emu
+        .errors()
+        .iter()
+        .map(|(error, count)| (*error, count))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        [
+            (CoreError::UnimplementedWrite(Port::RP, 0x01), 2),
+            (CoreError::UnimplementedRead(Port::BCPS), 1),
+        ]
+    );
+    assert_eq!(
+        errors[0].0.to_string(),
+        "game wrote 01 to unimplemented port FF56 (RP)"
+    );
+}
+
+#[test]
+fn io_log_picks_ports_by_address_or_name() {
+    let log: IoLog = "FF40,$FF46-FF4B, nr52 ,FF7E-FFFF".parse().unwrap();
+    assert!(log.contains(Port::LCDC));
+    assert!(!log.contains(Port::STAT));
+    assert!((Port::DMA..=Port::WX).all(|addr| log.contains(addr)));
+    assert!(log.contains(Port::NR52));
+    assert!(log.contains(0xFF7F) && log.contains(Port::IE));
+    assert!(!log.contains(0xFF80));
+    assert!(!log.contains(Port::P1));
+    assert!("C000".parse::<IoLog>().is_err());
+    assert!("WX-LCDC".parse::<IoLog>().is_err());
+    assert!("LCD".parse::<IoLog>().is_err());
+}
+
+#[test]
+fn serial_output_is_captured_a_line_at_a_time() {
+    let mut rom = cgb_rom();
+    rom[0x0100..0x010E].copy_from_slice(&[
+        0x3E, b'O', //       ld a, "O"
+        0xE0, 0x01, //       ldh [SB], a
+        0x3E, 0x81, //       ld a, $81
+        0xE0, 0x02, //       ldh [SC], a
+        0x3E, b'K', //       ld a, "K"
+        0xE0, 0x01, //       ldh [SB], a
+        0x18, 0xFE, //       jr @
+    ]);
+    let mut sram = vec![0; 0x2000];
+    let mut emu = emu(Model::Dmg, &rom, &mut sram);
+    emu.cpu_view().0.set_wide_register(WideRegister::PC, 0x0100);
+    for _ in 0..8 {
+        emu.step();
+    }
+    // only bytes a transfer was started with count
+    assert_eq!(emu.serial_output().text(), "O");
+    assert!(emu.serial_output_mut().take_lines().is_empty());
+
+    let mut output = SerialOutput::default();
+    for &byte in b"Failed\r\nFailed\nFailed\nPassed\n" {
+        output.push(byte);
+    }
+    assert_eq!(
+        output.take_lines(),
+        ["Failed", "(last line repeated 2 more times)", "Passed"]
+    );
+    assert!(output.take_lines().is_empty());
+    for _ in 0..(SerialOutput::MAX_PENDING + 5) {
+        output.push(b'x');
+        output.push(b'\n');
+        output.push(b'\n');
+    }
+    let lines = output.take_lines();
+    assert_eq!(lines.len(), SerialOutput::MAX_PENDING + 1);
+    assert_eq!(lines.last().unwrap(), "(110 more lines dropped)");
+}
+
+#[test]
+fn serial_output_ending_on_a_repeated_line_says_so() {
+    let mut output = SerialOutput::default();
+    for &byte in b"Done\nDone\nDone\n" {
+        output.push(byte);
+    }
+    assert_eq!(
+        output.take_lines(),
+        ["Done", "(last line repeated 2 more times)"]
+    );
+    assert!(output.take_lines().is_empty());
+    // it's still counted against the same line afterwards
+    for &byte in b"Done\nNext\n" {
+        output.push(byte);
+    }
+    assert_eq!(
+        output.take_lines(),
+        ["(last line repeated once more)", "Next"]
+    );
+}
@@ -0,0 +1,133 @@
+use gb23::patch::{self, Format};
+
+mod common;
+
+use common::Rng;
+
+// a base ROM and a hack of it: a few scattered edits, a filled in block and a new length
+fn hack(rng: &mut Rng, len: usize, new_len: usize) -> (Vec<u8>, Vec<u8>) {
+    let base = rng.bytes(len);
+    let mut target = base.clone();
+    target.resize(new_len, 0);
+    for _ in 0..20 {
+        let at = rng.below(new_len);
+        target[at] = rng.next() as u8;
+    }
+    let at = rng.below(new_len - 100);
+    target[at..(at + 100)].fill(0xFF);
+    (base, target)
+}
+
+#[test]
+fn patches_round_trip() {
+    let mut rng = Rng(0x1234_5678_9ABC_DEF0);
+    for format in [Format::Ips, Format::Bps] {
+        for (len, new_len) in [(0x8000, 0x8000), (0x8000, 0x10000), (0x10000, 0x8000)] {
+            let (base, target) = hack(&mut rng, len, new_len);
+            let patch = format.diff(&base, &target).unwrap();
+            assert!(patch.len() < 0x1000, "{format:?} {len:X}->{new_len:X}");
+            assert_eq!(
+                patch::apply(&patch, &base).unwrap(),
+                target,
+                "{format:?} {len:X}->{new_len:X}"
+            );
+        }
+    }
+}
+
+#[test]
+fn unchanged_roms_make_empty_patches() {
+    let rom = Rng(1).bytes(0x8000);
+    assert_eq!(patch::ips(&rom, &rom).unwrap(), b"PATCHEOF");
+    assert_eq!(patch::apply(&patch::bps(&rom, &rom), &rom).unwrap(), rom);
+}
+
+#[test]
+fn ips_steps_around_its_eof_marker() {
+    let base = vec![0; 0x460000];
+    let mut target = base.clone();
+    target[0x454F46] = 1;
+    target[0x454F50..0x454F60].fill(2);
+    let patch = patch::ips(&base, &target).unwrap();
+    // the first record starts a byte early instead
+    assert_eq!(&patch[5..8], &[0x45, 0x4F, 0x45]);
+    assert_eq!(patch::apply(&patch, &base).unwrap(), target);
+}
+
+#[test]
+fn ips_writes_long_runs_as_rle() {
+    let base = vec![0; 0x1000];
+    let mut target = base.clone();
+    target[0x100..0x200].fill(0xAA);
+    let patch = patch::ips(&base, &target).unwrap();
+    assert_eq!(
+        patch,
+        [
+            b"PATCH".as_slice(),
+            &[0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0xAA],
+            b"EOF"
+        ]
+        .concat()
+    );
+    assert_eq!(patch::apply(&patch, &base).unwrap(), target);
+}
+
+#[test]
+fn bps_checks_what_it_applies_to() {
+    let mut rng = Rng(42);
+    let (base, target) = hack(&mut rng, 0x8000, 0x8000);
+    let patch = patch::bps(&base, &target);
+    let mut other = base.clone();
+    other[0] ^= 1;
+    assert!(patch::apply(&patch, &other).is_err());
+    let mut corrupt = patch.clone();
+    corrupt[10] ^= 1;
+    assert!(patch::apply(&corrupt, &base).is_err());
+    assert!(patch::apply(b"not a patch", &base).is_err());
+}
+
+// a BPS number, 7 bits a byte with the last byte marked
+fn bps_number(mut n: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let low = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(0x80 | low);
+            return bytes;
+        }
+        bytes.push(low);
+        n -= 1;
+    }
+}
+
+// a patch with the right checksums for `base` around whatever actions, so only the
+// actions themselves can be wrong with it
+fn crafted_bps(base: &[u8], target_len: u64, actions: &[u64]) -> Vec<u8> {
+    let mut patch = b"BPS1".to_vec();
+    for n in [base.len() as u64, target_len, 0]
+        .into_iter()
+        .chain(actions.iter().copied())
+    {
+        patch.extend(bps_number(n));
+    }
+    patch.extend(patch::crc32(base).to_le_bytes());
+    patch.extend(0u32.to_le_bytes());
+    patch.extend(patch::crc32(&patch).to_le_bytes());
+    patch
+}
+
+#[test]
+fn bps_rejects_crafted_lengths() {
+    let base = Rng(7).bytes(0x100);
+    // a target bigger than any cart
+    assert!(patch::apply(&crafted_bps(&base, u64::MAX >> 8, &[]), &base).is_err());
+    // a target copy that would run on far past the target
+    let copy = ((1 << 40) << 2) | 3;
+    assert!(patch::apply(&crafted_bps(&base, 0x100, &[0, copy, 0]), &base).is_err());
+    // a source copy from so far along its offset wraps around
+    let far = (u64::MAX >> 2) << 1;
+    assert!(patch::apply(&crafted_bps(&base, 0x100, &[(3 << 2) | 2, far]), &base).is_err());
+    // and one that reads past the end of the source
+    assert!(patch::apply(&crafted_bps(&base, 0x200, &[(0x1FF << 2) | 2, 0]), &base).is_err());
+}
@@ -0,0 +1,210 @@
+use gb23::emu::ppu::{LineState, Ppu};
+
+// shades come out as themselves, so lines can be compared against plain numbers
+const SHADES: [u32; 4] = [0, 1, 2, 3];
+
+// a line as shade digits, so expected rows read left to right like the screen
+fn shades(line: &[u32]) -> String {
+    line.iter()
+        .map(|shade| char::from(b'0' + *shade as u8))
+        .collect()
+}
+
+// fills a tile with the same row 8 times, the row given as shade digits
+fn tile(state: &mut LineState, tile: usize, row: &str) {
+    let (mut lo, mut hi) = (0, 0);
+    for (i, shade) in row.bytes().map(|c| c - b'0').enumerate() {
+        lo |= (shade & 0x01) << (7 - i);
+        hi |= (shade >> 1) << (7 - i);
+    }
+    for y in 0..8 {
+        state.vram[(tile * 16) + (y * 2)] = lo;
+        state.vram[(tile * 16) + (y * 2) + 1] = hi;
+    }
+}
+
+fn render(state: &LineState, ly: u8) -> [u32; 160] {
+    let mut line = [0xFF; 160];
+    Ppu::render_line(state, SHADES, ly, &mut line);
+    line
+}
+
+#[test]
+fn render_line_draws_from_the_given_state() {
+    let mut state = LineState {
+        bgp: 0xE4,
+        ..LineState::default()
+    };
+    tile(&mut state, 1, "01230123");
+    // the top left of the $9800 map
+    state.vram[0x1800] = 1;
+    let line = render(&state, 0);
+    assert_eq!(line[..8], [0, 1, 2, 3, 0, 1, 2, 3]);
+    assert!(line[8..].iter().all(|&shade| shade == 0));
+
+    // BGP maps every shade, here backwards
+    state.bgp = 0x1B;
+    let line = render(&state, 7);
+    assert_eq!(line[..8], [3, 2, 1, 0, 3, 2, 1, 0]);
+    assert_eq!(line[8], 3);
+
+    // the next row of tiles is a different part of the map
+    assert!(render(&state, 8).iter().all(|&shade| shade == 3));
+}
+
+#[test]
+fn scx_fine_scroll() {
+    let mut state = LineState {
+        bgp: 0xE4,
+        ..LineState::default()
+    };
+    tile(&mut state, 1, "01230123");
+    state.vram[0x1800] = 1;
+    for scx in 1..8 {
+        state.scx = scx;
+        let line = render(&state, 0);
+        let expected = format!(
+            "{}{}",
+            &"01230123"[scx as usize..],
+            "0".repeat(scx as usize)
+        );
+        assert_eq!(shades(&line[..8]), expected, "SCX={scx}");
+        assert_eq!(shades(&line[8..]), "0".repeat(152), "SCX={scx}");
+    }
+}
+
+#[test]
+fn scroll_wraps_around_the_map() {
+    let mut state = LineState {
+        bgp: 0xE4,
+        scx: 252,
+        scy: 250,
+        ..LineState::default()
+    };
+    tile(&mut state, 1, "01230123");
+    state.vram[0x1800] = 1;
+    // 6 lines down from 250 is the map's first row again, 4 dots across from 252 its
+    // first column
+    let line = render(&state, 6);
+    assert_eq!(shades(&line[..16]), "0000012301230000");
+    // still the map's last row of tiles
+    assert_eq!(shades(&render(&state, 5)[..16]), "0".repeat(16));
+}
+
+// a window of the same tile all over, from the $9C00 map, over a blank BG
+fn window_state(wx: u8) -> LineState {
+    let mut state = LineState {
+        lcdc: 0xF1,
+        bgp: 0xE4,
+        wx,
+        ..LineState::default()
+    };
+    tile(&mut state, 2, "32100000");
+    state.vram[0x1C00..].fill(2);
+    state
+}
+
+#[test]
+fn wx_7_is_the_left_edge() {
+    let line = render(&window_state(7), 0);
+    assert_eq!(shades(&line), "32100000".repeat(20));
+}
+
+#[test]
+fn wx_below_7_cuts_off_the_window_left_side() {
+    let line = render(&window_state(0), 0);
+    assert_eq!(shades(&line[..9]), "032100000");
+    let line = render(&window_state(3), 0);
+    assert_eq!(shades(&line[..9]), "000032100");
+}
+
+#[test]
+fn wx_past_7_starts_the_window_part_way() {
+    let line = render(&window_state(87), 0);
+    assert_eq!(shades(&line[..80]), "0".repeat(80));
+    assert_eq!(shades(&line[80..]), "32100000".repeat(10));
+}
+
+#[test]
+fn wx_166_leaves_one_dot_of_window() {
+    let line = render(&window_state(166), 0);
+    assert_eq!(shades(&line[..159]), "0".repeat(159));
+    assert_eq!(shades(&line[159..]), "3");
+    let line = render(&window_state(167), 0);
+    assert_eq!(shades(&line), "0".repeat(160));
+}
+
+#[test]
+fn window_ignores_scroll_and_waits_for_wy() {
+    let mut state = window_state(7);
+    state.scx = 3;
+    state.scy = 100;
+    state.wy = 10;
+    assert_eq!(shades(&render(&state, 9)), "0".repeat(160));
+    assert_eq!(shades(&render(&state, 10)), "32100000".repeat(20));
+}
+
+// 8x16 objects over a blank BG, tile 4 on top and the solid tile 5 under it
+fn tall_objects(objs: &[[u8; 4]]) -> LineState {
+    let mut state = LineState {
+        lcdc: 0x97,
+        bgp: 0xE4,
+        obp0: 0xE4,
+        ..LineState::default()
+    };
+    tile(&mut state, 4, "12312312");
+    tile(&mut state, 5, "33333333");
+    for (i, obj) in objs.iter().enumerate() {
+        state.oam[(i * 4)..((i * 4) + 4)].copy_from_slice(obj);
+    }
+    state
+}
+
+#[test]
+fn tall_objects_ignore_the_low_bit_of_the_tile() {
+    for tile in [4, 5] {
+        let state = tall_objects(&[[16, 8, tile, 0x00]]);
+        assert_eq!(shades(&render(&state, 0)[..9]), "123123120", "tile {tile}");
+        assert_eq!(shades(&render(&state, 15)[..9]), "333333330", "tile {tile}");
+    }
+}
+
+#[test]
+fn tall_objects_flip_as_a_whole() {
+    let state = tall_objects(&[[16, 8, 4, 0x40]]);
+    assert_eq!(shades(&render(&state, 0)[..8]), "33333333");
+    assert_eq!(shades(&render(&state, 8)[..8]), "12312312");
+    let state = tall_objects(&[[16, 8, 4, 0x60]]);
+    assert_eq!(shades(&render(&state, 15)[..8]), "21321321");
+}
+
+#[test]
+fn tall_objects_clip_at_the_left_and_right_edges() {
+    let state = tall_objects(&[[16, 1, 4, 0x00], [16, 167, 4, 0x00]]);
+    let line = render(&state, 0);
+    assert_eq!(shades(&line[..2]), "20");
+    assert_eq!(shades(&line[158..]), "01");
+    // flipped, the other end of the row peeks in
+    let state = tall_objects(&[[16, 1, 4, 0x20], [16, 167, 4, 0x20]]);
+    let line = render(&state, 0);
+    assert_eq!(shades(&line[..2]), "10");
+    assert_eq!(shades(&line[158..]), "02");
+    // and X 0 or 168 is off screen altogether
+    let state = tall_objects(&[[16, 0, 4, 0x00], [16, 168, 4, 0x00]]);
+    assert_eq!(shades(&render(&state, 0)), "0".repeat(160));
+}
+
+#[test]
+fn tall_objects_clip_at_the_top_and_bottom_edges() {
+    // Y 1 leaves only the bottom row on line 0, Y 0 nothing at all
+    let state = tall_objects(&[[1, 8, 4, 0x00], [0, 16, 4, 0x00]]);
+    assert_eq!(shades(&render(&state, 0)[..16]), "3333333300000000");
+    // Y 159 shows its top row on the last line, Y 160 is below the screen
+    let state = tall_objects(&[[159, 8, 4, 0x00], [160, 16, 4, 0x00]]);
+    assert_eq!(shades(&render(&state, 143)[..16]), "1231231200000000");
+    // and with Y 8 the top half is gone, line 0 is the bottom half's first row
+    let state = tall_objects(&[[8, 8, 4, 0x00]]);
+    assert_eq!(shades(&render(&state, 0)[..8]), "33333333");
+    assert_eq!(shades(&render(&state, 7)[..8]), "33333333");
+    assert_eq!(shades(&render(&state, 8)[..8]), "00000000");
+}